@@ -24,6 +24,8 @@ pub enum Error {
     HardenedDerivationXpub,
     /// The descriptor contains multipath keys
     MultiPath,
+    /// The multipath descriptor doesn't have exactly two paths, one for each keychain
+    MultiPathCount(usize),
 
     /// Error thrown while working with [`keys`](crate::keys)
     Key(crate::keys::KeyError),
@@ -68,7 +70,12 @@ impl fmt::Display for Error {
             ),
             Self::MultiPath => write!(
                 f,
-                "The descriptor contains multipath keys, which are not supported yet"
+                "The descriptor contains multipath keys, which are only supported by `Wallet::new_multipath`"
+            ),
+            Self::MultiPathCount(count) => write!(
+                f,
+                "The multipath descriptor must have exactly two paths, found {}",
+                count
             ),
             Self::Key(err) => write!(f, "Key error: {}", err),
             Self::Policy(err) => write!(f, "Policy error: {}", err),
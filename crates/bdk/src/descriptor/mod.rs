@@ -319,6 +319,200 @@ pub(crate) fn into_wallet_descriptor_checked<T: IntoWalletDescriptor>(
     Ok((descriptor, keymap))
 }
 
+/// Split a multipath descriptor string into one wallet descriptor per path, each checked with
+/// [`into_wallet_descriptor_checked`]
+///
+/// The split is done on the string, before parsing, because miniscript can't parse extended
+/// private keys with multiple paths. A descriptor without multipath keys is returned as is.
+pub(crate) fn into_single_wallet_descriptors_checked(
+    descriptor: &str,
+    secp: &SecpCtx,
+    network: Network,
+) -> Result<Vec<(ExtendedDescriptor, KeyMap)>, DescriptorError> {
+    let descriptor = match descriptor.split_once('#') {
+        Some((desc, original_checksum)) => {
+            let checksum = calc_checksum_bytes(desc)?;
+            if original_checksum.as_bytes() != checksum {
+                return Err(DescriptorError::InvalidDescriptorChecksum);
+            }
+            desc
+        }
+        None => descriptor,
+    };
+
+    // Split the descriptor into the parts outside of the `<a;b;...>` groups and the groups
+    let mut parts = Vec::new();
+    let mut groups = Vec::new();
+    let mut rest = descriptor;
+    while let Some(start) = rest.find('<') {
+        let end = rest[start..]
+            .find('>')
+            .map(|end| start + end)
+            .ok_or(DescriptorError::MultiPath)?;
+        parts.push(&rest[..start]);
+        groups.push(rest[start + 1..end].split(';').collect::<Vec<_>>());
+        rest = &rest[end + 1..];
+    }
+    parts.push(rest);
+
+    let count = groups.first().map(|g| g.len()).unwrap_or(1);
+    if groups.iter().any(|g| g.len() != count) {
+        return Err(DescriptorError::MultiPath);
+    }
+
+    (0..count)
+        .map(|index| {
+            let mut single = String::from(parts[0]);
+            for (group, part) in groups.iter().zip(&parts[1..]) {
+                single.push_str(group[index]);
+                single.push_str(part);
+            }
+            into_wallet_descriptor_checked(single.as_str(), secp, network)
+        })
+        .collect()
+}
+
+/// Return the satisfaction weight of a `tr()` descriptor spent through the script path of the
+/// tap leaf at `leaf_index`
+///
+/// The weight is computed like [`Descriptor::max_satisfaction_weight`], but only for the given
+/// leaf. Returns `None` if the descriptor isn't `tr()`, doesn't have such a leaf or the leaf
+/// can't be satisfied.
+pub(crate) fn tap_leaf_satisfaction_weight<Pk: MiniscriptKey>(
+    descriptor: &Descriptor<Pk>,
+    leaf_index: usize,
+) -> Option<usize> {
+    let varint_len = |n: usize| bitcoin::VarInt(n as u64).len();
+
+    let (depth, ms) = match descriptor {
+        Descriptor::Tr(tr) => tr.iter_scripts().nth(leaf_index)?,
+        _ => return None,
+    };
+    let script_size = ms.script_size();
+    let max_sat_elems = ms.max_satisfaction_witness_elements().ok()?;
+    let max_sat_size = ms.max_satisfaction_size().ok()?;
+    let control_block_size =
+        taproot::TAPROOT_CONTROL_BASE_SIZE + taproot::TAPROOT_CONTROL_NODE_SIZE * depth as usize;
+
+    Some(
+        // scriptSig len byte
+        4 +
+        // witness field stack len (+2 for control block & script)
+        varint_len(max_sat_elems + 2) +
+        // size of elements to satisfy script
+        max_sat_size +
+        // second to last element: script
+        varint_len(script_size) +
+        script_size +
+        // last element: control block
+        varint_len(control_block_size) +
+        control_block_size,
+    )
+}
+
+/// Return the hash of the tap leaf at `leaf_index` of a derived `tr()` descriptor
+pub(crate) fn tap_leaf_hash(
+    descriptor: &DerivedDescriptor,
+    leaf_index: usize,
+) -> Option<taproot::TapLeafHash> {
+    match descriptor {
+        Descriptor::Tr(tr) => tr.iter_scripts().nth(leaf_index).map(|(_, ms)| {
+            taproot::TapLeafHash::from_script(&ms.encode(), taproot::LeafVersion::TapScript)
+        }),
+        _ => None,
+    }
+}
+
+/// Combine descriptors that only differ in one derivation step of their extended keys into a
+/// single multipath descriptor
+///
+/// This is the inverse of [`Descriptor::into_single_descriptors`]. Returns `None` if the
+/// descriptors can't be expressed as a multipath descriptor.
+pub(crate) fn into_multipath_descriptor(
+    descriptors: &[&ExtendedDescriptor],
+) -> Option<ExtendedDescriptor> {
+    struct Combiner {
+        keys: Vec<Vec<DescriptorPublicKey>>,
+        next: usize,
+    }
+
+    impl miniscript::Translator<DescriptorPublicKey, DescriptorPublicKey, ()> for Combiner {
+        fn pk(&mut self, _pk: &DescriptorPublicKey) -> Result<DescriptorPublicKey, ()> {
+            let index = self.next;
+            self.next += 1;
+            let keys = self
+                .keys
+                .iter()
+                .map(|keys| keys.get(index).ok_or(()))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if keys.iter().all(|k| k == &keys[0]) {
+                return Ok(keys[0].clone());
+            }
+
+            let xkeys = keys
+                .iter()
+                .map(|k| match k {
+                    DescriptorPublicKey::XPub(xkey) => Ok(xkey),
+                    _ => Err(()),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let first = xkeys[0];
+            let same_key = xkeys.iter().all(|x| {
+                x.origin == first.origin
+                    && x.xkey == first.xkey
+                    && x.wildcard == first.wildcard
+                    && x.derivation_path.len() == first.derivation_path.len()
+            });
+            if !same_key {
+                return Err(());
+            }
+            // The paths must differ in a single step, the one written as `<a;b;...>`
+            let differing_steps = (0..first.derivation_path.len())
+                .filter(|&i| {
+                    xkeys
+                        .iter()
+                        .any(|x| x.derivation_path[i] != first.derivation_path[i])
+                })
+                .count();
+            if differing_steps != 1 {
+                return Err(());
+            }
+
+            let paths = xkeys.iter().map(|x| x.derivation_path.clone()).collect();
+            Ok(DescriptorPublicKey::MultiXPub(DescriptorMultiXKey {
+                origin: first.origin.clone(),
+                xkey: first.xkey,
+                derivation_paths: miniscript::descriptor::DerivPaths::new(paths).ok_or(())?,
+                wildcard: first.wildcard,
+            }))
+        }
+        miniscript::translate_hash_clone!(DescriptorPublicKey, DescriptorPublicKey, ());
+    }
+
+    let first = descriptors.first()?;
+    let keys = descriptors
+        .iter()
+        .map(|descriptor| {
+            let mut keys = Vec::new();
+            descriptor.for_each_key(|k| {
+                keys.push(k.clone());
+                true
+            });
+            keys
+        })
+        .collect();
+    let multipath = first.translate_pk(&mut Combiner { keys, next: 0 }).ok()?;
+
+    // Make sure that the keys were matched up correctly
+    let single_descriptors = multipath.clone().into_single_descriptors().ok()?;
+    if single_descriptors.iter().ne(descriptors.iter().copied()) {
+        return None;
+    }
+
+    Some(multipath)
+}
+
 #[doc(hidden)]
 /// Used internally mainly by the `descriptor!()` and `fragment!()` macros
 pub trait CheckMiniscript<Ctx: miniscript::ScriptContext> {
@@ -881,6 +1075,47 @@ mod test {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_single_wallet_descriptors_roundtrip() {
+        let secp = Secp256k1::new();
+
+        let descriptor = "wsh(multi(2,tprv8ZgxMBicQKsPdDArR4xSAECuVxeX1jwwSXR4ApKbkYgZiziDc4LdBy2WvJeGDfUSE4UT4hHhbgEwbdq8ajjUHiKDegkwrNU6V55CxcxonVN/<0;1>/*,[c258d2e4/84h/1h/0h]tpubD6NzVbkrYhZ4XHndKkuB8FifXm8r5FQHwrN6oZuWCz13qb93rtgKvD4PQsqC4HP4yhV3tA2fqr2RbY5mNXfM7RxXUoeABoDtsFUq2zJq6YK/<0;1>/*))";
+        let descriptors =
+            into_single_wallet_descriptors_checked(descriptor, &secp, Network::Testnet).unwrap();
+        assert_eq!(descriptors.len(), 2);
+        for (i, (descriptor, keymap)) in descriptors.iter().enumerate() {
+            assert!(!descriptor.is_multipath());
+            assert!(descriptor.to_string().contains(&format!("/{}/*", i)));
+            assert_eq!(keymap.len(), 1);
+            let (pk, sk) = keymap.iter().next().unwrap();
+            assert_matches!(pk, DescriptorPublicKey::XPub(_));
+            assert_matches!(sk, DescriptorSecretKey::XPrv(_));
+            assert!(descriptor.for_any_key(|k| k == pk));
+        }
+
+        let multipath = into_multipath_descriptor(&[&descriptors[0].0, &descriptors[1].0]).unwrap();
+        assert!(multipath.is_multipath());
+        assert_eq!(
+            multipath.into_single_descriptors().unwrap(),
+            vec![descriptors[0].0.clone(), descriptors[1].0.clone()]
+        );
+
+        // descriptors that differ in more than a derivation step can't be combined
+        let other = ExtendedDescriptor::from_str("wpkh(tpubD6NzVbkrYhZ4XHndKkuB8FifXm8r5FQHwrN6oZuWCz13qb93rtgKvD4PQsqC4HP4yhV3tA2fqr2RbY5mNXfM7RxXUoeABoDtsFUq2zJq6YK/1/*)").unwrap();
+        assert!(into_multipath_descriptor(&[&descriptors[0].0, &other]).is_none());
+
+        // neither can keys with derivation paths of different lengths
+        let short = ExtendedDescriptor::from_str("wpkh(tpubD6NzVbkrYhZ4XHndKkuB8FifXm8r5FQHwrN6oZuWCz13qb93rtgKvD4PQsqC4HP4yhV3tA2fqr2RbY5mNXfM7RxXUoeABoDtsFUq2zJq6YK/0/*)").unwrap();
+        let long = ExtendedDescriptor::from_str("wpkh(tpubD6NzVbkrYhZ4XHndKkuB8FifXm8r5FQHwrN6oZuWCz13qb93rtgKvD4PQsqC4HP4yhV3tA2fqr2RbY5mNXfM7RxXUoeABoDtsFUq2zJq6YK/1/0/*)").unwrap();
+        assert!(into_multipath_descriptor(&[&short, &long]).is_none());
+        assert!(into_multipath_descriptor(&[&long, &short]).is_none());
+
+        let descriptor = "wpkh(tpubD6NzVbkrYhZ4XHndKkuB8FifXm8r5FQHwrN6oZuWCz13qb93rtgKvD4PQsqC4HP4yhV3tA2fqr2RbY5mNXfM7RxXUoeABoDtsFUq2zJq6YK/0/*)";
+        let descriptors =
+            into_single_wallet_descriptors_checked(descriptor, &secp, Network::Testnet).unwrap();
+        assert_eq!(descriptors.len(), 1);
+    }
+
     #[test]
     fn test_sh_wsh_sortedmulti_redeemscript() {
         use miniscript::psbt::PsbtInputExt;
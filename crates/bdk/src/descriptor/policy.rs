@@ -41,20 +41,25 @@ use alloc::string::String;
 use alloc::vec::Vec;
 use core::cmp::max;
 use core::fmt;
+use core::ops::Range;
 
 use serde::ser::SerializeMap;
 use serde::{Serialize, Serializer};
 
-use bitcoin::bip32::Fingerprint;
-use bitcoin::hashes::{hash160, ripemd160, sha256};
-use bitcoin::{absolute, key::XOnlyPublicKey, PublicKey, Sequence};
+use bitcoin::bip32::{ChildNumber, Fingerprint, KeySource};
+use bitcoin::hashes::{hash160, ripemd160, sha256, sha256d, Hash};
+use bitcoin::key::TapTweak;
+use bitcoin::secp256k1::Message;
+use bitcoin::taproot::{self, TapLeafHash};
+use bitcoin::{absolute, ecdsa, key::XOnlyPublicKey, relative, PublicKey, Sequence};
 
 use miniscript::descriptor::{
-    DescriptorPublicKey, ShInner, SinglePub, SinglePubKey, SortedMultiVec, WshInner,
+    DescriptorPublicKey, ShInner, SinglePub, SinglePubKey, SortedMultiVec, Wildcard, WshInner,
 };
 use miniscript::hash256;
 use miniscript::{
-    Descriptor, Miniscript, Satisfier, ScriptContext, SigType, Terminal, ToPublicKey,
+    Descriptor, Legacy, Miniscript, Satisfier, ScriptContext, Segwitv0, SigType, Tap, Terminal,
+    ToPublicKey,
 };
 
 #[allow(unused_imports)]
@@ -62,7 +67,7 @@ use log::{debug, error, info, trace};
 
 use crate::descriptor::ExtractPolicy;
 use crate::keys::ExtScriptContext;
-use crate::wallet::signer::{SignerId, SignersContainer};
+use crate::wallet::signer::{ComputeSighash, SignerId, SignersContainer};
 use crate::wallet::utils::{After, Older, SecpCtx};
 
 use super::checksum::calc_checksum;
@@ -405,7 +410,11 @@ impl Satisfaction {
                             .or_insert_with(HashSet::new)
                             .insert(val.unwrap());
                     });
-                // TODO: if the map is empty, the conditions are not compatible, return an error?
+                // if the map is empty none of the combinations has compatible conditions, so the
+                // threshold can't actually be reached
+                if map.is_empty() {
+                    return;
+                }
                 *self = Satisfaction::PartialComplete {
                     n: *n,
                     m: *m,
@@ -446,7 +455,10 @@ pub struct Policy {
 }
 
 /// An extra condition that must be satisfied but that is out of control of the user
-/// TODO: use `bitcoin::LockTime` and `bitcoin::Sequence`
+///
+/// Conditions of the same kind are combined by taking the strictest of the two values, which is
+/// only possible if both are expressed in the same unit: a transaction can't be locked both to a
+/// block height and to a timestamp, and the same goes for the relative timelocks of an input.
 #[derive(Hash, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Default, Serialize)]
 pub struct Condition {
     /// Optional CheckSequenceVerify condition
@@ -472,10 +484,16 @@ impl Condition {
     }
 
     fn merge_nsequence(a: Sequence, b: Sequence) -> Result<Sequence, PolicyError> {
-        if a.is_time_locked() != b.is_time_locked() {
-            Err(PolicyError::MixedTimelockUnits)
-        } else {
-            Ok(max(a, b))
+        match (a.to_relative_lock_time(), b.to_relative_lock_time()) {
+            (Some(relative::LockTime::Blocks(x)), Some(relative::LockTime::Blocks(y))) => {
+                Ok(if x > y { a } else { b })
+            }
+            (Some(relative::LockTime::Time(x)), Some(relative::LockTime::Time(y))) => {
+                Ok(if x > y { a } else { b })
+            }
+            (Some(_), Some(_)) => Err(PolicyError::MixedTimelockUnits),
+            // one of the two doesn't enable a relative timelock at all
+            _ => Err(PolicyError::IncompatibleConditions),
         }
     }
 
@@ -499,6 +517,43 @@ impl Condition {
     pub fn is_null(&self) -> bool {
         self.csv.is_none() && self.timelock.is_none()
     }
+
+    // returns the earliest height and median time past at which the condition is satisfied
+    fn earliest_availability(&self, assets: &PolicyAssets) -> (Option<u32>, Option<u32>) {
+        let mut height = None;
+        let mut time = None;
+
+        match self.timelock {
+            Some(absolute::LockTime::Blocks(value)) => height = Some(value.to_consensus_u32()),
+            // BIP113 requires the median time past to be strictly greater than the locktime
+            Some(absolute::LockTime::Seconds(value)) => {
+                time = Some(value.to_consensus_u32().saturating_add(1))
+            }
+            None => {}
+        }
+
+        match self.csv.and_then(|csv| csv.to_relative_lock_time()) {
+            Some(relative::LockTime::Blocks(value)) => {
+                let confirmation_height = assets
+                    .confirmation_height
+                    .unwrap_or_else(|| assets.current_height.saturating_add(1));
+                // like for absolute timelocks this is the height of the tip, and the spending
+                // transaction can be mined in the block `confirmation_height + value`
+                let csv_height = confirmation_height
+                    .saturating_add(value.value().into())
+                    .saturating_sub(1);
+                height = Some(height.map_or(csv_height, |h| max(h, csv_height)));
+            }
+            Some(relative::LockTime::Time(value)) => {
+                let confirmation_time = assets.confirmation_time.unwrap_or(assets.current_time);
+                let csv_time = confirmation_time.saturating_add(value.value() as u32 * 512);
+                time = Some(time.map_or(csv_time, |t| max(t, csv_time)));
+            }
+            None => {}
+        }
+
+        (height, time)
+    }
 }
 
 /// Errors that can happen while extracting and manipulating policies
@@ -514,7 +569,7 @@ pub enum PolicyError {
     AddOnPartialComplete,
     /// Can not merge CSV or timelock values unless both are less than or both are equal or greater than 500_000_000
     MixedTimelockUnits,
-    /// Incompatible conditions (not currently used)
+    /// Incompatible conditions, like a CSV value that doesn't enable a relative timelock
     IncompatibleConditions,
 }
 
@@ -622,7 +677,7 @@ impl Policy {
             }
 
             if let Some(psbt) = build_sat.psbt() {
-                if Ctx::find_signature(psbt, key, secp) {
+                if Ctx::find_signature(psbt, build_sat.inputs(), key, secp) {
                     satisfaction.add(
                         &Satisfaction::Complete {
                             condition: Default::default(),
@@ -735,6 +790,273 @@ impl Policy {
     }
 }
 
+/// The assets available to a spender, used to simulate how a [`Policy`] can be satisfied with
+/// [`Policy::simulate`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PolicyAssets {
+    /// Keys that can produce a signature
+    pub keys: HashSet<PkOrF>,
+    /// Images of the known SHA256 preimages
+    pub sha256: HashSet<sha256::Hash>,
+    /// Images of the known double SHA256 preimages
+    pub hash256: HashSet<hash256::Hash>,
+    /// Images of the known RIPEMD160 preimages
+    pub ripemd160: HashSet<ripemd160::Hash>,
+    /// Images of the known HASH160 preimages
+    pub hash160: HashSet<hash160::Hash>,
+    /// Current blockchain height
+    pub current_height: u32,
+    /// Current median time past of the blockchain
+    pub current_time: u32,
+    /// Height at which the coin being spent was confirmed, used for relative timelocks
+    ///
+    /// If `None` the coin is assumed to be confirmed in the next block.
+    pub confirmation_height: Option<u32>,
+    /// Median time past at which the coin being spent was confirmed, used for relative timelocks
+    ///
+    /// If `None` the coin is assumed to be confirmed at `current_time`.
+    pub confirmation_time: Option<u32>,
+}
+
+impl PolicyAssets {
+    /// Build the assets from the keys and preimages held by a [`SignersContainer`]
+    ///
+    /// The heights and times are left to their default value and should be set by the caller.
+    pub fn from_signers(signers: &SignersContainer, secp: &SecpCtx) -> Self {
+        let mut keys = HashSet::new();
+        for key in signers
+            .signers()
+            .into_iter()
+            .filter_map(|signer| signer.descriptor_secret_key()?.to_public(secp).ok())
+        {
+            let key = PkOrF::from_key(&key, secp);
+            // taproot policies contain x-only keys
+            if let PkOrF::Pubkey(pk) = key {
+                keys.insert(PkOrF::XOnlyPubkey(pk.inner.into()));
+            }
+            keys.insert(key);
+        }
+
+        let preimages = signers.preimages().preimages();
+        PolicyAssets {
+            keys,
+            sha256: preimages.iter().map(|p| sha256::Hash::hash(*p)).collect(),
+            hash256: preimages.iter().map(|p| hash256::Hash::hash(*p)).collect(),
+            ripemd160: preimages
+                .iter()
+                .map(|p| ripemd160::Hash::hash(*p))
+                .collect(),
+            hash160: preimages.iter().map(|p| hash160::Hash::hash(*p)).collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A way of satisfying a [`Policy`] with a set of [`PolicyAssets`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpendingPath {
+    /// The items selected in the policy tree, in the format expected by
+    /// [`TxBuilder::policy_path`](crate::wallet::tx_builder::TxBuilder::policy_path)
+    pub path: BTreeMap<String, Vec<usize>>,
+    /// The timelocks required by the path
+    pub condition: Condition,
+    /// Estimated weight of the witness items satisfying the path
+    ///
+    /// This only accounts for the signatures and preimages: the script, the control block and
+    /// the dissatisfactions of the branches that are not taken are not included.
+    pub satisfaction_weight: usize,
+    /// The earliest blockchain height at which the path can be used, if it's height-locked
+    ///
+    /// This is the height of the tip once the path is usable: a transaction spending it can be
+    /// mined in the following block.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub earliest_height: Option<u32>,
+    /// The earliest median time past at which the path can be used, if it's time-locked
+    ///
+    /// For an absolute timelock this is one second after the locktime, as the median time past
+    /// has to be past it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub earliest_time: Option<u32>,
+}
+
+/// The result of [`Policy::simulate`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SpendingSimulation {
+    /// Paths that can be used right now, sorted by increasing satisfaction weight
+    pub available: Vec<SpendingPath>,
+    /// Paths for which all the keys and preimages are available, but whose timelocks haven't
+    /// expired yet, sorted by the earliest height and time at which they become usable
+    pub pending: Vec<SpendingPath>,
+}
+
+// witness weight of an ECDSA signature with its sighash byte and length prefix
+const ECDSA_SIGNATURE_WEIGHT: usize = 1 + 72 + 1;
+// witness weight of a Schnorr signature with its sighash byte and length prefix
+const SCHNORR_SIGNATURE_WEIGHT: usize = 1 + 64 + 1;
+// witness weight of a 32-byte preimage with its length prefix
+const PREIMAGE_WEIGHT: usize = 1 + 32;
+
+impl Policy {
+    /// Simulate which paths of the policy can be satisfied with the given `assets`, and when
+    /// the ones that are timelocked become usable
+    ///
+    /// Paths that require a key or a preimage that is not among the `assets` are never returned,
+    /// and neither are paths with incompatible timelocks.
+    pub fn simulate(&self, assets: &PolicyAssets) -> SpendingSimulation {
+        let mut simulation = SpendingSimulation::default();
+
+        for mut spending_path in self.spending_paths(assets) {
+            let (height, time) = spending_path.condition.earliest_availability(assets);
+            spending_path.earliest_height = height;
+            spending_path.earliest_time = time;
+
+            if height.map_or(true, |h| assets.current_height >= h)
+                && time.map_or(true, |t| assets.current_time >= t)
+            {
+                simulation.available.push(spending_path);
+            } else {
+                simulation.pending.push(spending_path);
+            }
+        }
+
+        simulation
+            .available
+            .sort_by_key(|spending_path| spending_path.satisfaction_weight);
+        simulation.pending.sort_by_key(|spending_path| {
+            (
+                spending_path.earliest_height,
+                spending_path.earliest_time,
+                spending_path.satisfaction_weight,
+            )
+        });
+
+        simulation
+    }
+
+    // enumerate all the paths that can be satisfied with the keys and preimages in `assets`,
+    // regardless of their timelocks
+    fn spending_paths(&self, assets: &PolicyAssets) -> Vec<SpendingPath> {
+        let leaf = |satisfaction_weight: usize, condition: Condition| {
+            vec![SpendingPath {
+                path: BTreeMap::new(),
+                condition,
+                satisfaction_weight,
+                earliest_height: None,
+                earliest_time: None,
+            }]
+        };
+
+        match &self.item {
+            SatisfiableItem::EcdsaSignature(key) if assets.keys.contains(key) => {
+                leaf(ECDSA_SIGNATURE_WEIGHT, Condition::default())
+            }
+            SatisfiableItem::SchnorrSignature(key) if assets.keys.contains(key) => {
+                leaf(SCHNORR_SIGNATURE_WEIGHT, Condition::default())
+            }
+            SatisfiableItem::Sha256Preimage { hash } if assets.sha256.contains(hash) => {
+                leaf(PREIMAGE_WEIGHT, Condition::default())
+            }
+            SatisfiableItem::Hash256Preimage { hash } if assets.hash256.contains(hash) => {
+                leaf(PREIMAGE_WEIGHT, Condition::default())
+            }
+            SatisfiableItem::Ripemd160Preimage { hash } if assets.ripemd160.contains(hash) => {
+                leaf(PREIMAGE_WEIGHT, Condition::default())
+            }
+            SatisfiableItem::Hash160Preimage { hash } if assets.hash160.contains(hash) => {
+                leaf(PREIMAGE_WEIGHT, Condition::default())
+            }
+            SatisfiableItem::AbsoluteTimelock { value } => leaf(
+                0,
+                Condition {
+                    csv: None,
+                    timelock: Some(*value),
+                },
+            ),
+            SatisfiableItem::RelativeTimelock { value } => leaf(
+                0,
+                Condition {
+                    csv: Some(*value),
+                    timelock: None,
+                },
+            ),
+            SatisfiableItem::Multisig { keys, threshold } => {
+                let selected = keys
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, key)| assets.keys.contains(key))
+                    .map(|(index, _)| index)
+                    .take(*threshold)
+                    .collect::<Vec<_>>();
+                if selected.len() < *threshold {
+                    return vec![];
+                }
+
+                // `multi_a` only ever contains x-only keys, and has an empty push for each
+                // missing signature. `multi` needs an extra dummy element instead.
+                let satisfaction_weight = if keys.iter().all(|k| matches!(k, PkOrF::XOnlyPubkey(_)))
+                {
+                    threshold * SCHNORR_SIGNATURE_WEIGHT + (keys.len() - threshold)
+                } else {
+                    threshold * ECDSA_SIGNATURE_WEIGHT + 1
+                };
+
+                let mut spending_paths = leaf(satisfaction_weight, Condition::default());
+                spending_paths[0].path.insert(self.id.clone(), selected);
+                spending_paths
+            }
+            SatisfiableItem::Thresh { items, threshold } => {
+                let items_paths = items
+                    .iter()
+                    .map(|item| item.spending_paths(assets))
+                    .collect::<Vec<_>>();
+                let satisfiable = items_paths
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, paths)| !paths.is_empty())
+                    .map(|(index, _)| index)
+                    .collect::<Vec<_>>();
+                if satisfiable.len() < *threshold {
+                    return vec![];
+                }
+
+                combinations(&satisfiable, *threshold)
+                    .into_iter()
+                    .flat_map(|selected| {
+                        let options = selected
+                            .iter()
+                            .map(|index| items_paths[*index].clone())
+                            .collect();
+                        mix(options)
+                            .into_iter()
+                            .filter_map(|children| {
+                                let mut path = BTreeMap::new();
+                                path.insert(self.id.clone(), selected.clone());
+                                let mut condition = Condition::default();
+                                let mut satisfaction_weight = 0;
+                                for child in children {
+                                    path.extend(child.path);
+                                    // incompatible timelocks can't be satisfied together
+                                    condition = condition.merge(&child.condition).ok()?;
+                                    satisfaction_weight += child.satisfaction_weight;
+                                }
+
+                                Some(SpendingPath {
+                                    path,
+                                    condition,
+                                    satisfaction_weight,
+                                    earliest_height: None,
+                                    earliest_time: None,
+                                })
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+}
+
 impl From<SatisfiableItem> for Policy {
     fn from(other: SatisfiableItem) -> Self {
         Self::new(other)
@@ -790,39 +1112,167 @@ fn make_generic_signature<M: Fn() -> SatisfiableItem, F: Fn(&Psbt) -> bool>(
     policy
 }
 
+fn make_preimage<F: Fn(&psbt::Input) -> bool>(
+    item: SatisfiableItem,
+    has_preimage: bool,
+    build_sat: BuildSatisfaction,
+    find_preimage: F,
+) -> Policy {
+    let mut policy: Policy = item.into();
+
+    if has_preimage {
+        policy.contribution = Satisfaction::Complete {
+            condition: Default::default(),
+        };
+    }
+
+    if let Some(psbt) = build_sat.psbt() {
+        if build_sat
+            .inputs()
+            .all(|index| psbt.inputs.get(index).map_or(false, &find_preimage))
+        {
+            policy.satisfaction = Satisfaction::Complete {
+                condition: Default::default(),
+            };
+        }
+    }
+
+    policy
+}
+
 fn generic_sig_in_psbt<
-    // C is for "check", it's a closure we use to *check* if a psbt input contains the signature
-    // for a specific key
-    C: Fn(&psbt::Input, &SinglePubKey) -> bool,
-    // E is for "extract", it extracts a key from the bip32 derivations found in the psbt input
-    E: Fn(&psbt::Input, Fingerprint) -> Option<SinglePubKey>,
+    // C is for "check", it's a closure we use to *check* if a psbt input contains a valid
+    // signature for a specific key
+    C: Fn(usize, &SinglePubKey) -> bool,
+    // E is for "extract", it extracts the keys and their origin from the derivations found in the
+    // psbt input
+    E: Fn(&psbt::Input) -> Vec<(SinglePubKey, KeySource)>,
 >(
     psbt: &Psbt,
+    mut inputs: Range<usize>,
     key: &DescriptorPublicKey,
     secp: &SecpCtx,
     check: C,
     extract: E,
 ) -> bool {
-    //TODO check signature validity
-    psbt.inputs.iter().all(|input| match key {
-        DescriptorPublicKey::Single(SinglePub { key, .. }) => check(input, key),
-        DescriptorPublicKey::XPub(xpub) => {
-            //TODO check actual derivation matches
-            match extract(input, xpub.root_fingerprint(secp)) {
-                Some(pubkey) => check(input, &pubkey),
-                None => false,
-            }
+    inputs.all(|input_index| match (psbt.inputs.get(input_index), key) {
+        (None, _) => false,
+        (Some(_), DescriptorPublicKey::Single(SinglePub { key, .. })) => check(input_index, key),
+        (Some(input), DescriptorPublicKey::XPub(_) | DescriptorPublicKey::MultiXPub(_)) => {
+            extract(input)
+                .into_iter()
+                .filter(|(pubkey, key_source)| is_derived_from(key, pubkey, key_source, secp))
+                .any(|(pubkey, _)| check(input_index, &pubkey))
         }
-        DescriptorPublicKey::MultiXPub(xpub) => {
-            //TODO check actual derivation matches
-            match extract(input, xpub.root_fingerprint(secp)) {
-                Some(pubkey) => check(input, &pubkey),
-                None => false,
+    })
+}
+
+// Check that `pubkey` is derived from the extended key `key` following the derivation in
+// `key_source`, to make sure a signature made with another key of the same master key isn't
+// counted
+fn is_derived_from(
+    key: &DescriptorPublicKey,
+    pubkey: &SinglePubKey,
+    (fingerprint, path): &KeySource,
+    secp: &SecpCtx,
+) -> bool {
+    key.clone().into_single_keys().into_iter().any(|key| {
+        let full_path = match key.full_derivation_path() {
+            Some(full_path) if &key.master_fingerprint() == fingerprint => full_path,
+            _ => return false,
+        };
+        let index = if key.has_wildcard() {
+            match path.as_ref().split_last() {
+                Some((ChildNumber::Normal { index } | ChildNumber::Hardened { index }, parent))
+                    if parent == full_path.as_ref() =>
+                {
+                    *index
+                }
+                _ => return false,
+            }
+        } else if path == &full_path {
+            0
+        } else {
+            return false;
+        };
+
+        // keys with hardened steps after the extended public key can't be derived, so only the
+        // derivation path can be checked
+        let hardened = match &key {
+            DescriptorPublicKey::XPub(xkey) => {
+                xkey.wildcard == Wildcard::Hardened
+                    || xkey
+                        .derivation_path
+                        .into_iter()
+                        .any(ChildNumber::is_hardened)
             }
+            _ => false,
+        };
+        if hardened {
+            return true;
+        }
+
+        match key
+            .at_derivation_index(index)
+            .and_then(|key| key.derive_public_key(secp))
+        {
+            Ok(derived) => match pubkey {
+                SinglePubKey::FullKey(pk) => pk == &derived,
+                SinglePubKey::XOnly(pk) => pk == &XOnlyPublicKey::from(derived.inner),
+            },
+            Err(_) => false,
         }
     })
 }
 
+// Verify an ECDSA signature against the sighash of the input. Signatures made with a different
+// sighash type than the one requested by the psbt input are not considered valid.
+fn ecdsa_sig_is_valid<Ctx: ScriptContext + 'static>(
+    psbt: &Psbt,
+    input_index: usize,
+    pubkey: &PublicKey,
+    signature: &ecdsa::Signature,
+    secp: &SecpCtx,
+) -> bool {
+    let sighash = if Ctx::as_enum().is_legacy() {
+        Legacy::sighash(psbt, input_index, ()).map(|(hash, hash_ty)| (Message::from(hash), hash_ty))
+    } else {
+        Segwitv0::sighash(psbt, input_index, ())
+            .map(|(hash, hash_ty)| (Message::from(hash), hash_ty))
+    };
+
+    match sighash {
+        Ok((msg, hash_ty)) => {
+            signature.hash_ty == hash_ty
+                && secp
+                    .verify_ecdsa(&msg, &signature.sig, &pubkey.inner)
+                    .is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
+// Verify a Schnorr signature against the sighash of the input, for the key spend if `leaf_hash` is
+// `None` or for the script spend of that leaf otherwise
+fn schnorr_sig_is_valid(
+    psbt: &Psbt,
+    input_index: usize,
+    leaf_hash: Option<TapLeafHash>,
+    pubkey: &XOnlyPublicKey,
+    signature: &taproot::Signature,
+    secp: &SecpCtx,
+) -> bool {
+    match Tap::sighash(psbt, input_index, leaf_hash) {
+        Ok((hash, hash_ty)) => {
+            signature.hash_ty == hash_ty
+                && secp
+                    .verify_schnorr(&signature.sig, &Message::from(hash), pubkey)
+                    .is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
 trait SigExt: ScriptContext {
     fn make_signature(
         key: &DescriptorPublicKey,
@@ -831,7 +1281,12 @@ trait SigExt: ScriptContext {
         secp: &SecpCtx,
     ) -> Policy;
 
-    fn find_signature(psbt: &Psbt, key: &DescriptorPublicKey, secp: &SecpCtx) -> bool;
+    fn find_signature(
+        psbt: &Psbt,
+        inputs: Range<usize>,
+        key: &DescriptorPublicKey,
+        secp: &SecpCtx,
+    ) -> bool;
 }
 
 impl<T: ScriptContext + 'static> SigExt for T {
@@ -848,7 +1303,7 @@ impl<T: ScriptContext + 'static> SigExt for T {
                 build_sat,
                 secp,
                 || SatisfiableItem::SchnorrSignature(PkOrF::from_key(key, secp)),
-                |psbt| Self::find_signature(psbt, key, secp),
+                |psbt| Self::find_signature(psbt, build_sat.inputs(), key, secp),
             )
         } else {
             make_generic_signature(
@@ -857,52 +1312,94 @@ impl<T: ScriptContext + 'static> SigExt for T {
                 build_sat,
                 secp,
                 || SatisfiableItem::EcdsaSignature(PkOrF::from_key(key, secp)),
-                |psbt| Self::find_signature(psbt, key, secp),
+                |psbt| Self::find_signature(psbt, build_sat.inputs(), key, secp),
             )
         }
     }
 
-    fn find_signature(psbt: &Psbt, key: &DescriptorPublicKey, secp: &SecpCtx) -> bool {
+    fn find_signature(
+        psbt: &Psbt,
+        inputs: Range<usize>,
+        key: &DescriptorPublicKey,
+        secp: &SecpCtx,
+    ) -> bool {
         if T::as_enum().is_taproot() {
             generic_sig_in_psbt(
                 psbt,
+                inputs,
                 key,
                 secp,
-                |input, pk| {
+                |input_index, pk| {
                     let pk = match pk {
                         SinglePubKey::XOnly(pk) => pk,
                         _ => return false,
                     };
-
-                    if input.tap_internal_key == Some(*pk) && input.tap_key_sig.is_some() {
-                        true
-                    } else {
-                        input.tap_script_sigs.keys().any(|(sk, _)| sk == pk)
-                    }
+                    let input = &psbt.inputs[input_index];
+
+                    // the key spend signature is made with the tweaked internal key
+                    let key_spend = input.tap_internal_key == Some(*pk)
+                        && input.tap_key_sig.map_or(false, |sig| {
+                            let output_key = pk.tap_tweak(secp, input.tap_merkle_root).0;
+                            schnorr_sig_is_valid(
+                                psbt,
+                                input_index,
+                                None,
+                                &output_key.to_inner(),
+                                &sig,
+                                secp,
+                            )
+                        });
+
+                    key_spend
+                        || input
+                            .tap_script_sigs
+                            .iter()
+                            .filter(|((sk, _), _)| sk == pk)
+                            .any(|((_, leaf_hash), sig)| {
+                                schnorr_sig_is_valid(
+                                    psbt,
+                                    input_index,
+                                    Some(*leaf_hash),
+                                    pk,
+                                    sig,
+                                    secp,
+                                )
+                            })
                 },
-                |input, fing| {
+                |input| {
                     input
                         .tap_key_origins
                         .iter()
-                        .find(|(_, (_, (f, _)))| f == &fing)
-                        .map(|(pk, _)| SinglePubKey::XOnly(*pk))
+                        .map(|(pk, (_, key_source))| (SinglePubKey::XOnly(*pk), key_source.clone()))
+                        .collect()
                 },
             )
         } else {
             generic_sig_in_psbt(
                 psbt,
+                inputs,
                 key,
                 secp,
-                |input, pk| match pk {
-                    SinglePubKey::FullKey(pk) => input.partial_sigs.contains_key(pk),
+                |input_index, pk| match pk {
+                    SinglePubKey::FullKey(pk) => psbt.inputs[input_index]
+                        .partial_sigs
+                        .get(pk)
+                        .map_or(false, |sig| {
+                            ecdsa_sig_is_valid::<T>(psbt, input_index, pk, sig, secp)
+                        }),
                     _ => false,
                 },
-                |input, fing| {
+                |input| {
                     input
                         .bip32_derivation
                         .iter()
-                        .find(|(_, (f, _))| f == &fing)
-                        .map(|(pk, _)| SinglePubKey::FullKey(PublicKey::new(*pk)))
+                        .map(|(pk, key_source)| {
+                            (
+                                SinglePubKey::FullKey(PublicKey::new(*pk)),
+                                key_source.clone(),
+                            )
+                        })
+                        .collect()
                 },
             )
         }
@@ -978,16 +1475,34 @@ impl<Ctx: ScriptContext + 'static> ExtractPolicy for Miniscript<DescriptorPublic
 
                 Some(policy)
             }
-            Terminal::Sha256(hash) => Some(SatisfiableItem::Sha256Preimage { hash: *hash }.into()),
-            Terminal::Hash256(hash) => {
-                Some(SatisfiableItem::Hash256Preimage { hash: *hash }.into())
-            }
-            Terminal::Ripemd160(hash) => {
-                Some(SatisfiableItem::Ripemd160Preimage { hash: *hash }.into())
-            }
-            Terminal::Hash160(hash) => {
-                Some(SatisfiableItem::Hash160Preimage { hash: *hash }.into())
-            }
+            Terminal::Sha256(hash) => Some(make_preimage(
+                SatisfiableItem::Sha256Preimage { hash: *hash },
+                signers.preimages().sha256(hash).is_some(),
+                build_sat,
+                |input| input.sha256_preimages.contains_key(hash),
+            )),
+            Terminal::Hash256(hash) => Some(make_preimage(
+                SatisfiableItem::Hash256Preimage { hash: *hash },
+                signers.preimages().hash256(hash).is_some(),
+                build_sat,
+                |input| {
+                    input
+                        .hash256_preimages
+                        .contains_key(&sha256d::Hash::from_byte_array(hash.to_byte_array()))
+                },
+            )),
+            Terminal::Ripemd160(hash) => Some(make_preimage(
+                SatisfiableItem::Ripemd160Preimage { hash: *hash },
+                signers.preimages().ripemd160(hash).is_some(),
+                build_sat,
+                |input| input.ripemd160_preimages.contains_key(hash),
+            )),
+            Terminal::Hash160(hash) => Some(make_preimage(
+                SatisfiableItem::Hash160Preimage { hash: *hash },
+                signers.preimages().hash160(hash).is_some(),
+                build_sat,
+                |input| input.hash160_preimages.contains_key(hash),
+            )),
             Terminal::Multi(k, pks) | Terminal::MultiA(k, pks) => {
                 Policy::make_multisig::<Ctx>(pks, signers, build_sat, *k, false, secp)?
             }
@@ -1055,6 +1570,15 @@ pub enum BuildSatisfaction<'a> {
     None,
     /// Analyze the given PSBT to check for existing signatures
     Psbt(&'a Psbt),
+    /// Like `Psbt` variant, but only analyze the input at `input_index`
+    ///
+    /// This is useful when the inputs of the PSBT are spent with different descriptors.
+    PsbtInput {
+        /// Given PSBT
+        psbt: &'a Psbt,
+        /// Index of the input to analyze
+        input_index: usize,
+    },
     /// Like `Psbt` variant and also check for expired timelocks
     PsbtTimelocks {
         /// Given PSBT
@@ -1071,9 +1595,21 @@ impl<'a> BuildSatisfaction<'a> {
         match self {
             BuildSatisfaction::None => None,
             BuildSatisfaction::Psbt(psbt) => Some(psbt),
+            BuildSatisfaction::PsbtInput { psbt, .. } => Some(psbt),
             BuildSatisfaction::PsbtTimelocks { psbt, .. } => Some(psbt),
         }
     }
+
+    // the indexes of the psbt inputs that should be analyzed
+    fn inputs(&self) -> Range<usize> {
+        match self {
+            BuildSatisfaction::None => 0..0,
+            BuildSatisfaction::PsbtInput { input_index, .. } => *input_index..*input_index + 1,
+            BuildSatisfaction::Psbt(psbt) | BuildSatisfaction::PsbtTimelocks { psbt, .. } => {
+                0..psbt.inputs.len()
+            }
+        }
+    }
 }
 
 impl ExtractPolicy for Descriptor<DescriptorPublicKey> {
@@ -1450,7 +1986,6 @@ mod test {
     // - mixed timelocks should fail
 
     #[test]
-    #[ignore]
     fn test_extract_policy_for_wsh_mixed_timelocks() {
         let secp = Secp256k1::new();
         let (prvkey0, _pubkey0, _fingerprint0) = setup_keys(TPRV0_STR, PATH, &secp);
@@ -1466,17 +2001,24 @@ mod test {
             .into_wallet_descriptor(&secp, Network::Testnet)
             .unwrap();
         let signers_container = Arc::new(SignersContainer::build(keymap, &wallet_desc, &secp));
-        let _policy = wallet_desc
+        let policy = wallet_desc
             .extract_policy(&signers_container, BuildSatisfaction::None, &secp)
             .unwrap()
             .unwrap();
-        // println!("desc policy = {:?}", policy); // TODO remove
-        // TODO how should this fail with mixed timelocks?
+
+        // the timelocks can't be satisfied together, so the policy can't be completed
+        assert_matches!(&policy.contribution, Satisfaction::Partial { n, m, items, .. } if n == &2
+             && m == &2
+             && items == &vec![0]
+        );
+        assert_eq!(
+            policy.get_condition(&BTreeMap::new()),
+            Err(PolicyError::MixedTimelockUnits)
+        );
     }
 
     // - multiple timelocks of the same type should be correctly merged together
     #[test]
-    #[ignore]
     fn test_extract_policy_for_multiple_same_timelocks() {
         let secp = Secp256k1::new();
         let (prvkey0, _pubkey0, _fingerprint0) = setup_keys(TPRV0_STR, PATH, &secp);
@@ -1491,12 +2033,17 @@ mod test {
             .into_wallet_descriptor(&secp, Network::Testnet)
             .unwrap();
         let signers_container = Arc::new(SignersContainer::build(keymap, &wallet_desc, &secp));
-        let _policy = wallet_desc
+        let policy = wallet_desc
             .extract_policy(&signers_container, BuildSatisfaction::None, &secp)
             .unwrap()
             .unwrap();
-        // println!("desc policy = {:?}", policy); // TODO remove
-        // TODO how should this merge timelocks?
+        let expected = Condition {
+            csv: None,
+            timelock: Some(absolute::LockTime::from_consensus(locktime_blocks1)),
+        };
+        assert_matches!(&policy.contribution, Satisfaction::PartialComplete { conditions, .. } if conditions.get(&vec![0, 1]).unwrap().iter().collect::<Vec<_>>() == vec![&expected]);
+        assert_eq!(policy.get_condition(&BTreeMap::new()), Ok(expected));
+
         let (prvkey1, _pubkey1, _fingerprint1) = setup_keys(TPRV0_STR, PATH, &secp);
         let locktime_seconds0 = 500000100;
         let locktime_seconds1 = 500000200;
@@ -1509,14 +2056,41 @@ mod test {
             .into_wallet_descriptor(&secp, Network::Testnet)
             .unwrap();
         let signers_container = Arc::new(SignersContainer::build(keymap, &wallet_desc, &secp));
-        let _policy = wallet_desc
+        let policy = wallet_desc
             .extract_policy(&signers_container, BuildSatisfaction::None, &secp)
             .unwrap()
             .unwrap();
+        let expected = Condition {
+            csv: None,
+            timelock: Some(absolute::LockTime::from_consensus(locktime_seconds1)),
+        };
+        assert_eq!(policy.get_condition(&BTreeMap::new()), Ok(expected));
+    }
 
-        // println!("desc policy = {:?}", policy); // TODO remove
+    #[test]
+    fn test_merge_condition_relative_timelocks() {
+        let blocks = |n| Condition {
+            csv: Some(Sequence::from_height(n)),
+            timelock: None,
+        };
+        let time = |n| Condition {
+            csv: Some(Sequence::from_512_second_intervals(n)),
+            timelock: None,
+        };
 
-        // TODO how should this merge timelocks?
+        assert_eq!(blocks(10).merge(&blocks(20)), Ok(blocks(20)));
+        assert_eq!(time(20).merge(&time(10)), Ok(time(20)));
+        assert_eq!(
+            blocks(10).merge(&time(10)),
+            Err(PolicyError::MixedTimelockUnits)
+        );
+        assert_eq!(
+            blocks(10).merge(&Condition {
+                csv: Some(Sequence::MAX),
+                timelock: None,
+            }),
+            Err(PolicyError::IncompatibleConditions)
+        );
     }
 
     #[test]
@@ -1904,4 +2478,148 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn test_simulate_relative_timelock() {
+        let secp = Secp256k1::new();
+
+        let (prvkey0, _, _) = setup_keys(TPRV0_STR, PATH, &secp);
+        let (_, pubkey1, _) = setup_keys(TPRV1_STR, PATH, &secp);
+        let desc = descriptor!(wsh(and_v(v:pk(prvkey0),or_d(pk(pubkey1),older(12960))))).unwrap();
+        let (wallet_desc, keymap) = desc
+            .into_wallet_descriptor(&secp, Network::Testnet)
+            .unwrap();
+        let signers_container = Arc::new(SignersContainer::build(keymap, &wallet_desc, &secp));
+        let policy = wallet_desc
+            .extract_policy(&signers_container, BuildSatisfaction::None, &secp)
+            .unwrap()
+            .unwrap();
+
+        let mut assets = PolicyAssets::from_signers(&signers_container, &secp);
+        assets.current_height = 1_000;
+        assets.confirmation_height = Some(900);
+
+        let simulation = policy.simulate(&assets);
+        assert!(simulation.available.is_empty());
+        assert_eq!(simulation.pending.len(), 1);
+        let pending = &simulation.pending[0];
+        assert_eq!(pending.condition.csv, Some(Sequence(12960)));
+        // the coin is spendable in block 900 + 12960, so once the tip is one block before that
+        assert_eq!(pending.earliest_height, Some(900 + 12960 - 1));
+        assert_eq!(pending.earliest_time, None);
+        assert_eq!(policy.get_condition(&pending.path), Ok(pending.condition));
+
+        assets.current_height = 900 + 12960 - 2;
+        let simulation = policy.simulate(&assets);
+        assert!(simulation.available.is_empty());
+
+        assets.current_height = 900 + 12960 - 1;
+        let simulation = policy.simulate(&assets);
+        assert_eq!(simulation.available.len(), 1);
+        assert!(simulation.pending.is_empty());
+    }
+
+    #[test]
+    fn test_simulate_all_keys() {
+        let secp = Secp256k1::new();
+
+        let (prvkey0, _, _) = setup_keys(TPRV0_STR, PATH, &secp);
+        let (prvkey1, _, _) = setup_keys(TPRV1_STR, PATH, &secp);
+        let desc = descriptor!(wsh(and_v(v:pk(prvkey0),or_d(pk(prvkey1),older(12960))))).unwrap();
+        let (wallet_desc, keymap) = desc
+            .into_wallet_descriptor(&secp, Network::Testnet)
+            .unwrap();
+        let signers_container = Arc::new(SignersContainer::build(keymap, &wallet_desc, &secp));
+        let policy = wallet_desc
+            .extract_policy(&signers_container, BuildSatisfaction::None, &secp)
+            .unwrap()
+            .unwrap();
+
+        // unconfirmed coins are assumed to be confirmed in the next block
+        let assets = PolicyAssets::from_signers(&signers_container, &secp);
+        let simulation = policy.simulate(&assets);
+        assert_eq!(simulation.available.len(), 1);
+        assert!(simulation.available[0].condition.is_null());
+        assert_eq!(simulation.available[0].satisfaction_weight, 2 * 74);
+        assert_eq!(simulation.pending.len(), 1);
+        assert_eq!(simulation.pending[0].earliest_height, Some(1 + 12960 - 1));
+        assert_eq!(simulation.pending[0].satisfaction_weight, 74);
+
+        for spending_path in simulation.available.iter().chain(&simulation.pending) {
+            assert_eq!(
+                policy.get_condition(&spending_path.path),
+                Ok(spending_path.condition)
+            );
+        }
+
+        // without keys nothing can be satisfied
+        let simulation = policy.simulate(&PolicyAssets::default());
+        assert!(simulation.available.is_empty());
+        assert!(simulation.pending.is_empty());
+    }
+
+    #[test]
+    fn test_simulate_absolute_timelocks() {
+        let secp = Secp256k1::new();
+
+        let (prvkey0, _, _) = setup_keys(TPRV0_STR, PATH, &secp);
+        let desc = descriptor!(wsh(and_v(v:pk(prvkey0),and_v(v:after(100),after(200))))).unwrap();
+        let (wallet_desc, keymap) = desc
+            .into_wallet_descriptor(&secp, Network::Testnet)
+            .unwrap();
+        let signers_container = Arc::new(SignersContainer::build(keymap, &wallet_desc, &secp));
+        let policy = wallet_desc
+            .extract_policy(&signers_container, BuildSatisfaction::None, &secp)
+            .unwrap()
+            .unwrap();
+
+        let mut assets = PolicyAssets::from_signers(&signers_container, &secp);
+        assets.current_height = 150;
+        let simulation = policy.simulate(&assets);
+        assert!(simulation.available.is_empty());
+        assert_eq!(simulation.pending.len(), 1);
+        assert_eq!(simulation.pending[0].earliest_height, Some(200));
+
+        assets.current_height = 200;
+        let simulation = policy.simulate(&assets);
+        assert_eq!(simulation.available.len(), 1);
+        assert!(simulation.pending.is_empty());
+
+        // height and time based timelocks can't be satisfied together
+        let (prvkey0, _, _) = setup_keys(TPRV0_STR, PATH, &secp);
+        let desc =
+            descriptor!(wsh(and_v(v:pk(prvkey0),and_v(v:after(500_000_100),after(100))))).unwrap();
+        let (wallet_desc, keymap) = desc
+            .into_wallet_descriptor(&secp, Network::Testnet)
+            .unwrap();
+        let signers_container = Arc::new(SignersContainer::build(keymap, &wallet_desc, &secp));
+        let policy = wallet_desc
+            .extract_policy(&signers_container, BuildSatisfaction::None, &secp)
+            .unwrap()
+            .unwrap();
+        let assets = PolicyAssets::from_signers(&signers_container, &secp);
+        assert_eq!(policy.simulate(&assets), SpendingSimulation::default());
+
+        // a time based timelock needs the median time past to be greater than the locktime
+        let (prvkey0, _, _) = setup_keys(TPRV0_STR, PATH, &secp);
+        let desc = descriptor!(wsh(and_v(v:pk(prvkey0),after(500_000_100)))).unwrap();
+        let (wallet_desc, keymap) = desc
+            .into_wallet_descriptor(&secp, Network::Testnet)
+            .unwrap();
+        let signers_container = Arc::new(SignersContainer::build(keymap, &wallet_desc, &secp));
+        let policy = wallet_desc
+            .extract_policy(&signers_container, BuildSatisfaction::None, &secp)
+            .unwrap()
+            .unwrap();
+        let mut assets = PolicyAssets::from_signers(&signers_container, &secp);
+        assets.current_time = 500_000_100;
+        let simulation = policy.simulate(&assets);
+        assert!(simulation.available.is_empty());
+        assert_eq!(simulation.pending[0].earliest_time, Some(500_000_101));
+
+        assets.current_time = 500_000_101;
+        let simulation = policy.simulate(&assets);
+        assert_eq!(simulation.available.len(), 1);
+        assert!(simulation.pending.is_empty());
+    }
 }
@@ -14,6 +14,8 @@
 //! This module contains the definition of various common script templates that are ready to be
 //! used. See the documentation of each template for an example.
 
+use alloc::vec::Vec;
+
 use bitcoin::bip32;
 use bitcoin::Network;
 
@@ -480,6 +482,484 @@ impl<K: DerivableKey<Tap>> DescriptorTemplate for Bip86Public<K> {
     }
 }
 
+/// BIP48 P2WSH multisig template. Expands to `wsh(sortedmulti(threshold, key/48'/{0,1}'/0'/2'/{0,1}/*, ...))`
+///
+/// Since there are hardened derivation steps, this template requires private derivable keys
+/// (generally `xprv`/`tprv`). This is mostly useful when all the keys of the wallet are held by
+/// the same party, for instance in tests.
+///
+/// See [`Bip48WshPublic`] for a template that can work with `xpub`/`tpub`.
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk::bitcoin::Network;
+/// # use bdk::{Wallet, KeychainKind};
+/// # use bdk::wallet::AddressIndex::New;
+/// use bdk::template::Bip48Wsh;
+///
+/// let key_a = bitcoin::bip32::ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m")?;
+/// let key_b = bitcoin::bip32::ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS")?;
+/// let mut wallet = Wallet::new_no_persist(
+///     Bip48Wsh(2, vec![key_a, key_b], KeychainKind::External),
+///     Some(Bip48Wsh(2, vec![key_a, key_b], KeychainKind::Internal)),
+///     Network::Testnet,
+/// )?;
+///
+/// assert_eq!(wallet.get_address(New).to_string(), "tb1qnvd46ny8qwv94w5mgthn0ucd4ylvchu58ru24c43xas4uxvz44pskd0p9r");
+/// assert_eq!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string(), "wsh(sortedmulti(2,[c55b303f/48'/1'/0'/2']tpubDEU3eBekc59Yw68Nb3dmoXcinikF3qhGW9ymz39kMuBFSHFqX8MFuyt4mC3y9EiCWDmVw1rmQ3s7GjERKkARjGFwA2dAWRLpeMCU5oUuMXU/0/*,[b6dff990/48'/1'/0'/2']tpubDEYNGD172pDL2SiB6bfaZ1DJKby3EW7du9Wuuxs6HgvsaE1p8sWobVY528eycGSGPDjoayyevzaoEfYz7d6YxiDotkCJhxnSC6fopgD2sGC/0/*))#2m4pcdlw");
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct Bip48Wsh<K: DerivableKey<Segwitv0>>(pub usize, pub Vec<K>, pub KeychainKind);
+
+impl<K: DerivableKey<Segwitv0>> DescriptorTemplate for Bip48Wsh<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        let keys = segwit_v0::make_bip48_private(2, self.1, self.2, network)?;
+        descriptor!(wsh(sortedmulti_vec(self.0, keys)))
+    }
+}
+
+/// BIP48 P2WSH multisig public template. Expands to `wsh(sortedmulti(threshold, key/{0,1}/*, ...))`
+///
+/// This assumes that the keys used have already been derived with `m/48'/0'/0'/2'` for Mainnet
+/// or `m/48'/1'/0'/2'` for Testnet.
+///
+/// This template requires the parent fingerprint of each key to populate correctly the metadata
+/// of PSBTs.
+///
+/// See [`Bip48Wsh`] for a template that does the full derivation, but requires private data
+/// for the keys.
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk::bitcoin::Network;
+/// # use bdk::{Wallet, KeychainKind};
+/// # use bdk::wallet::AddressIndex::New;
+/// use bdk::template::Bip48WshPublic;
+///
+/// let key_a = bitcoin::bip32::ExtendedPubKey::from_str("tpubDEU3eBekc59Yw68Nb3dmoXcinikF3qhGW9ymz39kMuBFSHFqX8MFuyt4mC3y9EiCWDmVw1rmQ3s7GjERKkARjGFwA2dAWRLpeMCU5oUuMXU")?;
+/// let key_b = bitcoin::bip32::ExtendedPubKey::from_str("tpubDEYNGD172pDL2SiB6bfaZ1DJKby3EW7du9Wuuxs6HgvsaE1p8sWobVY528eycGSGPDjoayyevzaoEfYz7d6YxiDotkCJhxnSC6fopgD2sGC")?;
+/// let keys = vec![
+///     (key_a, bitcoin::bip32::Fingerprint::from_str("c55b303f")?),
+///     (key_b, bitcoin::bip32::Fingerprint::from_str("b6dff990")?),
+/// ];
+/// let mut wallet = Wallet::new_no_persist(
+///     Bip48WshPublic(2, keys.clone(), KeychainKind::External),
+///     Some(Bip48WshPublic(2, keys, KeychainKind::Internal)),
+///     Network::Testnet,
+/// )?;
+///
+/// assert_eq!(wallet.get_address(New).to_string(), "tb1qnvd46ny8qwv94w5mgthn0ucd4ylvchu58ru24c43xas4uxvz44pskd0p9r");
+/// assert_eq!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string(), "wsh(sortedmulti(2,[c55b303f/48'/1'/0'/2']tpubDEU3eBekc59Yw68Nb3dmoXcinikF3qhGW9ymz39kMuBFSHFqX8MFuyt4mC3y9EiCWDmVw1rmQ3s7GjERKkARjGFwA2dAWRLpeMCU5oUuMXU/0/*,[b6dff990/48'/1'/0'/2']tpubDEYNGD172pDL2SiB6bfaZ1DJKby3EW7du9Wuuxs6HgvsaE1p8sWobVY528eycGSGPDjoayyevzaoEfYz7d6YxiDotkCJhxnSC6fopgD2sGC/0/*))#2m4pcdlw");
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct Bip48WshPublic<K: DerivableKey<Segwitv0>>(
+    pub usize,
+    pub Vec<(K, bip32::Fingerprint)>,
+    pub KeychainKind,
+);
+
+impl<K: DerivableKey<Segwitv0>> DescriptorTemplate for Bip48WshPublic<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        let keys = segwit_v0::make_bip48_public(2, self.1, self.2, network)?;
+        descriptor!(wsh(sortedmulti_vec(self.0, keys)))
+    }
+}
+
+/// BIP48 P2WSH-P2SH multisig template. Expands to `sh(wsh(sortedmulti(threshold, key/48'/{0,1}'/0'/1'/{0,1}/*, ...)))`
+///
+/// Since there are hardened derivation steps, this template requires private derivable keys
+/// (generally `xprv`/`tprv`).
+///
+/// See [`Bip48ShWshPublic`] for a template that can work with `xpub`/`tpub`.
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk::bitcoin::Network;
+/// # use bdk::{Wallet, KeychainKind};
+/// # use bdk::wallet::AddressIndex::New;
+/// use bdk::template::Bip48ShWsh;
+///
+/// let key_a = bitcoin::bip32::ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m")?;
+/// let key_b = bitcoin::bip32::ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS")?;
+/// let mut wallet = Wallet::new_no_persist(
+///     Bip48ShWsh(2, vec![key_a, key_b], KeychainKind::External),
+///     Some(Bip48ShWsh(2, vec![key_a, key_b], KeychainKind::Internal)),
+///     Network::Testnet,
+/// )?;
+///
+/// assert_eq!(wallet.get_address(New).to_string(), "2N6FqZ2ETbPdwyNdNffAg7zDXUhearokZS8");
+/// assert_eq!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string(), "sh(wsh(sortedmulti(2,[c55b303f/48'/1'/0'/1']tpubDEU3eBekc59YtsJckhTkCZ3PXiaNNdZZcxtVDzBMh2ti43jMPFgczbFAtZXLjynJxA1K64HoxZSvi4T5CEHx8YQo7r9Y4CxfKXBSAkAkTXr/0/*,[b6dff990/48'/1'/0'/1']tpubDEYNGD172pDL1iGHVzRox52gs1eJZa9aWGCwXMhLhu97picjtNs7jq41d5xbXruAXRSkc93xn7x8jj8bZ9D8T1fv8zyKjoupGHm31xETEHf/0/*)))#emk8z4lz");
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct Bip48ShWsh<K: DerivableKey<Segwitv0>>(pub usize, pub Vec<K>, pub KeychainKind);
+
+impl<K: DerivableKey<Segwitv0>> DescriptorTemplate for Bip48ShWsh<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        let keys = segwit_v0::make_bip48_private(1, self.1, self.2, network)?;
+        descriptor!(sh(wsh(sortedmulti_vec(self.0, keys))))
+    }
+}
+
+/// BIP48 P2WSH-P2SH multisig public template. Expands to `sh(wsh(sortedmulti(threshold, key/{0,1}/*, ...)))`
+///
+/// This assumes that the keys used have already been derived with `m/48'/0'/0'/1'` for Mainnet
+/// or `m/48'/1'/0'/1'` for Testnet.
+///
+/// This template requires the parent fingerprint of each key to populate correctly the metadata
+/// of PSBTs.
+///
+/// See [`Bip48ShWsh`] for a template that does the full derivation, but requires private data
+/// for the keys.
+pub struct Bip48ShWshPublic<K: DerivableKey<Segwitv0>>(
+    pub usize,
+    pub Vec<(K, bip32::Fingerprint)>,
+    pub KeychainKind,
+);
+
+impl<K: DerivableKey<Segwitv0>> DescriptorTemplate for Bip48ShWshPublic<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        let keys = segwit_v0::make_bip48_public(1, self.1, self.2, network)?;
+        descriptor!(sh(wsh(sortedmulti_vec(self.0, keys))))
+    }
+}
+
+/// Taproot multisig template. Expands to a descriptor `tr(internal_key, multi_a(threshold, key, ...))`
+///
+/// The internal key is used for cooperative spends through the key path, for instance the
+/// aggregate key of all the signers with [MuSig2](crate::wallet::musig), while `threshold` of the
+/// keys can always spend through the script path.
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk::bitcoin::Network;
+/// # use bdk::Wallet;
+/// # use bdk::wallet::AddressIndex::New;
+/// use bdk::template::P2TRMultisig;
+///
+/// let internal_key = bitcoin::PublicKey::from_str(
+///     "02e96fe52ef0e22d2f131dd425ce1893073a3c6ad20e8cac36726393dfb4856a4c",
+/// )?;
+/// let keys = vec![
+///     bitcoin::PublicKey::from_str(
+///         "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd",
+///     )?,
+///     bitcoin::PublicKey::from_str(
+///         "032e58afe51f9ed8ad3cc7897f634d881fdbe49a81564629ded8156bebd2ffd1af",
+///     )?,
+/// ];
+/// let mut wallet =
+///     Wallet::new_no_persist(P2TRMultisig(internal_key, 1, keys), None, Network::Testnet)?;
+///
+/// assert_eq!(
+///     wallet.get_address(New).to_string(),
+///     "tb1p2rfma5sq6qp4eu7x4evnq7advgve6kjdacqu2jdpyvcw2t6pczxqg6hmre"
+/// );
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct P2TRMultisig<K: IntoDescriptorKey<Tap>>(pub K, pub usize, pub Vec<K>);
+
+impl<K: IntoDescriptorKey<Tap>> DescriptorTemplate for P2TRMultisig<K> {
+    fn build(self, _network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        descriptor!(tr(self.0, multi_a_vec(self.1, self.2)))
+    }
+}
+
+/// Taproot multisig template. Expands to `tr(internal_key, multi_a(threshold, key/87'/{0,1}'/0'/{0,1}/*, ...))`
+///
+/// The keys are derived following [BIP87]. Since there are hardened derivation steps, this
+/// template requires private derivable keys (generally `xprv`/`tprv`).
+///
+/// The internal key is used as is, and must not be controlled by a single signer, otherwise it
+/// could spend alone through the key path: use the [MuSig2](crate::wallet::musig) aggregate of
+/// all the keys for cooperative spends, or a NUMS point without a known private key to disable the
+/// key path. Since the internal key is not derived, each keychain can be given its own.
+///
+/// See [`TrMultisigPublic`] for a template that can work with `xpub`/`tpub`, and an example.
+///
+/// [BIP87]: https://github.com/bitcoin/bips/blob/master/bip-0087.mediawiki
+pub struct TrMultisig<I: IntoDescriptorKey<Tap>, K: DerivableKey<Tap>>(
+    pub I,
+    pub usize,
+    pub Vec<K>,
+    pub KeychainKind,
+);
+
+impl<I: IntoDescriptorKey<Tap>, K: DerivableKey<Tap>> DescriptorTemplate for TrMultisig<I, K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        let keys = self
+            .2
+            .into_iter()
+            .map(|key| segwit_v1::make_bipxx_private(87, key, self.3, network))
+            .collect::<Result<Vec<_>, _>>()?;
+        descriptor!(tr(self.0, multi_a_vec(self.1, keys)))
+    }
+}
+
+/// Taproot multisig public template. Expands to `tr(internal_key, multi_a(threshold, key/{0,1}/*, ...))`
+///
+/// This assumes that the keys used have already been derived with `m/87'/0'/0'` for Mainnet or
+/// `m/87'/1'/0'` for Testnet.
+///
+/// This template requires the parent fingerprint of each key to populate correctly the metadata
+/// of PSBTs. See [`TrMultisig`] for the requirements of the internal key.
+///
+/// See [`TrMultisig`] for a template that does the full derivation, but requires private data
+/// for the keys.
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk::bitcoin::Network;
+/// # use bdk::{Wallet, KeychainKind};
+/// # use bdk::wallet::AddressIndex::New;
+/// use bdk::template::TrMultisigPublic;
+///
+/// // the NUMS point of BIP341, nobody knows its private key so the key path can't be used
+/// let internal_key = bitcoin::key::XOnlyPublicKey::from_str("50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0")?;
+/// let keys = vec![
+///     (bitcoin::bip32::ExtendedPubKey::from_str("tpubDCrDhg1mchkTEpDNx8dw9icJA3SG51YW4LkCqLeKb4EhMPQwByQSpWJ6KD9wAjWqtnzDyPGjo9rE5Qnhhf1fNmAtVyjPsgyn9xryYdZPyoD")?, bitcoin::bip32::Fingerprint::from_str("c55b303f")?),
+///     (bitcoin::bip32::ExtendedPubKey::from_str("tpubDCYPm5BVAwpgRqW7mLMgWKtxQ8mEWyrHDRfsGo3GNL633A7UaYZ1pTjk8aYBAMguGB5LvpEftGxEK3TafLmS6NeqNj9TMmszXNosdN3CV7K")?, bitcoin::bip32::Fingerprint::from_str("b6dff990")?),
+/// ];
+/// let mut wallet = Wallet::new_no_persist(
+///     TrMultisigPublic(internal_key, 2, keys.clone(), KeychainKind::External),
+///     Some(TrMultisigPublic(internal_key, 2, keys, KeychainKind::Internal)),
+///     Network::Testnet,
+/// )?;
+///
+/// assert_eq!(wallet.get_address(New).to_string(), "tb1p7mdutac8ghfgz0ue2rq4mf7t802z96cu00u58lpgv27c0nsad4hsvhdgxv");
+/// assert_eq!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string(), "tr(50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0,multi_a(2,[c55b303f/87'/1'/0']tpubDCrDhg1mchkTEpDNx8dw9icJA3SG51YW4LkCqLeKb4EhMPQwByQSpWJ6KD9wAjWqtnzDyPGjo9rE5Qnhhf1fNmAtVyjPsgyn9xryYdZPyoD/0/*,[b6dff990/87'/1'/0']tpubDCYPm5BVAwpgRqW7mLMgWKtxQ8mEWyrHDRfsGo3GNL633A7UaYZ1pTjk8aYBAMguGB5LvpEftGxEK3TafLmS6NeqNj9TMmszXNosdN3CV7K/0/*))#jjwg55v9");
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct TrMultisigPublic<I: IntoDescriptorKey<Tap>, K: DerivableKey<Tap>>(
+    pub I,
+    pub usize,
+    pub Vec<(K, bip32::Fingerprint)>,
+    pub KeychainKind,
+);
+
+impl<I: IntoDescriptorKey<Tap>, K: DerivableKey<Tap>> DescriptorTemplate
+    for TrMultisigPublic<I, K>
+{
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        let keys = self
+            .2
+            .into_iter()
+            .map(|(key, fingerprint)| {
+                segwit_v1::make_bipxx_public(87, key, fingerprint, self.3, network)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        descriptor!(tr(self.0, multi_a_vec(self.1, keys)))
+    }
+}
+
+/// Decaying P2WSH template. Expands to a descriptor `wsh(or_d(pk(key_a),and_v(v:pk(key_b),older(blocks))))`
+///
+/// The first key can always spend, while the second one can only spend outputs that have been
+/// confirmed for at least `blocks` blocks, for instance for inheritance or as a backup.
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk::bitcoin::Network;
+/// # use bdk::Wallet;
+/// # use bdk::wallet::AddressIndex::New;
+/// use bdk::template::P2WshDecaying;
+///
+/// let key_a = bitcoin::PublicKey::from_str(
+///     "02e96fe52ef0e22d2f131dd425ce1893073a3c6ad20e8cac36726393dfb4856a4c",
+/// )?;
+/// let key_b = bitcoin::PublicKey::from_str(
+///     "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd",
+/// )?;
+/// // about a year
+/// let mut wallet =
+///     Wallet::new_no_persist(P2WshDecaying(key_a, key_b, 52_560), None, Network::Testnet)?;
+///
+/// assert_eq!(
+///     wallet.get_address(New).to_string(),
+///     "tb1qn0ptzncgv4tqfpeqqugu7x4mnxdjfs425qaw7akg6ct44qtv6jusvfjycx"
+/// );
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct P2WshDecaying<K: IntoDescriptorKey<Segwitv0>>(pub K, pub K, pub u16);
+
+impl<K: IntoDescriptorKey<Segwitv0>> DescriptorTemplate for P2WshDecaying<K> {
+    fn build(self, _network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        let blocks = u32::from(self.2);
+        descriptor!(wsh(or_d(pk(self.0), and_v(v: pk(self.1), older(blocks)))))
+    }
+}
+
+/// Decaying taproot template. Expands to a descriptor `tr(key_a,and_v(v:pk(key_b),older(blocks)))`
+///
+/// The first key can always spend through the key path, while the second one can only spend
+/// outputs that have been confirmed for at least `blocks` blocks through the script path.
+///
+/// See [`P2WshDecaying`] for an example.
+pub struct P2TRDecaying<K: IntoDescriptorKey<Tap>>(pub K, pub K, pub u16);
+
+impl<K: IntoDescriptorKey<Tap>> DescriptorTemplate for P2TRDecaying<K> {
+    fn build(self, _network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        let blocks = u32::from(self.2);
+        descriptor!(tr(self.0, and_v(v: pk(self.1), older(blocks))))
+    }
+}
+
+/// Decaying P2WSH template. Expands to `wsh(or_d(pk(key_a/87'/{0,1}'/0'/{0,1}/*),and_v(v:pk(key_b/87'/{0,1}'/0'/{0,1}/*),older(blocks))))`
+///
+/// The keys are derived following [BIP87], which defines the account path of the keys of
+/// wallets shared between several signers independently of their script: each key of a decaying
+/// descriptor is held by a different party, like the cosigners of a multisig.
+///
+/// Since there are hardened derivation steps, this template requires private derivable keys
+/// (generally `xprv`/`tprv`).
+///
+/// See [`WshDecayingPublic`] for a template that can work with `xpub`/`tpub`.
+///
+/// [BIP87]: https://github.com/bitcoin/bips/blob/master/bip-0087.mediawiki
+pub struct WshDecaying<K: DerivableKey<Segwitv0>>(pub K, pub K, pub u16, pub KeychainKind);
+
+impl<K: DerivableKey<Segwitv0>> DescriptorTemplate for WshDecaying<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        P2WshDecaying(
+            segwit_v0::make_bipxx_private(87, self.0, self.3, network)?,
+            segwit_v0::make_bipxx_private(87, self.1, self.3, network)?,
+            self.2,
+        )
+        .build(network)
+    }
+}
+
+/// Decaying P2WSH public template. Expands to `wsh(or_d(pk(key_a/{0,1}/*),and_v(v:pk(key_b/{0,1}/*),older(blocks))))`
+///
+/// This assumes that the keys used have already been derived with `m/87'/0'/0'` for Mainnet or
+/// `m/87'/1'/0'` for Testnet.
+///
+/// This template requires the parent fingerprint of each key to populate correctly the metadata
+/// of PSBTs.
+///
+/// See [`WshDecaying`] for a template that does the full derivation, but requires private data
+/// for the keys.
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk::bitcoin::Network;
+/// # use bdk::{Wallet, KeychainKind};
+/// # use bdk::wallet::AddressIndex::New;
+/// use bdk::template::WshDecayingPublic;
+///
+/// let key_a = (
+///     bitcoin::bip32::ExtendedPubKey::from_str("tpubDCrDhg1mchkTEpDNx8dw9icJA3SG51YW4LkCqLeKb4EhMPQwByQSpWJ6KD9wAjWqtnzDyPGjo9rE5Qnhhf1fNmAtVyjPsgyn9xryYdZPyoD")?,
+///     bitcoin::bip32::Fingerprint::from_str("c55b303f")?,
+/// );
+/// let key_b = (
+///     bitcoin::bip32::ExtendedPubKey::from_str("tpubDCYPm5BVAwpgRqW7mLMgWKtxQ8mEWyrHDRfsGo3GNL633A7UaYZ1pTjk8aYBAMguGB5LvpEftGxEK3TafLmS6NeqNj9TMmszXNosdN3CV7K")?,
+///     bitcoin::bip32::Fingerprint::from_str("b6dff990")?,
+/// );
+/// let mut wallet = Wallet::new_no_persist(
+///     WshDecayingPublic(key_a, key_b, 52_560, KeychainKind::External),
+///     Some(WshDecayingPublic(key_a, key_b, 52_560, KeychainKind::Internal)),
+///     Network::Testnet,
+/// )?;
+///
+/// assert_eq!(wallet.get_address(New).to_string(), "tb1qu6x90unrn5sgzrx5taetlxkwnl043x2p0snnkx6lh38txt6ehyks32rr3d");
+/// assert_eq!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string(), "wsh(or_d(pk([c55b303f/87'/1'/0']tpubDCrDhg1mchkTEpDNx8dw9icJA3SG51YW4LkCqLeKb4EhMPQwByQSpWJ6KD9wAjWqtnzDyPGjo9rE5Qnhhf1fNmAtVyjPsgyn9xryYdZPyoD/0/*),and_v(v:pk([b6dff990/87'/1'/0']tpubDCYPm5BVAwpgRqW7mLMgWKtxQ8mEWyrHDRfsGo3GNL633A7UaYZ1pTjk8aYBAMguGB5LvpEftGxEK3TafLmS6NeqNj9TMmszXNosdN3CV7K/0/*),older(52560))))#tuf353gc");
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct WshDecayingPublic<K: DerivableKey<Segwitv0>>(
+    pub (K, bip32::Fingerprint),
+    pub (K, bip32::Fingerprint),
+    pub u16,
+    pub KeychainKind,
+);
+
+impl<K: DerivableKey<Segwitv0>> DescriptorTemplate for WshDecayingPublic<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        let ((key_a, fingerprint_a), (key_b, fingerprint_b)) = (self.0, self.1);
+        P2WshDecaying(
+            segwit_v0::make_bipxx_public(87, key_a, fingerprint_a, self.3, network)?,
+            segwit_v0::make_bipxx_public(87, key_b, fingerprint_b, self.3, network)?,
+            self.2,
+        )
+        .build(network)
+    }
+}
+
+/// Decaying taproot template. Expands to `tr(key_a/87'/{0,1}'/0'/{0,1}/*,and_v(v:pk(key_b/87'/{0,1}'/0'/{0,1}/*),older(blocks)))`
+///
+/// The keys are derived following [BIP87], like for [`WshDecaying`]. Since there are hardened
+/// derivation steps, this template requires private derivable keys (generally `xprv`/`tprv`).
+///
+/// See [`TrDecayingPublic`] for a template that can work with `xpub`/`tpub`.
+///
+/// [BIP87]: https://github.com/bitcoin/bips/blob/master/bip-0087.mediawiki
+pub struct TrDecaying<K: DerivableKey<Tap>>(pub K, pub K, pub u16, pub KeychainKind);
+
+impl<K: DerivableKey<Tap>> DescriptorTemplate for TrDecaying<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        P2TRDecaying(
+            segwit_v1::make_bipxx_private(87, self.0, self.3, network)?,
+            segwit_v1::make_bipxx_private(87, self.1, self.3, network)?,
+            self.2,
+        )
+        .build(network)
+    }
+}
+
+/// Decaying taproot public template. Expands to `tr(key_a/{0,1}/*,and_v(v:pk(key_b/{0,1}/*),older(blocks)))`
+///
+/// This assumes that the keys used have already been derived with `m/87'/0'/0'` for Mainnet or
+/// `m/87'/1'/0'` for Testnet.
+///
+/// This template requires the parent fingerprint of each key to populate correctly the metadata
+/// of PSBTs.
+///
+/// See [`TrDecaying`] for a template that does the full derivation, but requires private data
+/// for the keys, and [`WshDecayingPublic`] for an example.
+pub struct TrDecayingPublic<K: DerivableKey<Tap>>(
+    pub (K, bip32::Fingerprint),
+    pub (K, bip32::Fingerprint),
+    pub u16,
+    pub KeychainKind,
+);
+
+impl<K: DerivableKey<Tap>> DescriptorTemplate for TrDecayingPublic<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        let ((key_a, fingerprint_a), (key_b, fingerprint_b)) = (self.0, self.1);
+        P2TRDecaying(
+            segwit_v1::make_bipxx_public(87, key_a, fingerprint_a, self.3, network)?,
+            segwit_v1::make_bipxx_public(87, key_b, fingerprint_b, self.3, network)?,
+            self.2,
+        )
+        .build(network)
+    }
+}
+
+/// Returns the BIP44 `change` derivation step for `keychain`.
+///
+/// Custom keychains have no standard derivation path, so they cannot be used with templates.
+fn chain_index(keychain: KeychainKind) -> Result<bip32::ChildNumber, DescriptorError> {
+    match keychain {
+        KeychainKind::External => Ok(bip32::ChildNumber::from_normal_idx(0)?),
+        KeychainKind::Internal => Ok(bip32::ChildNumber::from_normal_idx(1)?),
+        KeychainKind::Custom(_) => Err(DescriptorError::Key(crate::keys::KeyError::Message(
+            "templates only support the external and internal keychains".into(),
+        ))),
+    }
+}
+
 macro_rules! expand_make_bipxx {
     ( $mod_name:ident, $ctx:ty ) => {
         mod $mod_name {
@@ -491,31 +971,7 @@ macro_rules! expand_make_bipxx {
                 keychain: KeychainKind,
                 network: Network,
             ) -> Result<impl IntoDescriptorKey<$ctx>, DescriptorError> {
-                let mut derivation_path = alloc::vec::Vec::with_capacity(4);
-                derivation_path.push(bip32::ChildNumber::from_hardened_idx(bip)?);
-
-                match network {
-                    Network::Bitcoin => {
-                        derivation_path.push(bip32::ChildNumber::from_hardened_idx(0)?);
-                    }
-                    _ => {
-                        derivation_path.push(bip32::ChildNumber::from_hardened_idx(1)?);
-                    }
-                }
-                derivation_path.push(bip32::ChildNumber::from_hardened_idx(0)?);
-
-                match keychain {
-                    KeychainKind::External => {
-                        derivation_path.push(bip32::ChildNumber::from_normal_idx(0)?)
-                    }
-                    KeychainKind::Internal => {
-                        derivation_path.push(bip32::ChildNumber::from_normal_idx(1)?)
-                    }
-                };
-
-                let derivation_path: bip32::DerivationPath = derivation_path.into();
-
-                Ok((key, derivation_path))
+                make_private(key, account_path(bip, None, network)?, keychain)
             }
             pub(super) fn make_bipxx_public<K: DerivableKey<$ctx>>(
                 bip: u32,
@@ -524,19 +980,56 @@ macro_rules! expand_make_bipxx {
                 keychain: KeychainKind,
                 network: Network,
             ) -> Result<impl IntoDescriptorKey<$ctx>, DescriptorError> {
-                let derivation_path: bip32::DerivationPath = match keychain {
-                    KeychainKind::External => vec![bip32::ChildNumber::from_normal_idx(0)?].into(),
-                    KeychainKind::Internal => vec![bip32::ChildNumber::from_normal_idx(1)?].into(),
-                };
-
-                let source_path = bip32::DerivationPath::from(vec![
-                    bip32::ChildNumber::from_hardened_idx(bip)?,
-                    match network {
-                        Network::Bitcoin => bip32::ChildNumber::from_hardened_idx(0)?,
-                        _ => bip32::ChildNumber::from_hardened_idx(1)?,
-                    },
-                    bip32::ChildNumber::from_hardened_idx(0)?,
-                ]);
+                make_public(
+                    key,
+                    parent_fingerprint,
+                    account_path(bip, None, network)?,
+                    keychain,
+                )
+            }
+            #[allow(dead_code)]
+            pub(super) fn make_bip48_private<K: DerivableKey<$ctx>>(
+                script_type: u32,
+                keys: Vec<K>,
+                keychain: KeychainKind,
+                network: Network,
+            ) -> Result<Vec<impl IntoDescriptorKey<$ctx>>, DescriptorError> {
+                let source_path = account_path(48, Some(script_type), network)?;
+                keys.into_iter()
+                    .map(|key| make_private(key, source_path.clone(), keychain))
+                    .collect()
+            }
+            #[allow(dead_code)]
+            pub(super) fn make_bip48_public<K: DerivableKey<$ctx>>(
+                script_type: u32,
+                keys: Vec<(K, bip32::Fingerprint)>,
+                keychain: KeychainKind,
+                network: Network,
+            ) -> Result<Vec<impl IntoDescriptorKey<$ctx>>, DescriptorError> {
+                let source_path = account_path(48, Some(script_type), network)?;
+                keys.into_iter()
+                    .map(|(key, parent_fingerprint)| {
+                        make_public(key, parent_fingerprint, source_path.clone(), keychain)
+                    })
+                    .collect()
+            }
+
+            fn make_private<K: DerivableKey<$ctx>>(
+                key: K,
+                source_path: bip32::DerivationPath,
+                keychain: KeychainKind,
+            ) -> Result<impl IntoDescriptorKey<$ctx>, DescriptorError> {
+                let derivation_path = source_path.child(chain_index(keychain)?);
+
+                Ok((key, derivation_path))
+            }
+            fn make_public<K: DerivableKey<$ctx>>(
+                key: K,
+                parent_fingerprint: bip32::Fingerprint,
+                source_path: bip32::DerivationPath,
+                keychain: KeychainKind,
+            ) -> Result<impl IntoDescriptorKey<$ctx>, DescriptorError> {
+                let derivation_path: bip32::DerivationPath = vec![chain_index(keychain)?].into();
 
                 Ok((key, (parent_fingerprint, source_path), derivation_path))
             }
@@ -544,6 +1037,27 @@ macro_rules! expand_make_bipxx {
     };
 }
 
+/// Returns the account-level derivation path `m/bip'/{0,1}'/0'`, followed by the `script_type`
+/// step if there's one, as in BIP48.
+fn account_path(
+    bip: u32,
+    script_type: Option<u32>,
+    network: Network,
+) -> Result<bip32::DerivationPath, DescriptorError> {
+    let mut path = Vec::with_capacity(4);
+    path.push(bip32::ChildNumber::from_hardened_idx(bip)?);
+    match network {
+        Network::Bitcoin => path.push(bip32::ChildNumber::from_hardened_idx(0)?),
+        _ => path.push(bip32::ChildNumber::from_hardened_idx(1)?),
+    }
+    path.push(bip32::ChildNumber::from_hardened_idx(0)?);
+    if let Some(script_type) = script_type {
+        path.push(bip32::ChildNumber::from_hardened_idx(script_type)?);
+    }
+
+    Ok(path.into())
+}
+
 expand_make_bipxx!(legacy, Legacy);
 expand_make_bipxx!(segwit_v0, Segwitv0);
 expand_make_bipxx!(segwit_v1, Tap);
@@ -982,4 +1496,461 @@ mod test {
             ],
         );
     }
+
+    // P2TR multisig
+    #[test]
+    fn test_p2tr_multisig_template() {
+        let key_a = bitcoin::PublicKey::from_str(
+            "02e96fe52ef0e22d2f131dd425ce1893073a3c6ad20e8cac36726393dfb4856a4c",
+        )
+        .unwrap();
+        let key_b = bitcoin::PublicKey::from_str(
+            "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd",
+        )
+        .unwrap();
+        let key_c = bitcoin::PublicKey::from_str(
+            "032e58afe51f9ed8ad3cc7897f634d881fdbe49a81564629ded8156bebd2ffd1af",
+        )
+        .unwrap();
+        check(
+            P2TRMultisig(key_a, 1, vec![key_b, key_c]).build(Network::Testnet),
+            false,
+            true,
+            true,
+            Network::Testnet,
+            &["tb1p2rfma5sq6qp4eu7x4evnq7advgve6kjdacqu2jdpyvcw2t6pczxqg6hmre"],
+        );
+        assert_matches!(
+            P2TRMultisig(key_a, 3, vec![key_b, key_c]).build(Network::Testnet),
+            Err(DescriptorError::Miniscript(_))
+        );
+    }
+
+    // P2WSH decaying
+    #[test]
+    fn test_p2wsh_decaying_template() {
+        let key_a = bitcoin::PublicKey::from_str(
+            "02e96fe52ef0e22d2f131dd425ce1893073a3c6ad20e8cac36726393dfb4856a4c",
+        )
+        .unwrap();
+        let key_b = bitcoin::PublicKey::from_str(
+            "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd",
+        )
+        .unwrap();
+        check(
+            P2WshDecaying(key_a, key_b, 52_560).build(Network::Testnet),
+            true,
+            false,
+            true,
+            Network::Testnet,
+            &["tb1qn0ptzncgv4tqfpeqqugu7x4mnxdjfs425qaw7akg6ct44qtv6jusvfjycx"],
+        );
+    }
+
+    // P2TR decaying
+    #[test]
+    fn test_p2tr_decaying_template() {
+        let key_a = bitcoin::PublicKey::from_str(
+            "02e96fe52ef0e22d2f131dd425ce1893073a3c6ad20e8cac36726393dfb4856a4c",
+        )
+        .unwrap();
+        let key_b = bitcoin::PublicKey::from_str(
+            "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd",
+        )
+        .unwrap();
+        check(
+            P2TRDecaying(key_a, key_b, 52_560).build(Network::Testnet),
+            false,
+            true,
+            true,
+            Network::Testnet,
+            &["tb1p3lkvexlw7vdyfy2cxnzt7e44f90s4xc6ztz5nghf0as4m8y0wjqqed7yr3"],
+        );
+    }
+
+    // BIP48 P2WSH
+    #[test]
+    fn test_bip48_wsh_template() {
+        let key_a = bitcoin::bip32::ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m").unwrap();
+        let key_b = bitcoin::bip32::ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS").unwrap();
+        check(
+            Bip48Wsh(2, vec![key_a, key_b], KeychainKind::External).build(Network::Testnet),
+            true,
+            false,
+            false,
+            Network::Testnet,
+            &[
+                "tb1qnvd46ny8qwv94w5mgthn0ucd4ylvchu58ru24c43xas4uxvz44pskd0p9r",
+                "tb1qa6awmpvw5prjxnfktac79pyq8pn3hy3dk8etvpyjv5vwn72vel5ss27zwp",
+                "tb1q597lc5wnhhsl3smtsp4h4excr2rfmke43mxrsu6p9claxqpaltmsqfdh80",
+            ],
+        );
+        check(
+            Bip48Wsh(2, vec![key_a, key_b], KeychainKind::Internal).build(Network::Testnet),
+            true,
+            false,
+            false,
+            Network::Testnet,
+            &[
+                "tb1qr3g5g8eflsuarszaczpx2waht7y5etkz5n4cnj2dkk4vewz4j2kqe86n7m",
+                "tb1qsd2avuf43xn724j7wgup33045qgnyfjxev84aty9ct8lekvfuvhq4eyhar",
+                "tb1qa3cjs5mqz8223c7p63qk9kwlctafzgz8jhmyemwlzpmppugn0hws5e8a5h",
+            ],
+        );
+    }
+
+    // BIP48 P2WSH public
+    #[test]
+    fn test_bip48_wsh_public_template() {
+        let fingerprint_a = bitcoin::bip32::Fingerprint::from_str("c55b303f").unwrap();
+        let fingerprint_b = bitcoin::bip32::Fingerprint::from_str("b6dff990").unwrap();
+        let key_a = bitcoin::bip32::ExtendedPubKey::from_str("tpubDEU3eBekc59Yw68Nb3dmoXcinikF3qhGW9ymz39kMuBFSHFqX8MFuyt4mC3y9EiCWDmVw1rmQ3s7GjERKkARjGFwA2dAWRLpeMCU5oUuMXU").unwrap();
+        let key_b = bitcoin::bip32::ExtendedPubKey::from_str("tpubDEYNGD172pDL2SiB6bfaZ1DJKby3EW7du9Wuuxs6HgvsaE1p8sWobVY528eycGSGPDjoayyevzaoEfYz7d6YxiDotkCJhxnSC6fopgD2sGC").unwrap();
+        check(
+            Bip48WshPublic(
+                2,
+                vec![(key_a, fingerprint_a), (key_b, fingerprint_b)],
+                KeychainKind::External,
+            )
+            .build(Network::Testnet),
+            true,
+            false,
+            false,
+            Network::Testnet,
+            &[
+                "tb1qnvd46ny8qwv94w5mgthn0ucd4ylvchu58ru24c43xas4uxvz44pskd0p9r",
+                "tb1qa6awmpvw5prjxnfktac79pyq8pn3hy3dk8etvpyjv5vwn72vel5ss27zwp",
+                "tb1q597lc5wnhhsl3smtsp4h4excr2rfmke43mxrsu6p9claxqpaltmsqfdh80",
+            ],
+        );
+        check(
+            Bip48WshPublic(
+                2,
+                vec![(key_a, fingerprint_a), (key_b, fingerprint_b)],
+                KeychainKind::Internal,
+            )
+            .build(Network::Testnet),
+            true,
+            false,
+            false,
+            Network::Testnet,
+            &[
+                "tb1qr3g5g8eflsuarszaczpx2waht7y5etkz5n4cnj2dkk4vewz4j2kqe86n7m",
+                "tb1qsd2avuf43xn724j7wgup33045qgnyfjxev84aty9ct8lekvfuvhq4eyhar",
+                "tb1qa3cjs5mqz8223c7p63qk9kwlctafzgz8jhmyemwlzpmppugn0hws5e8a5h",
+            ],
+        );
+    }
+
+    // BIP48 P2WSH-P2SH
+    #[test]
+    fn test_bip48_sh_wsh_template() {
+        let key_a = bitcoin::bip32::ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m").unwrap();
+        let key_b = bitcoin::bip32::ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS").unwrap();
+        check(
+            Bip48ShWsh(2, vec![key_a, key_b], KeychainKind::External).build(Network::Testnet),
+            true,
+            false,
+            false,
+            Network::Testnet,
+            &[
+                "2N6FqZ2ETbPdwyNdNffAg7zDXUhearokZS8",
+                "2N9Jn7yKEwoYdUKWC9UW9mauXvyfMuCaWGE",
+                "2NCnrhKPUSeukAoMYtXAfFAGxc57aGwEZQ9",
+            ],
+        );
+        check(
+            Bip48ShWsh(2, vec![key_a, key_b], KeychainKind::Internal).build(Network::Testnet),
+            true,
+            false,
+            false,
+            Network::Testnet,
+            &[
+                "2Mwht44kL2ru1kUhYVx5YPbgZvHz48Gjww3",
+                "2MuxmWpV48b9N6z5YLNCEBkLmoHWYa1pPcU",
+                "2N3SVK89GhcnxqxPyoGKkLGvsf8fmeWozkA",
+            ],
+        );
+    }
+
+    // BIP48 P2WSH-P2SH public
+    #[test]
+    fn test_bip48_sh_wsh_public_template() {
+        let fingerprint_a = bitcoin::bip32::Fingerprint::from_str("c55b303f").unwrap();
+        let fingerprint_b = bitcoin::bip32::Fingerprint::from_str("b6dff990").unwrap();
+        let key_a = bitcoin::bip32::ExtendedPubKey::from_str("tpubDEU3eBekc59YtsJckhTkCZ3PXiaNNdZZcxtVDzBMh2ti43jMPFgczbFAtZXLjynJxA1K64HoxZSvi4T5CEHx8YQo7r9Y4CxfKXBSAkAkTXr").unwrap();
+        let key_b = bitcoin::bip32::ExtendedPubKey::from_str("tpubDEYNGD172pDL1iGHVzRox52gs1eJZa9aWGCwXMhLhu97picjtNs7jq41d5xbXruAXRSkc93xn7x8jj8bZ9D8T1fv8zyKjoupGHm31xETEHf").unwrap();
+        check(
+            Bip48ShWshPublic(
+                2,
+                vec![(key_a, fingerprint_a), (key_b, fingerprint_b)],
+                KeychainKind::External,
+            )
+            .build(Network::Testnet),
+            true,
+            false,
+            false,
+            Network::Testnet,
+            &[
+                "2N6FqZ2ETbPdwyNdNffAg7zDXUhearokZS8",
+                "2N9Jn7yKEwoYdUKWC9UW9mauXvyfMuCaWGE",
+                "2NCnrhKPUSeukAoMYtXAfFAGxc57aGwEZQ9",
+            ],
+        );
+        check(
+            Bip48ShWshPublic(
+                2,
+                vec![(key_a, fingerprint_a), (key_b, fingerprint_b)],
+                KeychainKind::Internal,
+            )
+            .build(Network::Testnet),
+            true,
+            false,
+            false,
+            Network::Testnet,
+            &[
+                "2Mwht44kL2ru1kUhYVx5YPbgZvHz48Gjww3",
+                "2MuxmWpV48b9N6z5YLNCEBkLmoHWYa1pPcU",
+                "2N3SVK89GhcnxqxPyoGKkLGvsf8fmeWozkA",
+            ],
+        );
+    }
+
+    // The NUMS point of BIP341, used as the internal key of the taproot multisig templates
+    fn nums_key() -> bitcoin::key::XOnlyPublicKey {
+        bitcoin::key::XOnlyPublicKey::from_str(
+            "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0",
+        )
+        .unwrap()
+    }
+
+    // Taproot multisig
+    #[test]
+    fn test_tr_multisig_template() {
+        let key_a = bitcoin::bip32::ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m").unwrap();
+        let key_b = bitcoin::bip32::ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS").unwrap();
+        check(
+            TrMultisig(nums_key(), 2, vec![key_a, key_b], KeychainKind::External)
+                .build(Network::Testnet),
+            false,
+            true,
+            false,
+            Network::Testnet,
+            &[
+                "tb1p7mdutac8ghfgz0ue2rq4mf7t802z96cu00u58lpgv27c0nsad4hsvhdgxv",
+                "tb1phzywgk2nyjkwv3pace7um86zm9d2f25w2h5s4nh3sz4h7hg2vnnqj4j7q9",
+                "tb1peglp7y540q6kcvkdeqp8qhg7wvnm5g9n3lnhc2ugrfnu66xq0w9qgg7d63",
+            ],
+        );
+        check(
+            TrMultisig(nums_key(), 2, vec![key_a, key_b], KeychainKind::Internal)
+                .build(Network::Testnet),
+            false,
+            true,
+            false,
+            Network::Testnet,
+            &[
+                "tb1p22ffw5uhqe9n4xmp47yhd2fgkasjdwlk8wxx8q0754n86c2as20qmg9mwg",
+                "tb1pnafmu9g5w4ha79r585rc2utgnhw8k3r8npkd2tsvan36lqcf5f7sl99yxw",
+                "tb1pjrp55zgshxtzakw6yqufju2s3aa97w0l9cuful3gvyyv7mtlfkcqnucexu",
+            ],
+        );
+    }
+
+    // Taproot multisig public
+    #[test]
+    fn test_tr_multisig_public_template() {
+        let fingerprint_a = bitcoin::bip32::Fingerprint::from_str("c55b303f").unwrap();
+        let fingerprint_b = bitcoin::bip32::Fingerprint::from_str("b6dff990").unwrap();
+        let key_a = bitcoin::bip32::ExtendedPubKey::from_str("tpubDCrDhg1mchkTEpDNx8dw9icJA3SG51YW4LkCqLeKb4EhMPQwByQSpWJ6KD9wAjWqtnzDyPGjo9rE5Qnhhf1fNmAtVyjPsgyn9xryYdZPyoD").unwrap();
+        let key_b = bitcoin::bip32::ExtendedPubKey::from_str("tpubDCYPm5BVAwpgRqW7mLMgWKtxQ8mEWyrHDRfsGo3GNL633A7UaYZ1pTjk8aYBAMguGB5LvpEftGxEK3TafLmS6NeqNj9TMmszXNosdN3CV7K").unwrap();
+        check(
+            TrMultisigPublic(
+                nums_key(),
+                2,
+                vec![(key_a, fingerprint_a), (key_b, fingerprint_b)],
+                KeychainKind::External,
+            )
+            .build(Network::Testnet),
+            false,
+            true,
+            false,
+            Network::Testnet,
+            &[
+                "tb1p7mdutac8ghfgz0ue2rq4mf7t802z96cu00u58lpgv27c0nsad4hsvhdgxv",
+                "tb1phzywgk2nyjkwv3pace7um86zm9d2f25w2h5s4nh3sz4h7hg2vnnqj4j7q9",
+                "tb1peglp7y540q6kcvkdeqp8qhg7wvnm5g9n3lnhc2ugrfnu66xq0w9qgg7d63",
+            ],
+        );
+        check(
+            TrMultisigPublic(
+                nums_key(),
+                2,
+                vec![(key_a, fingerprint_a), (key_b, fingerprint_b)],
+                KeychainKind::Internal,
+            )
+            .build(Network::Testnet),
+            false,
+            true,
+            false,
+            Network::Testnet,
+            &[
+                "tb1p22ffw5uhqe9n4xmp47yhd2fgkasjdwlk8wxx8q0754n86c2as20qmg9mwg",
+                "tb1pnafmu9g5w4ha79r585rc2utgnhw8k3r8npkd2tsvan36lqcf5f7sl99yxw",
+                "tb1pjrp55zgshxtzakw6yqufju2s3aa97w0l9cuful3gvyyv7mtlfkcqnucexu",
+            ],
+        );
+    }
+
+    // P2WSH decaying
+    #[test]
+    fn test_wsh_decaying_template() {
+        let key_a = bitcoin::bip32::ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m").unwrap();
+        let key_b = bitcoin::bip32::ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS").unwrap();
+        check(
+            WshDecaying(key_a, key_b, 52_560, KeychainKind::External).build(Network::Testnet),
+            true,
+            false,
+            false,
+            Network::Testnet,
+            &[
+                "tb1qu6x90unrn5sgzrx5taetlxkwnl043x2p0snnkx6lh38txt6ehyks32rr3d",
+                "tb1qwdleuz48pc2plsx9a9e3g047seeq8q0eqwnxvaqxqv8e5x6nwtyq7nzft8",
+                "tb1qlmd360nwfwa7l5xjzdmhqrlaxgs70cq3pg7pej77xam7nyk5m7wqawx767",
+            ],
+        );
+        check(
+            WshDecaying(key_a, key_b, 52_560, KeychainKind::Internal).build(Network::Testnet),
+            true,
+            false,
+            false,
+            Network::Testnet,
+            &[
+                "tb1q0nj276va2qd50y6z60rrfyupsqrnhz26a3ukwcqjkaz0det6t4pqyq7thl",
+                "tb1q3wyeccr77ns4er4fkdyuqj8uxcaxluuunu4ntzy406023hyyc5rqpt0vzh",
+                "tb1qg3kd7lrm7yggu77ctug9unfdv5u3krpqst2sdy9tm0g9nyjlklyqcpj7dk",
+            ],
+        );
+    }
+
+    // P2WSH decaying public
+    #[test]
+    fn test_wsh_decaying_public_template() {
+        let fingerprint_a = bitcoin::bip32::Fingerprint::from_str("c55b303f").unwrap();
+        let fingerprint_b = bitcoin::bip32::Fingerprint::from_str("b6dff990").unwrap();
+        let key_a = bitcoin::bip32::ExtendedPubKey::from_str("tpubDCrDhg1mchkTEpDNx8dw9icJA3SG51YW4LkCqLeKb4EhMPQwByQSpWJ6KD9wAjWqtnzDyPGjo9rE5Qnhhf1fNmAtVyjPsgyn9xryYdZPyoD").unwrap();
+        let key_b = bitcoin::bip32::ExtendedPubKey::from_str("tpubDCYPm5BVAwpgRqW7mLMgWKtxQ8mEWyrHDRfsGo3GNL633A7UaYZ1pTjk8aYBAMguGB5LvpEftGxEK3TafLmS6NeqNj9TMmszXNosdN3CV7K").unwrap();
+        check(
+            WshDecayingPublic(
+                (key_a, fingerprint_a),
+                (key_b, fingerprint_b),
+                52_560,
+                KeychainKind::External,
+            )
+            .build(Network::Testnet),
+            true,
+            false,
+            false,
+            Network::Testnet,
+            &[
+                "tb1qu6x90unrn5sgzrx5taetlxkwnl043x2p0snnkx6lh38txt6ehyks32rr3d",
+                "tb1qwdleuz48pc2plsx9a9e3g047seeq8q0eqwnxvaqxqv8e5x6nwtyq7nzft8",
+                "tb1qlmd360nwfwa7l5xjzdmhqrlaxgs70cq3pg7pej77xam7nyk5m7wqawx767",
+            ],
+        );
+        check(
+            WshDecayingPublic(
+                (key_a, fingerprint_a),
+                (key_b, fingerprint_b),
+                52_560,
+                KeychainKind::Internal,
+            )
+            .build(Network::Testnet),
+            true,
+            false,
+            false,
+            Network::Testnet,
+            &[
+                "tb1q0nj276va2qd50y6z60rrfyupsqrnhz26a3ukwcqjkaz0det6t4pqyq7thl",
+                "tb1q3wyeccr77ns4er4fkdyuqj8uxcaxluuunu4ntzy406023hyyc5rqpt0vzh",
+                "tb1qg3kd7lrm7yggu77ctug9unfdv5u3krpqst2sdy9tm0g9nyjlklyqcpj7dk",
+            ],
+        );
+    }
+
+    // Taproot decaying
+    #[test]
+    fn test_tr_decaying_template() {
+        let key_a = bitcoin::bip32::ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m").unwrap();
+        let key_b = bitcoin::bip32::ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS").unwrap();
+        check(
+            TrDecaying(key_a, key_b, 52_560, KeychainKind::External).build(Network::Testnet),
+            false,
+            true,
+            false,
+            Network::Testnet,
+            &[
+                "tb1pqjdrvk363ujuxup30jlv34h8e885pc44lyvdakwpunfefvec7aksht98su",
+                "tb1pxtju6233z3lk06dqts3k97gvkgs6r884u9ar0nww7ce6puwzl9lsecgf9a",
+                "tb1pkxa7wjdjhj9f0z7djvd73ykmyy6mwpdmmlc7q2thgw8v29cc2d7qp2vj28",
+            ],
+        );
+        check(
+            TrDecaying(key_a, key_b, 52_560, KeychainKind::Internal).build(Network::Testnet),
+            false,
+            true,
+            false,
+            Network::Testnet,
+            &[
+                "tb1ppgjfg6ywprhvvfqffq85x6ungamxsgpc7fscnmwlwamlq5lzk4jsc9592f",
+                "tb1pv6q4xz89dk2uee9fn2q5yu0697y3tc9cj3knnr4emyas4rrnjcvsr27g9x",
+                "tb1pnd7y8amtq7qzj5t8pachvchu98dfkahyu0ytut7n6u0z6cvyt6usdj4ztd",
+            ],
+        );
+    }
+
+    // Taproot decaying public
+    #[test]
+    fn test_tr_decaying_public_template() {
+        let fingerprint_a = bitcoin::bip32::Fingerprint::from_str("c55b303f").unwrap();
+        let fingerprint_b = bitcoin::bip32::Fingerprint::from_str("b6dff990").unwrap();
+        let key_a = bitcoin::bip32::ExtendedPubKey::from_str("tpubDCrDhg1mchkTEpDNx8dw9icJA3SG51YW4LkCqLeKb4EhMPQwByQSpWJ6KD9wAjWqtnzDyPGjo9rE5Qnhhf1fNmAtVyjPsgyn9xryYdZPyoD").unwrap();
+        let key_b = bitcoin::bip32::ExtendedPubKey::from_str("tpubDCYPm5BVAwpgRqW7mLMgWKtxQ8mEWyrHDRfsGo3GNL633A7UaYZ1pTjk8aYBAMguGB5LvpEftGxEK3TafLmS6NeqNj9TMmszXNosdN3CV7K").unwrap();
+        check(
+            TrDecayingPublic(
+                (key_a, fingerprint_a),
+                (key_b, fingerprint_b),
+                52_560,
+                KeychainKind::External,
+            )
+            .build(Network::Testnet),
+            false,
+            true,
+            false,
+            Network::Testnet,
+            &[
+                "tb1pqjdrvk363ujuxup30jlv34h8e885pc44lyvdakwpunfefvec7aksht98su",
+                "tb1pxtju6233z3lk06dqts3k97gvkgs6r884u9ar0nww7ce6puwzl9lsecgf9a",
+                "tb1pkxa7wjdjhj9f0z7djvd73ykmyy6mwpdmmlc7q2thgw8v29cc2d7qp2vj28",
+            ],
+        );
+        check(
+            TrDecayingPublic(
+                (key_a, fingerprint_a),
+                (key_b, fingerprint_b),
+                52_560,
+                KeychainKind::Internal,
+            )
+            .build(Network::Testnet),
+            false,
+            true,
+            false,
+            Network::Testnet,
+            &[
+                "tb1ppgjfg6ywprhvvfqffq85x6ungamxsgpc7fscnmwlwamlq5lzk4jsc9592f",
+                "tb1pv6q4xz89dk2uee9fn2q5yu0697y3tc9cj3knnr4emyas4rrnjcvsr27g9x",
+                "tb1pnd7y8amtq7qzj5t8pachvchu98dfkahyu0ytut7n6u0z6cvyt6usdj4ztd",
+            ],
+        );
+    }
 }
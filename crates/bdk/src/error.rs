@@ -73,6 +73,11 @@ pub enum Error {
     SpendingPolicyRequired(crate::types::KeychainKind),
     /// Error while extracting and manipulating policies
     InvalidPolicyPathError(crate::descriptor::policy::PolicyError),
+    /// The tap leaf selected with [`TxBuilder::tap_leaf`] doesn't exist in the descriptor of the
+    /// [`KeychainKind`](crate::types::KeychainKind)
+    ///
+    /// [`TxBuilder::tap_leaf`]: crate::wallet::tx_builder::TxBuilder::tap_leaf
+    InvalidTapLeaf(crate::types::KeychainKind, usize),
     /// Signing error
     Signer(crate::wallet::signer::SignerError),
     /// Requested outpoint doesn't exist in the tx (vout greater than available outputs)
@@ -87,6 +92,18 @@ pub enum Error {
     Bip32(bitcoin::bip32::Error),
     /// Partially signed bitcoin transaction error
     Psbt(bitcoin::psbt::Error),
+    /// Silent payments error
+    SilentPayment(crate::wallet::silent_payments::SilentPaymentError),
+    /// Payment URI error
+    PaymentUri(crate::wallet::payment_uri::PaymentUriError),
+    /// Payjoin error
+    Payjoin(crate::wallet::payjoin::PayjoinError),
+    /// Message signing error
+    Message(crate::wallet::message::MessageError),
+    /// Proof of reserves error
+    Proof(crate::wallet::reserves::ProofError),
+    /// Sweep error
+    Sweep(crate::wallet::sweep::SweepError),
 }
 
 /// Errors returned by miniscript when updating inconsistent PSBTs
@@ -149,6 +166,11 @@ impl fmt::Display for Error {
                 write!(f, "Spending policy required: {:?}", keychain_kind)
             }
             Self::InvalidPolicyPathError(err) => write!(f, "Invalid policy path: {}", err),
+            Self::InvalidTapLeaf(keychain_kind, leaf_index) => write!(
+                f,
+                "Invalid tap leaf: {:?} has no tap leaf {}",
+                keychain_kind, leaf_index
+            ),
             Self::Signer(err) => write!(f, "Signer error: {}", err),
             Self::InvalidOutpoint(outpoint) => write!(
                 f,
@@ -160,6 +182,12 @@ impl fmt::Display for Error {
             Self::MiniscriptPsbt(err) => write!(f, "Miniscript PSBT error: {}", err),
             Self::Bip32(err) => write!(f, "BIP32 error: {}", err),
             Self::Psbt(err) => write!(f, "PSBT error: {}", err),
+            Self::SilentPayment(err) => write!(f, "Silent payments error: {}", err),
+            Self::PaymentUri(err) => write!(f, "Payment URI error: {}", err),
+            Self::Payjoin(err) => write!(f, "Payjoin error: {}", err),
+            Self::Message(err) => write!(f, "Message signing error: {}", err),
+            Self::Proof(err) => write!(f, "Proof of reserves error: {}", err),
+            Self::Sweep(err) => write!(f, "Sweep error: {}", err),
         }
     }
 }
@@ -183,6 +211,12 @@ macro_rules! impl_error {
 impl_error!(descriptor::error::Error, Descriptor);
 impl_error!(descriptor::policy::PolicyError, InvalidPolicyPathError);
 impl_error!(wallet::signer::SignerError, Signer);
+impl_error!(wallet::silent_payments::SilentPaymentError, SilentPayment);
+impl_error!(wallet::payment_uri::PaymentUriError, PaymentUri);
+impl_error!(wallet::payjoin::PayjoinError, Payjoin);
+impl_error!(wallet::message::MessageError, Message);
+impl_error!(wallet::reserves::ProofError, Proof);
+impl_error!(wallet::sweep::SweepError, Sweep);
 
 impl From<crate::keys::KeyError> for Error {
     fn from(key_error: crate::keys::KeyError) -> Error {
@@ -0,0 +1,31 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Transaction broadcasting
+//!
+//! This module re-exports [`Broadcaster`] (and [`AsyncBroadcaster`] with the `async-interface`
+//! feature) from [`bdk_chain::chain_source`]. They submit a transaction to the network through a
+//! chain source. When a node rejects the transaction for a common reason, it is reported as a
+//! [`RejectReason`] rather than as the source-specific error.
+//!
+//! [`Wallet::broadcast`] broadcasts a transaction and, once it has been accepted, inserts it into
+//! the wallet so that it's reflected in the balance right away, without waiting for the next sync.
+//!
+//! Implementations are provided by the chain source crates for wrappers around their clients:
+//!
+//! * `bdk_esplora`: `EsploraSource`, for both the blocking and the async client
+//! * `bdk_electrum`: `ElectrumSource`
+//! * `bdk_bitcoind_rpc`: `BitcoindSource`
+//!
+//! [`Wallet::broadcast`]: crate::Wallet::broadcast
+
+#[cfg(feature = "async-interface")]
+pub use bdk_chain::chain_source::AsyncBroadcaster;
+pub use bdk_chain::chain_source::{BroadcastError, Broadcaster, RejectReason};
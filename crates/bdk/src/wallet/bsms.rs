@@ -0,0 +1,883 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Bitcoin Secure Multisig Setup
+//!
+//! This module implements the [BIP129] setup of a multisig wallet, between a coordinator and the
+//! signers holding its keys:
+//!
+//! 1. The coordinator picks the policy of the wallet and creates a [`BsmsCoordinator`], which
+//!    generates the session [`Token`] to share with the signers.
+//! 2. Each signer answers with a [`KeyRecord`], containing its extended public key signed with
+//!    the corresponding private key.
+//! 3. The coordinator verifies and collects the key records with
+//!    [`BsmsCoordinator::add_key_record`], then produces the [`DescriptorRecord`] to send back to
+//!    the signers, who check it with [`DescriptorRecord::verify`] before registering the wallet.
+//!
+//! The descriptors of the wallet are built with the [`descriptor!`] macro, using `multi` or
+//! `sortedmulti`, and can be used to create a [`Wallet`]:
+//!
+//! ```
+//! # use std::str::FromStr;
+//! # use bdk::bitcoin::bip32::{DerivationPath, ExtendedPrivKey};
+//! # use bdk::bitcoin::secp256k1::Secp256k1;
+//! # use bdk::bitcoin::Network;
+//! # use bdk::wallet::bsms::*;
+//! # use bdk::Wallet;
+//! let secp = Secp256k1::new();
+//! let mut coordinator = BsmsCoordinator::new(2, 2, BsmsScript::Wsh, true)?;
+//!
+//! // on each signer
+//! # let mut records = Vec::new();
+//! # for seed in [[1u8; 32], [2u8; 32]] {
+//! let root = ExtendedPrivKey::new_master(Network::Testnet, &seed)?;
+//! let path = DerivationPath::from_str("m/48'/1'/0'/2'")?;
+//! let record = KeyRecord::new(&secp, coordinator.token().clone(), &root, &path, "Signer")?;
+//! # records.push(record.to_string());
+//! # }
+//!
+//! // back on the coordinator
+//! # for record in records {
+//! coordinator.add_key_record(&secp, KeyRecord::from_str(&record)?)?;
+//! # }
+//! let descriptor_record = coordinator.descriptor_record(Network::Testnet)?;
+//! // send `descriptor_record.to_string()` to the signers
+//!
+//! let (descriptor, change_descriptor) = coordinator.descriptors()?;
+//! let wallet = Wallet::new_no_persist(descriptor, Some(change_descriptor), Network::Testnet)?;
+//! # Ok::<_, Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! The session above isn't encrypted, its [`Token`] is `00`. For an encrypted session, the
+//! coordinator generates a [`Token::new_standard`] or [`Token::new_extended`] token instead, and
+//! passes it to [`BsmsCoordinator::with_token`]. The records are then exchanged encrypted with
+//! [`Token::encrypt`], and read back with [`Token::decrypt`]:
+//!
+//! ```
+//! # use std::str::FromStr;
+//! # use bdk::bitcoin::bip32::{DerivationPath, ExtendedPrivKey};
+//! # use bdk::bitcoin::secp256k1::Secp256k1;
+//! # use bdk::bitcoin::Network;
+//! # use bdk::wallet::bsms::*;
+//! # let secp = Secp256k1::new();
+//! let token = Token::new_standard();
+//! let mut coordinator = BsmsCoordinator::new(1, 1, BsmsScript::Wsh, true)?.with_token(token);
+//!
+//! // the signer got the token from the coordinator
+//! let token = coordinator.token().clone();
+//! let root = ExtendedPrivKey::new_master(Network::Testnet, &[1u8; 32])?;
+//! let path = DerivationPath::from_str("m/48'/1'/0'/2'")?;
+//! let record = KeyRecord::new(&secp, token.clone(), &root, &path, "Signer")?;
+//! let encrypted = token.encrypt(&record.to_string());
+//!
+//! let record = KeyRecord::from_str(&coordinator.token().decrypt(&encrypted)?)?;
+//! coordinator.add_key_record(&secp, record)?;
+//! # Ok::<_, Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [BIP129]: https://github.com/bitcoin/bips/blob/master/bip-0129.mediawiki
+//! [`descriptor!`]: crate::descriptor!
+//! [`Wallet`]: crate::wallet::Wallet
+
+use core::fmt;
+use core::str::FromStr;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes256;
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::bip32::{self, DerivationPath, ExtendedPrivKey, ExtendedPubKey, Fingerprint};
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::hashes::{sha256, sha512, Hash, HashEngine, Hmac, HmacEngine};
+use bitcoin::secp256k1::{Message, Secp256k1, Signing, Verification};
+use bitcoin::sign_message::{signed_msg_hash, MessageSignature};
+use bitcoin::{Address, Network};
+use miniscript::descriptor::{DescriptorPublicKey, DescriptorXKey, Wildcard};
+use miniscript::ForEachKey;
+use rand::RngCore;
+
+use crate::descriptor;
+use crate::descriptor::template::DescriptorTemplateOut;
+use crate::descriptor::{DescriptorError, ExtendedDescriptor};
+
+// The version of BIP129 implemented
+const VERSION: &str = "BSMS 1.0";
+
+// The path restrictions of the descriptor template, which uses `/**` for both keychains
+const PATH_RESTRICTIONS: &str = "/0/*,/1/*";
+
+// The maximum length of the description of a key record
+const MAX_DESCRIPTION_LEN: usize = 80;
+
+// The password of the key derivation of encrypted sessions
+const KDF_PASSWORD: &[u8] = b"No SPOF";
+
+/// Errors related to the multisig setup
+#[derive(Debug)]
+pub enum BsmsError {
+    /// The record is malformed
+    InvalidRecord(&'static str),
+    /// The record is of an unsupported version of BIP129
+    UnsupportedVersion(String),
+    /// The token is malformed, or isn't the token of the session
+    InvalidToken,
+    /// The record couldn't be decrypted with the token
+    Decryption,
+    /// The signature of the key record doesn't match its key
+    InvalidSignature,
+    /// A key record with the same key was already added
+    DuplicateKey,
+    /// All the key records have already been added
+    TooManyKeys,
+    /// Not all the key records have been added yet
+    MissingKeys,
+    /// The threshold isn't between 1 and the number of signers
+    InvalidThreshold,
+    /// The first address doesn't match the descriptor
+    AddressMismatch,
+    /// The descriptor doesn't contain the key of the signer
+    KeyNotIncluded,
+    /// Error while deriving a key
+    Bip32(bip32::Error),
+    /// Error while building the descriptor
+    Descriptor(DescriptorError),
+}
+
+impl fmt::Display for BsmsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidRecord(err) => write!(f, "Invalid record: {}", err),
+            Self::UnsupportedVersion(version) => write!(f, "Unsupported version: {}", version),
+            Self::InvalidToken => write!(f, "Invalid token"),
+            Self::Decryption => write!(f, "The record couldn't be decrypted"),
+            Self::InvalidSignature => write!(f, "Invalid key record signature"),
+            Self::DuplicateKey => write!(f, "The key was already added"),
+            Self::TooManyKeys => write!(f, "All the keys were already added"),
+            Self::MissingKeys => write!(f, "Not all the keys were added"),
+            Self::InvalidThreshold => write!(f, "Invalid threshold"),
+            Self::AddressMismatch => write!(f, "The first address doesn't match the descriptor"),
+            Self::KeyNotIncluded => write!(f, "The descriptor doesn't contain the key"),
+            Self::Bip32(err) => write!(f, "BIP32 error: {}", err),
+            Self::Descriptor(err) => write!(f, "Descriptor error: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BsmsError {}
+
+impl From<bip32::Error> for BsmsError {
+    fn from(err: bip32::Error) -> Self {
+        BsmsError::Bip32(err)
+    }
+}
+
+impl From<DescriptorError> for BsmsError {
+    fn from(err: DescriptorError) -> Self {
+        BsmsError::Descriptor(err)
+    }
+}
+
+/// The token of a setup session
+///
+/// The token of a session without encryption is `00`. Otherwise it's 8 random bytes in the
+/// STANDARD mode of BIP129, or 16 in the EXTENDED mode, and the records are encrypted with a key
+/// derived from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token(Vec<u8>);
+
+impl Token {
+    /// Return the token of a session without encryption
+    pub fn new() -> Self {
+        Token(vec![0])
+    }
+
+    /// Generate the random token of a STANDARD encrypted session
+    pub fn new_standard() -> Self {
+        Self::random(8)
+    }
+
+    /// Generate the random token of an EXTENDED encrypted session
+    pub fn new_extended() -> Self {
+        Self::random(16)
+    }
+
+    fn random(len: usize) -> Self {
+        let mut token = vec![0; len];
+        rand::thread_rng().fill_bytes(&mut token);
+        Token(token)
+    }
+
+    /// Return the bytes of the token
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Return whether the records of the session are encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.0 != [0]
+    }
+
+    /// Encrypt a serialized record, returning it in hex
+    ///
+    /// The record is returned as is in a session without encryption.
+    pub fn encrypt(&self, record: &str) -> String {
+        if !self.is_encrypted() {
+            return record.into();
+        }
+        let key = self.encryption_key();
+        let mac = self.mac(&key, record.as_bytes());
+        let mut data = record.as_bytes().to_vec();
+        aes_256_ctr(&key, &mac[..16], &mut data);
+
+        let mut encrypted = mac.to_vec();
+        encrypted.extend(data);
+        to_hex(&encrypted)
+    }
+
+    /// Decrypt a record encrypted with [`encrypt`](Self::encrypt)
+    ///
+    /// The record is returned as is in a session without encryption.
+    pub fn decrypt(&self, encrypted: &str) -> Result<String, BsmsError> {
+        if !self.is_encrypted() {
+            return Ok(encrypted.into());
+        }
+        let encrypted = Vec::<u8>::from_hex(encrypted.trim()).map_err(|_| BsmsError::Decryption)?;
+        if encrypted.len() < 32 {
+            return Err(BsmsError::Decryption);
+        }
+        let (mac, data) = encrypted.split_at(32);
+        let key = self.encryption_key();
+        let mut data = data.to_vec();
+        aes_256_ctr(&key, &mac[..16], &mut data);
+        if self.mac(&key, &data)[..] != *mac {
+            return Err(BsmsError::Decryption);
+        }
+        String::from_utf8(data).map_err(|_| BsmsError::Decryption)
+    }
+
+    // PBKDF2 with HMAC-SHA512 and 2048 iterations, the token being the salt: a 32-byte key takes
+    // a single block
+    fn encryption_key(&self) -> [u8; 32] {
+        let prf = |data: &[&[u8]]| {
+            let mut engine = HmacEngine::<sha512::Hash>::new(KDF_PASSWORD);
+            for data in data {
+                engine.input(data);
+            }
+            Hmac::<sha512::Hash>::from_engine(engine).to_byte_array()
+        };
+        let mut u = prf(&[&self.0, &1u32.to_be_bytes()]);
+        let mut block = u;
+        for _ in 1..2048 {
+            u = prf(&[&u]);
+            block.iter_mut().zip(&u).for_each(|(b, u)| *b ^= u);
+        }
+        let mut key = [0; 32];
+        key.copy_from_slice(&block[..32]);
+        key
+    }
+
+    // HMAC-SHA256 of the token and the plaintext, keyed with the hash of the encryption key
+    fn mac(&self, key: &[u8; 32], data: &[u8]) -> [u8; 32] {
+        let mut engine = HmacEngine::<sha256::Hash>::new(&sha256::Hash::hash(key)[..]);
+        engine.input(&self.0);
+        engine.input(data);
+        Hmac::<sha256::Hash>::from_engine(engine).to_byte_array()
+    }
+}
+
+impl Default for Token {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", to_hex(&self.0))
+    }
+}
+
+impl FromStr for Token {
+    type Err = BsmsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let token = Vec::<u8>::from_hex(s).map_err(|_| BsmsError::InvalidToken)?;
+        match token.len() {
+            1 if token == [0] => Ok(Token(token)),
+            8 | 16 => Ok(Token(token)),
+            _ => Err(BsmsError::InvalidToken),
+        }
+    }
+}
+
+/// The key of a signer, sent to the coordinator
+///
+/// The record is signed with the private key of [`xpub`](Self::xpub), using the legacy Bitcoin
+/// message signing format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyRecord {
+    /// The token of the session
+    pub token: Token,
+    /// The fingerprint of the root key of the signer, and the derivation path of the key
+    pub origin: (Fingerprint, DerivationPath),
+    /// The extended public key of the signer
+    pub xpub: ExtendedPubKey,
+    /// A description of the signer, up to 80 characters
+    pub description: String,
+    /// The signature of the record
+    pub signature: MessageSignature,
+}
+
+impl KeyRecord {
+    /// Create the key record of a signer, with the key derived from `root` at `path`
+    pub fn new<C: Signing>(
+        secp: &Secp256k1<C>,
+        token: Token,
+        root: &ExtendedPrivKey,
+        path: &DerivationPath,
+        description: &str,
+    ) -> Result<Self, BsmsError> {
+        if description.len() > MAX_DESCRIPTION_LEN || description.contains('\n') {
+            return Err(BsmsError::InvalidRecord("invalid description"));
+        }
+        let xprv = root.derive_priv(secp, path)?;
+        let origin = (root.fingerprint(secp), path.clone());
+        let xpub = ExtendedPubKey::from_priv(secp, &xprv);
+
+        let msg_hash = signed_msg_hash(&record_message(&token, &origin, &xpub, description));
+        let msg = Message::from_slice(msg_hash.as_ref()).expect("32 bytes");
+        let signature = secp.sign_ecdsa_recoverable(&msg, &xprv.private_key);
+        Ok(KeyRecord {
+            token,
+            origin,
+            xpub,
+            description: description.into(),
+            signature: MessageSignature::new(signature, true),
+        })
+    }
+
+    /// Verify the signature of the record
+    pub fn verify<C: Verification>(&self, secp: &Secp256k1<C>) -> Result<(), BsmsError> {
+        let msg_hash = signed_msg_hash(&self.message());
+        match self.signature.recover_pubkey(secp, msg_hash) {
+            Ok(pubkey) if pubkey.inner == self.xpub.public_key => Ok(()),
+            _ => Err(BsmsError::InvalidSignature),
+        }
+    }
+
+    fn message(&self) -> String {
+        record_message(&self.token, &self.origin, &self.xpub, &self.description)
+    }
+
+    // The key derived at `chain/*`, for the descriptors of the wallet
+    fn descriptor_key(&self, chain: u32) -> DescriptorPublicKey {
+        DescriptorPublicKey::XPub(DescriptorXKey {
+            origin: Some(self.origin.clone()),
+            xkey: self.xpub,
+            derivation_path: DerivationPath::from(vec![bip32::ChildNumber::Normal {
+                index: chain,
+            }]),
+            wildcard: Wildcard::Unhardened,
+        })
+    }
+}
+
+impl fmt::Display for KeyRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.message())?;
+        write!(f, "{}", self.signature.to_base64())
+    }
+}
+
+impl FromStr for KeyRecord {
+    type Err = BsmsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines = s.trim_end().lines().collect::<Vec<_>>();
+        if lines.len() != 5 {
+            return Err(BsmsError::InvalidRecord("a key record must have 5 lines"));
+        }
+        check_version(lines[0])?;
+
+        let invalid_key = || BsmsError::InvalidRecord("invalid key");
+        let key = DescriptorPublicKey::from_str(lines[2]).map_err(|_| invalid_key())?;
+        let (origin, xpub) = match key {
+            DescriptorPublicKey::XPub(DescriptorXKey {
+                origin: Some(origin),
+                xkey,
+                derivation_path,
+                wildcard: Wildcard::None,
+            }) if derivation_path.is_empty() => (origin, xkey),
+            _ => return Err(invalid_key()),
+        };
+        if lines[3].len() > MAX_DESCRIPTION_LEN {
+            return Err(BsmsError::InvalidRecord("invalid description"));
+        }
+
+        Ok(KeyRecord {
+            token: Token::from_str(lines[1])?,
+            origin,
+            xpub,
+            description: lines[3].into(),
+            signature: MessageSignature::from_base64(lines[4])
+                .map_err(|_| BsmsError::InvalidRecord("invalid signature"))?,
+        })
+    }
+}
+
+/// The script of the multisig wallet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BsmsScript {
+    /// Legacy P2SH
+    Sh,
+    /// P2WSH nested in P2SH
+    ShWsh,
+    /// Native segwit P2WSH
+    Wsh,
+}
+
+/// The coordinator of the setup of a multisig wallet
+///
+/// For a usage example see [this module](crate::wallet::bsms)'s documentation.
+#[derive(Debug, Clone)]
+pub struct BsmsCoordinator {
+    token: Token,
+    threshold: usize,
+    signers: usize,
+    script: BsmsScript,
+    sorted: bool,
+    key_records: Vec<KeyRecord>,
+}
+
+impl BsmsCoordinator {
+    /// Start the setup of a `threshold` of `signers` wallet
+    ///
+    /// The keys are sorted in the scripts, with `sortedmulti`, if `sorted` is `true`, or
+    /// otherwise kept in the order their key records are added, with `multi`.
+    pub fn new(
+        threshold: usize,
+        signers: usize,
+        script: BsmsScript,
+        sorted: bool,
+    ) -> Result<Self, BsmsError> {
+        if threshold == 0 || threshold > signers {
+            return Err(BsmsError::InvalidThreshold);
+        }
+        Ok(BsmsCoordinator {
+            token: Token::new(),
+            threshold,
+            signers,
+            script,
+            sorted,
+            key_records: Vec::with_capacity(signers),
+        })
+    }
+
+    /// Use `token` for the session instead of the one without encryption
+    ///
+    /// All the signers share the same token.
+    pub fn with_token(mut self, token: Token) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Return the token of the session, to share with the signers
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+
+    /// Verify and add the key record of a signer
+    pub fn add_key_record<C: Verification>(
+        &mut self,
+        secp: &Secp256k1<C>,
+        record: KeyRecord,
+    ) -> Result<(), BsmsError> {
+        if record.token != self.token {
+            return Err(BsmsError::InvalidToken);
+        }
+        record.verify(secp)?;
+        if self.key_records.iter().any(|r| r.xpub == record.xpub) {
+            return Err(BsmsError::DuplicateKey);
+        }
+        if self.is_complete() {
+            return Err(BsmsError::TooManyKeys);
+        }
+        self.key_records.push(record);
+        Ok(())
+    }
+
+    /// Return the key records added so far
+    pub fn key_records(&self) -> &[KeyRecord] {
+        &self.key_records
+    }
+
+    /// Return whether the key records of all the signers have been added
+    pub fn is_complete(&self) -> bool {
+        self.key_records.len() == self.signers
+    }
+
+    /// Return the external and internal descriptors of the wallet, once all the key records
+    /// have been added
+    pub fn descriptors(&self) -> Result<(DescriptorTemplateOut, DescriptorTemplateOut), BsmsError> {
+        if !self.is_complete() {
+            return Err(BsmsError::MissingKeys);
+        }
+        Ok((self.build_descriptor(0)?, self.build_descriptor(1)?))
+    }
+
+    /// Return the descriptor record to send to the signers, once all the key records have been
+    /// added
+    pub fn descriptor_record(&self, network: Network) -> Result<DescriptorRecord, BsmsError> {
+        let ((descriptor, _, _), (change_descriptor, _, _)) = self.descriptors()?;
+        let first_address = first_address(&descriptor, network)?;
+        Ok(DescriptorRecord {
+            descriptor,
+            change_descriptor,
+            first_address: Address::new(network, first_address.payload),
+        })
+    }
+
+    fn build_descriptor(&self, chain: u32) -> Result<DescriptorTemplateOut, DescriptorError> {
+        let keys = self
+            .key_records
+            .iter()
+            .map(|record| record.descriptor_key(chain))
+            .collect::<Vec<_>>();
+        let threshold = self.threshold;
+        match (self.script, self.sorted) {
+            (BsmsScript::Sh, true) => descriptor!(sh(sortedmulti_vec(threshold, keys))),
+            (BsmsScript::ShWsh, true) => descriptor!(sh(wsh(sortedmulti_vec(threshold, keys)))),
+            (BsmsScript::Wsh, true) => descriptor!(wsh(sortedmulti_vec(threshold, keys))),
+            (BsmsScript::Sh, false) => descriptor!(sh(multi_vec(threshold, keys))),
+            (BsmsScript::ShWsh, false) => descriptor!(sh(wsh(multi_vec(threshold, keys)))),
+            (BsmsScript::Wsh, false) => descriptor!(wsh(multi_vec(threshold, keys))),
+        }
+    }
+}
+
+/// The descriptor of the wallet, sent by the coordinator to the signers
+///
+/// The two descriptors are serialized as a single template, with `/**` standing for the `/0/*`
+/// and `/1/*` steps of the keys, along with the first address of the wallet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorRecord {
+    /// The external descriptor
+    pub descriptor: ExtendedDescriptor,
+    /// The internal descriptor
+    pub change_descriptor: ExtendedDescriptor,
+    /// The first external address of the wallet
+    pub first_address: Address<NetworkUnchecked>,
+}
+
+impl DescriptorRecord {
+    /// Verify that the record contains the key of the signer, and that its first address
+    /// matches the descriptor
+    pub fn verify(&self, network: Network, key_record: &KeyRecord) -> Result<(), BsmsError> {
+        let key = key_record.descriptor_key(0);
+        if !self.descriptor.for_any_key(|pk| pk == &key) {
+            return Err(BsmsError::KeyNotIncluded);
+        }
+
+        let expected = first_address(&self.descriptor, network)?;
+        if !self.first_address.is_valid_for_network(network)
+            || self.first_address.clone().assume_checked() != expected
+        {
+            return Err(BsmsError::AddressMismatch);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DescriptorRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let descriptor = self.descriptor.to_string();
+        let descriptor = descriptor
+            .split_once('#')
+            .map_or(descriptor.as_str(), |(descriptor, _)| descriptor);
+        writeln!(f, "{}", VERSION)?;
+        writeln!(f, "{}", descriptor.replace("/0/*", "/**"))?;
+        writeln!(f, "{}", PATH_RESTRICTIONS)?;
+        write!(f, "{}", self.first_address.clone().assume_checked())
+    }
+}
+
+impl FromStr for DescriptorRecord {
+    type Err = BsmsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines = s.trim_end().lines().collect::<Vec<_>>();
+        if lines.len() != 4 {
+            return Err(BsmsError::InvalidRecord(
+                "a descriptor record must have 4 lines",
+            ));
+        }
+        check_version(lines[0])?;
+        if lines[2] != PATH_RESTRICTIONS {
+            return Err(BsmsError::InvalidRecord("unsupported path restrictions"));
+        }
+
+        let template = lines[1]
+            .split_once('#')
+            .map_or(lines[1], |(template, _)| template);
+        if !template.contains("/**") {
+            return Err(BsmsError::InvalidRecord("the template must use /**"));
+        }
+        let parse = |chain: &str| {
+            ExtendedDescriptor::from_str(&template.replace("/**", chain))
+                .map_err(|_| BsmsError::InvalidRecord("invalid descriptor template"))
+        };
+
+        Ok(DescriptorRecord {
+            descriptor: parse("/0/*")?,
+            change_descriptor: parse("/1/*")?,
+            first_address: Address::from_str(lines[3])
+                .map_err(|_| BsmsError::InvalidRecord("invalid address"))?,
+        })
+    }
+}
+
+// The signed part of a key record
+fn record_message(
+    token: &Token,
+    origin: &(Fingerprint, DerivationPath),
+    xpub: &ExtendedPubKey,
+    description: &str,
+) -> String {
+    let path = origin.1.to_string();
+    format!(
+        "{}\n{}\n[{}{}]{}\n{}",
+        VERSION,
+        token,
+        origin.0,
+        path.trim_start_matches('m'),
+        xpub,
+        description
+    )
+}
+
+// AES-256 in counter mode, with a big-endian counter starting at `iv`
+fn aes_256_ctr(key: &[u8; 32], iv: &[u8], data: &mut [u8]) {
+    let cipher = Aes256::new(GenericArray::from_slice(key));
+    let mut counter = u128::from_be_bytes(iv.try_into().expect("16 bytes"));
+    for chunk in data.chunks_mut(16) {
+        let mut keystream = GenericArray::from(counter.to_be_bytes());
+        cipher.encrypt_block(&mut keystream);
+        chunk.iter_mut().zip(keystream).for_each(|(b, k)| *b ^= k);
+        counter = counter.wrapping_add(1);
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn check_version(version: &str) -> Result<(), BsmsError> {
+    if version != VERSION {
+        return Err(BsmsError::UnsupportedVersion(version.into()));
+    }
+    Ok(())
+}
+
+fn first_address(descriptor: &ExtendedDescriptor, network: Network) -> Result<Address, BsmsError> {
+    let definite = descriptor
+        .at_derivation_index(0)
+        .map_err(|_| BsmsError::InvalidRecord("the descriptor must be ranged"))?;
+    definite
+        .address(network)
+        .map_err(|e| BsmsError::Descriptor(e.into()))
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::ToString;
+    use core::str::FromStr;
+
+    use bitcoin::bip32::{DerivationPath, ExtendedPrivKey};
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::Network;
+
+    use super::*;
+    use crate::wallet::Wallet;
+
+    fn key_record(seed: u8, token: &Token) -> KeyRecord {
+        let secp = Secp256k1::new();
+        let root = ExtendedPrivKey::new_master(Network::Testnet, &[seed; 32]).unwrap();
+        let path = DerivationPath::from_str("m/48'/1'/0'/2'").unwrap();
+        KeyRecord::new(&secp, token.clone(), &root, &path, "Signer").unwrap()
+    }
+
+    #[test]
+    fn test_token() {
+        assert_eq!(Token::new().to_string(), "00");
+        assert_eq!(Token::from_str("00").unwrap(), Token::new());
+        assert!(!Token::new().is_encrypted());
+        // the tokens of STANDARD and EXTENDED sessions
+        let standard = Token::from_str("a54044308ceac9b7").unwrap();
+        assert!(standard.is_encrypted());
+        assert_eq!(standard.to_string(), "a54044308ceac9b7");
+        let extended = Token::from_str("6d4b0c3a5f2e7a9e2e5f4d5b9c0e1a6f").unwrap();
+        assert_eq!(extended.to_string(), "6d4b0c3a5f2e7a9e2e5f4d5b9c0e1a6f");
+        assert_eq!(Token::new_standard().as_bytes().len(), 8);
+        assert_eq!(Token::new_extended().as_bytes().len(), 16);
+        assert_ne!(Token::new_standard(), Token::new_standard());
+
+        assert!(matches!(
+            Token::from_str("0011"),
+            Err(BsmsError::InvalidToken)
+        ));
+        assert!(Token::from_str("01").is_err());
+        assert!(Token::from_str("a54044308ceac9").is_err());
+    }
+
+    #[test]
+    fn test_encryption() {
+        let record = key_record(1, &Token::new()).to_string();
+        assert_eq!(Token::new().encrypt(&record), record);
+        assert_eq!(Token::new().decrypt(&record).unwrap(), record);
+
+        let token = Token::from_str("a54044308ceac9b7").unwrap();
+        assert_eq!(
+            token.encrypt("BSMS 1.0"),
+            "b3b4c13adc68363a67df0c34bbb8539d43fbb7e6c3912a4f60a25422b0285688a91b0f1ead0cf20d"
+        );
+
+        for token in [Token::new_standard(), Token::new_extended()] {
+            let encrypted = token.encrypt(&record);
+            assert!(!encrypted.contains("BSMS"));
+            // the MAC and the record, in hex
+            assert_eq!(encrypted.len(), 2 * (32 + record.len()));
+            assert_eq!(token.decrypt(&encrypted).unwrap(), record);
+
+            // with another token, or once tampered with, the record is rejected
+            assert!(matches!(
+                Token::new_standard().decrypt(&encrypted),
+                Err(BsmsError::Decryption)
+            ));
+            let mut tampered = encrypted.into_bytes();
+            let last = tampered.len() - 1;
+            tampered[last] = if tampered[last] == b'0' { b'1' } else { b'0' };
+            assert!(matches!(
+                token.decrypt(&String::from_utf8(tampered).unwrap()),
+                Err(BsmsError::Decryption)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_key_record() {
+        let secp = Secp256k1::new();
+        let token = Token::new();
+        let record = key_record(1, &token);
+
+        let serialized = record.to_string();
+        assert_eq!(serialized.lines().count(), 5);
+        assert!(serialized.starts_with(&format!("BSMS 1.0\n{}\n[", token)));
+        let parsed = KeyRecord::from_str(&serialized).unwrap();
+        assert_eq!(parsed, record);
+        parsed.verify(&secp).unwrap();
+
+        let tampered = KeyRecord {
+            description: "Another signer".into(),
+            ..record
+        };
+        assert!(matches!(
+            tampered.verify(&secp),
+            Err(BsmsError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_setup() {
+        let secp = Secp256k1::new();
+        let mut coordinator = BsmsCoordinator::new(2, 3, BsmsScript::ShWsh, true).unwrap();
+        let records = [1, 2, 3]
+            .iter()
+            .map(|&seed| key_record(seed, coordinator.token()))
+            .collect::<Vec<_>>();
+
+        coordinator
+            .add_key_record(&secp, records[0].clone())
+            .unwrap();
+        assert!(matches!(
+            coordinator.add_key_record(&secp, records[0].clone()),
+            Err(BsmsError::DuplicateKey)
+        ));
+        assert!(matches!(
+            coordinator.add_key_record(&secp, key_record(4, &Token::new_standard())),
+            Err(BsmsError::InvalidToken)
+        ));
+        assert!(matches!(
+            coordinator.descriptor_record(Network::Testnet),
+            Err(BsmsError::MissingKeys)
+        ));
+        for record in &records[1..] {
+            coordinator.add_key_record(&secp, record.clone()).unwrap();
+        }
+        assert!(coordinator.is_complete());
+
+        let descriptor_record = coordinator.descriptor_record(Network::Testnet).unwrap();
+        let serialized = descriptor_record.to_string();
+        let lines = serialized.lines().collect::<Vec<_>>();
+        assert!(lines[1].starts_with("sh(wsh(sortedmulti(2,["));
+        assert_eq!(lines[1].matches("/**").count(), 3);
+        assert_eq!(lines[2], "/0/*,/1/*");
+
+        // each signer checks the record before registering the wallet
+        let parsed = DescriptorRecord::from_str(&serialized).unwrap();
+        assert_eq!(parsed, descriptor_record);
+        for record in &records {
+            parsed.verify(Network::Testnet, record).unwrap();
+        }
+        let outsider = key_record(4, coordinator.token());
+        assert!(matches!(
+            parsed.verify(Network::Testnet, &outsider),
+            Err(BsmsError::KeyNotIncluded)
+        ));
+        let wrong_address = DescriptorRecord {
+            first_address: Address::from_str("2N4eQYCbKUHCCTUjBJeHcJp9ok6J2GZsTDt").unwrap(),
+            ..parsed.clone()
+        };
+        assert!(matches!(
+            wrong_address.verify(Network::Testnet, &records[0]),
+            Err(BsmsError::AddressMismatch)
+        ));
+
+        let (descriptor, change_descriptor) = coordinator.descriptors().unwrap();
+        let mut wallet =
+            Wallet::new_no_persist(descriptor, Some(change_descriptor), Network::Testnet).unwrap();
+        assert_eq!(
+            wallet.get_address(crate::wallet::AddressIndex::New).address,
+            parsed.first_address.assume_checked()
+        );
+    }
+
+    #[test]
+    fn test_unsorted() {
+        let secp = Secp256k1::new();
+        let mut coordinator = BsmsCoordinator::new(1, 2, BsmsScript::Wsh, false).unwrap();
+        for seed in [2, 1] {
+            let record = key_record(seed, coordinator.token());
+            coordinator.add_key_record(&secp, record).unwrap();
+        }
+        let record = coordinator.descriptor_record(Network::Testnet).unwrap();
+        let serialized = record.to_string();
+        assert!(serialized.contains("wsh(multi(1,["));
+        assert_eq!(DescriptorRecord::from_str(&serialized).unwrap(), record);
+
+        assert!(matches!(
+            BsmsCoordinator::new(3, 2, BsmsScript::Sh, true),
+            Err(BsmsError::InvalidThreshold)
+        ));
+    }
+}
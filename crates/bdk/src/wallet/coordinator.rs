@@ -0,0 +1,337 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Multisig coordination
+//!
+//! This module provides [`Coordinator`], which keeps track of the PSBTs of a multisig [`Wallet`]
+//! while they are passed around between the cosigners:
+//!
+//! 1. The coordinator creates a PSBT with the wallet and starts tracking it with
+//!    [`Coordinator::track`].
+//! 2. Every time a cosigner sends back a signed copy, it's merged with [`Coordinator::combine`].
+//! 3. [`Coordinator::signing_status`] uses the wallet's spending policies to report which keys
+//!    have signed each input, and which ones still need to.
+//! 4. Once the threshold is met, [`Coordinator::finalize`] finalizes the PSBT and returns the
+//!    transaction, ready to be broadcast.
+//!
+//! ```
+//! # use bdk::wallet::coordinator::Coordinator;
+//! # use bdk::*;
+//! # let mut wallet = doctest_wallet!();
+//! # let to_address = wallet.get_address(wallet::AddressIndex::New);
+//! let psbt = {
+//!     let mut builder = wallet.build_tx();
+//!     builder.add_recipient(to_address.script_pubkey(), 50_000);
+//!     builder.finish()?
+//! };
+//!
+//! let mut coordinator = Coordinator::new();
+//! let txid = coordinator.track(psbt)?;
+//!
+//! // send the psbt to the cosigners, then merge their signatures
+//! # let signed_psbt = coordinator.get(&txid).unwrap().clone();
+//! coordinator.combine(signed_psbt)?;
+//!
+//! if coordinator.signing_status(&wallet, &txid)?.is_complete() {
+//!     let tx = coordinator.finalize(&wallet, &txid, SignOptions::default())?;
+//!     // broadcast `tx` ...
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use core::fmt;
+
+use alloc::vec::Vec;
+use bitcoin::psbt::{self, PartiallySignedTransaction as Psbt};
+use bitcoin::{Transaction, Txid};
+
+use crate::collections::BTreeMap;
+use crate::descriptor::policy::{BuildSatisfaction, PkOrF, Policy, Satisfaction, SatisfiableItem};
+use crate::descriptor::ExtractPolicy;
+use crate::psbt::PsbtUtils;
+use crate::signer::SignOptions;
+use crate::types::KeychainKind;
+use crate::wallet::Wallet;
+
+/// Errors returned by the [`Coordinator`]
+#[derive(Debug)]
+pub enum CoordinatorError {
+    /// No PSBT with this unsigned transaction id is being tracked
+    UnknownPsbt(Txid),
+    /// The PSBTs could not be combined
+    Combine(psbt::Error),
+    /// The signatures of the PSBT are not enough to finalize it
+    ThresholdNotMet,
+    /// Error while extracting the policies or finalizing the PSBT
+    Wallet(crate::Error),
+}
+
+impl fmt::Display for CoordinatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoordinatorError::UnknownPsbt(txid) => write!(f, "Unknown PSBT: {}", txid),
+            CoordinatorError::Combine(err) => write!(f, "Cannot combine PSBTs: {}", err),
+            CoordinatorError::ThresholdNotMet => write!(f, "The signing threshold is not met"),
+            CoordinatorError::Wallet(err) => write!(f, "Wallet error: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CoordinatorError {}
+
+impl From<crate::Error> for CoordinatorError {
+    fn from(err: crate::Error) -> Self {
+        CoordinatorError::Wallet(err)
+    }
+}
+
+/// The signing status of an input spending one of the wallet's outputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputSigningStatus {
+    /// The keychain of the output being spent
+    pub keychain: KeychainKind,
+    /// Whether the input has already been finalized
+    pub finalized: bool,
+    /// How much the signatures in the PSBT satisfy the keychain's spending policy
+    pub satisfaction: Satisfaction,
+    /// The keys that have signed the input
+    pub signed: Vec<PkOrF>,
+    /// The keys that haven't signed the input yet
+    pub missing: Vec<PkOrF>,
+}
+
+impl InputSigningStatus {
+    /// Returns whether the input has enough signatures to be finalized
+    ///
+    /// Timelocks are not taken into account.
+    pub fn is_complete(&self) -> bool {
+        self.finalized
+            || matches!(
+                self.satisfaction,
+                Satisfaction::Complete { .. } | Satisfaction::PartialComplete { .. }
+            )
+    }
+}
+
+/// The signing status of a PSBT, see [`Coordinator::signing_status`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningStatus {
+    /// The status of each input, or `None` if the input doesn't spend one of the wallet's
+    /// outputs
+    pub inputs: Vec<Option<InputSigningStatus>>,
+}
+
+impl SigningStatus {
+    /// Returns whether all the inputs spending the wallet's outputs have enough signatures
+    pub fn is_complete(&self) -> bool {
+        self.inputs
+            .iter()
+            .flatten()
+            .all(InputSigningStatus::is_complete)
+    }
+
+    /// Returns the keys that still need to sign at least one of the incomplete inputs
+    pub fn missing_keys(&self) -> Vec<PkOrF> {
+        let mut missing = Vec::new();
+        for input in self.inputs.iter().flatten().filter(|i| !i.is_complete()) {
+            for key in &input.missing {
+                if !missing.contains(key) {
+                    missing.push(key.clone());
+                }
+            }
+        }
+        missing
+    }
+}
+
+/// Keeps track of the PSBTs of a multisig wallet while they are being signed by the cosigners
+///
+/// PSBTs are identified by the txid of their unsigned transaction, and only kept in memory.
+#[derive(Debug, Default, Clone)]
+pub struct Coordinator {
+    psbts: BTreeMap<Txid, Psbt>,
+}
+
+impl Coordinator {
+    /// Create a coordinator that doesn't track any PSBT
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking `psbt`, returning the txid used to identify it
+    ///
+    /// If the PSBT is already tracked, `psbt` is combined with it. This fails with
+    /// [`CoordinatorError::Combine`] if the two copies disagree, for instance on the source of a
+    /// global xpub, in which case the tracked PSBT is left untouched.
+    pub fn track(&mut self, psbt: Psbt) -> Result<Txid, CoordinatorError> {
+        let txid = psbt.unsigned_tx.txid();
+        match self.psbts.get_mut(&txid) {
+            Some(tracked) => combine_into(tracked, psbt)?,
+            None => {
+                self.psbts.insert(txid, psbt);
+            }
+        }
+        Ok(txid)
+    }
+
+    /// Stop tracking a PSBT, returning it
+    pub fn untrack(&mut self, txid: &Txid) -> Option<Psbt> {
+        self.psbts.remove(txid)
+    }
+
+    /// Get a tracked PSBT
+    pub fn get(&self, txid: &Txid) -> Option<&Psbt> {
+        self.psbts.get(txid)
+    }
+
+    /// Get a mutable reference to a tracked PSBT, for instance to sign it with [`Wallet::sign`]
+    pub fn get_mut(&mut self, txid: &Txid) -> Option<&mut Psbt> {
+        self.psbts.get_mut(txid)
+    }
+
+    /// Iterate over the tracked PSBTs
+    pub fn psbts(&self) -> impl Iterator<Item = (&Txid, &Psbt)> {
+        self.psbts.iter()
+    }
+
+    /// Merge the signatures and the other fields of a PSBT returned by a cosigner into the
+    /// tracked one, returning its txid
+    ///
+    /// If the PSBTs can't be combined the tracked one is left untouched.
+    pub fn combine(&mut self, psbt: Psbt) -> Result<Txid, CoordinatorError> {
+        let txid = psbt.unsigned_tx.txid();
+        let tracked = self
+            .psbts
+            .get_mut(&txid)
+            .ok_or(CoordinatorError::UnknownPsbt(txid))?;
+        combine_into(tracked, psbt)?;
+
+        Ok(txid)
+    }
+
+    /// Report which keys have signed each input of a tracked PSBT, and which ones still need to
+    ///
+    /// The signatures are checked against the spending policy of the keychain of each input.
+    pub fn signing_status<D>(
+        &self,
+        wallet: &Wallet<D>,
+        txid: &Txid,
+    ) -> Result<SigningStatus, CoordinatorError> {
+        let psbt = self
+            .psbts
+            .get(txid)
+            .ok_or(CoordinatorError::UnknownPsbt(*txid))?;
+
+        let mut inputs = Vec::with_capacity(psbt.inputs.len());
+        for (input_index, input) in psbt.inputs.iter().enumerate() {
+            let keychain = match psbt
+                .get_utxo_for(input_index)
+                .and_then(|txout| wallet.derivation_of_spk(&txout.script_pubkey))
+            {
+                Some((keychain, _)) => keychain,
+                None => {
+                    inputs.push(None);
+                    continue;
+                }
+            };
+            let descriptor = wallet
+                .public_descriptor(keychain)
+                .expect("the keychain must exist");
+            let policy = descriptor
+                .extract_policy(
+                    &wallet.get_signers(keychain),
+                    BuildSatisfaction::PsbtInput { psbt, input_index },
+                    wallet.secp_ctx(),
+                )
+                .map_err(crate::Error::from)?
+                .expect("descriptor policy must exist");
+
+            let mut signed = Vec::new();
+            let mut missing = Vec::new();
+            collect_signers(&policy, &mut signed, &mut missing);
+
+            inputs.push(Some(InputSigningStatus {
+                keychain,
+                finalized: input.final_script_sig.is_some() || input.final_script_witness.is_some(),
+                satisfaction: policy.satisfaction,
+                signed,
+                missing,
+            }));
+        }
+
+        Ok(SigningStatus { inputs })
+    }
+
+    /// Finalize a tracked PSBT and extract its transaction, ready to be broadcast
+    ///
+    /// The PSBT stops being tracked once it has been finalized. If some input can't be finalized
+    /// yet, [`CoordinatorError::ThresholdNotMet`] is returned and the PSBT is left untouched.
+    pub fn finalize<D>(
+        &mut self,
+        wallet: &Wallet<D>,
+        txid: &Txid,
+        sign_options: SignOptions,
+    ) -> Result<Transaction, CoordinatorError> {
+        let mut psbt = self
+            .psbts
+            .get(txid)
+            .ok_or(CoordinatorError::UnknownPsbt(*txid))?
+            .clone();
+
+        if !wallet.finalize_psbt(&mut psbt, sign_options)? {
+            return Err(CoordinatorError::ThresholdNotMet);
+        }
+        self.psbts.remove(txid);
+
+        Ok(psbt.extract_tx())
+    }
+}
+
+// Sort the keys of the policy tree depending on whether their signature is in the PSBT
+fn collect_signers(policy: &Policy, signed: &mut Vec<PkOrF>, missing: &mut Vec<PkOrF>) {
+    match &policy.item {
+        SatisfiableItem::EcdsaSignature(key) | SatisfiableItem::SchnorrSignature(key) => {
+            match policy.satisfaction {
+                Satisfaction::Complete { .. } => signed.push(key.clone()),
+                _ => missing.push(key.clone()),
+            }
+        }
+        SatisfiableItem::Multisig { keys, .. } => {
+            let items = match &policy.satisfaction {
+                Satisfaction::Partial { items, .. }
+                | Satisfaction::PartialComplete { items, .. } => items.as_slice(),
+                _ => &[],
+            };
+            for (index, key) in keys.iter().enumerate() {
+                if items.contains(&index) {
+                    signed.push(key.clone());
+                } else {
+                    missing.push(key.clone());
+                }
+            }
+        }
+        SatisfiableItem::Thresh { items, .. } => {
+            for item in items {
+                collect_signers(item, signed, missing);
+            }
+        }
+        _ => {}
+    }
+}
+
+// `Psbt::combine` can fail halfway through, so combine into a copy and only replace `tracked` on
+// success
+fn combine_into(tracked: &mut Psbt, psbt: Psbt) -> Result<(), CoordinatorError> {
+    let mut combined = tracked.clone();
+    combined.combine(psbt).map_err(CoordinatorError::Combine)?;
+    *tracked = combined;
+    Ok(())
+}
@@ -0,0 +1,252 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Bitcoin Core `listdescriptors`
+
+use core::fmt;
+use core::str::FromStr;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use miniscript::descriptor::DescriptorType;
+use miniscript::Descriptor;
+
+use super::{descriptor_with_secret, first_confirmation, ImportError, ImportedWallet};
+use crate::types::KeychainKind;
+use crate::wallet::{Birthday, Wallet};
+
+/// The output of Bitcoin Core's `listdescriptors` RPC
+///
+/// The descriptors contain private keys if the RPC was called with `private` set to `true`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoreDescriptors {
+    /// The name of the wallet
+    pub wallet_name: String,
+    /// The descriptors of the wallet
+    pub descriptors: Vec<CoreDescriptor>,
+}
+
+/// A descriptor of a Bitcoin Core wallet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoreDescriptor {
+    /// The descriptor, with its checksum
+    pub desc: String,
+    /// The unix timestamp of the creation of the descriptor
+    pub timestamp: u64,
+    /// Whether the wallet uses this descriptor for new addresses
+    pub active: bool,
+    /// Whether the descriptor is used for change, only present for active descriptors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub internal: Option<bool>,
+    /// The range of derivation indexes the wallet watches, only present for ranged descriptors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<(u32, u32)>,
+    /// The next index used for new addresses, only present for ranged descriptors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<u32>,
+}
+
+impl fmt::Display for CoreDescriptors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            serde_json::to_string(self).map_err(|_| fmt::Error)?
+        )
+    }
+}
+
+impl FromStr for CoreDescriptors {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+impl CoreDescriptors {
+    /// Import the active descriptors of type `desc_type`
+    ///
+    /// Bitcoin Core wallets have an active pair of descriptors for each address type: pick the
+    /// one to use, for instance [`DescriptorType::Wpkh`]. The birthday is the creation time of the
+    /// oldest of the two descriptors.
+    pub fn import(&self, desc_type: DescriptorType) -> Result<ImportedWallet, ImportError> {
+        let mut external = None;
+        let mut internal = None;
+        for descriptor in self.descriptors.iter().filter(|d| d.active) {
+            let parsed = Descriptor::<String>::from_str(&descriptor.desc)
+                .map_err(|e| ImportError::InvalidFormat(e.to_string()))?;
+            if parsed.desc_type() != desc_type {
+                continue;
+            }
+            match descriptor.internal {
+                Some(true) => internal = Some(descriptor),
+                _ => external = Some(descriptor),
+            }
+        }
+        let external = external.ok_or(ImportError::Unsupported(
+            "no active descriptor of this type",
+        ))?;
+
+        let timestamp = internal
+            .iter()
+            .map(|d| d.timestamp)
+            .fold(external.timestamp, core::cmp::min);
+        Ok(ImportedWallet {
+            descriptor: external.desc.clone(),
+            change_descriptor: internal.map(|d| d.desc.clone()),
+            // Core uses a timestamp of 0 to rescan from the genesis block
+            birthday: Some(timestamp)
+                .filter(|&timestamp| timestamp > 0)
+                .map(Birthday::Time),
+            label: Some(self.wallet_name.clone()),
+        })
+    }
+
+    /// Export a wallet, in the format expected by Bitcoin Core's `importdescriptors`
+    ///
+    /// The descriptors contain the private keys of the wallet, if it has any. Their timestamp is
+    /// the birthday of the wallet if it's a [`Birthday::Time`], or otherwise the time of the
+    /// oldest block with a transaction of the wallet. If neither is known the timestamp is `0`,
+    /// which makes Bitcoin Core rescan the whole chain.
+    pub fn export_wallet<D>(wallet: &Wallet<D>, wallet_name: &str) -> Self {
+        let timestamp = match wallet.birthday() {
+            Some(Birthday::Time(time)) => time,
+            _ => first_confirmation(wallet).map_or(0, |(_, time)| time),
+        };
+
+        let descriptors = [KeychainKind::External, KeychainKind::Internal]
+            .into_iter()
+            .filter_map(|keychain| {
+                let ranged = wallet.public_descriptor(keychain)?.has_wildcard();
+                Some(CoreDescriptor {
+                    desc: descriptor_with_secret(wallet, keychain)?,
+                    timestamp,
+                    active: true,
+                    internal: Some(keychain == KeychainKind::Internal),
+                    range: Some((0, wallet.derivation_index(keychain).unwrap_or(0)))
+                        .filter(|_| ranged),
+                    next: Some(wallet.next_derivation_index(keychain)).filter(|_| ranged),
+                })
+            })
+            .collect();
+
+        CoreDescriptors {
+            wallet_name: wallet_name.into(),
+            descriptors,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::ToString;
+    use core::str::FromStr;
+
+    use bitcoin::Network;
+    use miniscript::descriptor::DescriptorType;
+
+    use super::CoreDescriptors;
+    use crate::wallet::{Birthday, Wallet};
+
+    // `listdescriptors` of a new regtest wallet, trimmed to two address types
+    const LIST_DESCRIPTORS: &str = r#"{
+        "wallet_name": "test",
+        "descriptors": [
+            {
+                "desc": "pkh([3fda8bd9/44h/1h/0h]tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/0/*)",
+                "timestamp": 1696248113,
+                "active": true,
+                "internal": false,
+                "range": [0, 999],
+                "next": 0
+            },
+            {
+                "desc": "pkh([3fda8bd9/44h/1h/0h]tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/1/*)",
+                "timestamp": 1696248113,
+                "active": true,
+                "internal": true,
+                "range": [0, 999],
+                "next": 0
+            },
+            {
+                "desc": "wpkh([3fda8bd9/84h/1h/0h]tpubDEnoLuPdBep9bzw5LoGYpsxUQYheRQ9gcgrJhJEcdKFB9cWQRyYmkCyRoTqeD4tJYiVVgt6A3rN6rWn9RYhR9sBsGxji29LYWHuKKbdb1ev/0/*)",
+                "timestamp": 1696248112,
+                "active": true,
+                "internal": false,
+                "range": [0, 999],
+                "next": 2
+            },
+            {
+                "desc": "wpkh([3fda8bd9/84h/1h/0h]tpubDEnoLuPdBep9bzw5LoGYpsxUQYheRQ9gcgrJhJEcdKFB9cWQRyYmkCyRoTqeD4tJYiVVgt6A3rN6rWn9RYhR9sBsGxji29LYWHuKKbdb1ev/1/*)",
+                "timestamp": 1696248114,
+                "active": true,
+                "internal": true,
+                "range": [0, 999],
+                "next": 0
+            },
+            {
+                "desc": "wpkh(02e96fe52ef0e22d2f131dd425ce1893073a3c6ad20e8cac36726393dfb4856a4c)",
+                "timestamp": 1696248000,
+                "active": false
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_import() {
+        let descriptors = CoreDescriptors::from_str(LIST_DESCRIPTORS).unwrap();
+        let import = descriptors.import(DescriptorType::Wpkh).unwrap();
+        assert_eq!(import.descriptor, descriptors.descriptors[2].desc);
+        assert_eq!(
+            import.change_descriptor.as_ref(),
+            Some(&descriptors.descriptors[3].desc)
+        );
+        assert_eq!(import.birthday, Some(Birthday::Time(1696248112)));
+        assert_eq!(import.label.as_deref(), Some("test"));
+
+        let wallet = import.into_wallet((), Network::Regtest).unwrap();
+        assert_eq!(wallet.birthday(), Some(Birthday::Time(1696248112)));
+
+        assert!(descriptors.import(DescriptorType::Tr).is_err());
+    }
+
+    #[test]
+    fn test_export() {
+        let mut wallet = Wallet::new_no_persist(
+            "wpkh(tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/84'/1'/0'/0/*)",
+            Some("wpkh(tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/84'/1'/0'/1/*)"),
+            Network::Testnet,
+        )
+        .unwrap();
+        wallet.set_birthday(Birthday::Time(1_700_000_000));
+
+        let export = CoreDescriptors::export_wallet(&wallet, "exported");
+        assert_eq!(export.descriptors.len(), 2);
+        assert!(export.descriptors[0].desc.contains("tprv"));
+        assert_eq!(export.descriptors[0].internal, Some(false));
+        assert_eq!(export.descriptors[1].internal, Some(true));
+        assert_eq!(export.descriptors[0].timestamp, 1_700_000_000);
+        assert_eq!(export.descriptors[0].next, Some(0));
+
+        let import = CoreDescriptors::from_str(&export.to_string())
+            .unwrap()
+            .import(DescriptorType::Wpkh)
+            .unwrap();
+        let imported = import.into_wallet((), Network::Testnet).unwrap();
+        assert_eq!(
+            imported.public_descriptor(crate::KeychainKind::Internal),
+            wallet.public_descriptor(crate::KeychainKind::Internal)
+        );
+        assert_eq!(imported.birthday(), Some(Birthday::Time(1_700_000_000)));
+    }
+}
@@ -0,0 +1,247 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Coldcard multisig setup files
+
+use core::fmt;
+use core::str::FromStr;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use bitcoin::bip32::{DerivationPath, Fingerprint};
+
+use super::{decode_slip132, ImportError, ImportedWallet, OriginXpub, ScriptKind, StandardWallet};
+use crate::wallet::Wallet;
+
+/// A multisig setup file, as imported and exported by Coldcard and the wallets that work with it
+///
+/// The file lists the name of the wallet, its policy and script type, and the extended public
+/// key of each signer along with the fingerprint of its root key and its derivation path:
+///
+/// ```text
+/// Name: Vault
+/// Policy: 2 of 3
+/// Derivation: m/48'/0'/0'/2'
+/// Format: P2WSH
+///
+/// 0F056943: xpub6F...
+/// 6BA6CFD0: xpub6E...
+/// 747B698E: xpub6E...
+/// ```
+///
+/// The wallet uses `sortedmulti`, with `/0/*` and `/1/*` keychains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColdcardMultisig {
+    name: String,
+    wallet: StandardWallet,
+}
+
+impl fmt::Display for ColdcardMultisig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Name: {}", self.name)?;
+        writeln!(
+            f,
+            "Policy: {} of {}",
+            self.wallet.threshold,
+            self.wallet.keys.len()
+        )?;
+        let format = match self.wallet.kind {
+            ScriptKind::P2sh => "P2SH",
+            ScriptKind::P2shP2wsh => "P2SH-P2WSH",
+            _ => "P2WSH",
+        };
+        writeln!(f, "Format: {}", format)?;
+
+        // the derivation applies to all the keys that follow it
+        let mut derivation = None;
+        for key in &self.wallet.keys {
+            let (fingerprint, path) = key.origin.as_ref().expect("checked on creation");
+            if derivation != Some(path) {
+                writeln!(f, "\nDerivation: {}", path)?;
+                derivation = Some(path);
+            }
+            writeln!(
+                f,
+                "{}: {}",
+                fingerprint.to_string().to_uppercase(),
+                key.xkey
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ColdcardMultisig {
+    type Err = ImportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |msg: &str| ImportError::InvalidFormat(msg.to_string());
+
+        let mut name = None;
+        let mut policy = None;
+        let mut kind = ScriptKind::P2sh;
+        let mut derivation = None;
+        let mut keys = Vec::new();
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (label, value) = line
+                .split_once(':')
+                .ok_or_else(|| invalid("expected a `label: value` line"))?;
+            let value = value.trim();
+            match label.trim().to_lowercase().as_str() {
+                "name" => name = Some(value.to_string()),
+                "policy" => {
+                    let (m, n) = value
+                        .split_once("of")
+                        .ok_or_else(|| invalid("invalid policy"))?;
+                    let m = m.trim().parse::<usize>();
+                    let n = n.trim().parse::<usize>();
+                    match (m, n) {
+                        (Ok(m), Ok(n)) if m > 0 && m <= n => policy = Some((m, n)),
+                        _ => return Err(invalid("invalid policy")),
+                    }
+                }
+                "derivation" => {
+                    derivation = Some(
+                        DerivationPath::from_str(value)
+                            .map_err(|_| invalid("invalid derivation"))?,
+                    )
+                }
+                "format" => {
+                    kind = match value.to_uppercase().as_str() {
+                        "P2SH" => ScriptKind::P2sh,
+                        "P2SH-P2WSH" | "P2WSH-P2SH" => ScriptKind::P2shP2wsh,
+                        "P2WSH" => ScriptKind::P2wsh,
+                        _ => return Err(invalid("unknown format")),
+                    }
+                }
+                fingerprint => {
+                    let fingerprint =
+                        Fingerprint::from_str(fingerprint).map_err(|_| invalid("unknown label"))?;
+                    let path = derivation
+                        .clone()
+                        .ok_or_else(|| invalid("missing derivation"))?;
+                    // the script type is given by the format, whatever the version of the keys
+                    let (xkey, _) = decode_slip132(value)?;
+                    keys.push(OriginXpub {
+                        origin: Some((fingerprint, path)),
+                        xkey,
+                    });
+                }
+            }
+        }
+
+        let (threshold, n) = policy.ok_or_else(|| invalid("missing policy"))?;
+        if keys.len() != n {
+            return Err(invalid("the number of keys doesn't match the policy"));
+        }
+        Ok(ColdcardMultisig {
+            name: name.ok_or_else(|| invalid("missing name"))?,
+            wallet: StandardWallet {
+                kind,
+                threshold,
+                keys,
+            },
+        })
+    }
+}
+
+impl ColdcardMultisig {
+    /// Import the wallet
+    pub fn import(&self) -> ImportedWallet {
+        self.wallet.import(Some(self.name.clone()))
+    }
+
+    /// Export a multisig wallet
+    ///
+    /// The wallet must use `sortedmulti` with `/0/*` and `/1/*` keychains, and every key must
+    /// have its origin.
+    pub fn export_wallet<D>(wallet: &Wallet<D>, name: &str) -> Result<Self, ImportError> {
+        let wallet = StandardWallet::from_wallet(wallet)?;
+        if !wallet.kind.is_multisig() {
+            return Err(ImportError::Unsupported("the wallet isn't multisig"));
+        }
+        if wallet.keys.iter().any(|key| key.origin.is_none()) {
+            return Err(ImportError::Unsupported("the keys must have their origin"));
+        }
+        Ok(ColdcardMultisig {
+            name: name.into(),
+            wallet,
+        })
+    }
+
+    /// Return the name of the wallet
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::ToString;
+    use core::str::FromStr;
+
+    use bitcoin::Network;
+
+    use super::ColdcardMultisig;
+    use crate::wallet::Wallet;
+
+    const SETUP: &str = "\
+# Coldcard Multisig setup file (created on 73756C7F)
+#
+Name: Vault
+Policy: 2 of 2
+Format: P2SH-P2WSH
+
+Derivation: m/48'/1'/0'/1'
+73756C7F: tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3
+
+Derivation: m/48'/1'/1'/1'
+D34DB33F: tpubDEnoLuPdBep9bzw5LoGYpsxUQYheRQ9gcgrJhJEcdKFB9cWQRyYmkCyRoTqeD4tJYiVVgt6A3rN6rWn9RYhR9sBsGxji29LYWHuKKbdb1ev
+";
+
+    #[test]
+    fn test_import() {
+        let setup = ColdcardMultisig::from_str(SETUP).unwrap();
+        assert_eq!(setup.name(), "Vault");
+
+        let import = setup.import();
+        assert_eq!(
+            import.descriptor,
+            "sh(wsh(sortedmulti(2,[73756c7f/48'/1'/0'/1']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/0/*,[d34db33f/48'/1'/1'/1']tpubDEnoLuPdBep9bzw5LoGYpsxUQYheRQ9gcgrJhJEcdKFB9cWQRyYmkCyRoTqeD4tJYiVVgt6A3rN6rWn9RYhR9sBsGxji29LYWHuKKbdb1ev/0/*)))"
+        );
+        assert_eq!(import.label.as_deref(), Some("Vault"));
+
+        let wallet = import.into_wallet((), Network::Testnet).unwrap();
+        let export = ColdcardMultisig::export_wallet(&wallet, "Vault").unwrap();
+        assert_eq!(export, setup);
+        assert_eq!(
+            ColdcardMultisig::from_str(&export.to_string()).unwrap(),
+            setup
+        );
+
+        assert!(ColdcardMultisig::from_str(&SETUP.replace("2 of 2", "2 of 3")).is_err());
+        assert!(ColdcardMultisig::from_str(&SETUP.replace("Derivation", "#")).is_err());
+    }
+
+    #[test]
+    fn test_export_single_key() {
+        let wallet = Wallet::new_no_persist(
+            "wpkh([73756c7f/84'/1'/0']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/0/*)",
+            Some("wpkh([73756c7f/84'/1'/0']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/1/*)"),
+            Network::Testnet,
+        )
+        .unwrap();
+        assert!(ColdcardMultisig::export_wallet(&wallet, "Single").is_err());
+    }
+}
@@ -0,0 +1,319 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Electrum wallet files
+
+use core::fmt;
+use core::str::FromStr;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use bitcoin::bip32::{DerivationPath, Fingerprint};
+
+use super::{
+    decode_slip132, encode_slip132, ImportError, ImportedWallet, OriginXpub, ScriptKind,
+    StandardWallet,
+};
+use crate::wallet::Wallet;
+
+/// An unencrypted Electrum wallet file, of a standard or multisig wallet
+///
+/// Electrum doesn't store the script type of the wallet: it's encoded in the [SLIP132] version of
+/// its extended keys, such as `zpub` for P2WPKH or `Zpub` for P2WSH multisig.
+///
+/// [SLIP132]: https://github.com/satoshilabs/slips/blob/master/slip-0132.md
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElectrumWallet {
+    /// The type of wallet, `standard` or `MofN` for a M of N multisig
+    pub wallet_type: String,
+    /// The keystores of the wallet, a single one for standard wallets
+    pub keystores: Vec<ElectrumKeystore>,
+}
+
+/// A keystore of an [`ElectrumWallet`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ElectrumKeystore {
+    /// The kind of keystore, `bip32` for software keystores or `hardware`
+    #[serde(rename = "type")]
+    pub keystore_type: String,
+    /// The extended public key, with a SLIP132 version
+    pub xpub: String,
+    /// The extended private key, with a SLIP132 version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xprv: Option<String>,
+    /// The derivation path of the extended key from the root key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derivation: Option<String>,
+    /// The fingerprint of the root key, in hex
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_fingerprint: Option<String>,
+    /// The name of the device holding the key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+// The first version of Electrum's wallet file with `root_fingerprint`
+const SEED_VERSION: u32 = 17;
+
+impl fmt::Display for ElectrumWallet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut file = Map::new();
+        if self.wallet_type == "standard" {
+            for keystore in &self.keystores {
+                file.insert("keystore".into(), serde_json::to_value(keystore).unwrap());
+            }
+        } else {
+            for (i, keystore) in self.keystores.iter().enumerate() {
+                file.insert(
+                    format!("x{}/", i + 1),
+                    serde_json::to_value(keystore).unwrap(),
+                );
+            }
+        }
+        file.insert("seed_version".into(), SEED_VERSION.into());
+        file.insert("use_encryption".into(), false.into());
+        file.insert("wallet_type".into(), self.wallet_type.clone().into());
+        write!(f, "{}", Value::Object(file))
+    }
+}
+
+impl FromStr for ElectrumWallet {
+    type Err = ImportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut file: Map<String, Value> = serde_json::from_str(s)?;
+        if file.get("use_encryption") == Some(&Value::Bool(true)) {
+            return Err(ImportError::Unsupported("the wallet file is encrypted"));
+        }
+        let wallet_type = match file.remove("wallet_type") {
+            Some(Value::String(wallet_type)) => wallet_type,
+            _ => return Err(ImportError::InvalidFormat("missing wallet_type".into())),
+        };
+
+        let mut take_keystore = |name: &str| -> Result<ElectrumKeystore, ImportError> {
+            let keystore = file
+                .remove(name)
+                .ok_or_else(|| ImportError::InvalidFormat(format!("missing {}", name)))?;
+            Ok(serde_json::from_value(keystore)?)
+        };
+        let keystores = if wallet_type == "standard" {
+            vec![take_keystore("keystore")?]
+        } else {
+            let (_, n) = parse_multisig_type(&wallet_type)?;
+            (1..=n)
+                .map(|i| take_keystore(&format!("x{}/", i)))
+                .collect::<Result<_, _>>()?
+        };
+
+        Ok(ElectrumWallet {
+            wallet_type,
+            keystores,
+        })
+    }
+}
+
+impl ElectrumWallet {
+    /// Import the wallet, with its private keys if the file has them
+    pub fn import(&self) -> Result<ImportedWallet, ImportError> {
+        let (threshold, multisig) = match self.wallet_type.as_str() {
+            "standard" => (1, false),
+            wallet_type => (parse_multisig_type(wallet_type)?.0, true),
+        };
+
+        let mut kind = None;
+        let mut keys = Vec::with_capacity(self.keystores.len());
+        for keystore in &self.keystores {
+            let (xkey, key_kind) =
+                decode_slip132(keystore.xprv.as_ref().unwrap_or(&keystore.xpub))?;
+            // multisig wallets use the single-key version for legacy P2SH
+            let key_kind = match (multisig, key_kind) {
+                (false, ScriptKind::P2pkh | ScriptKind::P2shP2wpkh | ScriptKind::P2wpkh)
+                | (true, ScriptKind::P2shP2wsh | ScriptKind::P2wsh) => key_kind,
+                (true, ScriptKind::P2pkh) => ScriptKind::P2sh,
+                _ => {
+                    return Err(ImportError::Unsupported(
+                        "the key version doesn't match the wallet type",
+                    ))
+                }
+            };
+            if kind
+                .replace(key_kind)
+                .map_or(false, |kind| kind != key_kind)
+            {
+                return Err(ImportError::Unsupported(
+                    "the keys have different script types",
+                ));
+            }
+
+            let origin = match (&keystore.root_fingerprint, &keystore.derivation) {
+                (Some(fingerprint), Some(derivation)) => Some((
+                    Fingerprint::from_str(fingerprint)
+                        .map_err(|e| ImportError::InvalidFormat(e.to_string()))?,
+                    DerivationPath::from_str(derivation)
+                        .map_err(|e| ImportError::InvalidFormat(e.to_string()))?,
+                )),
+                _ => None,
+            };
+            keys.push(OriginXpub { origin, xkey });
+        }
+
+        let kind = kind.ok_or_else(|| ImportError::InvalidFormat("no keystore".into()))?;
+        Ok(StandardWallet {
+            kind,
+            threshold,
+            keys,
+        }
+        .import(None))
+    }
+
+    /// Export a wallet as a watch-only Electrum wallet
+    ///
+    /// The wallet must be single-key or `sortedmulti`, with `/0/*` and `/1/*` keychains, as
+    /// created by Electrum. Taproot isn't supported.
+    pub fn export_wallet<D>(wallet: &Wallet<D>) -> Result<Self, ImportError> {
+        let standard = StandardWallet::from_wallet(wallet)?;
+        let wallet_type = if standard.kind.is_multisig() {
+            format!("{}of{}", standard.threshold, standard.keys.len())
+        } else {
+            "standard".into()
+        };
+
+        let keystores = standard
+            .keys
+            .iter()
+            .map(|key| {
+                Ok(ElectrumKeystore {
+                    keystore_type: "bip32".into(),
+                    xpub: encode_slip132(&key.xkey, standard.kind)?,
+                    xprv: None,
+                    derivation: key.origin.as_ref().map(|(_, path)| path.to_string()),
+                    root_fingerprint: key
+                        .origin
+                        .as_ref()
+                        .map(|(fingerprint, _)| fingerprint.to_string()),
+                    label: None,
+                })
+            })
+            .collect::<Result<_, ImportError>>()?;
+
+        Ok(ElectrumWallet {
+            wallet_type,
+            keystores,
+        })
+    }
+}
+
+// Parse a `MofN` wallet type
+fn parse_multisig_type(wallet_type: &str) -> Result<(usize, usize), ImportError> {
+    let invalid = || ImportError::InvalidFormat(format!("unknown wallet type {}", wallet_type));
+    let (m, n) = wallet_type.split_once("of").ok_or_else(invalid)?;
+    let m = m.parse::<usize>().map_err(|_| invalid())?;
+    let n = n.parse::<usize>().map_err(|_| invalid())?;
+    if m == 0 || m > n {
+        return Err(invalid());
+    }
+    Ok((m, n))
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::ToString;
+    use core::str::FromStr;
+
+    use bitcoin::Network;
+
+    use super::ElectrumWallet;
+    use crate::wallet::Wallet;
+    use crate::KeychainKind;
+
+    #[test]
+    fn test_standard() {
+        // a testnet P2WPKH wallet, with a `vprv`
+        let file = r#"{
+            "keystore": {
+                "type": "bip32",
+                "xpub": "vpub5XznHkaxFPYPPiNL1JbexbikP9UuXx34yM4oDf8zHVty2mTo7jcfCe6XnWBLnJwaNmrZ2CUpXc4tkD342SDqe1HizZwTNsf9KzL4r1YGQ66",
+                "xprv": "vprv9K1RtF44R1z6BEHruH4ebTn1q7eR8VKDc89CRGjNjAMz9y8eaCJQeqn3wEeYejhoL32EEXjJKpfBZBSsfaH4uV5skos6tETs1ytkvBQfE7t",
+                "derivation": "m/84'/1'/0'",
+                "root_fingerprint": "b6dff990",
+                "label": ""
+            },
+            "seed_version": 52,
+            "use_encryption": false,
+            "wallet_type": "standard"
+        }"#;
+        let electrum = ElectrumWallet::from_str(file).unwrap();
+        let import = electrum.import().unwrap();
+        assert!(import
+            .descriptor
+            .starts_with("wpkh([b6dff990/84'/1'/0']tprv"));
+        assert!(import.descriptor.ends_with("/0/*)"));
+        assert!(import
+            .change_descriptor
+            .as_ref()
+            .unwrap()
+            .ends_with("/1/*)"));
+
+        let wallet = import.into_wallet((), Network::Testnet).unwrap();
+        let export = ElectrumWallet::export_wallet(&wallet).unwrap();
+        assert_eq!(export.wallet_type, "standard");
+        assert_eq!(export.keystores[0].xpub, electrum.keystores[0].xpub);
+        assert_eq!(export.keystores[0].xprv, None);
+        assert_eq!(
+            export.keystores[0].derivation.as_deref(),
+            Some("m/84'/1'/0'")
+        );
+
+        let exported = ElectrumWallet::from_str(&export.to_string()).unwrap();
+        assert_eq!(exported, export);
+    }
+
+    #[test]
+    fn test_multisig() {
+        let wallet = Wallet::new_no_persist(
+            "wsh(sortedmulti(2,[73756c7f/48'/1'/0'/2']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/0/*,[d34db33f/48'/1'/0'/2']tpubDEnoLuPdBep9bzw5LoGYpsxUQYheRQ9gcgrJhJEcdKFB9cWQRyYmkCyRoTqeD4tJYiVVgt6A3rN6rWn9RYhR9sBsGxji29LYWHuKKbdb1ev/0/*))",
+            Some("wsh(sortedmulti(2,[73756c7f/48'/1'/0'/2']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/1/*,[d34db33f/48'/1'/0'/2']tpubDEnoLuPdBep9bzw5LoGYpsxUQYheRQ9gcgrJhJEcdKFB9cWQRyYmkCyRoTqeD4tJYiVVgt6A3rN6rWn9RYhR9sBsGxji29LYWHuKKbdb1ev/1/*))"),
+            Network::Testnet,
+        )
+        .unwrap();
+
+        let export = ElectrumWallet::export_wallet(&wallet).unwrap();
+        assert_eq!(export.wallet_type, "2of2");
+        assert!(export.keystores[0].xpub.starts_with("Vpub"));
+
+        let file = export.to_string();
+        assert!(file.contains("\"x2/\""));
+        let imported = ElectrumWallet::from_str(&file)
+            .unwrap()
+            .import()
+            .unwrap()
+            .into_wallet((), Network::Testnet)
+            .unwrap();
+        assert_eq!(
+            imported.public_descriptor(KeychainKind::External),
+            wallet.public_descriptor(KeychainKind::External)
+        );
+        assert_eq!(
+            imported.public_descriptor(KeychainKind::Internal),
+            wallet.public_descriptor(KeychainKind::Internal)
+        );
+
+        let single_key = Wallet::new_no_persist(
+            "tr([73756c7f/86'/1'/0']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/0/*)",
+            Some("tr([73756c7f/86'/1'/0']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/1/*)"),
+            Network::Testnet,
+        )
+        .unwrap();
+        assert!(ElectrumWallet::export_wallet(&single_key).is_err());
+    }
+}
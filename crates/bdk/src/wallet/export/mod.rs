@@ -0,0 +1,731 @@
+// Bitcoin Dev Kit
+// Written in 2020 by Alekos Filini <alekos.filini@gmail.com>
+//
+// Copyright (c) 2020-2021 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Wallet export
+//!
+//! This modules implements the wallet export format used by [FullyNoded](https://github.com/Fonta1n3/FullyNoded/blob/10b7808c8b929b171cca537fb50522d015168ac9/Docs/Wallets/Wallet-Export-Spec.md).
+//!
+//! ## Examples
+//!
+//! ### Import from JSON
+//!
+//! ```
+//! # use std::str::FromStr;
+//! # use bitcoin::*;
+//! # use bdk::wallet::export::*;
+//! # use bdk::*;
+//! let import = r#"{
+//!     "descriptor": "wpkh([c258d2e4\/84h\/1h\/0h]tpubDD3ynpHgJQW8VvWRzQ5WFDCrs4jqVFGHB3vLC3r49XHJSqP8bHKdK4AriuUKLccK68zfzowx7YhmDN8SiSkgCDENUFx9qVw65YyqM78vyVe\/0\/*)",
+//!     "blockheight":1782088,
+//!     "label":"testnet"
+//! }"#;
+//!
+//! let import = FullyNodedExport::from_str(import)?;
+//! let wallet = Wallet::new_no_persist(
+//!     &import.descriptor(),
+//!     import.change_descriptor().as_ref(),
+//!     Network::Testnet,
+//! )?;
+//! # Ok::<_, Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! ### Export a `Wallet`
+//! ```
+//! # use bitcoin::*;
+//! # use bdk::wallet::export::*;
+//! # use bdk::*;
+//! let wallet = Wallet::new_no_persist(
+//!     "wpkh([c258d2e4/84h/1h/0h]tpubDD3ynpHgJQW8VvWRzQ5WFDCrs4jqVFGHB3vLC3r49XHJSqP8bHKdK4AriuUKLccK68zfzowx7YhmDN8SiSkgCDENUFx9qVw65YyqM78vyVe/0/*)",
+//!     Some("wpkh([c258d2e4/84h/1h/0h]tpubDD3ynpHgJQW8VvWRzQ5WFDCrs4jqVFGHB3vLC3r49XHJSqP8bHKdK4AriuUKLccK68zfzowx7YhmDN8SiSkgCDENUFx9qVw65YyqM78vyVe/1/*)"),
+//!     Network::Testnet,
+//! )?;
+//! let export = FullyNodedExport::export_wallet(&wallet, "exported wallet", true).unwrap();
+//!
+//! println!("Exported: {}", export.to_string());
+//! # Ok::<_, Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! ## Other formats
+//!
+//! Wallets can also be imported from and exported to the formats of other wallets:
+//!
+//! * [`CoreDescriptors`]: the output of Bitcoin Core's `listdescriptors`
+//! * [`SpecterExport`]: the wallet JSON of Specter Desktop, also read and written by Sparrow
+//! * [`ElectrumWallet`]: unencrypted Electrum wallet files, standard and multisig
+//! * [`ColdcardMultisig`]: Coldcard multisig setup files
+//!
+//! Importing produces an [`ImportedWallet`], which creates the [`Wallet`] with the right
+//! keychains, and the birthday when the format has one:
+//!
+//! ```
+//! # use std::str::FromStr;
+//! # use bitcoin::*;
+//! # use bdk::wallet::export::*;
+//! let setup = "\
+//! Name: Vault
+//! Policy: 2 of 2
+//! Derivation: m/48'/1'/0'/2'
+//! Format: P2WSH
+//!
+//! 73756C7F: tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3
+//! D34DB33F: tpubDEnoLuPdBep9bzw5LoGYpsxUQYheRQ9gcgrJhJEcdKFB9cWQRyYmkCyRoTqeD4tJYiVVgt6A3rN6rWn9RYhR9sBsGxji29LYWHuKKbdb1ev
+//! ";
+//! let import = ColdcardMultisig::from_str(setup)?.import();
+//! let wallet = import.into_wallet((), Network::Testnet)?;
+//! # Ok::<_, Box<dyn std::error::Error>>(())
+//! ```
+
+use core::fmt;
+use core::str::FromStr;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use bdk_chain::PersistBackend;
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint};
+use bitcoin::{base58, Network};
+use miniscript::descriptor::{DescriptorPublicKey, ShInner, SortedMultiVec, Wildcard, WshInner};
+use miniscript::{Descriptor, ScriptContext, Terminal};
+
+use crate::types::KeychainKind;
+use crate::wallet::{Birthday, ChangeSet, NewError, Wallet};
+
+mod bitcoin_core;
+mod coldcard;
+mod electrum;
+mod specter;
+
+pub use bitcoin_core::{CoreDescriptor, CoreDescriptors};
+pub use coldcard::ColdcardMultisig;
+pub use electrum::{ElectrumKeystore, ElectrumWallet};
+pub use specter::{SpecterDevice, SpecterExport};
+
+/// Alias for [`FullyNodedExport`]
+#[deprecated(since = "0.18.0", note = "Please use [`FullyNodedExport`] instead")]
+pub type WalletExport = FullyNodedExport;
+
+/// Structure that contains the export of a wallet
+///
+/// For a usage example see [this module](crate::wallet::export)'s documentation.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FullyNodedExport {
+    descriptor: String,
+    /// Earliest block to rescan when looking for the wallet's transactions
+    pub blockheight: u32,
+    /// Arbitrary label for the wallet
+    pub label: String,
+}
+
+impl ToString for FullyNodedExport {
+    fn to_string(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl FromStr for FullyNodedExport {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+fn remove_checksum(s: String) -> String {
+    s.split_once('#').map(|(a, _)| String::from(a)).unwrap()
+}
+
+impl FullyNodedExport {
+    /// Export a wallet
+    ///
+    /// This function returns an error if it determines that the `wallet`'s descriptor(s) are not
+    /// supported by Bitcoin Core or don't follow the standard derivation paths defined by BIP44
+    /// and others.
+    ///
+    /// If `include_blockheight` is `true`, this function will look into the `wallet`'s database
+    /// for the oldest transaction it knows and use that as the earliest block to rescan.
+    ///
+    /// If the database is empty or `include_blockheight` is false, the `blockheight` field
+    /// returned will be `0`.
+    pub fn export_wallet<D>(
+        wallet: &Wallet<D>,
+        label: &str,
+        include_blockheight: bool,
+    ) -> Result<Self, &'static str> {
+        let descriptor = wallet
+            .get_descriptor_for_keychain(KeychainKind::External)
+            .to_string_with_secret(
+                &wallet
+                    .get_signers(KeychainKind::External)
+                    .as_key_map(wallet.secp_ctx()),
+            );
+        let descriptor = remove_checksum(descriptor);
+        Self::is_compatible_with_core(&descriptor)?;
+
+        let blockheight = if include_blockheight {
+            wallet.transactions().next().map_or(0, |canonical_tx| {
+                match canonical_tx.chain_position {
+                    bdk_chain::ChainPosition::Confirmed(a) => a.confirmation_height,
+                    bdk_chain::ChainPosition::Unconfirmed(_) => 0,
+                }
+            })
+        } else {
+            0
+        };
+
+        let export = FullyNodedExport {
+            descriptor,
+            label: label.into(),
+            blockheight,
+        };
+
+        let change_descriptor = match wallet.public_descriptor(KeychainKind::Internal).is_some() {
+            false => None,
+            true => {
+                let descriptor = wallet
+                    .get_descriptor_for_keychain(KeychainKind::Internal)
+                    .to_string_with_secret(
+                        &wallet
+                            .get_signers(KeychainKind::Internal)
+                            .as_key_map(wallet.secp_ctx()),
+                    );
+                Some(remove_checksum(descriptor))
+            }
+        };
+        if export.change_descriptor() != change_descriptor {
+            return Err("Incompatible change descriptor");
+        }
+
+        Ok(export)
+    }
+
+    fn is_compatible_with_core(descriptor: &str) -> Result<(), &'static str> {
+        fn check_ms<Ctx: ScriptContext>(
+            terminal: &Terminal<String, Ctx>,
+        ) -> Result<(), &'static str> {
+            if let Terminal::Multi(_, _) = terminal {
+                Ok(())
+            } else {
+                Err("The descriptor contains operators not supported by Bitcoin Core")
+            }
+        }
+
+        // pkh(), wpkh(), sh(wpkh()) are always fine, as well as multi() and sortedmulti()
+        match Descriptor::<String>::from_str(descriptor).map_err(|_| "Invalid descriptor")? {
+            Descriptor::Pkh(_) | Descriptor::Wpkh(_) => Ok(()),
+            Descriptor::Sh(sh) => match sh.as_inner() {
+                ShInner::Wpkh(_) => Ok(()),
+                ShInner::SortedMulti(_) => Ok(()),
+                ShInner::Wsh(wsh) => match wsh.as_inner() {
+                    WshInner::SortedMulti(_) => Ok(()),
+                    WshInner::Ms(ms) => check_ms(&ms.node),
+                },
+                ShInner::Ms(ms) => check_ms(&ms.node),
+            },
+            Descriptor::Wsh(wsh) => match wsh.as_inner() {
+                WshInner::SortedMulti(_) => Ok(()),
+                WshInner::Ms(ms) => check_ms(&ms.node),
+            },
+            _ => Err("The descriptor is not compatible with Bitcoin Core"),
+        }
+    }
+
+    /// Return the external descriptor
+    pub fn descriptor(&self) -> String {
+        self.descriptor.clone()
+    }
+
+    /// Return the internal descriptor, if present
+    pub fn change_descriptor(&self) -> Option<String> {
+        let replaced = self.descriptor.replace("/0/*", "/1/*");
+
+        if replaced != self.descriptor {
+            Some(replaced)
+        } else {
+            None
+        }
+    }
+}
+
+/// Errors related to importing and exporting wallets
+#[derive(Debug)]
+pub enum ImportError {
+    /// The file isn't valid JSON, or doesn't have the expected fields
+    Json(serde_json::Error),
+    /// The file is malformed
+    InvalidFormat(String),
+    /// An extended key can't be decoded
+    InvalidKey(String),
+    /// The wallet can't be represented in the format
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(err) => write!(f, "Invalid JSON: {}", err),
+            Self::InvalidFormat(err) => write!(f, "Invalid format: {}", err),
+            Self::InvalidKey(key) => write!(f, "Invalid extended key: {}", key),
+            Self::Unsupported(reason) => write!(f, "Unsupported wallet: {}", reason),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ImportError {}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(err: serde_json::Error) -> Self {
+        ImportError::Json(err)
+    }
+}
+
+/// A wallet imported from one of the supported formats
+///
+/// For a usage example see [this module](crate::wallet::export)'s documentation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedWallet {
+    /// The external descriptor
+    pub descriptor: String,
+    /// The internal descriptor, if the wallet has one
+    pub change_descriptor: Option<String>,
+    /// The earliest point in the chain where the wallet may have history, if known
+    pub birthday: Option<Birthday>,
+    /// The name of the wallet, if any
+    pub label: Option<String>,
+}
+
+impl ImportedWallet {
+    /// Create the wallet, loading its data from `db`
+    ///
+    /// The birthday is only set if `db` doesn't have one already, and must then be committed.
+    pub fn into_wallet<D>(
+        self,
+        db: D,
+        network: Network,
+    ) -> Result<Wallet<D>, NewError<D::LoadError>>
+    where
+        D: PersistBackend<ChangeSet>,
+    {
+        let mut wallet = Wallet::new(
+            self.descriptor.as_str(),
+            self.change_descriptor.as_deref(),
+            db,
+            network,
+        )?;
+        if let (Some(birthday), None) = (self.birthday, wallet.birthday()) {
+            wallet.set_birthday(birthday);
+        }
+        Ok(wallet)
+    }
+}
+
+// The descriptor of `keychain`, with its private keys
+fn descriptor_with_secret<D>(wallet: &Wallet<D>, keychain: KeychainKind) -> Option<String> {
+    wallet.public_descriptor(keychain).map(|descriptor| {
+        descriptor
+            .to_string_with_secret(&wallet.get_signers(keychain).as_key_map(wallet.secp_ctx()))
+    })
+}
+
+// The height and time of the earliest block with a transaction of the wallet
+fn first_confirmation<D>(wallet: &Wallet<D>) -> Option<(u32, u64)> {
+    wallet
+        .transactions()
+        .filter_map(|canonical_tx| match canonical_tx.chain_position {
+            bdk_chain::ChainPosition::Confirmed(anchor) => {
+                Some((anchor.confirmation_height, anchor.confirmation_time))
+            }
+            bdk_chain::ChainPosition::Unconfirmed(_) => None,
+        })
+        .min()
+}
+
+// The script types that Electrum and Coldcard can represent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScriptKind {
+    P2pkh,
+    P2shP2wpkh,
+    P2wpkh,
+    P2sh,
+    P2shP2wsh,
+    P2wsh,
+}
+
+impl ScriptKind {
+    fn is_multisig(&self) -> bool {
+        matches!(
+            self,
+            ScriptKind::P2sh | ScriptKind::P2shP2wsh | ScriptKind::P2wsh
+        )
+    }
+}
+
+// An extended public key with its origin, as it appears in Electrum and Coldcard files
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct OriginXpub {
+    origin: Option<(Fingerprint, DerivationPath)>,
+    // base58-encoded with the standard `xpub`/`tpub` (or `xprv`/`tprv`) version
+    xkey: String,
+}
+
+impl OriginXpub {
+    fn descriptor_key(&self, chain: u32) -> String {
+        match &self.origin {
+            Some((fingerprint, path)) => {
+                let path = path.to_string();
+                let path = path.trim_start_matches('m');
+                format!("[{}{}]{}/{}/*", fingerprint, path, self.xkey, chain)
+            }
+            None => format!("{}/{}/*", self.xkey, chain),
+        }
+    }
+}
+
+// A single-key or `sortedmulti` wallet, with `/0/*` and `/1/*` keychains
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StandardWallet {
+    kind: ScriptKind,
+    threshold: usize,
+    keys: Vec<OriginXpub>,
+}
+
+impl StandardWallet {
+    fn descriptor(&self, chain: u32) -> String {
+        let keys = self
+            .keys
+            .iter()
+            .map(|key| key.descriptor_key(chain))
+            .collect::<Vec<_>>();
+        let multi = format!("sortedmulti({},{})", self.threshold, keys.join(","));
+        match self.kind {
+            ScriptKind::P2pkh => format!("pkh({})", keys[0]),
+            ScriptKind::P2shP2wpkh => format!("sh(wpkh({}))", keys[0]),
+            ScriptKind::P2wpkh => format!("wpkh({})", keys[0]),
+            ScriptKind::P2sh => format!("sh({})", multi),
+            ScriptKind::P2shP2wsh => format!("sh(wsh({}))", multi),
+            ScriptKind::P2wsh => format!("wsh({})", multi),
+        }
+    }
+
+    fn import(&self, label: Option<String>) -> ImportedWallet {
+        ImportedWallet {
+            descriptor: self.descriptor(0),
+            change_descriptor: Some(self.descriptor(1)),
+            birthday: None,
+            label,
+        }
+    }
+
+    // The structure of the public descriptors of `wallet`, which must have both keychains
+    fn from_wallet<D>(wallet: &Wallet<D>) -> Result<Self, ImportError> {
+        let unsupported =
+            "the wallet must be single-key or sortedmulti, with /0/* and /1/* keychains";
+        let external = wallet
+            .public_descriptor(KeychainKind::External)
+            .ok_or(ImportError::Unsupported(unsupported))?;
+        let internal = wallet
+            .public_descriptor(KeychainKind::Internal)
+            .ok_or(ImportError::Unsupported(unsupported))?;
+
+        let standard =
+            Self::from_descriptor(external, 0).ok_or(ImportError::Unsupported(unsupported))?;
+        if Self::from_descriptor(internal, 1) != Some(standard.clone()) {
+            return Err(ImportError::Unsupported(unsupported));
+        }
+        Ok(standard)
+    }
+
+    fn from_descriptor(descriptor: &Descriptor<DescriptorPublicKey>, chain: u32) -> Option<Self> {
+        fn multi<Ctx: ScriptContext>(
+            kind: ScriptKind,
+            multi: &SortedMultiVec<DescriptorPublicKey, Ctx>,
+            chain: u32,
+        ) -> Option<StandardWallet> {
+            Some(StandardWallet {
+                kind,
+                threshold: multi.k,
+                keys: multi
+                    .pks
+                    .iter()
+                    .map(|pk| origin_xpub(pk, chain))
+                    .collect::<Option<_>>()?,
+            })
+        }
+        let single = |kind, pk| {
+            Some(StandardWallet {
+                kind,
+                threshold: 1,
+                keys: vec![origin_xpub(pk, chain)?],
+            })
+        };
+
+        match descriptor {
+            Descriptor::Pkh(pkh) => single(ScriptKind::P2pkh, pkh.as_inner()),
+            Descriptor::Wpkh(wpkh) => single(ScriptKind::P2wpkh, wpkh.as_inner()),
+            Descriptor::Sh(sh) => match sh.as_inner() {
+                ShInner::Wpkh(wpkh) => single(ScriptKind::P2shP2wpkh, wpkh.as_inner()),
+                ShInner::SortedMulti(sorted) => multi(ScriptKind::P2sh, sorted, chain),
+                ShInner::Wsh(wsh) => match wsh.as_inner() {
+                    WshInner::SortedMulti(sorted) => multi(ScriptKind::P2shP2wsh, sorted, chain),
+                    _ => None,
+                },
+                _ => None,
+            },
+            Descriptor::Wsh(wsh) => match wsh.as_inner() {
+                WshInner::SortedMulti(sorted) => multi(ScriptKind::P2wsh, sorted, chain),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+// The extended key of `pk`, if it's derived at `chain/*`
+fn origin_xpub(pk: &DescriptorPublicKey, chain: u32) -> Option<OriginXpub> {
+    match pk {
+        DescriptorPublicKey::XPub(xpub)
+            if xpub.wildcard == Wildcard::Unhardened
+                && xpub.derivation_path
+                    == DerivationPath::from(vec![ChildNumber::Normal { index: chain }]) =>
+        {
+            Some(OriginXpub {
+                origin: xpub.origin.clone(),
+                xkey: xpub.xkey.to_string(),
+            })
+        }
+        _ => None,
+    }
+}
+
+// The SLIP132 versions of extended keys, which encode the script type: `(version, is_private,
+// is_mainnet, script kind)`. Multisig versions only differ for segwit.
+const SLIP132_VERSIONS: &[([u8; 4], bool, bool, ScriptKind)] = &[
+    ([0x04, 0x88, 0xb2, 0x1e], false, true, ScriptKind::P2pkh),
+    (
+        [0x04, 0x9d, 0x7c, 0xb2],
+        false,
+        true,
+        ScriptKind::P2shP2wpkh,
+    ),
+    ([0x04, 0xb2, 0x47, 0x46], false, true, ScriptKind::P2wpkh),
+    ([0x02, 0x95, 0xb4, 0x3f], false, true, ScriptKind::P2shP2wsh),
+    ([0x02, 0xaa, 0x7e, 0xd3], false, true, ScriptKind::P2wsh),
+    ([0x04, 0x88, 0xad, 0xe4], true, true, ScriptKind::P2pkh),
+    ([0x04, 0x9d, 0x78, 0x78], true, true, ScriptKind::P2shP2wpkh),
+    ([0x04, 0xb2, 0x43, 0x0c], true, true, ScriptKind::P2wpkh),
+    ([0x02, 0x95, 0xb0, 0x05], true, true, ScriptKind::P2shP2wsh),
+    ([0x02, 0xaa, 0x7a, 0x99], true, true, ScriptKind::P2wsh),
+    ([0x04, 0x35, 0x87, 0xcf], false, false, ScriptKind::P2pkh),
+    (
+        [0x04, 0x4a, 0x52, 0x62],
+        false,
+        false,
+        ScriptKind::P2shP2wpkh,
+    ),
+    ([0x04, 0x5f, 0x1c, 0xf6], false, false, ScriptKind::P2wpkh),
+    (
+        [0x02, 0x42, 0x89, 0xef],
+        false,
+        false,
+        ScriptKind::P2shP2wsh,
+    ),
+    ([0x02, 0x57, 0x54, 0x83], false, false, ScriptKind::P2wsh),
+    ([0x04, 0x35, 0x83, 0x94], true, false, ScriptKind::P2pkh),
+    (
+        [0x04, 0x4a, 0x4e, 0x28],
+        true,
+        false,
+        ScriptKind::P2shP2wpkh,
+    ),
+    ([0x04, 0x5f, 0x18, 0xbc], true, false, ScriptKind::P2wpkh),
+    ([0x02, 0x42, 0x85, 0xb5], true, false, ScriptKind::P2shP2wsh),
+    ([0x02, 0x57, 0x50, 0x48], true, false, ScriptKind::P2wsh),
+];
+
+// Decode a SLIP132 extended key, returning it with the standard version and the script type it
+// is for
+fn decode_slip132(key: &str) -> Result<(String, ScriptKind), ImportError> {
+    let invalid = || ImportError::InvalidKey(key.to_string());
+    let mut data = base58::decode_check(key).map_err(|_| invalid())?;
+    if data.len() != 78 {
+        return Err(invalid());
+    }
+    let &(_, is_private, is_mainnet, kind) = SLIP132_VERSIONS
+        .iter()
+        .find(|(version, _, _, _)| data[..4] == version[..])
+        .ok_or_else(invalid)?;
+    let standard = SLIP132_VERSIONS
+        .iter()
+        .find(|&&(_, private, mainnet, standard_kind)| {
+            private == is_private && mainnet == is_mainnet && standard_kind == ScriptKind::P2pkh
+        })
+        .expect("the standard versions are in the table");
+    data[..4].copy_from_slice(&standard.0);
+    Ok((base58::encode_check(&data), kind))
+}
+
+// Encode a standard extended key with the SLIP132 version for `kind`
+fn encode_slip132(key: &str, kind: ScriptKind) -> Result<String, ImportError> {
+    let kind = match kind {
+        ScriptKind::P2sh => ScriptKind::P2pkh,
+        kind => kind,
+    };
+    let (_, standard_kind) = decode_slip132(key)?;
+    if standard_kind != ScriptKind::P2pkh {
+        return Err(ImportError::InvalidKey(key.to_string()));
+    }
+    let mut data =
+        base58::decode_check(key).map_err(|_| ImportError::InvalidKey(key.to_string()))?;
+    let &(_, is_private, is_mainnet, _) = SLIP132_VERSIONS
+        .iter()
+        .find(|(version, _, _, _)| data[..4] == version[..])
+        .expect("the key was decoded");
+    let version = SLIP132_VERSIONS
+        .iter()
+        .find(|&&(_, private, mainnet, version_kind)| {
+            private == is_private && mainnet == is_mainnet && version_kind == kind
+        })
+        .ok_or(ImportError::Unsupported(
+            "no SLIP132 version for this script type",
+        ))?;
+    data[..4].copy_from_slice(&version.0);
+    Ok(base58::encode_check(&data))
+}
+
+#[cfg(test)]
+mod test {
+    use core::str::FromStr;
+
+    use bdk_chain::{BlockId, ConfirmationTime};
+    use bitcoin::hashes::Hash;
+    use bitcoin::{BlockHash, Network, Transaction};
+
+    use super::*;
+    use crate::wallet::Wallet;
+
+    fn get_test_wallet(
+        descriptor: &str,
+        change_descriptor: Option<&str>,
+        network: Network,
+    ) -> Wallet<()> {
+        let mut wallet = Wallet::new_no_persist(descriptor, change_descriptor, network).unwrap();
+        let transaction = Transaction {
+            input: vec![],
+            output: vec![],
+            version: 0,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+        };
+        wallet
+            .insert_checkpoint(BlockId {
+                height: 5001,
+                hash: BlockHash::all_zeros(),
+            })
+            .unwrap();
+        wallet
+            .insert_tx(
+                transaction,
+                ConfirmationTime::Confirmed {
+                    height: 5000,
+                    time: 0,
+                },
+            )
+            .unwrap();
+        wallet
+    }
+
+    #[test]
+    fn test_export_bip44() {
+        let descriptor = "wpkh(xprv9s21ZrQH143K4CTb63EaMxja1YiTnSEWKMbn23uoEnAzxjdUJRQkazCAtzxGm4LSoTSVTptoV9RbchnKPW9HxKtZumdyxyikZFDLhogJ5Uj/44'/0'/0'/0/*)";
+        let change_descriptor = "wpkh(xprv9s21ZrQH143K4CTb63EaMxja1YiTnSEWKMbn23uoEnAzxjdUJRQkazCAtzxGm4LSoTSVTptoV9RbchnKPW9HxKtZumdyxyikZFDLhogJ5Uj/44'/0'/0'/1/*)";
+
+        let wallet = get_test_wallet(descriptor, Some(change_descriptor), Network::Bitcoin);
+        let export = FullyNodedExport::export_wallet(&wallet, "Test Label", true).unwrap();
+
+        assert_eq!(export.descriptor(), descriptor);
+        assert_eq!(export.change_descriptor(), Some(change_descriptor.into()));
+        assert_eq!(export.blockheight, 5000);
+        assert_eq!(export.label, "Test Label");
+    }
+
+    #[test]
+    #[should_panic(expected = "Incompatible change descriptor")]
+    fn test_export_no_change() {
+        // This wallet explicitly doesn't have a change descriptor. It should be impossible to
+        // export, because exporting this kind of external descriptor normally implies the
+        // existence of an internal descriptor
+
+        let descriptor = "wpkh(xprv9s21ZrQH143K4CTb63EaMxja1YiTnSEWKMbn23uoEnAzxjdUJRQkazCAtzxGm4LSoTSVTptoV9RbchnKPW9HxKtZumdyxyikZFDLhogJ5Uj/44'/0'/0'/0/*)";
+
+        let wallet = get_test_wallet(descriptor, None, Network::Bitcoin);
+        FullyNodedExport::export_wallet(&wallet, "Test Label", true).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Incompatible change descriptor")]
+    fn test_export_incompatible_change() {
+        // This wallet has a change descriptor, but the derivation path is not in the "standard"
+        // bip44/49/etc format
+
+        let descriptor = "wpkh(xprv9s21ZrQH143K4CTb63EaMxja1YiTnSEWKMbn23uoEnAzxjdUJRQkazCAtzxGm4LSoTSVTptoV9RbchnKPW9HxKtZumdyxyikZFDLhogJ5Uj/44'/0'/0'/0/*)";
+        let change_descriptor = "wpkh(xprv9s21ZrQH143K4CTb63EaMxja1YiTnSEWKMbn23uoEnAzxjdUJRQkazCAtzxGm4LSoTSVTptoV9RbchnKPW9HxKtZumdyxyikZFDLhogJ5Uj/50'/0'/1/*)";
+
+        let wallet = get_test_wallet(descriptor, Some(change_descriptor), Network::Bitcoin);
+        FullyNodedExport::export_wallet(&wallet, "Test Label", true).unwrap();
+    }
+
+    #[test]
+    fn test_export_multi() {
+        let descriptor = "wsh(multi(2,\
+                                [73756c7f/48'/0'/0'/2']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/0/*,\
+                                [f9f62194/48'/0'/0'/2']tpubDDp3ZSH1yCwusRppH7zgSxq2t1VEUyXSeEp8E5aFS8m43MknUjiF1bSLo3CGWAxbDyhF1XowA5ukPzyJZjznYk3kYi6oe7QxtX2euvKWsk4/0/*,\
+                                [c98b1535/48'/0'/0'/2']tpubDCDi5W4sP6zSnzJeowy8rQDVhBdRARaPhK1axABi8V1661wEPeanpEXj4ZLAUEoikVtoWcyK26TKKJSecSfeKxwHCcRrge9k1ybuiL71z4a/0/*\
+                          ))";
+        let change_descriptor = "wsh(multi(2,\
+                                       [73756c7f/48'/0'/0'/2']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/1/*,\
+                                       [f9f62194/48'/0'/0'/2']tpubDDp3ZSH1yCwusRppH7zgSxq2t1VEUyXSeEp8E5aFS8m43MknUjiF1bSLo3CGWAxbDyhF1XowA5ukPzyJZjznYk3kYi6oe7QxtX2euvKWsk4/1/*,\
+                                       [c98b1535/48'/0'/0'/2']tpubDCDi5W4sP6zSnzJeowy8rQDVhBdRARaPhK1axABi8V1661wEPeanpEXj4ZLAUEoikVtoWcyK26TKKJSecSfeKxwHCcRrge9k1ybuiL71z4a/1/*\
+                                 ))";
+
+        let wallet = get_test_wallet(descriptor, Some(change_descriptor), Network::Testnet);
+        let export = FullyNodedExport::export_wallet(&wallet, "Test Label", true).unwrap();
+
+        assert_eq!(export.descriptor(), descriptor);
+        assert_eq!(export.change_descriptor(), Some(change_descriptor.into()));
+        assert_eq!(export.blockheight, 5000);
+        assert_eq!(export.label, "Test Label");
+    }
+
+    #[test]
+    fn test_export_to_json() {
+        let descriptor = "wpkh(xprv9s21ZrQH143K4CTb63EaMxja1YiTnSEWKMbn23uoEnAzxjdUJRQkazCAtzxGm4LSoTSVTptoV9RbchnKPW9HxKtZumdyxyikZFDLhogJ5Uj/44'/0'/0'/0/*)";
+        let change_descriptor = "wpkh(xprv9s21ZrQH143K4CTb63EaMxja1YiTnSEWKMbn23uoEnAzxjdUJRQkazCAtzxGm4LSoTSVTptoV9RbchnKPW9HxKtZumdyxyikZFDLhogJ5Uj/44'/0'/0'/1/*)";
+
+        let wallet = get_test_wallet(descriptor, Some(change_descriptor), Network::Bitcoin);
+        let export = FullyNodedExport::export_wallet(&wallet, "Test Label", true).unwrap();
+
+        assert_eq!(export.to_string(), "{\"descriptor\":\"wpkh(xprv9s21ZrQH143K4CTb63EaMxja1YiTnSEWKMbn23uoEnAzxjdUJRQkazCAtzxGm4LSoTSVTptoV9RbchnKPW9HxKtZumdyxyikZFDLhogJ5Uj/44\'/0\'/0\'/0/*)\",\"blockheight\":5000,\"label\":\"Test Label\"}");
+    }
+
+    #[test]
+    fn test_export_from_json() {
+        let descriptor = "wpkh(xprv9s21ZrQH143K4CTb63EaMxja1YiTnSEWKMbn23uoEnAzxjdUJRQkazCAtzxGm4LSoTSVTptoV9RbchnKPW9HxKtZumdyxyikZFDLhogJ5Uj/44'/0'/0'/0/*)";
+        let change_descriptor = "wpkh(xprv9s21ZrQH143K4CTb63EaMxja1YiTnSEWKMbn23uoEnAzxjdUJRQkazCAtzxGm4LSoTSVTptoV9RbchnKPW9HxKtZumdyxyikZFDLhogJ5Uj/44'/0'/0'/1/*)";
+
+        let import_str = "{\"descriptor\":\"wpkh(xprv9s21ZrQH143K4CTb63EaMxja1YiTnSEWKMbn23uoEnAzxjdUJRQkazCAtzxGm4LSoTSVTptoV9RbchnKPW9HxKtZumdyxyikZFDLhogJ5Uj/44\'/0\'/0\'/0/*)\",\"blockheight\":5000,\"label\":\"Test Label\"}";
+        let export = FullyNodedExport::from_str(import_str).unwrap();
+
+        assert_eq!(export.descriptor(), descriptor);
+        assert_eq!(export.change_descriptor(), Some(change_descriptor.into()));
+        assert_eq!(export.blockheight, 5000);
+        assert_eq!(export.label, "Test Label");
+    }
+}
@@ -0,0 +1,204 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Specter and Sparrow wallet JSON
+
+use core::fmt;
+use core::str::FromStr;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use super::{first_confirmation, ImportError, ImportedWallet};
+use crate::types::KeychainKind;
+use crate::wallet::{Birthday, Wallet};
+
+/// The wallet export of Specter Desktop, which Sparrow can also import and export
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpecterExport {
+    /// The name of the wallet
+    pub label: String,
+    /// Earliest block to rescan when looking for the wallet's transactions, `0` if unknown
+    #[serde(default)]
+    pub blockheight: u32,
+    /// The external descriptor, from which the internal one is derived
+    pub descriptor: String,
+    /// The signing devices holding the keys of the wallet
+    #[serde(default)]
+    pub devices: Vec<SpecterDevice>,
+}
+
+/// A signing device of a [`SpecterExport`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpecterDevice {
+    /// The kind of device, such as `coldcard` or `trezor`
+    #[serde(rename = "type")]
+    pub device_type: String,
+    /// The name of the device
+    pub label: String,
+}
+
+impl fmt::Display for SpecterExport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            serde_json::to_string(self).map_err(|_| fmt::Error)?
+        )
+    }
+}
+
+impl FromStr for SpecterExport {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+impl SpecterExport {
+    /// Import the wallet
+    ///
+    /// The internal descriptor is derived from the external one by replacing its `/0/*` steps
+    /// with `/1/*`. Multipath `/<0;1>/*` steps are also supported.
+    pub fn import(&self) -> ImportedWallet {
+        let descriptor = strip_checksum(&self.descriptor);
+        let (descriptor, change_descriptor) = if descriptor.contains("/<0;1>/*") {
+            (
+                descriptor.replace("/<0;1>/*", "/0/*"),
+                Some(descriptor.replace("/<0;1>/*", "/1/*")),
+            )
+        } else {
+            let change = descriptor.replace("/0/*", "/1/*");
+            let change = Some(change).filter(|change| change != descriptor);
+            (descriptor.to_string(), change)
+        };
+
+        ImportedWallet {
+            descriptor,
+            change_descriptor,
+            birthday: Some(self.blockheight)
+                .filter(|&height| height > 0)
+                .map(Birthday::Height),
+            label: Some(self.label.clone()),
+        }
+    }
+
+    /// Export a wallet, without its private keys
+    ///
+    /// Returns an error if the internal descriptor can't be derived from the external one. The
+    /// block height is the birthday of the wallet if it's a [`Birthday::Height`], or otherwise the
+    /// height of its oldest transaction.
+    pub fn export_wallet<D>(wallet: &Wallet<D>, label: &str) -> Result<Self, ImportError> {
+        let descriptor = wallet
+            .public_descriptor(KeychainKind::External)
+            .expect("the wallet has an external descriptor")
+            .to_string();
+        let export = SpecterExport {
+            label: label.into(),
+            blockheight: match wallet.birthday() {
+                Some(Birthday::Height(height)) => height,
+                _ => first_confirmation(wallet).map_or(0, |(height, _)| height),
+            },
+            descriptor,
+            devices: Vec::new(),
+        };
+
+        let change_descriptor = wallet
+            .public_descriptor(KeychainKind::Internal)
+            .map(|descriptor| strip_checksum(&descriptor.to_string()).to_string());
+        if export.import().change_descriptor != change_descriptor {
+            return Err(ImportError::Unsupported(
+                "the internal descriptor must only differ by /1/* steps",
+            ));
+        }
+
+        Ok(export)
+    }
+}
+
+fn strip_checksum(descriptor: &str) -> &str {
+    descriptor
+        .split_once('#')
+        .map_or(descriptor, |(descriptor, _)| descriptor)
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::ToString;
+    use core::str::FromStr;
+
+    use bitcoin::Network;
+
+    use super::SpecterExport;
+    use crate::wallet::{Birthday, Wallet};
+    use crate::KeychainKind;
+
+    #[test]
+    fn test_import() {
+        let export = r#"{
+            "label": "Multisig",
+            "blockheight": 2440000,
+            "descriptor": "wsh(sortedmulti(1,[73756c7f/48h/1h/0h/2h]tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/0/*,[d34db33f/48h/1h/0h/2h]tpubDEnoLuPdBep9bzw5LoGYpsxUQYheRQ9gcgrJhJEcdKFB9cWQRyYmkCyRoTqeD4tJYiVVgt6A3rN6rWn9RYhR9sBsGxji29LYWHuKKbdb1ev/0/*))",
+            "devices": [
+                {"type": "coldcard", "label": "Coldcard"},
+                {"type": "trezor", "label": "Trezor"}
+            ]
+        }"#;
+        let export = SpecterExport::from_str(export).unwrap();
+        assert_eq!(export.devices[1].device_type, "trezor");
+
+        let import = export.import();
+        assert!(import.descriptor.ends_with("/0/*))"));
+        assert!(import.change_descriptor.unwrap().ends_with("/1/*))"));
+        assert_eq!(import.birthday, Some(Birthday::Height(2440000)));
+        assert_eq!(import.label.as_deref(), Some("Multisig"));
+
+        let multipath = SpecterExport {
+            descriptor: export.descriptor.replace("/0/*", "/<0;1>/*"),
+            ..export
+        };
+        assert_eq!(multipath.import().descriptor, import.descriptor);
+    }
+
+    #[test]
+    fn test_export() {
+        let mut wallet = Wallet::new_no_persist(
+            "wpkh([73756c7f/84'/1'/0']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/0/*)",
+            Some("wpkh([73756c7f/84'/1'/0']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/1/*)"),
+            Network::Testnet,
+        )
+        .unwrap();
+        wallet.set_birthday(Birthday::Height(2_500_000));
+
+        let export = SpecterExport::export_wallet(&wallet, "Single sig").unwrap();
+        assert_eq!(export.blockheight, 2_500_000);
+
+        let imported = SpecterExport::from_str(&export.to_string())
+            .unwrap()
+            .import()
+            .into_wallet((), Network::Testnet)
+            .unwrap();
+        assert_eq!(
+            imported.public_descriptor(KeychainKind::Internal),
+            wallet.public_descriptor(KeychainKind::Internal)
+        );
+        assert_eq!(imported.birthday(), Some(Birthday::Height(2_500_000)));
+
+        let wallet = Wallet::new_no_persist(
+            "wpkh([73756c7f/84'/1'/0']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/0/*)",
+            Some("wpkh([73756c7f/84'/1'/0']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/7/*)"),
+            Network::Testnet,
+        )
+        .unwrap();
+        assert!(SpecterExport::export_wallet(&wallet, "Custom").is_err());
+    }
+}
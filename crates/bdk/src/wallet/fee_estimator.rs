@@ -0,0 +1,40 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Fee estimation
+//!
+//! This module re-exports [`FeeEstimator`] (and [`AsyncFeeEstimator`] with the `async-interface`
+//! feature) from [`bdk_chain::chain_source`]. They return the [`FeeRate`] a transaction should pay
+//! to confirm within a target number of blocks, which can be passed straight to
+//! [`TxBuilder::fee_rate`].
+//!
+//! Implementations are provided by the chain source crates for wrappers around their clients:
+//!
+//! * `bdk_esplora`: `EsploraSource`, for both the blocking and the async client, using the
+//!   server's fee estimates
+//! * `bdk_electrum`: `ElectrumSource`, using `blockchain.estimatefee`
+//! * `bdk_bitcoind_rpc`: `BitcoindSource`, using `estimatesmartfee`
+//!
+//! ```ignore
+//! use bdk::wallet::fee_estimator::FeeEstimator;
+//! use bdk_esplora::{esplora_client, EsploraSource};
+//!
+//! let client = esplora_client::Builder::new("https://blockstream.info/testnet/api")
+//!     .build_blocking()?;
+//! let fee_rate = EsploraSource(&client).estimate_fee(6)?;
+//! println!("{} sat/vB to confirm within 6 blocks", fee_rate.as_sat_per_vb());
+//! ```
+//!
+//! [`FeeRate`]: crate::FeeRate
+//! [`TxBuilder::fee_rate`]: crate::wallet::tx_builder::TxBuilder::fee_rate
+
+#[cfg(feature = "async-interface")]
+pub use bdk_chain::chain_source::AsyncFeeEstimator;
+pub use bdk_chain::chain_source::FeeEstimator;
@@ -0,0 +1,388 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Generic message signing
+//!
+//! This module implements [BIP322] message signatures, which work for any address whose script
+//! can be satisfied, unlike the legacy `signmessage` that only supports P2PKH. Signing a message
+//! means signing a virtual `to_sign` transaction, that spends a `to_spend` transaction committing
+//! to the message and paying to the address. Neither of them is valid on the network.
+//!
+//! The `to_sign` transaction is signed like any other PSBT, so [`Wallet::sign_message`] works
+//! with every signer of the wallet, hardware ones included. The signature is either in the
+//! "simple" format, only the witness of the `to_sign` input, or in the "full" format, the whole
+//! `to_sign` transaction, which is required for addresses that aren't native segwit:
+//!
+//! ```
+//! # use core::str::FromStr;
+//! # use bdk::bitcoin::secp256k1::Secp256k1;
+//! # use bdk::wallet::message::{verify_message, MessageFormat, MessageSignature};
+//! # use bdk::wallet::AddressIndex;
+//! # use bdk::*;
+//! # let mut wallet = doctest_wallet!();
+//! let address = wallet.get_address(AddressIndex::New).address;
+//! let signature = wallet.sign_message(
+//!     &address,
+//!     "Hello World",
+//!     MessageFormat::Simple,
+//!     SignOptions::default(),
+//! )?;
+//!
+//! let signature = MessageSignature::from_str(&signature.to_string())?;
+//! verify_message(&Secp256k1::new(), &address, "Hello World", &signature)?;
+//! # Ok::<_, Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Signatures are checked with the miniscript interpreter, so only scripts that can be expressed
+//! as descriptors are supported. Proofs of funds, where `to_sign` spends additional inputs, are
+//! not.
+//!
+//! [BIP322]: https://github.com/bitcoin/bips/blob/master/bip-0322.mediawiki
+//! [`Wallet::sign_message`]: crate::wallet::Wallet::sign_message
+
+use alloc::string::{String, ToString};
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::blockdata::opcodes;
+use bitcoin::consensus::encode;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::psbt::{self, PartiallySignedTransaction as Psbt};
+use bitcoin::script::{Builder, Script, ScriptBuf};
+use bitcoin::secp256k1::{Secp256k1, Verification};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, TapSighashType};
+use bitcoin::{absolute, base64, Address, OutPoint, Sequence, Transaction, TxIn, TxOut, Witness};
+use miniscript::interpreter::{Interpreter, KeySigPair, SatisfiedConstraint};
+
+const TAG: &[u8] = b"BIP0322-signed-message";
+
+/// Errors related to message signatures
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageError {
+    /// The address doesn't belong to the wallet
+    UnknownAddress,
+    /// The simple format can only be used with native segwit addresses
+    UnsupportedFormat,
+    /// The signature isn't valid base64, or doesn't decode to a witness or a transaction
+    InvalidEncoding,
+    /// The `to_sign` transaction of a full signature is malformed
+    InvalidToSign(&'static str),
+    /// The wallet couldn't finalize the `to_sign` transaction
+    NotFinalized,
+    /// The message is signed with a sighash type other than `ALL`
+    UnsupportedSighashType,
+    /// The signature doesn't satisfy the script of the address
+    SignatureValidation(String),
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownAddress => write!(f, "The address doesn't belong to the wallet"),
+            Self::UnsupportedFormat => write!(
+                f,
+                "The simple format can only be used with native segwit addresses"
+            ),
+            Self::InvalidEncoding => write!(f, "Invalid signature encoding"),
+            Self::InvalidToSign(reason) => write!(f, "Invalid `to_sign` transaction: {}", reason),
+            Self::NotFinalized => write!(f, "The message signature couldn't be finalized"),
+            Self::UnsupportedSighashType => write!(f, "The message isn't signed with SIGHASH_ALL"),
+            Self::SignatureValidation(err) => write!(f, "Invalid message signature: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MessageError {}
+
+/// The format of a message signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// Only the witness of the `to_sign` input, for native segwit addresses
+    Simple,
+    /// The whole `to_sign` transaction
+    Full,
+}
+
+/// A [BIP322] message signature
+///
+/// It's displayed and parsed as base64. When parsing, the simple format is tried first.
+///
+/// [BIP322]: https://github.com/bitcoin/bips/blob/master/bip-0322.mediawiki
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageSignature {
+    /// The witness of the `to_sign` input
+    Simple(Witness),
+    /// The `to_sign` transaction
+    Full(Transaction),
+}
+
+impl MessageSignature {
+    /// Extract the signature from a finalized `to_sign` PSBT, see [`to_sign_psbt`]
+    pub fn from_psbt(psbt: &Psbt, format: MessageFormat) -> Result<Self, MessageError> {
+        let input = psbt.inputs.first().ok_or(MessageError::NotFinalized)?;
+        if input.final_script_sig.is_none() && input.final_script_witness.is_none() {
+            return Err(MessageError::NotFinalized);
+        }
+
+        let tx = psbt.clone().extract_tx();
+        match format {
+            MessageFormat::Simple if tx.input[0].script_sig.is_empty() => {
+                Ok(MessageSignature::Simple(tx.input[0].witness.clone()))
+            }
+            MessageFormat::Simple => Err(MessageError::UnsupportedFormat),
+            MessageFormat::Full => Ok(MessageSignature::Full(tx)),
+        }
+    }
+
+    /// The format of the signature
+    pub fn format(&self) -> MessageFormat {
+        match self {
+            MessageSignature::Simple(_) => MessageFormat::Simple,
+            MessageSignature::Full(_) => MessageFormat::Full,
+        }
+    }
+}
+
+impl fmt::Display for MessageSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = match self {
+            MessageSignature::Simple(witness) => encode::serialize(witness),
+            MessageSignature::Full(tx) => encode::serialize(tx),
+        };
+        write!(f, "{}", base64::encode(bytes))
+    }
+}
+
+impl FromStr for MessageSignature {
+    type Err = MessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = base64::decode(s.trim()).map_err(|_| MessageError::InvalidEncoding)?;
+        if let Ok(witness) = encode::deserialize(&bytes) {
+            return Ok(MessageSignature::Simple(witness));
+        }
+        encode::deserialize(&bytes)
+            .map(MessageSignature::Full)
+            .map_err(|_| MessageError::InvalidEncoding)
+    }
+}
+
+/// The tagged hash of `message`, committed to by the `to_spend` transaction
+pub fn message_hash(message: &str) -> sha256::Hash {
+    let tag = sha256::Hash::hash(TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    engine.input(message.as_bytes());
+    sha256::Hash::from_engine(engine)
+}
+
+/// The virtual `to_spend` transaction for `message`, paying to `script_pubkey`
+pub fn to_spend(script_pubkey: &Script, message: &str) -> Transaction {
+    let script_sig = Builder::new()
+        .push_opcode(opcodes::OP_0)
+        .push_slice(message_hash(message).to_byte_array())
+        .into_script();
+    Transaction {
+        version: 0,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig,
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: 0,
+            script_pubkey: script_pubkey.into(),
+        }],
+    }
+}
+
+/// The unsigned virtual `to_sign` transaction for `message` and `script_pubkey`, as a PSBT
+///
+/// It can be signed by any signer, then turned into a signature with
+/// [`MessageSignature::from_psbt`].
+pub fn to_sign_psbt(script_pubkey: &Script, message: &str) -> Psbt {
+    let to_spend = to_spend(script_pubkey, message);
+    let mut psbt = Psbt::from_unsigned_tx(to_sign(&to_spend, Witness::new()))
+        .expect("the transaction is unsigned");
+    psbt.inputs[0] = psbt::Input {
+        witness_utxo: Some(to_spend.output[0].clone()),
+        non_witness_utxo: Some(to_spend),
+        ..Default::default()
+    };
+    psbt
+}
+
+fn to_sign(to_spend: &Transaction, witness: Witness) -> Transaction {
+    Transaction {
+        version: 0,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                txid: to_spend.txid(),
+                vout: 0,
+            },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ZERO,
+            witness,
+        }],
+        output: vec![TxOut {
+            value: 0,
+            script_pubkey: Builder::new()
+                .push_opcode(opcodes::all::OP_RETURN)
+                .into_script(),
+        }],
+    }
+}
+
+/// Verify the `signature` of `message` for `address`
+pub fn verify_message<C: Verification>(
+    secp: &Secp256k1<C>,
+    address: &Address,
+    message: &str,
+    signature: &MessageSignature,
+) -> Result<(), MessageError> {
+    let to_spend = to_spend(&address.script_pubkey(), message);
+    let to_sign = match signature {
+        MessageSignature::Simple(witness) => to_sign(&to_spend, witness.clone()),
+        MessageSignature::Full(tx) => {
+            if tx.input.len() != 1 {
+                return Err(MessageError::InvalidToSign("must have exactly one input"));
+            }
+            if tx.input[0].previous_output.txid != to_spend.txid()
+                || tx.input[0].previous_output.vout != 0
+            {
+                return Err(MessageError::InvalidToSign("doesn't spend `to_spend`"));
+            }
+            if tx.output.len() != 1
+                || tx.output[0].value != 0
+                || !tx.output[0].script_pubkey.is_op_return()
+            {
+                return Err(MessageError::InvalidToSign(
+                    "must have a single empty OP_RETURN output",
+                ));
+            }
+            tx.clone()
+        }
+    };
+
+    let invalid =
+        |err: miniscript::interpreter::Error| MessageError::SignatureValidation(err.to_string());
+    let txin = &to_sign.input[0];
+    let interpreter = Interpreter::from_txdata(
+        &to_spend.output[0].script_pubkey,
+        &txin.script_sig,
+        &txin.witness,
+        txin.sequence,
+        to_sign.lock_time,
+    )
+    .map_err(invalid)?;
+    let prevouts = [to_spend.output[0].clone()];
+    for constraint in interpreter.iter(secp, &to_sign, 0, &Prevouts::All(&prevouts)) {
+        let key_sig = match constraint.map_err(invalid)? {
+            SatisfiedConstraint::PublicKey { key_sig }
+            | SatisfiedConstraint::PublicKeyHash { key_sig, .. } => key_sig,
+            _ => continue,
+        };
+        let sighash_all = match key_sig {
+            KeySigPair::Ecdsa(_, sig) => sig.hash_ty == EcdsaSighashType::All,
+            KeySigPair::Schnorr(_, sig) => {
+                matches!(sig.hash_ty, TapSighashType::Default | TapSighashType::All)
+            }
+        };
+        if !sighash_all {
+            return Err(MessageError::UnsupportedSighashType);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // test vectors from BIP322
+    const SEGWIT_ADDRESS: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    const TAPROOT_ADDRESS: &str = "bc1ppv609nr0vr25u07u95waq5lucwfm6tde4nydujnu8npg4q75mr5sxq8lt3";
+
+    fn address(s: &str) -> Address {
+        Address::from_str(s).unwrap().assume_checked()
+    }
+
+    #[test]
+    fn test_message_hash() {
+        assert_eq!(
+            message_hash("").to_string(),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            message_hash("Hello World").to_string(),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+    }
+
+    #[test]
+    fn test_virtual_transactions() {
+        let script_pubkey = address(SEGWIT_ADDRESS).script_pubkey();
+        let empty = to_spend(&script_pubkey, "");
+        assert_eq!(
+            empty.txid().to_string(),
+            "c5680aa69bb8d860bf82d4e9cd3504b55dde018de765a91bb566283c545a99a7"
+        );
+        assert_eq!(
+            to_sign(&empty, Witness::new()).txid().to_string(),
+            "1e9654e951a5ba44c8604c4de6c67fd78a27e81dcadcfe1edf638ba3aaebaed6"
+        );
+
+        let psbt = to_sign_psbt(&script_pubkey, "Hello World");
+        assert_eq!(
+            psbt.unsigned_tx.input[0].previous_output.txid.to_string(),
+            "b79d196740ad5217771c1098fc4a4b51e0535c32236c71f1ea4d61a2d603352b"
+        );
+        assert_eq!(
+            psbt.unsigned_tx.txid().to_string(),
+            "88737ae86f2077145f93cc4b153ae9a1cb8d56afa511988c149c5c8c9d93bddf"
+        );
+    }
+
+    #[test]
+    fn test_verify_message() {
+        let secp = Secp256k1::verification_only();
+        let segwit = address(SEGWIT_ADDRESS);
+
+        let signature = MessageSignature::from_str("AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=").unwrap();
+        assert_eq!(signature.format(), MessageFormat::Simple);
+        assert!(verify_message(&secp, &segwit, "", &signature).is_ok());
+        assert!(matches!(
+            verify_message(&secp, &segwit, "Hello World", &signature),
+            Err(MessageError::SignatureValidation(_))
+        ));
+
+        let signature = MessageSignature::from_str("AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=").unwrap();
+        assert!(verify_message(&secp, &segwit, "Hello World", &signature).is_ok());
+        assert!(
+            verify_message(&secp, &address(TAPROOT_ADDRESS), "Hello World", &signature).is_err()
+        );
+
+        let signature = MessageSignature::from_str("AUHd69PrJQEv+oKTfZ8l+WROBHuy9HKrbFCJu7U1iK2iiEy1vMU5EfMtjc+VSHM7aU0SDbak5IUZRVno2P5mjSafAQ==").unwrap();
+        assert!(
+            verify_message(&secp, &address(TAPROOT_ADDRESS), "Hello World", &signature).is_ok()
+        );
+
+        assert_eq!(
+            MessageSignature::from_str("not base64!"),
+            Err(MessageError::InvalidEncoding)
+        );
+    }
+}
@@ -28,25 +28,45 @@ use bdk_chain::{
     Append, BlockId, ChainPosition, ConfirmationTime, ConfirmationTimeAnchor, FullTxOut,
     IndexedTxGraph, Persist, PersistBackend,
 };
+use bitcoin::bip32::ChildNumber;
 use bitcoin::consensus::encode::serialize;
+use bitcoin::key::{TapTweak, XOnlyPublicKey};
 use bitcoin::psbt;
-use bitcoin::secp256k1::Secp256k1;
+use bitcoin::secp256k1::{KeyPair, Secp256k1, SecretKey};
 use bitcoin::sighash::{EcdsaSighashType, TapSighashType};
 use bitcoin::{
-    absolute, Address, Network, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxOut, Txid,
-    Weight, Witness,
+    absolute, taproot, Address, Block, Network, OutPoint, Script, ScriptBuf, Sequence, Transaction,
+    TxOut, Txid, Weight, Witness,
 };
 use core::fmt;
 use core::ops::Deref;
+use core::str::FromStr;
+use miniscript::descriptor::{Descriptor, DescriptorSecretKey, ShInner, Wildcard};
 use miniscript::psbt::{PsbtExt, PsbtInputExt, PsbtInputSatisfier};
 
 use bdk_chain::tx_graph::CalculateFeeError;
+
+#[cfg(feature = "async-interface")]
+use self::broadcaster::AsyncBroadcaster;
+use self::broadcaster::{BroadcastError, Broadcaster};
 #[allow(unused_imports)]
 use log::{debug, error, info, trace};
 
+pub mod broadcaster;
+pub mod bsms;
 pub mod coin_selection;
+pub mod coordinator;
 pub mod export;
+pub mod fee_estimator;
+pub mod message;
+pub mod musig;
+pub mod payjoin;
+pub mod payment_uri;
+pub mod recovery;
+pub mod reserves;
 pub mod signer;
+pub mod silent_payments;
+pub mod sweep;
 pub mod tx_builder;
 pub(crate) mod utils;
 
@@ -57,15 +77,20 @@ pub mod hardwaresigner;
 pub use utils::IsDust;
 
 #[allow(deprecated)]
-use coin_selection::DefaultCoinSelectionAlgorithm;
+use coin_selection::{CoinSelectionAlgorithm, DefaultCoinSelectionAlgorithm};
+use message::MessageError;
+use payjoin::{PayjoinError, PayjoinRequest, PayjoinTransport};
 use signer::{SignOptions, SignerOrdering, SignersContainer, TransactionSigner};
+use silent_payments::{SilentPaymentAddress, SilentPaymentError};
 use tx_builder::{BumpFee, CreateTx, FeePolicy, TxBuilder, TxParams};
 use utils::{check_nsequence_rbf, After, Older, SecpCtx};
 
-use crate::descriptor::policy::BuildSatisfaction;
+use crate::descriptor::policy::{BuildSatisfaction, Condition};
 use crate::descriptor::{
-    calc_checksum, into_wallet_descriptor_checked, DerivedDescriptor, DescriptorMeta,
-    ExtendedDescriptor, ExtractPolicy, IntoWalletDescriptor, Policy, XKeyUtils,
+    calc_checksum, into_multipath_descriptor, into_single_wallet_descriptors_checked,
+    into_wallet_descriptor_checked, tap_leaf_hash, tap_leaf_satisfaction_weight, DerivedDescriptor,
+    DescriptorError, DescriptorMeta, ExtendedDescriptor, ExtractPolicy, IntoWalletDescriptor,
+    Policy, XKeyUtils,
 };
 use crate::error::{Error, MiniscriptPsbtError};
 use crate::psbt::PsbtUtils;
@@ -86,12 +111,12 @@ const COINBASE_MATURITY: u32 = 100;
 /// [`signer`]: crate::signer
 #[derive(Debug)]
 pub struct Wallet<D = ()> {
-    signers: Arc<SignersContainer>,
-    change_signers: Arc<SignersContainer>,
+    signers: BTreeMap<KeychainKind, Arc<SignersContainer>>,
     chain: LocalChain,
     indexed_graph: IndexedTxGraph<ConfirmationTimeAnchor, KeychainTxOutIndex<KeychainKind>>,
     persist: Persist<D, ChangeSet>,
     network: Network,
+    birthday: Option<Birthday>,
     secp: SecpCtx,
 }
 
@@ -113,6 +138,21 @@ pub struct Update {
     pub chain: Option<local_chain::Update>,
 }
 
+/// The earliest point in the chain where a wallet may have transaction history.
+///
+/// Block-based chain sources do not need to look at blocks before the birthday when syncing the
+/// wallet for the first time. See [`Wallet::set_birthday`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Birthday {
+    /// The wallet has no transactions confirmed below this block height.
+    Height(u32),
+    /// The wallet has no transactions created before this unix timestamp.
+    ///
+    /// Use this when the creation height is not known. The chain source is responsible for mapping
+    /// the timestamp to a block height, keeping in mind that block timestamps are not monotonic.
+    Time(u64),
+}
+
 /// The changes made to a wallet by applying an [`Update`].
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, Default)]
 pub struct ChangeSet {
@@ -126,16 +166,22 @@ pub struct ChangeSet {
     /// [`IndexedTxGraph`]: bdk_chain::indexed_tx_graph::IndexedTxGraph
     pub indexed_tx_graph:
         indexed_tx_graph::ChangeSet<ConfirmationTimeAnchor, keychain::ChangeSet<KeychainKind>>,
+
+    /// The wallet's [`Birthday`] (if it is set or changed).
+    pub birthday: Option<Birthday>,
 }
 
 impl Append for ChangeSet {
     fn append(&mut self, other: Self) {
         Append::append(&mut self.chain, other.chain);
         Append::append(&mut self.indexed_tx_graph, other.indexed_tx_graph);
+        if other.birthday.is_some() {
+            self.birthday = other.birthday;
+        }
     }
 
     fn is_empty(&self) -> bool {
-        self.chain.is_empty() && self.indexed_tx_graph.is_empty()
+        self.chain.is_empty() && self.indexed_tx_graph.is_empty() && self.birthday.is_none()
     }
 }
 
@@ -164,6 +210,54 @@ impl From<indexed_tx_graph::ChangeSet<ConfirmationTimeAnchor, keychain::ChangeSe
     }
 }
 
+/// The layout of [`ChangeSet`] before it had a [`Birthday`].
+///
+/// Binary formats such as the one of `bdk_file_store` can't read the old layout as a [`ChangeSet`].
+/// To migrate such a database, load it as a `ChangeSetV0`, then write the aggregate converted into
+/// a [`ChangeSet`] to a new database:
+///
+/// ```rust,ignore
+/// let mut old_db = Store::<ChangeSetV0>::new_from_path(DB_MAGIC, old_path)?;
+/// let (changeset, result) = old_db.aggregate_changesets();
+/// result?;
+/// let mut new_db = Store::<ChangeSet>::new_from_path(NEW_DB_MAGIC, new_path)?;
+/// new_db.append_changeset(&changeset.into())?;
+/// ```
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, Default)]
+pub struct ChangeSetV0 {
+    /// Changes to the [`LocalChain`].
+    ///
+    /// [`LocalChain`]: local_chain::LocalChain
+    pub chain: local_chain::ChangeSet,
+
+    /// Changes to [`IndexedTxGraph`].
+    ///
+    /// [`IndexedTxGraph`]: bdk_chain::indexed_tx_graph::IndexedTxGraph
+    pub indexed_tx_graph:
+        indexed_tx_graph::ChangeSet<ConfirmationTimeAnchor, keychain::ChangeSet<KeychainKind>>,
+}
+
+impl Append for ChangeSetV0 {
+    fn append(&mut self, other: Self) {
+        Append::append(&mut self.chain, other.chain);
+        Append::append(&mut self.indexed_tx_graph, other.indexed_tx_graph);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.chain.is_empty() && self.indexed_tx_graph.is_empty()
+    }
+}
+
+impl From<ChangeSetV0> for ChangeSet {
+    fn from(changeset: ChangeSetV0) -> Self {
+        Self {
+            chain: changeset.chain,
+            indexed_tx_graph: changeset.indexed_tx_graph,
+            birthday: None,
+        }
+    }
+}
+
 /// The address index selection strategy to use to derived an address from the wallet's external
 /// descriptor. See [`Wallet::get_address`]. If you're unsure which one to use use `WalletIndex::New`.
 #[derive(Debug)]
@@ -271,6 +365,70 @@ impl<D> Wallet<D> {
     pub fn new<E: IntoWalletDescriptor>(
         descriptor: E,
         change_descriptor: Option<E>,
+        db: D,
+        network: Network,
+    ) -> Result<Self, NewError<D::LoadError>>
+    where
+        D: PersistBackend<ChangeSet>,
+    {
+        Self::new_with_keychains(
+            descriptor,
+            change_descriptor,
+            core::iter::empty(),
+            db,
+            network,
+        )
+    }
+
+    /// Create a wallet from a single [BIP389] multipath descriptor, such as
+    /// `wpkh(tprv.../84'/1'/0'/<0;1>/*)`, and load related transaction data from `db`.
+    ///
+    /// The first path of the descriptor is used for the external keychain and the second one for
+    /// the internal keychain. The wallet can be exported back into a single descriptor with
+    /// [`multipath_descriptor`].
+    ///
+    /// [BIP389]: https://github.com/bitcoin/bips/blob/master/bip-0389.mediawiki
+    /// [`multipath_descriptor`]: Self::multipath_descriptor
+    pub fn new_multipath(
+        descriptor: &str,
+        db: D,
+        network: Network,
+    ) -> Result<Self, NewError<D::LoadError>>
+    where
+        D: PersistBackend<ChangeSet>,
+    {
+        let secp = Secp256k1::new();
+        let mut descriptors = into_single_wallet_descriptors_checked(descriptor, &secp, network)
+            .map_err(NewError::Descriptor)?;
+        if descriptors.len() != 2 {
+            return Err(NewError::Descriptor(DescriptorError::MultiPathCount(
+                descriptors.len(),
+            )));
+        }
+        let change_descriptor = descriptors.pop();
+        let descriptor = descriptors.pop().expect("checked above");
+
+        Self::new(descriptor, change_descriptor, db, network)
+    }
+
+    /// Create a wallet like [`new`], that also tracks additional keychains.
+    ///
+    /// Each of the `custom_keychains` is added as a [`KeychainKind::Custom`] keychain with the
+    /// given identifier. They are synced along with the external and internal keychains and their
+    /// outputs are picked by coin selection like any other, as long as the wallet has signers for
+    /// them: the outputs of watch-only custom keychains are only spent when added manually with
+    /// [`TxBuilder::add_utxo`]. Use
+    /// [`get_keychain_address`] and [`get_keychain_balance`] to work with a single keychain.
+    ///
+    /// The same keychains must be passed every time the wallet is loaded from `db`.
+    ///
+    /// [`new`]: Self::new
+    /// [`get_keychain_address`]: Self::get_keychain_address
+    /// [`get_keychain_balance`]: Self::get_keychain_balance
+    pub fn new_with_keychains<E: IntoWalletDescriptor>(
+        descriptor: E,
+        change_descriptor: Option<E>,
+        custom_keychains: impl IntoIterator<Item = (u32, E)>,
         mut db: D,
         network: Network,
     ) -> Result<Self, NewError<D::LoadError>>
@@ -281,33 +439,24 @@ impl<D> Wallet<D> {
         let mut chain = LocalChain::default();
         let mut indexed_graph =
             IndexedTxGraph::<ConfirmationTimeAnchor, KeychainTxOutIndex<KeychainKind>>::default();
+        let mut signers = BTreeMap::new();
 
-        let (descriptor, keymap) = into_wallet_descriptor_checked(descriptor, &secp, network)
-            .map_err(NewError::Descriptor)?;
-        indexed_graph
-            .index
-            .add_keychain(KeychainKind::External, descriptor.clone());
-        let signers = Arc::new(SignersContainer::build(keymap, &descriptor, &secp));
-        let change_signers = match change_descriptor {
-            Some(desc) => {
-                let (change_descriptor, change_keymap) =
-                    into_wallet_descriptor_checked(desc, &secp, network)
-                        .map_err(NewError::Descriptor)?;
-
-                let change_signers = Arc::new(SignersContainer::build(
-                    change_keymap,
-                    &change_descriptor,
-                    &secp,
-                ));
-
-                indexed_graph
-                    .index
-                    .add_keychain(KeychainKind::Internal, change_descriptor);
-
-                change_signers
-            }
-            None => Arc::new(SignersContainer::new()),
-        };
+        let keychains = core::iter::once((KeychainKind::External, descriptor))
+            .chain(change_descriptor.map(|desc| (KeychainKind::Internal, desc)))
+            .chain(
+                custom_keychains
+                    .into_iter()
+                    .map(|(id, desc)| (KeychainKind::Custom(id), desc)),
+            );
+        for (keychain, descriptor) in keychains {
+            let (descriptor, keymap) = into_wallet_descriptor_checked(descriptor, &secp, network)
+                .map_err(NewError::Descriptor)?;
+            signers.insert(
+                keychain,
+                Arc::new(SignersContainer::build(keymap, &descriptor, &secp)),
+            );
+            indexed_graph.index.add_keychain(keychain, descriptor);
+        }
 
         let changeset = db.load_from_persistence().map_err(NewError::Persist)?;
         chain.apply_changeset(&changeset.chain);
@@ -317,11 +466,11 @@ impl<D> Wallet<D> {
 
         Ok(Wallet {
             signers,
-            change_signers,
             network,
             chain,
             indexed_graph,
             persist,
+            birthday: changeset.birthday,
             secp,
         })
     }
@@ -331,6 +480,72 @@ impl<D> Wallet<D> {
         self.network
     }
 
+    /// Get the wallet's [`Birthday`], if it is set.
+    pub fn birthday(&self) -> Option<Birthday> {
+        self.birthday
+    }
+
+    /// Set the wallet's [`Birthday`]. This stages but does not [`commit`] the change.
+    ///
+    /// The birthday should be set when creating a new wallet, so that block-based chain sources
+    /// know where to start syncing from. Do not set a birthday later than the wallet's first
+    /// transaction, as transactions confirmed before the birthday may be missed.
+    ///
+    /// [`commit`]: Self::commit
+    pub fn set_birthday(&mut self, birthday: Birthday)
+    where
+        D: PersistBackend<ChangeSet>,
+    {
+        if self.birthday != Some(birthday) {
+            self.birthday = Some(birthday);
+            self.persist.stage(ChangeSet {
+                birthday: Some(birthday),
+                ..Default::default()
+            });
+        }
+    }
+
+    /// The height a block-based chain source should start syncing the wallet from, if the wallet
+    /// has not been synced up to its [`Birthday`] yet.
+    ///
+    /// A [`Birthday::Time`] is mapped to a height with `height_at_time`, which is only called for
+    /// such a birthday. Chain sources provide it, e.g. `bdk_bitcoind_rpc::height_at_time`. To only
+    /// map the time once, set the resulting [`Birthday::Height`] with [`set_birthday`].
+    ///
+    /// Returns `None` once the wallet has a checkpoint at or above its birthday, or if it doesn't
+    /// have a birthday: sync from the [`latest_checkpoint`] instead. For example, with
+    /// `bdk_bitcoind_rpc`:
+    ///
+    /// ```rust,ignore
+    /// let start = wallet.birthday_start_height(|time| height_at_time(&rpc_client, time))?;
+    /// let mut emitter = match start {
+    ///     Some(height) => Emitter::from_height(&rpc_client, height),
+    ///     None => Emitter::from_checkpoint(&rpc_client, wallet.latest_checkpoint().unwrap()),
+    /// };
+    /// while let Some((height, block)) = emitter.next_block()? {
+    ///     wallet.apply_block(&block, height)?;
+    /// }
+    /// ```
+    ///
+    /// [`set_birthday`]: Self::set_birthday
+    /// [`latest_checkpoint`]: Self::latest_checkpoint
+    pub fn birthday_start_height<E>(
+        &self,
+        height_at_time: impl FnOnce(u64) -> Result<u32, E>,
+    ) -> Result<Option<u32>, E> {
+        let height = match self.birthday {
+            Some(Birthday::Height(height)) => height,
+            Some(Birthday::Time(time)) => height_at_time(time)?,
+            None => return Ok(None),
+        };
+        let synced = self.chain.tip().map_or(false, |tip| tip.height() >= height);
+        if synced {
+            Ok(None)
+        } else {
+            Ok(Some(height))
+        }
+    }
+
     /// Iterator over all keychains in this wallet
     pub fn keychains(&self) -> &BTreeMap<KeychainKind, ExtendedDescriptor> {
         self.indexed_graph.index.keychains()
@@ -362,7 +577,29 @@ impl<D> Wallet<D> {
             .expect("persistence backend must not fail")
     }
 
-    /// Return a derived address using the specified `keychain` (external/internal).
+    /// Return a [BIP21] payment URI for a derived address of the external descriptor, see
+    /// [`get_address`](Self::get_address), requesting `amount` satoshis if specified.
+    ///
+    /// Other parameters, like a message for the payer, can be set on the returned
+    /// [`PaymentUri`](payment_uri::PaymentUri) before displaying it.
+    ///
+    /// [BIP21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+    pub fn get_payment_uri(
+        &mut self,
+        address_index: AddressIndex,
+        amount: Option<u64>,
+        label: Option<String>,
+    ) -> payment_uri::PaymentUri
+    where
+        D: PersistBackend<ChangeSet>,
+    {
+        let mut uri = payment_uri::PaymentUri::new(self.get_address(address_index).address);
+        uri.amount = amount;
+        uri.label = label;
+        uri
+    }
+
+    /// Return a derived address using the specified `keychain`.
     ///
     /// If `keychain` is [`KeychainKind::External`], external addresses will be derived (used for
     /// receiving funds).
@@ -374,6 +611,23 @@ impl<D> Wallet<D> {
     /// See [`AddressIndex`] for available address index selection strategies. If none of the keys
     /// in the descriptor are derivable (i.e. does not end with /*) then the same address will
     /// always be returned for any [`AddressIndex`].
+    ///
+    /// Returns `None` if `keychain` is a [`KeychainKind::Custom`] keychain the wallet doesn't have.
+    pub fn get_keychain_address(
+        &mut self,
+        keychain: KeychainKind,
+        address_index: AddressIndex,
+    ) -> Option<AddressInfo>
+    where
+        D: PersistBackend<ChangeSet>,
+    {
+        self.public_descriptor(self.map_keychain(keychain))?;
+        Some(
+            self._get_address(keychain, address_index)
+                .expect("persistence backend must not fail"),
+        )
+    }
+
     fn _get_address(
         &mut self,
         keychain: KeychainKind,
@@ -687,6 +941,31 @@ impl<D> Wallet<D> {
         Ok(changed)
     }
 
+    /// Prepare the wallet to rescan the chain from `height`. This stages but does not [`commit`]
+    /// the change.
+    ///
+    /// All checkpoints at and above `height` are removed from the wallet's view of the chain, so
+    /// transactions anchored in those blocks become unconfirmed until the wallet is synced again.
+    /// Transactions are never removed from the wallet. A rescan re-anchors them, or leaves them
+    /// unconfirmed if they are no longer in the best chain.
+    ///
+    /// After calling this, sync the wallet from its [`latest_checkpoint`]. If there is no
+    /// checkpoint left, a block-based chain source should start from `height`.
+    ///
+    /// Returns whether any checkpoints were removed.
+    ///
+    /// [`commit`]: Self::commit
+    /// [`latest_checkpoint`]: Self::latest_checkpoint
+    pub fn rescan_from(&mut self, height: u32) -> bool
+    where
+        D: PersistBackend<ChangeSet>,
+    {
+        let changeset = self.chain.disconnect_from(height);
+        let changed = !changeset.is_empty();
+        self.persist.stage(changeset.into());
+        changed
+    }
+
     /// Add a transaction to the wallet's internal view of the chain. This stages but does not
     /// [`commit`] the change.
     ///
@@ -753,6 +1032,378 @@ impl<D> Wallet<D> {
         Ok(changed)
     }
 
+    /// Broadcast `tx` with `broadcaster` and, if it is accepted, insert it into the wallet as
+    /// unconfirmed and last seen at `seen_at`, so that it's reflected in the balance right away.
+    /// This stages but does not [`commit`] the change.
+    ///
+    /// Nothing is inserted if the transaction is rejected.
+    ///
+    /// [`commit`]: Self::commit
+    pub fn broadcast<B: Broadcaster>(
+        &mut self,
+        broadcaster: &B,
+        tx: Transaction,
+        seen_at: u64,
+    ) -> Result<(), BroadcastError<B::Error>>
+    where
+        D: PersistBackend<ChangeSet>,
+    {
+        broadcaster.broadcast_tx(&tx)?;
+        self.insert_broadcast_tx(tx, seen_at);
+        Ok(())
+    }
+
+    /// Async version of [`broadcast`].
+    ///
+    /// [`broadcast`]: Self::broadcast
+    #[cfg(feature = "async-interface")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async-interface")))]
+    pub async fn broadcast_async<B: AsyncBroadcaster>(
+        &mut self,
+        broadcaster: &B,
+        tx: Transaction,
+        seen_at: u64,
+    ) -> Result<(), BroadcastError<B::Error>>
+    where
+        D: PersistBackend<ChangeSet>,
+    {
+        broadcaster.broadcast_tx(&tx).await?;
+        self.insert_broadcast_tx(tx, seen_at);
+        Ok(())
+    }
+
+    fn insert_broadcast_tx(&mut self, tx: Transaction, seen_at: u64)
+    where
+        D: PersistBackend<ChangeSet>,
+    {
+        self.insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: seen_at })
+            .expect("inserting an unconfirmed tx cannot fail");
+    }
+
+    /// Send a [BIP78] payjoin `request` to the receiver with `transport`, then check its proposal
+    /// and sign it, see [`sign_payjoin_proposal`].
+    ///
+    /// If this fails, the original transaction should be broadcast instead, see
+    /// [`PayjoinRequest::original_tx`].
+    ///
+    /// [BIP78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
+    /// [`sign_payjoin_proposal`]: Self::sign_payjoin_proposal
+    pub fn send_payjoin<T: PayjoinTransport>(
+        &self,
+        request: &PayjoinRequest,
+        transport: &T,
+        sign_options: SignOptions,
+    ) -> Result<psbt::PartiallySignedTransaction, payjoin::SendError<T::Error>> {
+        let response = transport
+            .post(&request.url(), &request.body())
+            .map_err(payjoin::SendError::Transport)?;
+        self.sign_payjoin_proposal(request, &response, sign_options)
+            .map_err(payjoin::SendError::Proposal)
+    }
+
+    /// Check the proposal returned by the receiver of a payjoin `request` against the original
+    /// PSBT, and sign it.
+    ///
+    /// The `response` is the body of the response of the receiver, either the proposal or an
+    /// error. The proposal is rejected if it takes more from the wallet than allowed by the
+    /// [`PayjoinParams`](payjoin::PayjoinParams) of the request, or if it can't be finalized.
+    pub fn sign_payjoin_proposal(
+        &self,
+        request: &PayjoinRequest,
+        response: &str,
+        sign_options: SignOptions,
+    ) -> Result<psbt::PartiallySignedTransaction, Error> {
+        let mut proposal = request.process_response(response, |script| self.is_mine(script))?;
+        if !self.sign(&mut proposal, sign_options)? {
+            return Err(Error::Payjoin(PayjoinError::InvalidProposal(
+                "the proposal can't be finalized",
+            )));
+        }
+        Ok(proposal)
+    }
+
+    /// Respond to a [BIP78] payjoin request with the `query` string of its URL and its `body`,
+    /// returning the signed proposal to send back to the sender, base64-encoded.
+    ///
+    /// The wallet contributes confirmed inputs of the same type as the sender's, chosen with coin
+    /// selection, and their value is added to the output that receives the payment. If the
+    /// sender allows it, it pays for the fees of these inputs.
+    ///
+    /// This doesn't check that the original transaction can be broadcast, which must be done
+    /// beforehand to avoid revealing the wallet's UTXOs to a sender that doesn't intend to pay.
+    /// The same goes for the UTXOs being contributed to several payjoins at the same time, which
+    /// should be prevented by marking them as unspendable until the payjoin is broadcast.
+    ///
+    /// The errors can be sent back to the sender with [`payjoin::error_response`].
+    ///
+    /// [BIP78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
+    pub fn process_payjoin(
+        &mut self,
+        query: &str,
+        body: &str,
+        sign_options: SignOptions,
+    ) -> Result<psbt::PartiallySignedTransaction, Error>
+    where
+        D: PersistBackend<ChangeSet>,
+    {
+        let params = payjoin::PayjoinParams::from_query(query)?;
+        let original = psbt::PartiallySignedTransaction::from_str(body.trim())
+            .map_err(|_| PayjoinError::InvalidOriginal("invalid PSBT"))?;
+        let original_fee = payjoin::check_original(&original, |script| self.is_mine(script))?;
+        let original_tx = original.clone().extract_tx();
+
+        let mut fee_rate = FeeRate::from_wu(original_fee, original_tx.weight());
+        if let Some(min_fee_rate) = params.min_fee_rate {
+            if min_fee_rate > fee_rate {
+                fee_rate = min_fee_rate;
+            }
+        }
+        // the payment, the first output that's ours if there are several
+        let payment_index = original_tx
+            .output
+            .iter()
+            .position(|txout| self.is_mine(&txout.script_pubkey))
+            .expect("checked");
+        let payment_script = original_tx.output[payment_index].script_pubkey.clone();
+
+        let input_type = payjoin::input_type(&original);
+        let (_, candidates) = self.preselect_utxos(
+            tx_builder::ChangeSpendPolicy::ChangeAllowed,
+            &HashSet::new(),
+            vec![],
+            false,
+            false,
+            true,
+            None,
+        );
+        let candidates = candidates
+            .into_iter()
+            .filter(|weighted_utxo| {
+                input_type.is_none()
+                    || payjoin::address_type(&weighted_utxo.utxo.txout().script_pubkey)
+                        == input_type
+            })
+            .collect::<Vec<_>>();
+        let input_weights = candidates
+            .iter()
+            .map(|weighted_utxo| {
+                (
+                    weighted_utxo.utxo.outpoint(),
+                    coin_selection::TXIN_BASE_WEIGHT + weighted_utxo.satisfaction_weight,
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        // any amount will do, as long as some inputs are selected
+        let contribution = DefaultCoinSelectionAlgorithm::default()
+            .coin_select(vec![], candidates, fee_rate, 1, &payment_script)
+            .map_err(|_| PayjoinError::Unavailable)?
+            .selected
+            .iter()
+            .map(Utxo::outpoint)
+            .collect::<Vec<_>>();
+        let contribution_weight = contribution
+            .iter()
+            .map(|outpoint| input_weights[outpoint])
+            .sum();
+
+        // the payment output becomes the drain output, which gets the value of our inputs
+        let mut builder = self.build_tx();
+        builder
+            .ordering(tx_builder::TxOrdering::Untouched)
+            .version(original_tx.version)
+            .nlocktime(original_tx.lock_time)
+            .fee_rate(fee_rate)
+            .manually_selected_only()
+            .drain_to(payment_script);
+        for (txin, input) in original_tx.input.iter().zip(&original.inputs) {
+            builder.add_foreign_utxo(
+                txin.previous_output,
+                input.clone(),
+                payjoin::satisfaction_weight(input),
+            )?;
+        }
+        builder.add_utxos(&contribution)?;
+        for (index, txout) in original_tx.output.iter().enumerate() {
+            if index != payment_index {
+                builder.add_recipient(txout.script_pubkey.clone(), txout.value);
+            }
+        }
+        let mut psbt = builder.finish()?;
+
+        // with `TxOrdering::Untouched` the drain output is the last one, move it back
+        if psbt.unsigned_tx.output.len() != original_tx.output.len() {
+            return Err(Error::Payjoin(PayjoinError::Unavailable));
+        }
+        let drain = psbt.unsigned_tx.output.pop().expect("not empty");
+        psbt.unsigned_tx.output.insert(payment_index, drain);
+        let drain = psbt.outputs.pop().expect("not empty");
+        psbt.outputs.insert(payment_index, drain);
+
+        // the sender's inputs keep their sequence, and ours use the same
+        for txin in &mut psbt.unsigned_tx.input {
+            txin.sequence = original_tx
+                .input
+                .iter()
+                .find(|original| original.previous_output == txin.previous_output)
+                .unwrap_or(&original_tx.input[0])
+                .sequence;
+        }
+
+        // the sender pays for the fees of our inputs up to what it allows, and no more than what
+        // they cost at the fee rate of the original transaction
+        match params.additional_fee_output_index {
+            Some(index) if index != payment_index && index < original_tx.output.len() => {
+                let fee_increase = psbt
+                    .fee_amount()
+                    .expect("has utxos")
+                    .saturating_sub(original_fee);
+                let max_contribution = payjoin::max_fee_contribution(
+                    &original,
+                    original_fee,
+                    contribution.len(),
+                    contribution_weight,
+                );
+                let fee_contribution = fee_increase
+                    .min(params.max_additional_fee_contribution)
+                    .min(max_contribution);
+                let output = &mut psbt.unsigned_tx.output[index];
+                // the contribution is not taken if the output can't afford it
+                match output.value.checked_sub(fee_contribution) {
+                    Some(value) if !value.is_dust(&output.script_pubkey) => {
+                        output.value = value;
+                        psbt.unsigned_tx.output[payment_index].value += fee_contribution;
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+
+        if !self.sign(&mut psbt, sign_options)? {
+            return Err(Error::Payjoin(PayjoinError::Unavailable));
+        }
+
+        // the sender signs its inputs again, and doesn't need to know the derivation paths of
+        // our keys
+        for (input, txin) in psbt.inputs.iter_mut().zip(&psbt.unsigned_tx.input) {
+            if original_tx
+                .input
+                .iter()
+                .any(|original| original.previous_output == txin.previous_output)
+            {
+                *input = psbt::Input::default();
+            } else {
+                input.bip32_derivation.clear();
+                input.tap_key_origins.clear();
+            }
+        }
+        for output in &mut psbt.outputs {
+            *output = psbt::Output::default();
+        }
+
+        Ok(psbt)
+    }
+
+    /// Create a [BIP127] proof of reserves for `message`, spending all the UTXOs of the wallet
+    ///
+    /// The proof must then be signed with [`sign`], and can be checked with [`verify_proof`]. See
+    /// [the `reserves` module](reserves) for an example.
+    ///
+    /// [BIP127]: https://github.com/bitcoin/bips/blob/master/bip-0127.mediawiki
+    /// [`sign`]: Self::sign
+    /// [`verify_proof`]: Self::verify_proof
+    pub fn create_proof(&mut self, message: &str) -> Result<psbt::PartiallySignedTransaction, Error>
+    where
+        D: PersistBackend<ChangeSet>,
+    {
+        let mut builder = self.build_tx();
+        builder
+            .drain_wallet()
+            .drain_to(reserves::proof_output_script())
+            .fee_absolute(0)
+            .ordering(tx_builder::TxOrdering::Untouched);
+        let mut psbt = builder.finish()?;
+
+        psbt.unsigned_tx
+            .input
+            .insert(0, reserves::challenge_txin(message));
+        psbt.inputs.insert(0, reserves::challenge_psbt_input());
+
+        Ok(psbt)
+    }
+
+    /// Verify a signed proof of reserves for `message` against the UTXOs of the wallet, returning
+    /// the amount it proves
+    ///
+    /// See [`reserves::verify_proof`] to verify it against another UTXO set.
+    pub fn verify_proof(
+        &self,
+        psbt: &psbt::PartiallySignedTransaction,
+        message: &str,
+    ) -> Result<u64, Error> {
+        let amount = reserves::verify_proof(&self.secp, psbt, message, |outpoint| {
+            self.get_utxo(*outpoint).map(|utxo| utxo.txout)
+        })?;
+        Ok(amount)
+    }
+
+    /// Sign `message` for one of the wallet's addresses, producing a [BIP322] signature in the
+    /// given `format`
+    ///
+    /// The virtual `to_sign` transaction is signed with all the signers of the wallet, like
+    /// [`sign`] would. See [the `message` module](message) for an example.
+    ///
+    /// [BIP322]: https://github.com/bitcoin/bips/blob/master/bip-0322.mediawiki
+    /// [`sign`]: Self::sign
+    pub fn sign_message(
+        &self,
+        address: &Address,
+        message: &str,
+        format: message::MessageFormat,
+        sign_options: SignOptions,
+    ) -> Result<message::MessageSignature, Error> {
+        let script_pubkey = address.script_pubkey();
+        if !self.is_mine(&script_pubkey) {
+            return Err(MessageError::UnknownAddress.into());
+        }
+
+        let mut psbt = message::to_sign_psbt(&script_pubkey, message);
+        if !self.sign(&mut psbt, sign_options)? {
+            return Err(MessageError::NotFinalized.into());
+        }
+        Ok(message::MessageSignature::from_psbt(&psbt, format)?)
+    }
+
+    /// Spend all the UTXOs of `sweep` to an address of the wallet, returning the signed and
+    /// finalized transaction
+    ///
+    /// The UTXOs are spent as foreign UTXOs, and signed with the keys of the sweep only. See
+    /// [the `sweep` module](sweep) for an example.
+    pub fn sweep(
+        &mut self,
+        sweep: &sweep::Sweep,
+        fee_rate: FeeRate,
+    ) -> Result<psbt::PartiallySignedTransaction, Error>
+    where
+        D: PersistBackend<ChangeSet>,
+    {
+        let utxos = sweep.foreign_utxos()?;
+        let script_pubkey = self.get_address(AddressIndex::LastUnused).script_pubkey();
+
+        let mut builder = self.build_tx();
+        builder
+            .manually_selected_only()
+            .drain_to(script_pubkey)
+            .fee_rate(fee_rate);
+        for (outpoint, psbt_input, satisfaction_weight) in utxos {
+            builder.add_foreign_utxo(outpoint, psbt_input, satisfaction_weight)?;
+        }
+        let mut psbt = builder.finish()?;
+
+        sweep.sign(&mut psbt, &self.secp)?;
+        Ok(psbt)
+    }
+
     /// Iterate over the transactions in the wallet.
     pub fn transactions(
         &self,
@@ -765,11 +1416,38 @@ impl<D> Wallet<D> {
 
     /// Return the balance, separated into available, trusted-pending, untrusted-pending and immature
     /// values.
+    ///
+    /// The outputs of watch-only [`KeychainKind::Custom`] keychains are not part of the balance,
+    /// since the wallet can't spend them: use [`get_keychain_balance`] to get their balance.
+    ///
+    /// [`get_keychain_balance`]: Self::get_keychain_balance
     pub fn get_balance(&self) -> Balance {
         self.indexed_graph.graph().balance(
             &self.chain,
             self.chain.tip().map(|cp| cp.block_id()).unwrap_or_default(),
-            self.indexed_graph.index.outpoints().iter().cloned(),
+            self.indexed_graph
+                .index
+                .outpoints()
+                .iter()
+                .filter(|((k, _), _)| !self.is_watch_only_custom_keychain(*k))
+                .cloned(),
+            |&(k, _), _| k == KeychainKind::Internal,
+        )
+    }
+
+    /// Return the balance of a single `keychain`, see [`get_balance`].
+    ///
+    /// [`get_balance`]: Self::get_balance
+    pub fn get_keychain_balance(&self, keychain: KeychainKind) -> Balance {
+        self.indexed_graph.graph().balance(
+            &self.chain,
+            self.chain.tip().map(|cp| cp.block_id()).unwrap_or_default(),
+            self.indexed_graph
+                .index
+                .outpoints()
+                .iter()
+                .filter(|((k, _), _)| *k == keychain)
+                .cloned(),
             |&(k, _), _| k == KeychainKind::Internal,
         )
     }
@@ -783,14 +1461,20 @@ impl<D> Wallet<D> {
         ordering: SignerOrdering,
         signer: Arc<dyn TransactionSigner>,
     ) {
-        let signers = match keychain {
-            KeychainKind::External => Arc::make_mut(&mut self.signers),
-            KeychainKind::Internal => Arc::make_mut(&mut self.change_signers),
-        };
-
+        let signers = Arc::make_mut(self.signers.entry(keychain).or_default());
         signers.add_external(signer.id(&self.secp), ordering, signer);
     }
 
+    /// Add a hash preimage to the wallet, to satisfy the hash locks of a keychain's descriptor
+    ///
+    /// Like the private keys, preimages are only kept in memory. When signing, they are added to
+    /// the PSBT inputs whose descriptor requires them, so that HTLC-style descriptors can be
+    /// finalized. Preimages can also be provided for a single call with [`SignOptions::preimages`].
+    pub fn add_preimage(&mut self, keychain: KeychainKind, preimage: [u8; 32]) {
+        let signers = Arc::make_mut(self.signers.entry(keychain).or_default());
+        signers.add_preimage(preimage);
+    }
+
     /// Get the signers
     ///
     /// ## Example
@@ -807,10 +1491,7 @@ impl<D> Wallet<D> {
     /// Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     pub fn get_signers(&self, keychain: KeychainKind) -> Arc<SignersContainer> {
-        match keychain {
-            KeychainKind::External => Arc::clone(&self.signers),
-            KeychainKind::Internal => Arc::clone(&self.change_signers),
-        }
+        self.signers.get(&keychain).cloned().unwrap_or_default()
     }
 
     /// Start building a transaction.
@@ -847,6 +1528,65 @@ impl<D> Wallet<D> {
         }
     }
 
+    /// Combine the timelocks required by the spending policies of the keychains whose outputs
+    /// can be spent by the transaction, following the policy paths selected in `params`
+    ///
+    /// These are the keychains that coin selection can pick from, which are allowed by the change
+    /// spend policy and are not watch-only custom keychains, plus the ones of the UTXOs added
+    /// manually.
+    pub(crate) fn policy_requirements(&self, params: &TxParams) -> Result<Condition, Error> {
+        let manually_selected = params
+            .utxos
+            .iter()
+            .filter_map(|weighted_utxo| match &weighted_utxo.utxo {
+                Utxo::Local(local_utxo) => Some(local_utxo.keychain),
+                Utxo::Foreign { .. } => None,
+            })
+            .collect::<HashSet<_>>();
+
+        let mut requirements = Condition::default();
+        for (&keychain, descriptor) in self.indexed_graph.index.keychains() {
+            let is_manually_selected = manually_selected.contains(&keychain);
+            if !is_manually_selected
+                && (params.manually_selected_only
+                    || !params.change_policy.allows_keychain(keychain)
+                    || self.is_watch_only_custom_keychain(keychain))
+            {
+                continue;
+            }
+
+            let signers = self.signers.get(&keychain).cloned().unwrap_or_default();
+            let policy = descriptor
+                .extract_policy(&signers, BuildSatisfaction::None, &self.secp)?
+                .expect("descriptor policy must exist");
+            let policy_path = params.policy_paths.get(&keychain);
+
+            // The outputs of this keychain can be spent, but its policy requires a policy path
+            // that hasn't been provided
+            if policy.requires_path() && policy_path.is_none() {
+                return Err(Error::SpendingPolicyRequired(keychain));
+            }
+
+            let keychain_requirements =
+                policy.get_condition(policy_path.unwrap_or(&BTreeMap::new()))?;
+            requirements = requirements.merge(&keychain_requirements)?;
+        }
+
+        Ok(requirements)
+    }
+
+    /// Whether `keychain` is a [`KeychainKind::Custom`] keychain the wallet has no signers for
+    ///
+    /// The outputs of watch-only custom keychains are never picked by coin selection, and can
+    /// only be spent by adding them manually.
+    fn is_watch_only_custom_keychain(&self, keychain: KeychainKind) -> bool {
+        matches!(keychain, KeychainKind::Custom(_))
+            && self
+                .signers
+                .get(&keychain)
+                .map_or(true, |signers| signers.signers().is_empty())
+    }
+
     pub(crate) fn create_tx<Cs: coin_selection::CoinSelectionAlgorithm>(
         &mut self,
         coin_selection: Cs,
@@ -855,70 +1595,7 @@ impl<D> Wallet<D> {
     where
         D: PersistBackend<ChangeSet>,
     {
-        let external_descriptor = self
-            .indexed_graph
-            .index
-            .keychains()
-            .get(&KeychainKind::External)
-            .expect("must exist");
-        let internal_descriptor = self
-            .indexed_graph
-            .index
-            .keychains()
-            .get(&KeychainKind::Internal);
-
-        let external_policy = external_descriptor
-            .extract_policy(&self.signers, BuildSatisfaction::None, &self.secp)?
-            .unwrap();
-        let internal_policy = internal_descriptor
-            .as_ref()
-            .map(|desc| {
-                Ok::<_, Error>(
-                    desc.extract_policy(&self.change_signers, BuildSatisfaction::None, &self.secp)?
-                        .unwrap(),
-                )
-            })
-            .transpose()?;
-
-        // The policy allows spending external outputs, but it requires a policy path that hasn't been
-        // provided
-        if params.change_policy != tx_builder::ChangeSpendPolicy::OnlyChange
-            && external_policy.requires_path()
-            && params.external_policy_path.is_none()
-        {
-            return Err(Error::SpendingPolicyRequired(KeychainKind::External));
-        };
-        // Same for the internal_policy path, if present
-        if let Some(internal_policy) = &internal_policy {
-            if params.change_policy != tx_builder::ChangeSpendPolicy::ChangeForbidden
-                && internal_policy.requires_path()
-                && params.internal_policy_path.is_none()
-            {
-                return Err(Error::SpendingPolicyRequired(KeychainKind::Internal));
-            };
-        }
-
-        let external_requirements = external_policy.get_condition(
-            params
-                .external_policy_path
-                .as_ref()
-                .unwrap_or(&BTreeMap::new()),
-        )?;
-        let internal_requirements = internal_policy
-            .map(|policy| {
-                Ok::<_, Error>(
-                    policy.get_condition(
-                        params
-                            .internal_policy_path
-                            .as_ref()
-                            .unwrap_or(&BTreeMap::new()),
-                    )?,
-                )
-            })
-            .transpose()?;
-
-        let requirements =
-            external_requirements.merge(&internal_requirements.unwrap_or_default())?;
+        let requirements = self.policy_requirements(&params)?;
         debug!("Policy requirements: {:?}", requirements);
 
         let version = match params.version {
@@ -1054,7 +1731,26 @@ impl<D> Wallet<D> {
         let mut outgoing: u64 = 0;
         let mut received: u64 = 0;
 
-        let recipients = params.recipients.iter().map(|(r, v)| (r, *v));
+        // The output keys of silent payments depend on the inputs, so until they are selected
+        // a placeholder of the same size is used
+        let silent_payment_placeholders = params
+            .silent_payments
+            .iter()
+            .map(|(address, value)| {
+                if !address.is_valid_for_network(self.network) {
+                    return Err(Error::SilentPayment(SilentPaymentError::InvalidNetwork(
+                        self.network,
+                    )));
+                }
+                let (placeholder, _) = address.spend_pubkey.x_only_public_key();
+                Ok((silent_payments::output_script(placeholder), *value))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let recipients = params
+            .recipients
+            .iter()
+            .chain(&silent_payment_placeholders)
+            .map(|(r, v)| (r, *v));
 
         for (index, (script_pubkey, value)) in recipients.enumerate() {
             if !params.allow_dust
@@ -1092,13 +1788,33 @@ impl<D> Wallet<D> {
         fee_amount += fee_rate.fee_wu(Weight::from_wu(2));
 
         if params.change_policy != tx_builder::ChangeSpendPolicy::ChangeAllowed
-            && internal_descriptor.is_none()
+            && self.public_descriptor(KeychainKind::Internal).is_none()
         {
             return Err(Error::Generic(
                 "The `change_policy` can be set only if the wallet has a change_descriptor".into(),
             ));
         }
 
+        // spending through a specific tap leaf replaces the satisfaction weight of the whole tree
+        let tap_leaf_weights = params
+            .tap_leaves
+            .iter()
+            .map(|(&keychain, &leaf_index)| {
+                self.public_descriptor(keychain)
+                    .and_then(|desc| tap_leaf_satisfaction_weight(desc, leaf_index))
+                    .map(|weight| (keychain, weight))
+                    .ok_or(Error::InvalidTapLeaf(keychain, leaf_index))
+            })
+            .collect::<Result<BTreeMap<_, _>, _>>()?;
+        let with_tap_leaf_weight = |mut weighted_utxo: WeightedUtxo| {
+            if let Utxo::Local(utxo) = &weighted_utxo.utxo {
+                if let Some(&weight) = tap_leaf_weights.get(&utxo.keychain) {
+                    weighted_utxo.satisfaction_weight = weight;
+                }
+            }
+            weighted_utxo
+        };
+
         let (required_utxos, optional_utxos) = self.preselect_utxos(
             params.change_policy,
             &params.unspendable,
@@ -1109,6 +1825,15 @@ impl<D> Wallet<D> {
             current_height.map(absolute::LockTime::to_consensus_u32),
         );
 
+        let required_utxos = required_utxos
+            .into_iter()
+            .map(with_tap_leaf_weight)
+            .collect::<Vec<_>>();
+        let optional_utxos = optional_utxos
+            .into_iter()
+            .map(with_tap_leaf_weight)
+            .collect::<Vec<_>>();
+
         // get drain script
         let drain_script = match params.drain_to {
             Some(ref drain_recipient) => drain_recipient.clone(),
@@ -1148,6 +1873,22 @@ impl<D> Wallet<D> {
             })
             .collect();
 
+        if !params.silent_payments.is_empty() {
+            let recipients = params
+                .silent_payments
+                .iter()
+                .map(|(address, _)| *address)
+                .collect::<Vec<_>>();
+            let output_keys =
+                self.silent_payment_output_keys(&coin_selection.selected, &recipients)?;
+            for (output, key) in tx.output[params.recipients.len()..]
+                .iter_mut()
+                .zip(output_keys)
+            {
+                output.script_pubkey = silent_payments::output_script(key);
+            }
+        }
+
         if tx.output.is_empty() {
             // Uh oh, our transaction has no outputs.
             // We allow this when:
@@ -1432,15 +2173,12 @@ impl<D> Wallet<D> {
             return Err(Error::Signer(signer::SignerError::NonStandardSighash));
         }
 
-        for signer in self
-            .signers
-            .signers()
-            .iter()
-            .chain(self.change_signers.signers().iter())
-        {
+        for signer in self.signers.values().flat_map(|signers| signers.signers()) {
             signer.sign_transaction(psbt, &sign_options, &self.secp)?;
         }
 
+        self.update_psbt_with_preimages(psbt, &sign_options.preimages);
+
         // attempt to finalize
         if sign_options.try_finalize {
             self.finalize_psbt(psbt, sign_options)
@@ -1451,13 +2189,10 @@ impl<D> Wallet<D> {
 
     /// Return the spending policies for the wallet's descriptor
     pub fn policies(&self, keychain: KeychainKind) -> Result<Option<Policy>, Error> {
-        let signers = match keychain {
-            KeychainKind::External => &self.signers,
-            KeychainKind::Internal => &self.change_signers,
-        };
+        let signers = self.signers.get(&keychain).cloned().unwrap_or_default();
 
         match self.public_descriptor(keychain) {
-            Some(desc) => Ok(desc.extract_policy(signers, BuildSatisfaction::None, &self.secp)?),
+            Some(desc) => Ok(desc.extract_policy(&signers, BuildSatisfaction::None, &self.secp)?),
             None => Ok(None),
         }
     }
@@ -1470,6 +2205,22 @@ impl<D> Wallet<D> {
         self.indexed_graph.index.keychains().get(&keychain)
     }
 
+    /// Return the external and internal descriptors combined into a single [BIP389] multipath
+    /// descriptor, the inverse of [`new_multipath`]
+    ///
+    /// Like [`public_descriptor`], the returned descriptor doesn't contain any secret key. Returns
+    /// `None` if the wallet has no internal descriptor, or if the two descriptors differ by more
+    /// than one derivation step of their keys.
+    ///
+    /// [BIP389]: https://github.com/bitcoin/bips/blob/master/bip-0389.mediawiki
+    /// [`new_multipath`]: Self::new_multipath
+    /// [`public_descriptor`]: Self::public_descriptor
+    pub fn multipath_descriptor(&self) -> Option<ExtendedDescriptor> {
+        let external = self.public_descriptor(KeychainKind::External)?;
+        let internal = self.public_descriptor(KeychainKind::Internal)?;
+        into_multipath_descriptor(&[external, internal])
+    }
+
     /// Finalize a PSBT, i.e., for each input determine if sufficient data is available to pass
     /// validation and construct the respective `scriptSig` or `scriptWitness`. Please refer to
     /// [BIP174](https://github.com/bitcoin/bips/blob/master/bip-0174.mediawiki#Input_Finalizer)
@@ -1561,7 +2312,15 @@ impl<D> Wallet<D> {
                         }
                     }
                 }
-                None => finished = false,
+                // A key-spend signature is enough to finalize a taproot input, even if it's not
+                // derived from our descriptors, like the outputs of silent payments
+                None => match (&psbt_input.tap_key_sig, psbt.get_utxo_for(n)) {
+                    (Some(sig), Some(txout)) if txout.script_pubkey.is_v1_p2tr() => {
+                        let witness = Witness::from_slice(&[sig.to_vec()]);
+                        psbt.inputs[n].final_script_witness = Some(witness);
+                    }
+                    _ => finished = false,
+                },
             }
         }
 
@@ -1624,6 +2383,41 @@ impl<D> Wallet<D> {
         descriptor.at_derivation_index(child).ok()
     }
 
+    /// Add to the PSBT inputs we own the preimages required by their descriptor, taken from the
+    /// keychain's signers container and from `extra_preimages`
+    fn update_psbt_with_preimages(
+        &self,
+        psbt: &mut psbt::PartiallySignedTransaction,
+        extra_preimages: &signer::Preimages,
+    ) {
+        for n in 0..psbt.inputs.len() {
+            let psbt_input = &psbt.inputs[n];
+            if psbt_input.final_script_sig.is_some() || psbt_input.final_script_witness.is_some() {
+                continue;
+            }
+            let (keychain, child) = match psbt.get_utxo_for(n).and_then(|txout| {
+                self.indexed_graph
+                    .index
+                    .index_of_spk(&txout.script_pubkey)
+                    .copied()
+            }) {
+                Some(derivation) => derivation,
+                None => continue,
+            };
+            let descriptor = match self
+                .get_descriptor_for_keychain(keychain)
+                .at_derivation_index(child)
+            {
+                Ok(descriptor) => descriptor,
+                Err(_) => continue,
+            };
+
+            let mut preimages = self.get_signers(keychain).preimages().clone();
+            preimages.extend(extra_preimages);
+            preimages.update_psbt_input(&descriptor, &mut psbt.inputs[n]);
+        }
+    }
+
     fn get_available_utxos(&self) -> Vec<(LocalUtxo, usize)> {
         self.list_unspent()
             .map(|utxo| {
@@ -1639,6 +2433,124 @@ impl<D> Wallet<D> {
             .collect()
     }
 
+    // Derive the output keys of the silent payments from the private keys of the inputs
+    fn silent_payment_output_keys(
+        &self,
+        selected: &[Utxo],
+        recipients: &[SilentPaymentAddress],
+    ) -> Result<Vec<XOnlyPublicKey>, Error> {
+        let mut input_keys = Vec::new();
+        for utxo in selected {
+            match utxo {
+                Utxo::Local(utxo) => {
+                    let descriptor = self
+                        .get_descriptor_for_keychain(utxo.keychain)
+                        .at_derivation_index(utxo.derivation_index)
+                        .map_err(|_| Error::InvalidOutpoint(utxo.outpoint))?;
+                    if let Some(key) = self.silent_payment_input_key(utxo, &descriptor)? {
+                        input_keys.push(key);
+                    }
+                }
+                Utxo::Foreign {
+                    outpoint,
+                    psbt_input,
+                } => {
+                    let script_pubkey = psbt_input
+                        .witness_utxo
+                        .as_ref()
+                        .map(|txout| txout.script_pubkey.clone())
+                        .or_else(|| {
+                            psbt_input.non_witness_utxo.as_ref().and_then(|tx| {
+                                tx.output
+                                    .get(outpoint.vout as usize)
+                                    .map(|txout| txout.script_pubkey.clone())
+                            })
+                        });
+                    if script_pubkey.map_or(true, |spk| {
+                        spk.is_p2pkh() || spk.is_p2sh() || spk.is_v0_p2wpkh() || spk.is_v1_p2tr()
+                    }) {
+                        return Err(Error::SilentPayment(SilentPaymentError::MissingInputKey(
+                            *outpoint,
+                        )));
+                    }
+                }
+            }
+        }
+
+        let outpoints = selected.iter().map(Utxo::outpoint).collect::<Vec<_>>();
+        Ok(silent_payments::create_outputs(
+            &self.secp,
+            &outpoints,
+            &input_keys,
+            recipients,
+        )?)
+    }
+
+    // The private key of an input for silent payments, tweaked for taproot, or `None` if the
+    // input doesn't contribute to the output keys
+    fn silent_payment_input_key(
+        &self,
+        utxo: &LocalUtxo,
+        descriptor: &DerivedDescriptor,
+    ) -> Result<Option<(SecretKey, bool)>, Error> {
+        let (key, merkle_root) = match descriptor {
+            Descriptor::Pkh(pkh) => (pkh.as_inner(), None),
+            Descriptor::Wpkh(wpkh) => (wpkh.as_inner(), None),
+            Descriptor::Sh(sh) => match sh.as_inner() {
+                ShInner::Wpkh(wpkh) => (wpkh.as_inner(), None),
+                _ => return Ok(None),
+            },
+            Descriptor::Tr(tr) => (tr.internal_key(), Some(tr.spend_info().merkle_root())),
+            _ => return Ok(None),
+        };
+        let pubkey = key
+            .derive_public_key(&self.secp)
+            .map_err(|_| Error::InvalidOutpoint(utxo.outpoint))?
+            .inner;
+
+        let secret_key = self
+            .signers
+            .get(&utxo.keychain)
+            .into_iter()
+            .flat_map(|signers| signers.signers())
+            .filter_map(|signer| match signer.descriptor_secret_key()? {
+                DescriptorSecretKey::Single(single) => Some(single.key.inner),
+                DescriptorSecretKey::XPrv(xprv) => {
+                    let index = utxo.derivation_index;
+                    let path = match xprv.wildcard {
+                        Wildcard::None => xprv.derivation_path.clone(),
+                        Wildcard::Unhardened => xprv
+                            .derivation_path
+                            .child(ChildNumber::from_normal_idx(index).ok()?),
+                        Wildcard::Hardened => xprv
+                            .derivation_path
+                            .child(ChildNumber::from_hardened_idx(index).ok()?),
+                    };
+                    Some(xprv.xkey.derive_priv(&self.secp, &path).ok()?.private_key)
+                }
+                DescriptorSecretKey::MultiXPrv(_) => None,
+            })
+            .find(|secret_key| {
+                let derived = secret_key.public_key(&self.secp);
+                match merkle_root {
+                    Some(_) => derived.x_only_public_key().0 == pubkey.x_only_public_key().0,
+                    None => derived == pubkey,
+                }
+            })
+            .ok_or(Error::SilentPayment(SilentPaymentError::MissingInputKey(
+                utxo.outpoint,
+            )))?;
+
+        Ok(Some(match merkle_root {
+            Some(merkle_root) => {
+                let keypair = KeyPair::from_secret_key(&self.secp, &secret_key);
+                let tweaked = keypair.tap_tweak(&self.secp, merkle_root).to_inner();
+                (tweaked.secret_key(), true)
+            }
+            None => (secret_key, false),
+        }))
+    }
+
     /// Given the options returns the list of utxos that must be used to form the
     /// transaction and any further that may be used if needed.
     #[allow(clippy::too_many_arguments)]
@@ -1715,6 +2627,7 @@ impl<D> Wallet<D> {
         let mut i = 0;
         may_spend.retain(|u| {
             let retain = change_policy.is_satisfied_by(&u.0)
+                && !self.is_watch_only_custom_keychain(u.0.keychain)
                 && !unspendable.contains(&u.0.outpoint)
                 && satisfies_confirmed[i];
             i += 1;
@@ -1814,10 +2727,58 @@ impl<D> Wallet<D> {
         }
 
         self.update_psbt_with_descriptor(&mut psbt)?;
+        self.restrict_psbt_to_tap_leaves(&mut psbt, &params.tap_leaves);
 
         Ok(psbt)
     }
 
+    /// Remove the `tap_scripts` and `tap_key_origins` that are not needed to spend the inputs
+    /// through the tap leaves selected with [`TxBuilder::tap_leaf`]
+    fn restrict_psbt_to_tap_leaves(
+        &self,
+        psbt: &mut psbt::PartiallySignedTransaction,
+        tap_leaves: &BTreeMap<KeychainKind, usize>,
+    ) {
+        if tap_leaves.is_empty() {
+            return;
+        }
+
+        for index in 0..psbt.inputs.len() {
+            let (keychain, child) = match psbt
+                .get_utxo_for(index)
+                .and_then(|utxo| self.indexed_graph.index.index_of_spk(&utxo.script_pubkey))
+            {
+                Some(&keychain_index) => keychain_index,
+                None => continue,
+            };
+            let leaf_hash = match tap_leaves.get(&keychain).and_then(|&leaf_index| {
+                let desc = self
+                    .get_descriptor_for_keychain(keychain)
+                    .at_derivation_index(child)
+                    .expect("child can't be hardened");
+                tap_leaf_hash(&desc, leaf_index)
+            }) {
+                Some(leaf_hash) => leaf_hash,
+                None => continue,
+            };
+
+            let psbt_input = &mut psbt.inputs[index];
+            psbt_input.tap_scripts.retain(|_, (script, leaf_version)| {
+                taproot::TapLeafHash::from_script(script, *leaf_version) == leaf_hash
+            });
+            // The internal key's origin is kept, since it can still sign for the key path
+            let internal_key = psbt_input.tap_internal_key;
+            psbt_input.tap_key_origins.retain(|key, (leaf_hashes, _)| {
+                Some(*key) == internal_key
+                    || leaf_hashes.is_empty()
+                    || leaf_hashes.contains(&leaf_hash)
+            });
+            for (leaf_hashes, _) in psbt_input.tap_key_origins.values_mut() {
+                leaf_hashes.retain(|lh| *lh == leaf_hash);
+            }
+        }
+    }
+
     /// get the corresponding PSBT Input for a LocalUtxo
     pub fn get_psbt_input(
         &self,
@@ -1949,6 +2910,29 @@ impl<D> Wallet<D> {
         Ok(())
     }
 
+    /// Applies a block emitted by a block-based chain source at `height` to the wallet, and stages
+    /// the changes (but does not [`commit`] them).
+    ///
+    /// The block must connect to the wallet's chain through its previous block hash. Only the
+    /// transactions relevant to the wallet are kept. When syncing a new wallet, start from
+    /// [`birthday_start_height`] to skip the blocks before the wallet's birthday.
+    ///
+    /// [`commit`]: Self::commit
+    /// [`birthday_start_height`]: Self::birthday_start_height
+    pub fn apply_block(&mut self, block: &Block, height: u32) -> Result<(), CannotConnectError>
+    where
+        D: PersistBackend<ChangeSet>,
+    {
+        let chain_update = CheckPoint::from_header(&block.header, height).into_update(false);
+        let mut changeset = ChangeSet::from(self.chain.apply_update(chain_update)?);
+        changeset.append(ChangeSet::from(
+            self.indexed_graph
+                .apply_block_relevant(block.clone(), height),
+        ));
+        self.persist.stage(changeset);
+        Ok(())
+    }
+
     /// Commits all currently [`staged`] changed to the persistence backend returning and error when
     /// this fails.
     ///
@@ -0,0 +1,1557 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! MuSig2 multi-signatures
+//!
+//! This module implements the [BIP327] MuSig2 protocol, which lets a group of participants
+//! produce a single BIP340 signature for the aggregate of their public keys, so that a taproot
+//! output can be spent through the key path by multiple parties.
+//!
+//! Aggregate keys are used in descriptors through their [BIP328] synthetic xpub, returned by
+//! [`KeyAggContext::to_xpub`]: a descriptor like `tr(<synthetic xpub>/0/*)` derives a new
+//! aggregate key for every address.
+//!
+//! Each participant adds a [`MusigSigner`] to their wallet. Signing takes two rounds, with the
+//! PSBT being passed around between the participants (for instance with a
+//! [`Coordinator`](super::coordinator::Coordinator)) between them:
+//!
+//! 1. The first time [`Wallet::sign`](super::Wallet::sign) is called each signer adds its public
+//!    nonce to the PSBT, keeping the secret nonce in memory.
+//! 2. Once the nonces of all the participants are in the PSBT, signing again adds the partial
+//!    signatures.
+//! 3. Once all the partial signatures are in the PSBT, any signer aggregates them into the final
+//!    key-spend signature, and the PSBT can be finalized.
+//!
+//! Nonces and partial signatures are stored in the PSBT inputs using the [BIP373] fields.
+//!
+//! ```
+//! # use std::sync::Arc;
+//! # use bdk::bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+//! # use bdk::bitcoin::Network;
+//! # use bdk::signer::SignerOrdering;
+//! # use bdk::wallet::musig::{KeyAggContext, MusigSigner};
+//! # use bdk::{KeychainKind, Wallet};
+//! let secp = Secp256k1::new();
+//! let secret_key = SecretKey::from_slice(&[0x01; 32])?;
+//! let cosigner = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[0x02; 32])?);
+//!
+//! let key_agg = KeyAggContext::new(
+//!     &secp,
+//!     vec![PublicKey::from_secret_key(&secp, &secret_key), cosigner],
+//! )?;
+//! let descriptor = format!("tr({}/0/*)", key_agg.to_xpub(Network::Testnet));
+//!
+//! let mut wallet = Wallet::new_no_persist(&descriptor, None, Network::Testnet)?;
+//! wallet.add_signer(
+//!     KeychainKind::External,
+//!     SignerOrdering::default(),
+//!     Arc::new(MusigSigner::new(&secp, secret_key, key_agg)?),
+//! );
+//! # Ok::<_, Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [BIP327]: https://github.com/bitcoin/bips/blob/master/bip-0327.mediawiki
+//! [BIP328]: https://github.com/bitcoin/bips/blob/master/bip-0328.mediawiki
+//! [BIP373]: https://github.com/bitcoin/bips/blob/master/bip-0373.mediawiki
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use bitcoin::bip32::{ChainCode, ChildNumber, DerivationPath, ExtendedPubKey, Fingerprint};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::psbt::{self, raw};
+use bitcoin::secp256k1::{constants, schnorr, Parity, PublicKey, Scalar, SecretKey};
+use bitcoin::taproot::{TapNodeHash, TapTweakHash};
+use bitcoin::{key::XOnlyPublicKey, Network};
+use rand::RngCore;
+
+use super::utils::SecpCtx;
+use crate::collections::BTreeMap;
+
+/// Type of the PSBT input field listing the participants of an aggregate key (BIP373)
+pub const PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS: u8 = 0x1a;
+/// Type of the PSBT input field holding the public nonce of a participant (BIP373)
+pub const PSBT_IN_MUSIG2_PUB_NONCE: u8 = 0x1b;
+/// Type of the PSBT input field holding the partial signature of a participant (BIP373)
+pub const PSBT_IN_MUSIG2_PARTIAL_SIG: u8 = 0x1c;
+
+/// Errors in the MuSig2 protocol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MusigError {
+    /// The public keys can't be aggregated, either because the list is empty or because they
+    /// sum to the point at infinity
+    InvalidPublicKeys,
+    /// The tweak is out of range, or tweaking results in the point at infinity
+    InvalidTweak,
+    /// The public nonce can't be parsed
+    InvalidNonce,
+    /// The partial signature can't be parsed or isn't valid
+    InvalidPartialSignature,
+    /// The public key isn't one of the aggregated keys
+    UnknownPublicKey,
+    /// The secret nonce doesn't belong to the secret key, or its public nonce isn't the one in
+    /// the PSBT
+    InvalidSecretNonce,
+    /// The secret nonce of the signing session is not known, for instance because the signer
+    /// has been restarted after adding its public nonce to the PSBT
+    MissingSecretNonce,
+}
+
+impl fmt::Display for MusigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPublicKeys => write!(f, "Invalid public keys"),
+            Self::InvalidTweak => write!(f, "Invalid tweak"),
+            Self::InvalidNonce => write!(f, "Invalid public nonce"),
+            Self::InvalidPartialSignature => write!(f, "Invalid partial signature"),
+            Self::UnknownPublicKey => write!(f, "The public key isn't one of the aggregated keys"),
+            Self::InvalidSecretNonce => write!(f, "Invalid secret nonce"),
+            Self::MissingSecretNonce => write!(f, "Missing secret nonce for the signing session"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MusigError {}
+
+// An integer modulo the order of the curve, `None` being zero
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ModN(Option<SecretKey>);
+
+impl ModN {
+    const ZERO: ModN = ModN(None);
+
+    fn one() -> Self {
+        ModN(Some(
+            SecretKey::from_slice(&Scalar::ONE.to_be_bytes()).expect("one is in range"),
+        ))
+    }
+
+    // Parse a big-endian integer, failing if it's not lower than the order
+    fn from_bytes(bytes: [u8; 32]) -> Option<Self> {
+        if bytes == [0; 32] {
+            return Some(ModN::ZERO);
+        }
+        SecretKey::from_slice(&bytes).ok().map(|sk| ModN(Some(sk)))
+    }
+
+    // Reduce a big-endian integer modulo the order
+    fn reduce(mut bytes: [u8; 32]) -> Self {
+        // 2^256 < 2n, so subtracting the order once is enough
+        if bytes >= constants::CURVE_ORDER {
+            let mut borrow = 0;
+            for i in (0..32).rev() {
+                let diff = bytes[i] as i16 - constants::CURVE_ORDER[i] as i16 - borrow;
+                borrow = if diff < 0 { 1 } else { 0 };
+                bytes[i] = (diff + 256 * borrow) as u8;
+            }
+        }
+        Self::from_bytes(bytes).expect("reduced modulo the order")
+    }
+
+    fn to_bytes(self) -> [u8; 32] {
+        self.0.map(|sk| sk.secret_bytes()).unwrap_or([0; 32])
+    }
+
+    fn add(self, other: Self) -> Self {
+        match (self.0, other.0) {
+            (Some(a), Some(b)) => ModN(a.add_tweak(&Scalar::from(b)).ok()),
+            (None, _) => other,
+            (_, None) => self,
+        }
+    }
+
+    fn mul(self, other: Self) -> Self {
+        match (self.0, other.0) {
+            (Some(a), Some(b)) => ModN(Some(
+                a.mul_tweak(&Scalar::from(b))
+                    .expect("the order is prime, the product can't be zero"),
+            )),
+            _ => ModN::ZERO,
+        }
+    }
+
+    fn negate(self) -> Self {
+        ModN(self.0.map(SecretKey::negate))
+    }
+
+    fn negate_if(self, negate: bool) -> Self {
+        if negate {
+            self.negate()
+        } else {
+            self
+        }
+    }
+
+    // Multiply the generator, `None` being the point at infinity
+    fn mul_base(self, secp: &SecpCtx) -> Option<PublicKey> {
+        self.0.map(|sk| PublicKey::from_secret_key(secp, &sk))
+    }
+}
+
+// Multiply a point by a scalar, `None` being the point at infinity
+fn point_mul(secp: &SecpCtx, point: &PublicKey, k: ModN) -> Option<PublicKey> {
+    k.0.map(|k| {
+        point
+            .mul_tweak(secp, &Scalar::from(k))
+            .expect("k is not zero")
+    })
+}
+
+// Add two points, `None` being the point at infinity
+fn point_add(a: Option<PublicKey>, b: Option<PublicKey>) -> Option<PublicKey> {
+    match (a, b) {
+        (Some(a), Some(b)) => a.combine(&b).ok(),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+fn has_even_y(point: &PublicKey) -> bool {
+    point.x_only_public_key().1 == Parity::Even
+}
+
+fn xbytes(point: &PublicKey) -> [u8; 32] {
+    point.x_only_public_key().0.serialize()
+}
+
+fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag[..]);
+    engine.input(&tag[..]);
+    for chunk in data {
+        engine.input(chunk);
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Sort public keys as described in BIP327, so that the aggregate key doesn't depend on the
+/// order in which the participants' keys are collected
+pub fn sort_pubkeys(pubkeys: &mut [PublicKey]) {
+    pubkeys.sort_by_key(PublicKey::serialize);
+}
+
+/// Aggregation of the participants' public keys, together with the tweaks applied to it
+///
+/// This is the "KeyAgg Context" of BIP327.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyAggContext {
+    pubkeys: Vec<PublicKey>,
+    keys_hash: [u8; 32],
+    second_key: Option<PublicKey>,
+    q: PublicKey,
+    // Whether the accumulated sign `gacc` is -1
+    gacc_negated: bool,
+    tacc: ModN,
+}
+
+impl KeyAggContext {
+    /// Aggregate the participants' public keys, in the given order
+    ///
+    /// See [`sort_pubkeys`] to make the aggregate key independent of the order.
+    pub fn new(secp: &SecpCtx, pubkeys: Vec<PublicKey>) -> Result<Self, MusigError> {
+        let first = pubkeys.first().ok_or(MusigError::InvalidPublicKeys)?;
+        let serialized = pubkeys
+            .iter()
+            .flat_map(|pk| pk.serialize())
+            .collect::<Vec<_>>();
+        let keys_hash = tagged_hash("KeyAgg list", &[&serialized]);
+        let second_key = pubkeys.iter().find(|pk| *pk != first).cloned();
+
+        let mut ctx = KeyAggContext {
+            keys_hash,
+            second_key,
+            // placeholder, replaced below
+            q: *first,
+            gacc_negated: false,
+            tacc: ModN::ZERO,
+            pubkeys: Vec::new(),
+        };
+        let q = pubkeys.iter().fold(None, |q, pk| {
+            point_add(q, point_mul(secp, pk, ctx.key_agg_coeff(pk)))
+        });
+        ctx.q = q.ok_or(MusigError::InvalidPublicKeys)?;
+        ctx.pubkeys = pubkeys;
+
+        Ok(ctx)
+    }
+
+    /// The participants' public keys
+    pub fn pubkeys(&self) -> &[PublicKey] {
+        &self.pubkeys
+    }
+
+    /// The aggregate public key, including the tweaks applied so far
+    pub fn aggregated_pubkey(&self) -> PublicKey {
+        self.q
+    }
+
+    /// The x-only aggregate public key, including the tweaks applied so far
+    pub fn x_only_public_key(&self) -> XOnlyPublicKey {
+        self.q.x_only_public_key().0
+    }
+
+    fn key_agg_coeff(&self, pubkey: &PublicKey) -> ModN {
+        if Some(pubkey) == self.second_key.as_ref() {
+            ModN::one()
+        } else {
+            ModN::reduce(tagged_hash(
+                "KeyAgg coefficient",
+                &[&self.keys_hash, &pubkey.serialize()],
+            ))
+        }
+    }
+
+    /// Tweak the aggregate key by adding `tweak * G`
+    ///
+    /// With `is_xonly` the tweak is applied to the x-only key, as done by BIP341 for the output
+    /// key, otherwise it's applied to the plain key, as done by BIP32 derivations.
+    pub fn apply_tweak(
+        &mut self,
+        secp: &SecpCtx,
+        tweak: [u8; 32],
+        is_xonly: bool,
+    ) -> Result<(), MusigError> {
+        let negate = is_xonly && !has_even_y(&self.q);
+        let tweak = ModN::from_bytes(tweak).ok_or(MusigError::InvalidTweak)?;
+        let q = if negate { self.q.negate(secp) } else { self.q };
+        self.q = point_add(Some(q), tweak.mul_base(secp)).ok_or(MusigError::InvalidTweak)?;
+        self.gacc_negated ^= negate;
+        self.tacc = tweak.add(self.tacc.negate_if(negate));
+
+        Ok(())
+    }
+
+    /// Apply the BIP341 tweak, turning the aggregate key into the output key of a taproot
+    /// output with the given merkle root
+    pub fn tap_tweak(
+        &mut self,
+        secp: &SecpCtx,
+        merkle_root: Option<TapNodeHash>,
+    ) -> Result<(), MusigError> {
+        let tweak = TapTweakHash::from_key_and_tweak(self.x_only_public_key(), merkle_root);
+        self.apply_tweak(secp, tweak.to_byte_array(), true)
+    }
+
+    /// The BIP328 synthetic xpub of the aggregate key, to be used in descriptors
+    pub fn to_xpub(&self, network: Network) -> ExtendedPubKey {
+        ExtendedPubKey {
+            network,
+            depth: 0,
+            parent_fingerprint: Fingerprint::default(),
+            child_number: ChildNumber::from_normal_idx(0).expect("0 is a valid index"),
+            public_key: self.q,
+            chain_code: ChainCode::from(sha256::Hash::hash(b"MuSig2MuSig2MuSig2").to_byte_array()),
+        }
+    }
+
+    /// The fingerprint of the synthetic xpub
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.to_xpub(Network::Bitcoin).fingerprint()
+    }
+
+    /// Derive a child of the synthetic xpub, applying the BIP32 tweaks to the aggregate key
+    pub fn derive(&self, secp: &SecpCtx, path: &DerivationPath) -> Result<Self, MusigError> {
+        let mut ctx = self.clone();
+        let mut xpub = self.to_xpub(Network::Bitcoin);
+        for child in path {
+            let (tweak, chain_code) = xpub
+                .ckd_pub_tweak(*child)
+                .map_err(|_| MusigError::InvalidTweak)?;
+            ctx.apply_tweak(secp, tweak.secret_bytes(), false)?;
+            xpub.public_key = ctx.q;
+            xpub.chain_code = chain_code;
+        }
+
+        Ok(ctx)
+    }
+}
+
+/// Secret nonce of a participant for one signing session
+///
+/// It can't be copied, and it's consumed when signing: reusing a nonce to sign two different
+/// messages leaks the secret key.
+pub struct SecNonce {
+    k1: ModN,
+    k2: ModN,
+    pubkey: PublicKey,
+}
+
+impl fmt::Debug for SecNonce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecNonce")
+            .field("pubkey", &self.pubkey)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Public nonce of a participant for one signing session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PubNonce {
+    r1: PublicKey,
+    r2: PublicKey,
+}
+
+impl PubNonce {
+    /// Serialize the nonce as two compressed points
+    pub fn serialize(&self) -> [u8; 66] {
+        let mut bytes = [0; 66];
+        bytes[..33].copy_from_slice(&self.r1.serialize());
+        bytes[33..].copy_from_slice(&self.r2.serialize());
+        bytes
+    }
+
+    /// Parse a nonce serialized as two compressed points
+    pub fn from_slice(data: &[u8]) -> Result<Self, MusigError> {
+        if data.len() != 66 {
+            return Err(MusigError::InvalidNonce);
+        }
+        let parse = |data: &[u8]| PublicKey::from_slice(data).map_err(|_| MusigError::InvalidNonce);
+        Ok(PubNonce {
+            r1: parse(&data[..33])?,
+            r2: parse(&data[33..])?,
+        })
+    }
+}
+
+/// Generate the nonces of a participant for a signing session
+///
+/// All the optional arguments make the nonce more robust against a weak random number
+/// generator, and should be provided when known.
+pub fn nonce_gen(
+    secp: &SecpCtx,
+    secret_key: Option<&SecretKey>,
+    pubkey: &PublicKey,
+    agg_pubkey: Option<&XOnlyPublicKey>,
+    msg: Option<&[u8]>,
+    extra_in: Option<&[u8]>,
+) -> (SecNonce, PubNonce) {
+    let mut rand = [0; 32];
+    rand::thread_rng().fill_bytes(&mut rand);
+    nonce_gen_internal(secp, rand, secret_key, pubkey, agg_pubkey, msg, extra_in)
+}
+
+fn nonce_gen_internal(
+    secp: &SecpCtx,
+    mut rand: [u8; 32],
+    secret_key: Option<&SecretKey>,
+    pubkey: &PublicKey,
+    agg_pubkey: Option<&XOnlyPublicKey>,
+    msg: Option<&[u8]>,
+    extra_in: Option<&[u8]>,
+) -> (SecNonce, PubNonce) {
+    if let Some(secret_key) = secret_key {
+        let aux = tagged_hash("MuSig/aux", &[&rand]);
+        for (byte, (sk, aux)) in rand
+            .iter_mut()
+            .zip(secret_key.secret_bytes().iter().zip(aux.iter()))
+        {
+            *byte = sk ^ aux;
+        }
+    }
+
+    let pubkey_bytes = pubkey.serialize();
+    let agg_pubkey_bytes = agg_pubkey
+        .map(|pk| pk.serialize().to_vec())
+        .unwrap_or_default();
+    let msg_prefixed = match msg {
+        Some(msg) => {
+            let mut prefixed = vec![1];
+            prefixed.extend((msg.len() as u64).to_be_bytes());
+            prefixed.extend(msg);
+            prefixed
+        }
+        None => vec![0],
+    };
+    let extra_in = extra_in.unwrap_or_default();
+
+    let k = |i: u8| {
+        ModN::reduce(tagged_hash(
+            "MuSig/nonce",
+            &[
+                &rand,
+                &[pubkey_bytes.len() as u8],
+                &pubkey_bytes,
+                &[agg_pubkey_bytes.len() as u8],
+                &agg_pubkey_bytes,
+                &msg_prefixed,
+                &(extra_in.len() as u32).to_be_bytes(),
+                extra_in,
+                &[i],
+            ],
+        ))
+    };
+    let (k1, k2) = (k(0), k(1));
+
+    let pub_nonce = PubNonce {
+        r1: k1.mul_base(secp).expect("the nonce can't be zero"),
+        r2: k2.mul_base(secp).expect("the nonce can't be zero"),
+    };
+    let sec_nonce = SecNonce {
+        k1,
+        k2,
+        pubkey: *pubkey,
+    };
+
+    (sec_nonce, pub_nonce)
+}
+
+/// Aggregate of the public nonces of all the participants
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AggNonce {
+    r1: Option<PublicKey>,
+    r2: Option<PublicKey>,
+}
+
+impl AggNonce {
+    /// Aggregate the participants' public nonces
+    pub fn new(nonces: &[PubNonce]) -> Self {
+        nonces
+            .iter()
+            .fold(AggNonce { r1: None, r2: None }, |agg, nonce| AggNonce {
+                r1: point_add(agg.r1, Some(nonce.r1)),
+                r2: point_add(agg.r2, Some(nonce.r2)),
+            })
+    }
+
+    /// Serialize the nonce as two compressed points, the point at infinity being encoded as 33
+    /// zero bytes
+    pub fn serialize(&self) -> [u8; 66] {
+        let mut bytes = [0; 66];
+        if let Some(r1) = self.r1 {
+            bytes[..33].copy_from_slice(&r1.serialize());
+        }
+        if let Some(r2) = self.r2 {
+            bytes[33..].copy_from_slice(&r2.serialize());
+        }
+        bytes
+    }
+
+    /// Parse a nonce serialized as two compressed points, 33 zero bytes being the point at
+    /// infinity
+    pub fn from_slice(data: &[u8]) -> Result<Self, MusigError> {
+        if data.len() != 66 {
+            return Err(MusigError::InvalidNonce);
+        }
+        let parse = |data: &[u8]| {
+            if data == [0; 33] {
+                Ok(None)
+            } else {
+                PublicKey::from_slice(data)
+                    .map(Some)
+                    .map_err(|_| MusigError::InvalidNonce)
+            }
+        };
+        Ok(AggNonce {
+            r1: parse(&data[..33])?,
+            r2: parse(&data[33..])?,
+        })
+    }
+}
+
+/// Partial signature of a participant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartialSignature(ModN);
+
+impl PartialSignature {
+    /// Serialize the partial signature as a 32 bytes big-endian integer
+    pub fn serialize(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    /// Parse a partial signature serialized as a 32 bytes big-endian integer
+    pub fn from_slice(data: &[u8]) -> Result<Self, MusigError> {
+        let mut bytes = [0; 32];
+        if data.len() != 32 {
+            return Err(MusigError::InvalidPartialSignature);
+        }
+        bytes.copy_from_slice(data);
+        ModN::from_bytes(bytes)
+            .map(PartialSignature)
+            .ok_or(MusigError::InvalidPartialSignature)
+    }
+}
+
+/// Signing session for a message, once the nonces of all the participants are known
+#[derive(Debug, Clone)]
+pub struct Session {
+    key_agg: KeyAggContext,
+    b: ModN,
+    r: PublicKey,
+    e: ModN,
+}
+
+impl Session {
+    /// Start a session to sign `msg` with the (tweaked) aggregate key
+    pub fn new(secp: &SecpCtx, key_agg: &KeyAggContext, agg_nonce: &AggNonce, msg: &[u8]) -> Self {
+        let b = ModN::reduce(tagged_hash(
+            "MuSig/noncecoef",
+            &[&agg_nonce.serialize(), &xbytes(&key_agg.q), msg],
+        ));
+        let r = point_add(
+            agg_nonce.r1,
+            agg_nonce.r2.and_then(|r2| point_mul(secp, &r2, b)),
+        )
+        .unwrap_or_else(|| ModN::one().mul_base(secp).expect("one is not zero"));
+        let e = ModN::reduce(tagged_hash(
+            "BIP0340/challenge",
+            &[&xbytes(&r), &xbytes(&key_agg.q), msg],
+        ));
+
+        Session {
+            key_agg: key_agg.clone(),
+            b,
+            r,
+            e,
+        }
+    }
+
+    // The sign to apply to the participants' keys: `g * gacc`
+    fn keys_negated(&self) -> bool {
+        !has_even_y(&self.key_agg.q) ^ self.key_agg.gacc_negated
+    }
+
+    /// Make the partial signature of a participant, consuming its secret nonce
+    pub fn sign(
+        &self,
+        secp: &SecpCtx,
+        sec_nonce: SecNonce,
+        secret_key: &SecretKey,
+    ) -> Result<PartialSignature, MusigError> {
+        let pubkey = PublicKey::from_secret_key(secp, secret_key);
+        if pubkey != sec_nonce.pubkey {
+            return Err(MusigError::InvalidSecretNonce);
+        }
+        if !self.key_agg.pubkeys.contains(&pubkey) {
+            return Err(MusigError::UnknownPublicKey);
+        }
+        if sec_nonce.k1 == ModN::ZERO || sec_nonce.k2 == ModN::ZERO {
+            return Err(MusigError::InvalidSecretNonce);
+        }
+
+        let negate_nonce = !has_even_y(&self.r);
+        let k1 = sec_nonce.k1.negate_if(negate_nonce);
+        let k2 = sec_nonce.k2.negate_if(negate_nonce);
+        let a = self.key_agg.key_agg_coeff(&pubkey);
+        let d = ModN(Some(*secret_key)).negate_if(self.keys_negated());
+        let s = k1.add(self.b.mul(k2)).add(self.e.mul(a).mul(d));
+
+        Ok(PartialSignature(s))
+    }
+
+    /// Verify the partial signature of a participant
+    pub fn verify_partial(
+        &self,
+        secp: &SecpCtx,
+        partial_sig: &PartialSignature,
+        pub_nonce: &PubNonce,
+        pubkey: &PublicKey,
+    ) -> bool {
+        if !self.key_agg.pubkeys.contains(pubkey) {
+            return false;
+        }
+
+        let mut r = point_add(Some(pub_nonce.r1), point_mul(secp, &pub_nonce.r2, self.b));
+        if !has_even_y(&self.r) {
+            r = r.map(|r| r.negate(secp));
+        }
+        let a = self.key_agg.key_agg_coeff(pubkey);
+        let pubkey = if self.keys_negated() {
+            pubkey.negate(secp)
+        } else {
+            *pubkey
+        };
+        let expected = point_add(r, point_mul(secp, &pubkey, self.e.mul(a)));
+
+        partial_sig.0.mul_base(secp) == expected
+    }
+
+    /// Aggregate the partial signatures of all the participants into the final signature
+    pub fn aggregate(&self, partial_sigs: &[PartialSignature]) -> schnorr::Signature {
+        let g = !has_even_y(&self.key_agg.q);
+        let s = partial_sigs
+            .iter()
+            .fold(self.e.mul(self.key_agg.tacc.negate_if(g)), |s, psig| {
+                s.add(psig.0)
+            });
+
+        let mut bytes = [0; 64];
+        bytes[..32].copy_from_slice(&xbytes(&self.r));
+        bytes[32..].copy_from_slice(&s.to_bytes());
+        schnorr::Signature::from_slice(&bytes).expect("64 bytes")
+    }
+}
+
+fn psbt_key(type_value: u8, parts: &[&[u8]]) -> raw::Key {
+    raw::Key {
+        type_value,
+        key: parts.concat(),
+    }
+}
+
+/// Read the participants of the aggregate key `agg_pubkey` from a PSBT input
+///
+/// `agg_pubkey` is the output of the key aggregation, before any derivation.
+pub fn psbt_participant_pubkeys(
+    input: &psbt::Input,
+    agg_pubkey: &PublicKey,
+) -> Option<Vec<PublicKey>> {
+    let value = input.unknown.get(&psbt_key(
+        PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS,
+        &[&agg_pubkey.serialize()],
+    ))?;
+    value
+        .chunks(33)
+        .map(|pk| PublicKey::from_slice(pk).ok())
+        .collect()
+}
+
+/// Read the public nonces for a key-spend with the aggregate key `agg_pubkey` from a PSBT input
+///
+/// `agg_pubkey` is the aggregate key used as internal key, after the BIP32 derivations but before
+/// the BIP341 tweak. Invalid nonces are ignored.
+pub fn psbt_pub_nonces(
+    input: &psbt::Input,
+    agg_pubkey: &PublicKey,
+) -> BTreeMap<PublicKey, PubNonce> {
+    psbt_participant_values(input, PSBT_IN_MUSIG2_PUB_NONCE, agg_pubkey)
+        .filter_map(|(pk, value)| Some((pk, PubNonce::from_slice(value).ok()?)))
+        .collect()
+}
+
+/// Read the partial signatures for a key-spend with the aggregate key `agg_pubkey` from a PSBT
+/// input
+///
+/// `agg_pubkey` is the same key as in [`psbt_pub_nonces`]. Invalid signatures are ignored.
+pub fn psbt_partial_sigs(
+    input: &psbt::Input,
+    agg_pubkey: &PublicKey,
+) -> BTreeMap<PublicKey, PartialSignature> {
+    psbt_participant_values(input, PSBT_IN_MUSIG2_PARTIAL_SIG, agg_pubkey)
+        .filter_map(|(pk, value)| Some((pk, PartialSignature::from_slice(value).ok()?)))
+        .collect()
+}
+
+// Iterate over the per-participant values of a key-spend, whose keys are
+// `<participant pubkey><aggregate pubkey>`
+fn psbt_participant_values<'a>(
+    input: &'a psbt::Input,
+    type_value: u8,
+    agg_pubkey: &PublicKey,
+) -> impl Iterator<Item = (PublicKey, &'a [u8])> {
+    let agg_pubkey = agg_pubkey.serialize();
+    input.unknown.iter().filter_map(move |(key, value)| {
+        if key.type_value != type_value || key.key.len() != 66 || key.key[33..] != agg_pubkey {
+            return None;
+        }
+        let pubkey = PublicKey::from_slice(&key.key[..33]).ok()?;
+        Some((pubkey, value.as_slice()))
+    })
+}
+
+fn psbt_insert_participant_value(
+    input: &mut psbt::Input,
+    type_value: u8,
+    pubkey: &PublicKey,
+    agg_pubkey: &PublicKey,
+    value: &[u8],
+) {
+    input.unknown.insert(
+        psbt_key(type_value, &[&pubkey.serialize(), &agg_pubkey.serialize()]),
+        value.to_vec(),
+    );
+}
+
+#[cfg(feature = "std")]
+pub use self::signer::MusigSigner;
+
+#[cfg(feature = "std")]
+mod signer {
+    use std::sync::Mutex;
+
+    use bitcoin::secp256k1::Message;
+    use bitcoin::sighash::TapSighash;
+    use bitcoin::taproot;
+    use miniscript::Tap;
+
+    use crate::collections::btree_map::Entry;
+
+    use super::*;
+    use crate::signer::{
+        ComputeSighash, InputSigner, SignOptions, SignerCommon, SignerError, SignerId,
+    };
+
+    /// Signer of one of the participants of a MuSig2 aggregate key
+    ///
+    /// The signer only signs key-spends whose internal key is the aggregate key, or one derived
+    /// from its synthetic xpub. The secret nonces are kept in memory between the two signing
+    /// rounds, so the same signer must be used for both.
+    #[derive(Debug)]
+    pub struct MusigSigner {
+        secret_key: SecretKey,
+        pubkey: PublicKey,
+        key_agg: KeyAggContext,
+        // Nonces of the sessions waiting for the other participants, by sighash
+        sessions: Mutex<BTreeMap<TapSighash, (SecNonce, PubNonce)>>,
+    }
+
+    impl MusigSigner {
+        /// Create a signer for the participant with `secret_key`
+        ///
+        /// Fails if its public key isn't aggregated, or if it's aggregated more than once.
+        pub fn new(
+            secp: &SecpCtx,
+            secret_key: SecretKey,
+            key_agg: KeyAggContext,
+        ) -> Result<Self, MusigError> {
+            let pubkey = PublicKey::from_secret_key(secp, &secret_key);
+            if key_agg.pubkeys().iter().filter(|pk| **pk == pubkey).count() != 1 {
+                return Err(MusigError::UnknownPublicKey);
+            }
+
+            Ok(MusigSigner {
+                secret_key,
+                pubkey,
+                key_agg,
+                sessions: Mutex::new(BTreeMap::new()),
+            })
+        }
+
+        /// The aggregation of the participants' keys
+        pub fn key_agg(&self) -> &KeyAggContext {
+            &self.key_agg
+        }
+
+        // Find the aggregate key used as the internal key of the input
+        fn derive_internal_key(
+            &self,
+            secp: &SecpCtx,
+            input: &psbt::Input,
+            internal_key: &XOnlyPublicKey,
+        ) -> Result<Option<KeyAggContext>, MusigError> {
+            if self.key_agg.x_only_public_key() == *internal_key {
+                return Ok(Some(self.key_agg.clone()));
+            }
+
+            match input.tap_key_origins.get(internal_key) {
+                Some((_, (fingerprint, path))) if *fingerprint == self.key_agg.fingerprint() => {
+                    let derived = self.key_agg.derive(secp, path)?;
+                    Ok(Some(derived).filter(|d| d.x_only_public_key() == *internal_key))
+                }
+                _ => Ok(None),
+            }
+        }
+    }
+
+    impl SignerCommon for MusigSigner {
+        fn id(&self, _secp: &SecpCtx) -> SignerId {
+            SignerId::from(self.key_agg.fingerprint())
+        }
+    }
+
+    impl InputSigner for MusigSigner {
+        fn sign_input(
+            &self,
+            psbt: &mut psbt::PartiallySignedTransaction,
+            input_index: usize,
+            sign_options: &SignOptions,
+            secp: &SecpCtx,
+        ) -> Result<(), SignerError> {
+            if input_index >= psbt.inputs.len() || input_index >= psbt.unsigned_tx.input.len() {
+                return Err(SignerError::InputIndexOutOfRange);
+            }
+
+            let input = &psbt.inputs[input_index];
+            if input.final_script_sig.is_some()
+                || input.final_script_witness.is_some()
+                || input.tap_key_sig.is_some()
+                || !sign_options.sign_with_tap_internal_key
+            {
+                return Ok(());
+            }
+            let mut key_agg = match input
+                .tap_internal_key
+                .map(|key| self.derive_internal_key(secp, input, &key))
+                .transpose()?
+                .flatten()
+            {
+                Some(key_agg) => key_agg,
+                None => return Ok(()),
+            };
+            let agg_pubkey = key_agg.aggregated_pubkey();
+            key_agg.tap_tweak(secp, input.tap_merkle_root)?;
+
+            let (hash, hash_ty) = Tap::sighash(psbt, input_index, None)?;
+            let msg = hash.to_byte_array();
+            let input = &mut psbt.inputs[input_index];
+
+            input.unknown.insert(
+                psbt_key(
+                    PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS,
+                    &[&self.key_agg.aggregated_pubkey().serialize()],
+                ),
+                self.key_agg
+                    .pubkeys()
+                    .iter()
+                    .flat_map(|pk| pk.serialize())
+                    .collect(),
+            );
+
+            // First round: publish our nonce
+            let mut sessions = self.sessions.lock().expect("poisoned lock");
+            let nonces = psbt_pub_nonces(input, &agg_pubkey);
+            let our_nonce = match nonces.get(&self.pubkey) {
+                Some(nonce) => *nonce,
+                None => {
+                    let (sec_nonce, pub_nonce) = nonce_gen(
+                        secp,
+                        Some(&self.secret_key),
+                        &self.pubkey,
+                        Some(&key_agg.x_only_public_key()),
+                        Some(&msg),
+                        None,
+                    );
+                    psbt_insert_participant_value(
+                        input,
+                        PSBT_IN_MUSIG2_PUB_NONCE,
+                        &self.pubkey,
+                        &agg_pubkey,
+                        &pub_nonce.serialize(),
+                    );
+                    sessions.insert(hash, (sec_nonce, pub_nonce));
+                    return Ok(());
+                }
+            };
+            let nonces = match key_agg
+                .pubkeys()
+                .iter()
+                .map(|pk| nonces.get(pk).cloned())
+                .collect::<Option<Vec<_>>>()
+            {
+                Some(nonces) => nonces,
+                // Still waiting for the other participants
+                None => return Ok(()),
+            };
+            let session = Session::new(secp, &key_agg, &AggNonce::new(&nonces), &msg);
+
+            // Second round: publish our partial signature
+            let mut partial_sigs = psbt_partial_sigs(input, &agg_pubkey);
+            if let Entry::Vacant(entry) = partial_sigs.entry(self.pubkey) {
+                let (sec_nonce, pub_nonce) = sessions
+                    .remove(&hash)
+                    .ok_or(MusigError::MissingSecretNonce)?;
+                if pub_nonce != our_nonce {
+                    return Err(MusigError::InvalidSecretNonce.into());
+                }
+                let partial_sig = session.sign(secp, sec_nonce, &self.secret_key)?;
+                psbt_insert_participant_value(
+                    input,
+                    PSBT_IN_MUSIG2_PARTIAL_SIG,
+                    &self.pubkey,
+                    &agg_pubkey,
+                    &partial_sig.serialize(),
+                );
+                entry.insert(partial_sig);
+            }
+
+            // Once all the partial signatures are there, aggregate them
+            let mut sigs = Vec::with_capacity(nonces.len());
+            for (pubkey, nonce) in key_agg.pubkeys().iter().zip(&nonces) {
+                match partial_sigs.get(pubkey) {
+                    Some(sig) if session.verify_partial(secp, sig, nonce, pubkey) => {
+                        sigs.push(*sig)
+                    }
+                    Some(_) => return Err(MusigError::InvalidPartialSignature.into()),
+                    None => return Ok(()),
+                }
+            }
+            let sig = session.aggregate(&sigs);
+            secp.verify_schnorr(&sig, &Message::from(hash), &key_agg.x_only_public_key())
+                .map_err(|_| MusigError::InvalidPartialSignature)?;
+            input.tap_key_sig = Some(taproot::Signature { sig, hash_ty });
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::str::FromStr;
+
+    use bitcoin::hashes::hex::FromHex;
+    use bitcoin::secp256k1::{Message, Secp256k1};
+
+    use super::*;
+
+    fn pubkey(hex: &str) -> PublicKey {
+        PublicKey::from_str(hex).unwrap()
+    }
+
+    fn bytes(hex: &str) -> Vec<u8> {
+        Vec::<u8>::from_hex(hex).unwrap()
+    }
+
+    fn bytes32(hex: &str) -> [u8; 32] {
+        bytes(hex).try_into().unwrap()
+    }
+
+    // Parse a secret nonce serialized as `k1 || k2 || pubkey`, as in the BIP327 test vectors
+    fn sec_nonce(hex: &str) -> SecNonce {
+        let data = bytes(hex);
+        SecNonce {
+            k1: ModN::from_bytes(data[..32].try_into().unwrap()).unwrap(),
+            k2: ModN::from_bytes(data[32..64].try_into().unwrap()).unwrap(),
+            pubkey: PublicKey::from_slice(&data[64..]).unwrap(),
+        }
+    }
+
+    fn signers(secp: &SecpCtx, n: u8) -> (Vec<SecretKey>, KeyAggContext) {
+        let secret_keys = (1..=n)
+            .map(|i| SecretKey::from_slice(&[i; 32]).unwrap())
+            .collect::<Vec<_>>();
+        let pubkeys = secret_keys
+            .iter()
+            .map(|sk| PublicKey::from_secret_key(secp, sk))
+            .collect();
+        (secret_keys, KeyAggContext::new(secp, pubkeys).unwrap())
+    }
+
+    fn sign_all(
+        secp: &SecpCtx,
+        secret_keys: &[SecretKey],
+        key_agg: &KeyAggContext,
+        msg: &[u8],
+    ) -> (Session, Vec<PubNonce>, Vec<PartialSignature>) {
+        let (sec_nonces, pub_nonces): (Vec<_>, Vec<_>) = secret_keys
+            .iter()
+            .map(|sk| {
+                let pk = PublicKey::from_secret_key(secp, sk);
+                nonce_gen(secp, Some(sk), &pk, None, Some(msg), None)
+            })
+            .unzip();
+        let session = Session::new(secp, key_agg, &AggNonce::new(&pub_nonces), msg);
+        let partial_sigs = sec_nonces
+            .into_iter()
+            .zip(secret_keys)
+            .map(|(nonce, sk)| session.sign(secp, nonce, sk).unwrap())
+            .collect();
+
+        (session, pub_nonces, partial_sigs)
+    }
+
+    #[test]
+    fn test_key_agg_vectors() {
+        // From the BIP327 `key_agg_vectors.json`
+        let secp = Secp256k1::new();
+        let pubkeys = [
+            pubkey("02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9"),
+            pubkey("03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659"),
+            pubkey("023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66"),
+        ];
+        let cases = [
+            (
+                vec![0, 1, 2],
+                "90539EEDE565F5D054F32CC0C220126889ED1E5D193BAF15AEF344FE59D4610C",
+            ),
+            (
+                vec![2, 1, 0],
+                "6204DE8B083426DC6EAF9502D27024D53FC826BF7D2012148A0575435DF54B2B",
+            ),
+            (
+                vec![0, 0, 0],
+                "B436E3BAD62B8CD409969A224731C193D051162D8C5AE8B109306127DA3AA935",
+            ),
+            (
+                vec![0, 0, 1, 1],
+                "69BC22BFA5D106306E48A20679DE1D7389386124D07571D0D872686028C26A3E",
+            ),
+        ];
+
+        for (indices, expected) in cases {
+            let key_agg =
+                KeyAggContext::new(&secp, indices.iter().map(|i| pubkeys[*i]).collect()).unwrap();
+            assert_eq!(
+                key_agg.x_only_public_key(),
+                XOnlyPublicKey::from_str(expected).unwrap()
+            );
+        }
+
+        assert_eq!(
+            KeyAggContext::new(&secp, vec![]),
+            Err(MusigError::InvalidPublicKeys)
+        );
+    }
+
+    #[test]
+    fn test_nonce_agg_vectors() {
+        // From the BIP327 `nonce_agg_vectors.json`
+        let pnonces = [
+            "020151C80F435648DF67A22B749CD798CE54E0321D034B92B709B567D60A42E66603BA47FBC1834437B3212E89A84D8425E7BF12E0245D98262268EBDCB385D50641",
+            "03FF406FFD8ADB9CD29877E4985014F66A59F6CD01C0E88CAA8E5F3166B1F676A60248C264CDD57D3C24D79990B0F865674EB62A0F9018277A95011B41BFC193B833",
+            "020151C80F435648DF67A22B749CD798CE54E0321D034B92B709B567D60A42E6660279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798",
+            "03FF406FFD8ADB9CD29877E4985014F66A59F6CD01C0E88CAA8E5F3166B1F676A60379BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798",
+            "04FF406FFD8ADB9CD29877E4985014F66A59F6CD01C0E88CAA8E5F3166B1F676A60248C264CDD57D3C24D79990B0F865674EB62A0F9018277A95011B41BFC193B833",
+            "03FF406FFD8ADB9CD29877E4985014F66A59F6CD01C0E88CAA8E5F3166B1F676A60248C264CDD57D3C24D79990B0F865674EB62A0F9018277A95011B41BFC193B831",
+            "03FF406FFD8ADB9CD29877E4985014F66A59F6CD01C0E88CAA8E5F3166B1F676A602FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC30",
+        ]
+        .map(|nonce| PubNonce::from_slice(&bytes(nonce)));
+
+        let cases = [
+            (
+                [0, 1],
+                "035FE1873B4F2967F52FEA4A06AD5A8ECCBE9D0FD73068012C894E2E87CCB5804B024725377345BDE0E9C33AF3C43C0A29A9249F2F2956FA8CFEB55C8573D0262DC8",
+            ),
+            // The second points sum to the point at infinity, serialized as 33 zero bytes
+            (
+                [2, 3],
+                "035FE1873B4F2967F52FEA4A06AD5A8ECCBE9D0FD73068012C894E2E87CCB5804B000000000000000000000000000000000000000000000000000000000000000000",
+            ),
+        ];
+        for (indices, expected) in cases {
+            let nonces = indices.map(|i| pnonces[i].clone().unwrap());
+            let agg_nonce = AggNonce::new(&nonces);
+            assert_eq!(agg_nonce.serialize().to_vec(), bytes(expected));
+            assert_eq!(AggNonce::from_slice(&bytes(expected)), Ok(agg_nonce));
+        }
+
+        // Invalid tag, x coordinate not on the curve, x coordinate exceeding the field size
+        for invalid in &pnonces[4..] {
+            assert_eq!(invalid, &Err(MusigError::InvalidNonce));
+        }
+    }
+
+    #[test]
+    fn test_sign_verify_vectors() {
+        // From the BIP327 `sign_verify_vectors.json`
+        let secp = Secp256k1::new();
+        let sk =
+            SecretKey::from_str("7FB9E0E687ADA1EEBF7ECFE2F21E73EBDB51A7D450948DFE8D76D7F2D1007671")
+                .unwrap();
+        let pubkeys = [
+            pubkey("03935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9"),
+            pubkey("02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9"),
+            pubkey("02DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA661"),
+        ];
+        assert_eq!(PublicKey::from_secret_key(&secp, &sk), pubkeys[0]);
+        let secnonce = "508B81A611F100A6B2B6B29656590898AF488BCF2E1F55CF22E5CFB84421FE61FA27FD49B1D50085B481285E1CA205D55C82CC1B31FF5CD54A489829355901F703935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9";
+        let pnonces = [
+            "0337C87821AFD50A8644D820A8F3E02E499C931865C2360FB43D0A0D20DAFE07EA0287BF891D2A6DEAEBADC909352AA9405D1428C15F4B75F04DAE642A95C2548480",
+            "0279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F817980279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798",
+            "032DE2662628C90B03F5E720284EB52FF7D71F4284F627B68A853D78C78E1FFE9303E4C5524E83FFE1493B9077CF1CA6BEB2090C93D930321071AD40B2F44E599046",
+            "0237C87821AFD50A8644D820A8F3E02E499C931865C2360FB43D0A0D20DAFE07EA0387BF891D2A6DEAEBADC909352AA9405D1428C15F4B75F04DAE642A95C2548480",
+        ]
+        .map(|nonce| PubNonce::from_slice(&bytes(nonce)).unwrap());
+        let aggnonces = [
+            "028465FCF0BBDBCF443AABCCE533D42B4B5A10966AC09A49655E8C42DAAB8FCD61037496A3CC86926D452CAFCFD55D25972CA1675D549310DE296BFF42F72EEEA8C9",
+            "000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+        ]
+        .map(|nonce| AggNonce::from_slice(&bytes(nonce)).unwrap());
+        let msgs = [
+            bytes("F95466D086770E689964664219266FE5ED215C92AE20BAB5C9D79ADDDDF3C0CF"),
+            vec![],
+            vec![0x26; 38],
+        ];
+
+        // (key indices, nonce indices, aggnonce index, msg index, signer index, expected)
+        let cases = [
+            (
+                vec![0, 1, 2],
+                vec![0, 1, 2],
+                0,
+                0,
+                0,
+                "012ABBCB52B3016AC03AD82395A1A415C48B93DEF78718E62A7A90052FE224FB",
+            ),
+            (
+                vec![1, 0, 2],
+                vec![1, 0, 2],
+                0,
+                0,
+                1,
+                "9FF2F7AAA856150CC8819254218D3ADEEB0535269051897724F9DB3789513A52",
+            ),
+            (
+                vec![1, 2, 0],
+                vec![1, 2, 0],
+                0,
+                0,
+                2,
+                "FA23C359F6FAC4E7796BB93BC9F0532A95468C539BA20FF86D7C76ED92227900",
+            ),
+            // Both halves of the aggregate nonce are the point at infinity
+            (
+                vec![0, 1],
+                vec![0, 3],
+                1,
+                0,
+                0,
+                "AE386064B26105404798F75DE2EB9AF5EDA5387B064B83D049CB7C5E08879531",
+            ),
+            // Empty message
+            (
+                vec![0, 1, 2],
+                vec![0, 1, 2],
+                0,
+                1,
+                0,
+                "D7D63FFD644CCDA4E62BC2BC0B1D02DD32A1DC3030E155195810231D1037D82D",
+            ),
+            // 38-byte message
+            (
+                vec![0, 1, 2],
+                vec![0, 1, 2],
+                0,
+                2,
+                0,
+                "E184351828DA5094A97C79CABDAAA0BFB87608C32E8829A4DF5340A6F243B78C",
+            ),
+        ];
+        for (key_indices, nonce_indices, aggnonce_index, msg_index, signer, expected) in cases {
+            let nonces = nonce_indices
+                .iter()
+                .map(|i| pnonces[*i])
+                .collect::<Vec<_>>();
+            assert_eq!(
+                AggNonce::new(&nonces).serialize(),
+                aggnonces[aggnonce_index].serialize()
+            );
+            let key_agg =
+                KeyAggContext::new(&secp, key_indices.iter().map(|i| pubkeys[*i]).collect())
+                    .unwrap();
+            let session = Session::new(
+                &secp,
+                &key_agg,
+                &aggnonces[aggnonce_index],
+                &msgs[msg_index],
+            );
+
+            let sig = session.sign(&secp, sec_nonce(secnonce), &sk).unwrap();
+            assert_eq!(sig.serialize(), bytes32(expected));
+            assert!(session.verify_partial(&secp, &sig, &nonces[signer], &pubkeys[0]));
+
+            // The negation of the signature, or the signature of another signer, are invalid
+            let negated = PartialSignature(sig.0.negate());
+            assert!(!session.verify_partial(&secp, &negated, &nonces[signer], &pubkeys[0]));
+            let other = (signer + 1) % key_indices.len();
+            assert!(!session.verify_partial(
+                &secp,
+                &sig,
+                &nonces[other],
+                &pubkeys[key_indices[other]]
+            ));
+        }
+
+        // Signature exceeding the group size
+        assert_eq!(
+            PartialSignature::from_slice(&bytes(
+                "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141"
+            )),
+            Err(MusigError::InvalidPartialSignature)
+        );
+
+        let key_agg = KeyAggContext::new(&secp, pubkeys.to_vec()).unwrap();
+        let session = Session::new(&secp, &key_agg, &aggnonces[0], &msgs[0]);
+        // The signer's key isn't in the list of keys
+        let key_agg_without_signer = KeyAggContext::new(&secp, pubkeys[1..].to_vec()).unwrap();
+        assert_eq!(
+            Session::new(&secp, &key_agg_without_signer, &aggnonces[0], &msgs[0]).sign(
+                &secp,
+                sec_nonce(secnonce),
+                &sk
+            ),
+            Err(MusigError::UnknownPublicKey)
+        );
+        // Secret nonce with zero values, as done by implementations to prevent nonce reuse
+        assert_eq!(
+            session.sign(
+                &secp,
+                sec_nonce("0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000003935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9"),
+                &sk
+            ),
+            Err(MusigError::InvalidSecretNonce)
+        );
+        // Invalid public key, and aggregate nonces with an invalid tag, an x coordinate not on
+        // the curve or exceeding the field size
+        assert!(PublicKey::from_slice(&bytes(
+            "020000000000000000000000000000000000000000000000000000000000000007"
+        ))
+        .is_err());
+        for invalid in [
+            "048465FCF0BBDBCF443AABCCE533D42B4B5A10966AC09A49655E8C42DAAB8FCD61037496A3CC86926D452CAFCFD55D25972CA1675D549310DE296BFF42F72EEEA8C9",
+            "028465FCF0BBDBCF443AABCCE533D42B4B5A10966AC09A49655E8C42DAAB8FCD61020000000000000000000000000000000000000000000000000000000000000009",
+            "028465FCF0BBDBCF443AABCCE533D42B4B5A10966AC09A49655E8C42DAAB8FCD6102FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC30",
+        ] {
+            assert_eq!(
+                AggNonce::from_slice(&bytes(invalid)),
+                Err(MusigError::InvalidNonce)
+            );
+        }
+    }
+
+    #[test]
+    fn test_tweak_vectors() {
+        // From the BIP327 `tweak_vectors.json`
+        let secp = Secp256k1::new();
+        let sk =
+            SecretKey::from_str("7FB9E0E687ADA1EEBF7ECFE2F21E73EBDB51A7D450948DFE8D76D7F2D1007671")
+                .unwrap();
+        let pubkeys = [
+            pubkey("03935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9"),
+            pubkey("02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9"),
+            pubkey("02DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659"),
+        ];
+        let secnonce = "508B81A611F100A6B2B6B29656590898AF488BCF2E1F55CF22E5CFB84421FE61FA27FD49B1D50085B481285E1CA205D55C82CC1B31FF5CD54A489829355901F703935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9";
+        let pnonces = [
+            "0279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F817980279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798",
+            "032DE2662628C90B03F5E720284EB52FF7D71F4284F627B68A853D78C78E1FFE9303E4C5524E83FFE1493B9077CF1CA6BEB2090C93D930321071AD40B2F44E599046",
+            "0337C87821AFD50A8644D820A8F3E02E499C931865C2360FB43D0A0D20DAFE07EA0287BF891D2A6DEAEBADC909352AA9405D1428C15F4B75F04DAE642A95C2548480",
+        ]
+        .map(|nonce| PubNonce::from_slice(&bytes(nonce)).unwrap());
+        let aggnonce = AggNonce::from_slice(&bytes("028465FCF0BBDBCF443AABCCE533D42B4B5A10966AC09A49655E8C42DAAB8FCD61037496A3CC86926D452CAFCFD55D25972CA1675D549310DE296BFF42F72EEEA8C9")).unwrap();
+        assert_eq!(AggNonce::new(&pnonces), aggnonce);
+        let tweaks = [
+            "E8F791FF9225A2AF0102AFFF4A9A723D9612A682A25EBE79802B263CDFCD83BB",
+            "AE2EA797CC0FE72AC5B97B97F3C6957D7E4199A167A58EB08BCAFFDA70AC0455",
+            "F52ECBC565B3D8BEA2DFD5B75A4F457E54369809322E4120831626F290FA87E0",
+            "1969AD73CC177FA0B4FCED6DF1F7BF9907E665FDE9BA196A74FED0A3CF5AEF9D",
+        ]
+        .map(bytes32);
+        let msg = bytes("F95466D086770E689964664219266FE5ED215C92AE20BAB5C9D79ADDDDF3C0CF");
+
+        // The signer is the last of the keys `[1, 2, 0]`, with nonces in the same order
+        let cases = [
+            // A single x-only tweak
+            (
+                vec![(0, true)],
+                "E28A5C66E61E178C2BA19DB77B6CF9F7E2F0F56C17918CD13135E60CC848FE91",
+            ),
+            // A single plain tweak
+            (
+                vec![(0, false)],
+                "38B0767798252F21BF5702C48028B095428320F73A4B14DB1E25DE58543D2D2D",
+            ),
+            // A plain tweak followed by an x-only tweak
+            (
+                vec![(0, false), (1, true)],
+                "408A0A21C4A0F5DACAF9646AD6EB6FECD7F7A11F03ED1F48DFFF2185BC2C2408",
+            ),
+            // Four tweaks: plain, plain, x-only, x-only
+            (
+                vec![(0, false), (1, false), (2, true), (3, true)],
+                "45ABD206E61E3DF2EC9E264A6FEC8292141A633C28586388235541F9ADE75435",
+            ),
+            // Four tweaks: x-only, plain, x-only, plain
+            (
+                vec![(0, true), (1, false), (2, true), (3, false)],
+                "B255FDCAC27B40C7CE7848E2D3B7BF5EA0ED756DA81565AC804CCCA3E1D5D239",
+            ),
+        ];
+        for (tweak_indices, expected) in cases {
+            let mut key_agg =
+                KeyAggContext::new(&secp, vec![pubkeys[1], pubkeys[2], pubkeys[0]]).unwrap();
+            for (i, is_xonly) in tweak_indices {
+                key_agg.apply_tweak(&secp, tweaks[i], is_xonly).unwrap();
+            }
+            let session = Session::new(&secp, &key_agg, &aggnonce, &msg);
+            let sig = session.sign(&secp, sec_nonce(secnonce), &sk).unwrap();
+            assert_eq!(sig.serialize(), bytes32(expected));
+            assert!(session.verify_partial(&secp, &sig, &pnonces[2], &pubkeys[0]));
+        }
+
+        // Tweak out of range
+        let mut key_agg = KeyAggContext::new(&secp, pubkeys.to_vec()).unwrap();
+        assert_eq!(
+            key_agg.apply_tweak(
+                &secp,
+                bytes32("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141"),
+                false
+            ),
+            Err(MusigError::InvalidTweak)
+        );
+    }
+
+    #[test]
+    fn test_sig_agg_vectors() {
+        // From the BIP327 `sig_agg_vectors.json`
+        let secp = Secp256k1::new();
+        let pubkeys = [
+            pubkey("03935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9"),
+            pubkey("02D2DC6F5DF7C56ACF38C7FA0AE7A759AE30E19B37359DFDE015872324C7EF6E05"),
+            pubkey("03C7FB101D97FF930ACD0C6760852EF64E69083DE0B06AC6335724754BB4B0522C"),
+            pubkey("02352433B21E7E05D3B452B81CAE566E06D2E003ECE16D1074AABA4289E0E3D581"),
+        ];
+        let tweaks = [
+            "B511DA492182A91B0FFB9A98020D55F260AE86D7ECBD0399C7383D59A5F2AF7C",
+            "A815FE049EE3C5AAB66310477FBC8BCCCAC2F3395F59F921C364ACD78A2F48DC",
+            "75448A87274B056468B977BE06EB1E9F657577B7320B0A3376EA51FD420D18A8",
+        ]
+        .map(bytes32);
+        let psigs = [
+            "B15D2CD3C3D22B04DAE438CE653F6B4ECF042F42CFDED7C41B64AAF9B4AF53FB",
+            "6193D6AC61B354E9105BBDC8937A3454A6D705B6D57322A5A472A02CE99FCB64",
+            "9A87D3B79EC67228CB97878B76049B15DBD05B8158D17B5B9114D3C226887505",
+            "66F82EA90923689B855D36C6B7E032FB9970301481B99E01CDB4D6AC7C347A15",
+            "4F5AEE41510848A6447DCD1BBC78457EF69024944C87F40250D3EF2C25D33EFE",
+            "DDEF427BBB847CC027BEFF4EDB01038148917832253EBC355FC33F4A8E2FCCE4",
+            "97B890A26C981DA8102D3BC294159D171D72810FDF7C6A691DEF02F0F7AF3FDC",
+            "53FA9E08BA5243CBCB0D797C5EE83BC6728E539EB76C2D0BF0F971EE4E909971",
+        ]
+        .map(|sig| PartialSignature::from_slice(&bytes(sig)).unwrap());
+        let msg = bytes("599C67EA410D005B9DA90817CF03ED3B1C868E4DA4EDF00A5880B0082C237869");
+
+        // (aggnonce, key indices, tweaks, psig indices, expected)
+        let cases = [
+            (
+                "0341432722C5CD0268D829C702CF0D1CBCE57033EED201FD335191385227C3210C03D377F2D258B64AADC0E16F26462323D701D286046A2EA93365656AFD9875982B",
+                [0, 1],
+                vec![],
+                [0, 1],
+                "041DA22223CE65C92C9A0D6C2CAC828AAF1EEE56304FEC371DDF91EBB2B9EF0912F1038025857FEDEB3FF696F8B99FA4BB2C5812F6095A2E0004EC99CE18DE1E",
+            ),
+            (
+                "0224AFD36C902084058B51B5D36676BBA4DC97C775873768E58822F87FE437D792028CB15929099EEE2F5DAE404CD39357591BA32E9AF4E162B8D3E7CB5EFE31CB20",
+                [0, 2],
+                vec![],
+                [2, 3],
+                "1069B67EC3D2F3C7C08291ACCB17A9C9B8F2819A52EB5DF8726E17E7D6B52E9F01800260A7E9DAC450F4BE522DE4CE12BA91AEAF2B4279219EF74BE1D286ADD9",
+            ),
+            (
+                "0208C5C438C710F4F96A61E9FF3C37758814B8C3AE12BFEA0ED2C87FF6954FF186020B1816EA104B4FCA2D304D733E0E19CEAD51303FF6420BFD222335CAA402916D",
+                [0, 2],
+                vec![(0, false)],
+                [4, 5],
+                "5C558E1DCADE86DA0B2F02626A512E30A22CF5255CAEA7EE32C38E9A71A0E9148BA6C0E6EC7683B64220F0298696F1B878CD47B107B81F7188812D593971E0CC",
+            ),
+            (
+                "02B5AD07AFCD99B6D92CB433FBD2A28FDEB98EAE2EB09B6014EF0F8197CD58403302E8616910F9293CF692C49F351DB86B25E352901F0E237BAFDA11F1C1CEF29FFD",
+                [0, 3],
+                vec![(0, true), (1, false), (2, true)],
+                [6, 7],
+                "839B08820B681DBA8DAF4CC7B104E8F2638F9388F8D7A555DC17B6E6971D7426CE07BF6AB01F1DB50E4E33719295F4094572B79868E440FB3DEFD3FAC1DB589E",
+            ),
+        ];
+        for (aggnonce, key_indices, tweak_indices, psig_indices, expected) in cases {
+            let mut key_agg =
+                KeyAggContext::new(&secp, key_indices.iter().map(|i| pubkeys[*i]).collect())
+                    .unwrap();
+            for (i, is_xonly) in tweak_indices {
+                key_agg.apply_tweak(&secp, tweaks[i], is_xonly).unwrap();
+            }
+            let aggnonce = AggNonce::from_slice(&bytes(aggnonce)).unwrap();
+            let session = Session::new(&secp, &key_agg, &aggnonce, &msg);
+
+            let sig = session.aggregate(&psig_indices.map(|i| psigs[i]));
+            assert_eq!(sig.as_ref().to_vec(), bytes(expected));
+            secp.verify_schnorr(
+                &sig,
+                &Message::from_slice(&msg).unwrap(),
+                &key_agg.x_only_public_key(),
+            )
+            .unwrap();
+        }
+
+        // Partial signature exceeding the group size
+        assert_eq!(
+            PartialSignature::from_slice(&bytes(
+                "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141"
+            )),
+            Err(MusigError::InvalidPartialSignature)
+        );
+    }
+
+    #[test]
+    fn test_sign_and_aggregate() {
+        let secp = Secp256k1::new();
+        let (secret_keys, mut key_agg) = signers(&secp, 3);
+        key_agg.apply_tweak(&secp, [0x42; 32], false).unwrap();
+        key_agg.tap_tweak(&secp, None).unwrap();
+        let msg = [0x07; 32];
+
+        let (session, pub_nonces, partial_sigs) = sign_all(&secp, &secret_keys, &key_agg, &msg);
+        for ((sk, nonce), sig) in secret_keys.iter().zip(&pub_nonces).zip(&partial_sigs) {
+            let pk = PublicKey::from_secret_key(&secp, sk);
+            assert!(session.verify_partial(&secp, sig, nonce, &pk));
+        }
+        // a partial signature is only valid for its signer
+        let pk = PublicKey::from_secret_key(&secp, &secret_keys[1]);
+        assert!(!session.verify_partial(&secp, &partial_sigs[0], &pub_nonces[0], &pk));
+
+        let sig = session.aggregate(&partial_sigs);
+        secp.verify_schnorr(
+            &sig,
+            &Message::from_slice(&msg).unwrap(),
+            &key_agg.x_only_public_key(),
+        )
+        .unwrap();
+        // all the partial signatures are needed
+        let sig = session.aggregate(&partial_sigs[..2]);
+        assert!(secp
+            .verify_schnorr(
+                &sig,
+                &Message::from_slice(&msg).unwrap(),
+                &key_agg.x_only_public_key()
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_sign_wrong_secret_nonce() {
+        let secp = Secp256k1::new();
+        let (secret_keys, key_agg) = signers(&secp, 2);
+        let pk = PublicKey::from_secret_key(&secp, &secret_keys[0]);
+        let (sec_nonce, pub_nonce) = nonce_gen(&secp, None, &pk, None, None, None);
+        let session = Session::new(&secp, &key_agg, &AggNonce::new(&[pub_nonce]), &[]);
+
+        assert_eq!(
+            session.sign(&secp, sec_nonce, &secret_keys[1]),
+            Err(MusigError::InvalidSecretNonce)
+        );
+    }
+
+    #[test]
+    fn test_derive_synthetic_xpub() {
+        let secp = Secp256k1::new();
+        let (_, key_agg) = signers(&secp, 2);
+        let path = DerivationPath::from_str("m/0/42").unwrap();
+
+        let xpub = key_agg.to_xpub(Network::Testnet);
+        assert_eq!(
+            key_agg.derive(&secp, &path).unwrap().aggregated_pubkey(),
+            xpub.derive_pub(&secp, &path).unwrap().public_key
+        );
+        assert_eq!(key_agg.fingerprint(), xpub.fingerprint());
+    }
+
+    #[test]
+    fn test_psbt_fields() {
+        let secp = Secp256k1::new();
+        let (secret_keys, key_agg) = signers(&secp, 2);
+        let agg_pubkey = key_agg.aggregated_pubkey();
+        let pk = PublicKey::from_secret_key(&secp, &secret_keys[0]);
+        let (_, pub_nonce) = nonce_gen(&secp, None, &pk, None, None, None);
+
+        let mut input = psbt::Input::default();
+        psbt_insert_participant_value(
+            &mut input,
+            PSBT_IN_MUSIG2_PUB_NONCE,
+            &pk,
+            &agg_pubkey,
+            &pub_nonce.serialize(),
+        );
+        assert_eq!(
+            psbt_pub_nonces(&input, &agg_pubkey).get(&pk),
+            Some(&pub_nonce)
+        );
+        assert!(psbt_pub_nonces(&input, &pk).is_empty());
+        assert!(psbt_partial_sigs(&input, &agg_pubkey).is_empty());
+    }
+}
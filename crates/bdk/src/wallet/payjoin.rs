@@ -0,0 +1,682 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Payjoin
+//!
+//! This module implements [BIP78] payjoin, where the receiver of a payment adds some of its own
+//! inputs to the transaction of the sender, breaking the assumption that all the inputs of a
+//! transaction belong to the same owner.
+//!
+//! The sender builds and signs a transaction as usual, and sends it to the `pj` endpoint of the
+//! receiver's [`PaymentUri`] with [`Wallet::send_payjoin`]: the proposal returned by the receiver
+//! is checked against the original transaction and signed again. If anything goes wrong, the
+//! sender should broadcast the original transaction instead.
+//!
+//! ```no_run
+//! # use core::str::FromStr;
+//! # use bdk::wallet::payjoin::{PayjoinParams, PayjoinRequest, PayjoinTransport};
+//! # use bdk::wallet::payment_uri::PaymentUri;
+//! # use bdk::*;
+//! # struct HttpClient;
+//! # impl PayjoinTransport for HttpClient {
+//! #     type Error = ();
+//! #     fn post(&self, _: &str, _: &str) -> Result<String, ()> { unimplemented!() }
+//! # }
+//! # let mut wallet = doctest_wallet!();
+//! # let client = HttpClient;
+//! let uri = PaymentUri::from_str(
+//!     "bitcoin:bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080?amount=0.0002&pj=https://example.com/pj",
+//! )?;
+//! let mut builder = wallet.build_tx();
+//! builder.add_payment_uri(&uri)?;
+//! let mut psbt = builder.finish()?;
+//! wallet.sign(&mut psbt, SignOptions::default())?;
+//!
+//! // let the receiver take the fees of its inputs from our change, if any
+//! let change = psbt
+//!     .unsigned_tx
+//!     .output
+//!     .iter()
+//!     .position(|txout| wallet.is_mine(&txout.script_pubkey));
+//! let params = PayjoinParams {
+//!     additional_fee_output_index: change,
+//!     max_additional_fee_contribution: 500,
+//!     ..Default::default()
+//! };
+//! let request = PayjoinRequest::new(psbt, &uri, params)?;
+//! let tx = match wallet.send_payjoin(&request, &client, SignOptions::default()) {
+//!     Ok(psbt) => psbt.extract_tx(),
+//!     Err(_) => request.original_tx(),
+//! };
+//! # Ok::<_, Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! The receiver handles the request with [`Wallet::process_payjoin`], which contributes inputs
+//! of the wallet chosen with coin selection, and responds with the signed proposal, or with
+//! [`error_response`] if it failed. Before that, it should make sure that the original
+//! transaction can be broadcast, and broadcast it if the sender doesn't broadcast the payjoin.
+//!
+//! [BIP78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
+//! [`Wallet::send_payjoin`]: crate::wallet::Wallet::send_payjoin
+//! [`Wallet::process_payjoin`]: crate::wallet::Wallet::process_payjoin
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::address::Payload;
+use bitcoin::psbt::{self, PartiallySignedTransaction as Psbt};
+use bitcoin::{AddressType, Network, Script, ScriptBuf, Transaction, TxOut, VarInt, Witness};
+
+use super::coin_selection::TXIN_BASE_WEIGHT;
+use super::payment_uri::PaymentUri;
+use crate::psbt::PsbtUtils;
+use crate::types::FeeRate;
+use crate::Error;
+
+// The only version of the protocol
+const VERSION: &str = "1";
+
+/// Errors related to payjoin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayjoinError {
+    /// The payment URI doesn't have a `pj` endpoint
+    MissingEndpoint,
+    /// The version requested by the sender isn't supported
+    VersionUnsupported,
+    /// A parameter of the request isn't valid
+    InvalidParameter(String),
+    /// The original PSBT isn't valid, e.g. it isn't finalized
+    InvalidOriginal(&'static str),
+    /// The receiver has no inputs to contribute
+    Unavailable,
+    /// The receiver returned an error
+    Receiver {
+        /// The error code, e.g. `unavailable`
+        code: String,
+        /// A description of the error, for debugging
+        message: String,
+    },
+    /// The proposal of the receiver failed one of the checks of the sender
+    InvalidProposal(&'static str),
+}
+
+impl PayjoinError {
+    /// The code of the error, as returned to the sender
+    pub fn error_code(&self) -> &str {
+        match self {
+            Self::VersionUnsupported => "version-unsupported",
+            Self::Unavailable => "unavailable",
+            Self::Receiver { code, .. } => code,
+            _ => "original-psbt-rejected",
+        }
+    }
+}
+
+impl fmt::Display for PayjoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingEndpoint => write!(f, "The payment URI doesn't support payjoin"),
+            Self::VersionUnsupported => write!(f, "Unsupported version"),
+            Self::InvalidParameter(param) => write!(f, "Invalid parameter `{}`", param),
+            Self::InvalidOriginal(reason) => write!(f, "Invalid original PSBT: {}", reason),
+            Self::Unavailable => write!(f, "No inputs available"),
+            Self::Receiver { code, message } => {
+                write!(f, "The receiver returned an error: {} ({})", code, message)
+            }
+            Self::InvalidProposal(reason) => write!(f, "Invalid proposal: {}", reason),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PayjoinError {}
+
+/// The optional parameters sent by the sender together with the original PSBT
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PayjoinParams {
+    /// The index of the output the receiver can reduce to pay for the fees of its inputs,
+    /// usually the change of the sender
+    pub additional_fee_output_index: Option<usize>,
+    /// The maximum amount the receiver can take from that output, in satoshis
+    pub max_additional_fee_contribution: u64,
+    /// The minimum fee rate of the proposal
+    pub min_fee_rate: Option<FeeRate>,
+    /// Forbid the receiver from changing its output, e.g. to pay to a different address
+    pub disable_output_substitution: bool,
+}
+
+impl PayjoinParams {
+    /// Encode the parameters as the query string of a request
+    pub fn to_query(&self) -> String {
+        let mut query = format!("v={}", VERSION);
+        if let Some(index) = self.additional_fee_output_index {
+            query += &format!(
+                "&additionalfeeoutputindex={}&maxadditionalfeecontribution={}",
+                index, self.max_additional_fee_contribution
+            );
+        }
+        if let Some(fee_rate) = self.min_fee_rate {
+            query += &format!("&minfeerate={}", fee_rate.as_sat_per_vb());
+        }
+        if self.disable_output_substitution {
+            query += "&disableoutputsubstitution=true";
+        }
+        query
+    }
+
+    /// Decode the parameters from the query string of a request, ignoring unknown ones
+    pub fn from_query(query: &str) -> Result<Self, PayjoinError> {
+        let mut params = PayjoinParams::default();
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, value) = match param.find('=') {
+                Some(pos) => (&param[..pos], &param[pos + 1..]),
+                None => (param, ""),
+            };
+            let invalid = || PayjoinError::InvalidParameter(key.into());
+            match key {
+                "v" if value != VERSION => return Err(PayjoinError::VersionUnsupported),
+                "additionalfeeoutputindex" => {
+                    params.additional_fee_output_index = Some(value.parse().map_err(|_| invalid())?)
+                }
+                "maxadditionalfeecontribution" => {
+                    params.max_additional_fee_contribution = value.parse().map_err(|_| invalid())?
+                }
+                "minfeerate" => {
+                    let fee_rate = f32::from_str(value).map_err(|_| invalid())?;
+                    if !(fee_rate.is_finite() && fee_rate >= 0.0) {
+                        return Err(invalid());
+                    }
+                    params.min_fee_rate = Some(FeeRate::from_sat_per_vb(fee_rate));
+                }
+                "disableoutputsubstitution" => {
+                    params.disable_output_substitution = value.parse().map_err(|_| invalid())?
+                }
+                _ => {}
+            }
+        }
+        Ok(params)
+    }
+}
+
+/// Sends requests to the payjoin endpoint of a receiver
+pub trait PayjoinTransport {
+    /// Error returned when the request couldn't be sent, or the response couldn't be received
+    type Error;
+
+    /// Sends `body` with an HTTP POST request to `url`, and returns the body of the response,
+    /// which is also expected if the receiver returns an error.
+    fn post(&self, url: &str, body: &str) -> Result<String, Self::Error>;
+}
+
+/// Error returned by [`Wallet::send_payjoin`]
+///
+/// [`Wallet::send_payjoin`]: crate::wallet::Wallet::send_payjoin
+#[derive(Debug)]
+pub enum SendError<E> {
+    /// The request couldn't be sent, or the response couldn't be received
+    Transport(E),
+    /// The receiver returned an error, or its proposal is invalid or couldn't be signed
+    Proposal(Error),
+}
+
+impl<E: fmt::Display> fmt::Display for SendError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Transport(err) => write!(f, "failed to send the payjoin request: {}", err),
+            SendError::Proposal(err) => write!(f, "payjoin failed: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Display + fmt::Debug> std::error::Error for SendError<E> {}
+
+/// A payjoin request: the original PSBT, signed and finalized, and the parameters sent to the
+/// receiver
+#[derive(Debug, Clone)]
+pub struct PayjoinRequest {
+    original: Psbt,
+    endpoint: String,
+    payee: ScriptBuf,
+    params: PayjoinParams,
+}
+
+impl PayjoinRequest {
+    /// Create a request to pay `uri` with `original`, which must be signed and finalized
+    ///
+    /// Output substitution is disabled if the URI asks for it with `pjos=0`.
+    pub fn new(
+        original: Psbt,
+        uri: &PaymentUri,
+        mut params: PayjoinParams,
+    ) -> Result<Self, PayjoinError> {
+        let endpoint = uri
+            .extras
+            .iter()
+            .find(|(key, _)| key == "pj")
+            .map(|(_, endpoint)| endpoint.clone())
+            .ok_or(PayjoinError::MissingEndpoint)?;
+        if uri
+            .extras
+            .iter()
+            .any(|(key, value)| key == "pjos" && value == "0")
+        {
+            params.disable_output_substitution = true;
+        }
+
+        if !original.inputs.iter().all(is_finalized) {
+            return Err(PayjoinError::InvalidOriginal("not finalized"));
+        }
+        if (0..original.inputs.len()).any(|i| original.get_utxo_for(i).is_none()) {
+            return Err(PayjoinError::InvalidOriginal("missing UTXO"));
+        }
+        let payee = uri.address.payload.script_pubkey();
+        if !original
+            .unsigned_tx
+            .output
+            .iter()
+            .any(|txout| txout.script_pubkey == payee)
+        {
+            return Err(PayjoinError::InvalidOriginal("doesn't pay the receiver"));
+        }
+        match params.additional_fee_output_index {
+            Some(index) if index >= original.unsigned_tx.output.len() => {
+                return Err(PayjoinError::InvalidParameter(
+                    "additionalfeeoutputindex".into(),
+                ))
+            }
+            _ => {}
+        }
+
+        Ok(PayjoinRequest {
+            original,
+            endpoint,
+            payee,
+            params,
+        })
+    }
+
+    /// The URL of the request, including the parameters
+    pub fn url(&self) -> String {
+        let separator = if self.endpoint.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        format!("{}{}{}", self.endpoint, separator, self.params.to_query())
+    }
+
+    /// The body of the request, the base64-encoded original PSBT
+    pub fn body(&self) -> String {
+        self.original.to_string()
+    }
+
+    /// The original transaction, to broadcast if the payjoin fails
+    pub fn original_tx(&self) -> Transaction {
+        self.original.clone().extract_tx()
+    }
+
+    // Parse the response of the receiver and check the proposal against the original PSBT, then
+    // restore the data of our inputs and outputs so that it can be signed
+    pub(crate) fn process_response<F>(
+        &self,
+        response: &str,
+        is_mine: F,
+    ) -> Result<Psbt, PayjoinError>
+    where
+        F: Fn(&Script) -> bool,
+    {
+        let mut proposal = parse_response(response)?;
+        let invalid = PayjoinError::InvalidProposal;
+        let original_tx = &self.original.unsigned_tx;
+        let tx = proposal.unsigned_tx.clone();
+
+        if proposal.inputs.len() != tx.input.len() || proposal.outputs.len() != tx.output.len() {
+            return Err(invalid("inconsistent PSBT"));
+        }
+        if tx.version != original_tx.version || tx.lock_time != original_tx.lock_time {
+            return Err(invalid("the version or the locktime changed"));
+        }
+
+        let input_type = input_type(&self.original);
+        let sequence = original_tx.input[0].sequence;
+        let same_sequence = original_tx
+            .input
+            .iter()
+            .all(|txin| txin.sequence == sequence);
+
+        // fill in the final scripts of all the inputs to know the weight of the transaction
+        let mut finalized = tx.clone();
+        let mut input_value = 0;
+        let mut receiver_inputs = 0;
+        let mut receiver_inputs_weight = 0;
+        let mut found = Vec::with_capacity(original_tx.input.len());
+        let mut inputs = Vec::with_capacity(tx.input.len());
+        for (i, (txin, input)) in tx.input.iter().zip(&proposal.inputs).enumerate() {
+            let index = original_tx
+                .input
+                .iter()
+                .position(|original| original.previous_output == txin.previous_output);
+            let restored = match index {
+                Some(index) => {
+                    if found.contains(&index) {
+                        return Err(invalid("duplicate input"));
+                    }
+                    found.push(index);
+                    if txin.sequence != original_tx.input[index].sequence {
+                        return Err(invalid("the sequence of our input changed"));
+                    }
+                    if is_finalized(input)
+                        || !input.partial_sigs.is_empty()
+                        || input.tap_key_sig.is_some()
+                        || !input.tap_script_sigs.is_empty()
+                    {
+                        return Err(invalid("our input is signed"));
+                    }
+                    input_value += self.original.get_utxo_for(index).expect("checked").value;
+
+                    let mut restored = self.original.inputs[index].clone();
+                    finalized.input[i].script_sig =
+                        restored.final_script_sig.take().unwrap_or_default();
+                    finalized.input[i].witness =
+                        restored.final_script_witness.take().unwrap_or_default();
+                    restored
+                }
+                None => {
+                    if !is_finalized(input) {
+                        return Err(invalid("an input of the receiver isn't finalized"));
+                    }
+                    let utxo = proposal
+                        .get_utxo_for(i)
+                        .ok_or(invalid("missing UTXO of an input of the receiver"))?;
+                    if is_mine(&utxo.script_pubkey) {
+                        return Err(invalid("the receiver added one of our inputs"));
+                    }
+                    if input_type.is_some() && address_type(&utxo.script_pubkey) != input_type {
+                        return Err(invalid("the receiver added an input of a different type"));
+                    }
+                    if same_sequence && txin.sequence != sequence {
+                        return Err(invalid(
+                            "the receiver added an input with a different sequence",
+                        ));
+                    }
+                    input_value += utxo.value;
+                    receiver_inputs += 1;
+                    receiver_inputs_weight += TXIN_BASE_WEIGHT + satisfaction_weight(input);
+
+                    finalized.input[i].script_sig =
+                        input.final_script_sig.clone().unwrap_or_default();
+                    finalized.input[i].witness =
+                        input.final_script_witness.clone().unwrap_or_default();
+                    input.clone()
+                }
+            };
+            inputs.push(restored);
+        }
+        proposal.inputs = inputs;
+        if found.len() != original_tx.input.len() {
+            return Err(invalid("the receiver removed one of our inputs"));
+        }
+
+        let mut contribution = 0;
+        let mut matched = Vec::with_capacity(original_tx.output.len());
+        for (index, original) in original_tx.output.iter().enumerate() {
+            let position = (0..tx.output.len()).find(|i| {
+                !matched.contains(i) && tx.output[*i].script_pubkey == original.script_pubkey
+            });
+            let is_payee = original.script_pubkey == self.payee;
+            let position = match position {
+                Some(position) => position,
+                None if is_payee && !self.params.disable_output_substitution => continue,
+                None => return Err(invalid("the receiver removed one of the outputs")),
+            };
+            matched.push(position);
+
+            let value = tx.output[position].value;
+            if is_payee {
+                if self.params.disable_output_substitution && value < original.value {
+                    return Err(invalid("the receiver reduced its output"));
+                }
+                continue;
+            }
+            if self.params.additional_fee_output_index == Some(index) {
+                contribution = original.value.saturating_sub(value);
+                if contribution > self.params.max_additional_fee_contribution {
+                    return Err(invalid("the receiver took too much for the fees"));
+                }
+            } else if value != original.value {
+                return Err(invalid("the receiver changed one of the outputs"));
+            }
+            proposal.outputs[position] = self.original.outputs[index].clone();
+        }
+        let original_fee = self.original.fee_amount().expect("checked");
+        if contribution
+            > max_fee_contribution(
+                &self.original,
+                original_fee,
+                receiver_inputs,
+                receiver_inputs_weight,
+            )
+        {
+            return Err(invalid(
+                "the receiver took more than the fees of its inputs at the original fee rate",
+            ));
+        }
+
+        // what we send out can only increase by the contribution to the fees, which must all go
+        // to the fees
+        let ours = |outputs: &[TxOut]| -> u64 {
+            outputs
+                .iter()
+                .filter(|txout| is_mine(&txout.script_pubkey))
+                .map(|txout| txout.value)
+                .sum()
+        };
+        if ours(&original_tx.output)
+            > ours(&tx.output) + self.params.max_additional_fee_contribution
+        {
+            return Err(invalid("the receiver took too much for the fees"));
+        }
+        let output_value: u64 = tx.output.iter().map(|txout| txout.value).sum();
+        let fee = input_value
+            .checked_sub(output_value)
+            .ok_or(invalid("the outputs exceed the inputs"))?;
+        if fee < original_fee + contribution {
+            return Err(invalid(
+                "the receiver kept some of our contribution to the fees",
+            ));
+        }
+        if let Some(min_fee_rate) = self.params.min_fee_rate {
+            if FeeRate::from_wu(fee, finalized.weight()) < min_fee_rate {
+                return Err(invalid("the fee rate is below the minimum"));
+            }
+        }
+
+        Ok(proposal)
+    }
+}
+
+/// The body of the response to a request that failed, with the error code defined by [BIP78]
+///
+/// The message is meant for debugging, and may reveal some details about the wallet, like the
+/// lack of funds to contribute.
+///
+/// [BIP78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
+pub fn error_response(err: &Error) -> String {
+    let code = match err {
+        Error::Payjoin(err) => err.error_code(),
+        Error::InsufficientFunds { .. } => "not-enough-money",
+        _ => "unavailable",
+    };
+    serde_json::json!({ "errorCode": code, "message": err.to_string() }).to_string()
+}
+
+fn parse_response(response: &str) -> Result<Psbt, PayjoinError> {
+    let response = response.trim();
+    if let Ok(psbt) = Psbt::from_str(response) {
+        return Ok(psbt);
+    }
+
+    let invalid = PayjoinError::InvalidProposal("not a PSBT");
+    let error: serde_json::Value = serde_json::from_str(response).map_err(|_| invalid.clone())?;
+    let field = |name| error.get(name).and_then(|value| value.as_str());
+    Err(PayjoinError::Receiver {
+        code: field("errorCode").ok_or(invalid)?.into(),
+        message: field("message").unwrap_or_default().into(),
+    })
+}
+
+// Check the original PSBT received by the receiver, and return its fee
+pub(crate) fn check_original<F>(original: &Psbt, is_mine: F) -> Result<u64, PayjoinError>
+where
+    F: Fn(&Script) -> bool,
+{
+    let invalid = PayjoinError::InvalidOriginal;
+    if original.inputs.len() != original.unsigned_tx.input.len()
+        || original.outputs.len() != original.unsigned_tx.output.len()
+    {
+        return Err(invalid("inconsistent PSBT"));
+    }
+    if !original.inputs.iter().all(is_finalized) {
+        return Err(invalid("not finalized"));
+    }
+    for i in 0..original.inputs.len() {
+        let utxo = original.get_utxo_for(i).ok_or(invalid("missing UTXO"))?;
+        if is_mine(&utxo.script_pubkey) {
+            return Err(invalid("spends our outputs"));
+        }
+    }
+    if !original
+        .unsigned_tx
+        .output
+        .iter()
+        .any(|txout| is_mine(&txout.script_pubkey))
+    {
+        return Err(invalid("doesn't pay us"));
+    }
+
+    original
+        .fee_amount()
+        .ok_or(invalid("the outputs exceed the inputs"))
+}
+
+// The most the sender can pay for the fees of the `inputs` added by the receiver: as required by
+// BIP78 this is the fee rate of the original transaction times the size of the inputs, assumed to
+// be of the same type as the sender's. If the type is unknown, `inputs_weight` is used instead.
+pub(crate) fn max_fee_contribution(
+    original: &Psbt,
+    original_fee: u64,
+    inputs: usize,
+    inputs_weight: usize,
+) -> u64 {
+    let inputs_weight = match input_type(original) {
+        Some(AddressType::P2pkh) => inputs as u64 * 148 * 4,
+        // BIP78 only considers P2SH-P2WPKH among the P2SH inputs
+        Some(AddressType::P2sh) => inputs as u64 * 91 * 4,
+        Some(AddressType::P2wpkh) => inputs as u64 * 68 * 4,
+        Some(AddressType::P2tr) => inputs as u64 * 230,
+        _ => inputs_weight as u64,
+    };
+    let original_weight = original.clone().extract_tx().weight().to_wu();
+
+    (original_fee as u128 * inputs_weight as u128 / original_weight as u128) as u64
+}
+
+// The weight of the final scripts of a finalized input, including their length prefixes
+pub(crate) fn satisfaction_weight(input: &psbt::Input) -> usize {
+    let script_sig_len = input
+        .final_script_sig
+        .as_ref()
+        .map_or(0, |script| script.len());
+    (VarInt(script_sig_len as u64).len() + script_sig_len) * 4
+        + input
+            .final_script_witness
+            .as_ref()
+            .map_or(0, Witness::serialized_len)
+}
+
+// The type of the inputs, if they all have the same
+pub(crate) fn input_type(psbt: &Psbt) -> Option<AddressType> {
+    let mut types = (0..psbt.inputs.len()).map(|i| {
+        psbt.get_utxo_for(i)
+            .and_then(|utxo| address_type(&utxo.script_pubkey))
+    });
+    let first = types.next()??;
+    if types.all(|input_type| input_type == Some(first)) {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn address_type(script: &Script) -> Option<AddressType> {
+    // the network doesn't matter
+    Payload::from_script(script)
+        .ok()
+        .and_then(|payload| bitcoin::Address::new(Network::Bitcoin, payload).address_type())
+}
+
+fn is_finalized(input: &psbt::Input) -> bool {
+    input.final_script_sig.is_some() || input.final_script_witness.is_some()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_params_query() {
+        let params = PayjoinParams {
+            additional_fee_output_index: Some(1),
+            max_additional_fee_contribution: 182,
+            min_fee_rate: Some(FeeRate::from_sat_per_vb(2.0)),
+            disable_output_substitution: true,
+        };
+        let query = params.to_query();
+        assert_eq!(
+            query,
+            "v=1&additionalfeeoutputindex=1&maxadditionalfeecontribution=182&minfeerate=2&disableoutputsubstitution=true"
+        );
+        assert_eq!(PayjoinParams::from_query(&query), Ok(params));
+
+        assert_eq!(
+            PayjoinParams::from_query("v=2"),
+            Err(PayjoinError::VersionUnsupported)
+        );
+        assert_eq!(
+            PayjoinParams::from_query("v=1&maxadditionalfeecontribution=-1"),
+            Err(PayjoinError::InvalidParameter(
+                "maxadditionalfeecontribution".into()
+            ))
+        );
+        assert_eq!(
+            PayjoinParams::from_query("v=1&unknown=1"),
+            Ok(PayjoinParams::default())
+        );
+    }
+
+    #[test]
+    fn test_error_response() {
+        let response = error_response(&Error::Payjoin(PayjoinError::Unavailable));
+        assert_eq!(
+            parse_response(&response),
+            Err(PayjoinError::Receiver {
+                code: "unavailable".into(),
+                message: "Payjoin error: No inputs available".into(),
+            })
+        );
+        assert_eq!(
+            parse_response("not a psbt"),
+            Err(PayjoinError::InvalidProposal("not a PSBT"))
+        );
+    }
+}
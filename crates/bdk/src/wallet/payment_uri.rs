@@ -0,0 +1,354 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Payment URIs
+//!
+//! This module implements [BIP21] `bitcoin:` URIs, which carry an address together with an
+//! optional amount, a label and a message for the payer, and a BOLT11 invoice for wallets that
+//! prefer to pay over lightning.
+//!
+//! A receiver creates one with [`Wallet::get_payment_uri`], while a payer parses it and adds it
+//! to a transaction with [`TxBuilder::add_payment_uri`], which checks that the address is valid
+//! for the wallet's network:
+//!
+//! ```
+//! # use core::str::FromStr;
+//! # use bdk::wallet::payment_uri::PaymentUri;
+//! # use bdk::*;
+//! # let mut wallet = doctest_wallet!();
+//! let uri = PaymentUri::from_str(
+//!     "bitcoin:bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080?amount=0.0002&label=Coffee%20shop",
+//! )?;
+//! assert_eq!(uri.amount, Some(20_000));
+//! assert_eq!(uri.label.as_deref(), Some("Coffee shop"));
+//!
+//! let mut builder = wallet.build_tx();
+//! builder.add_payment_uri(&uri)?;
+//! # Ok::<_, Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [BIP21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+//! [`Wallet::get_payment_uri`]: crate::wallet::Wallet::get_payment_uri
+//! [`TxBuilder::add_payment_uri`]: crate::wallet::tx_builder::TxBuilder::add_payment_uri
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::address::{self, NetworkUnchecked};
+use bitcoin::amount::{Denomination, ParseAmountError};
+use bitcoin::{Address, Amount};
+
+const SCHEME: &str = "bitcoin:";
+
+/// Errors related to payment URIs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaymentUriError {
+    /// The URI doesn't start with `bitcoin:`
+    InvalidScheme,
+    /// The address is invalid, or is for a different network
+    Address(address::Error),
+    /// The amount isn't a valid number of bitcoins
+    InvalidAmount(ParseAmountError),
+    /// A parameter is not correctly percent-encoded, or isn't valid UTF-8
+    InvalidEncoding(String),
+    /// A parameter appears more than once
+    DuplicateParameter(String),
+    /// A `req-` parameter we don't understand, which means the URI can't be paid
+    UnknownRequiredParameter(String),
+    /// The URI doesn't specify an amount, so it can't be used as a recipient
+    MissingAmount,
+}
+
+impl fmt::Display for PaymentUriError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidScheme => write!(f, "The URI doesn't start with `{}`", SCHEME),
+            Self::Address(err) => write!(f, "Invalid address: {}", err),
+            Self::InvalidAmount(err) => write!(f, "Invalid amount: {}", err),
+            Self::InvalidEncoding(param) => write!(f, "Invalid encoding of `{}`", param),
+            Self::DuplicateParameter(param) => write!(f, "Duplicate parameter `{}`", param),
+            Self::UnknownRequiredParameter(param) => {
+                write!(f, "Unknown required parameter `{}`", param)
+            }
+            Self::MissingAmount => write!(f, "The URI doesn't specify an amount"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PaymentUriError {}
+
+impl From<address::Error> for PaymentUriError {
+    fn from(err: address::Error) -> Self {
+        PaymentUriError::Address(err)
+    }
+}
+
+impl From<ParseAmountError> for PaymentUriError {
+    fn from(err: ParseAmountError) -> Self {
+        PaymentUriError::InvalidAmount(err)
+    }
+}
+
+/// A [BIP21] payment URI
+///
+/// The address isn't checked against a network when parsing, that happens when it's added to a
+/// transaction with [`TxBuilder::add_payment_uri`], or can be done explicitly with
+/// [`Address::require_network`].
+///
+/// [BIP21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+/// [`TxBuilder::add_payment_uri`]: crate::wallet::tx_builder::TxBuilder::add_payment_uri
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentUri {
+    /// The address to pay
+    pub address: Address<NetworkUnchecked>,
+    /// The amount requested, in satoshis
+    pub amount: Option<u64>,
+    /// A label for the address, e.g. the name of the receiver
+    pub label: Option<String>,
+    /// A message describing the payment, for the payer
+    pub message: Option<String>,
+    /// A BOLT11 invoice that can be paid instead of the address
+    pub lightning: Option<String>,
+    /// Any other optional parameter, in the order they appear in the URI
+    pub extras: Vec<(String, String)>,
+}
+
+impl PaymentUri {
+    /// Create a URI for `address` without any parameter
+    pub fn new(address: Address) -> Self {
+        PaymentUri {
+            // there's no other way to go back to an unchecked address
+            address: Address::from_str(&address.to_string()).expect("valid address"),
+            amount: None,
+            label: None,
+            message: None,
+            lightning: None,
+            extras: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for PaymentUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // displaying doesn't depend on the network
+        write!(f, "{}{}", SCHEME, self.address.clone().assume_checked())?;
+
+        let amount = self
+            .amount
+            .map(|amount| Amount::from_sat(amount).to_string_in(Denomination::Bitcoin));
+        let params = [
+            ("amount", amount.as_deref()),
+            ("label", self.label.as_deref()),
+            ("message", self.message.as_deref()),
+            ("lightning", self.lightning.as_deref()),
+        ];
+        let params = params
+            .iter()
+            .filter_map(|(key, value)| value.map(|value| (*key, value)))
+            .chain(
+                self.extras
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), value.as_str())),
+            );
+        for (i, (key, value)) in params.enumerate() {
+            f.write_str(if i == 0 { "?" } else { "&" })?;
+            write_encoded(f, key)?;
+            f.write_str("=")?;
+            write_encoded(f, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for PaymentUri {
+    type Err = PaymentUriError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // the scheme is case insensitive, e.g. `BITCOIN:` is used in QR codes
+        match s.get(..SCHEME.len()) {
+            Some(scheme) if scheme.eq_ignore_ascii_case(SCHEME) => {}
+            _ => return Err(PaymentUriError::InvalidScheme),
+        }
+        let s = &s[SCHEME.len()..];
+        let (address, query) = match s.find('?') {
+            Some(pos) => (&s[..pos], &s[pos + 1..]),
+            None => (s, ""),
+        };
+
+        let mut uri = PaymentUri {
+            address: Address::from_str(address)?,
+            amount: None,
+            label: None,
+            message: None,
+            lightning: None,
+            extras: Vec::new(),
+        };
+
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, value) = match param.find('=') {
+                Some(pos) => (&param[..pos], &param[pos + 1..]),
+                None => (param, ""),
+            };
+            let key = decode(key)?;
+            let value = decode(value)?;
+
+            let field = match key.as_str() {
+                "amount" => {
+                    if uri.amount.is_some() {
+                        return Err(PaymentUriError::DuplicateParameter(key));
+                    }
+                    uri.amount = Some(Amount::from_str_in(&value, Denomination::Bitcoin)?.to_sat());
+                    continue;
+                }
+                "label" => &mut uri.label,
+                "message" => &mut uri.message,
+                "lightning" => &mut uri.lightning,
+                _ if key.starts_with("req-") => {
+                    return Err(PaymentUriError::UnknownRequiredParameter(key))
+                }
+                _ => {
+                    uri.extras.push((key, value));
+                    continue;
+                }
+            };
+            if field.is_some() {
+                return Err(PaymentUriError::DuplicateParameter(key));
+            }
+            *field = Some(value);
+        }
+
+        Ok(uri)
+    }
+}
+
+// Percent-encode everything but the unreserved characters of RFC 3986
+fn write_encoded(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                write!(f, "{}", byte as char)?
+            }
+            _ => write!(f, "%{:02X}", byte)?,
+        }
+    }
+    Ok(())
+}
+
+fn decode(s: &str) -> Result<String, PaymentUriError> {
+    let invalid = || PaymentUriError::InvalidEncoding(s.into());
+
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(byte) = iter.next() {
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+        let hex = [
+            iter.next().ok_or_else(invalid)?,
+            iter.next().ok_or_else(invalid)?,
+        ];
+        let hex = core::str::from_utf8(&hex).map_err(|_| invalid())?;
+        bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+    }
+
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bitcoin::Network;
+
+    const ADDRESS: &str = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+
+    #[test]
+    fn test_parse() {
+        let uri = PaymentUri::from_str(&format!(
+            "bitcoin:{}?amount=20.3&label=Luke-Jr&message=Donation%20for%20project%20xyz&somethingyoudontunderstand=50&lightning=lnbc1",
+            ADDRESS
+        ))
+        .unwrap();
+        assert_eq!(uri.address, Address::from_str(ADDRESS).unwrap());
+        assert_eq!(uri.amount, Some(2_030_000_000));
+        assert_eq!(uri.label.as_deref(), Some("Luke-Jr"));
+        assert_eq!(uri.message.as_deref(), Some("Donation for project xyz"));
+        assert_eq!(uri.lightning.as_deref(), Some("lnbc1"));
+        assert_eq!(
+            uri.extras,
+            vec![("somethingyoudontunderstand".to_string(), "50".to_string())]
+        );
+
+        let uri = PaymentUri::from_str(&format!("BITCOIN:{}", ADDRESS.to_uppercase())).unwrap();
+        assert_eq!(uri.address, Address::from_str(ADDRESS).unwrap());
+        assert_eq!(uri.amount, None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            PaymentUri::from_str(ADDRESS),
+            Err(PaymentUriError::InvalidScheme)
+        );
+        assert!(matches!(
+            PaymentUri::from_str("bitcoin:notanaddress"),
+            Err(PaymentUriError::Address(_))
+        ));
+        assert!(matches!(
+            PaymentUri::from_str(&format!("bitcoin:{}?amount=1,5", ADDRESS)),
+            Err(PaymentUriError::InvalidAmount(_))
+        ));
+        assert_eq!(
+            PaymentUri::from_str(&format!("bitcoin:{}?label=a&label=b", ADDRESS)),
+            Err(PaymentUriError::DuplicateParameter("label".to_string()))
+        );
+        assert_eq!(
+            PaymentUri::from_str(&format!(
+                "bitcoin:{}?req-somethingyoudontunderstand=50",
+                ADDRESS
+            )),
+            Err(PaymentUriError::UnknownRequiredParameter(
+                "req-somethingyoudontunderstand".to_string()
+            ))
+        );
+        assert_eq!(
+            PaymentUri::from_str(&format!("bitcoin:{}?message=%E2%82", ADDRESS)),
+            Err(PaymentUriError::InvalidEncoding("%E2%82".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_display_roundtrip() {
+        let address = Address::from_str(ADDRESS)
+            .unwrap()
+            .require_network(Network::Bitcoin)
+            .unwrap();
+        let mut uri = PaymentUri::new(address);
+        assert_eq!(uri.to_string(), format!("bitcoin:{}", ADDRESS));
+
+        uri.amount = Some(25_000);
+        uri.label = Some("Café & bar".to_string());
+        uri.extras
+            .push(("pj".to_string(), "https://example.com/pj?a=b".to_string()));
+        let s = uri.to_string();
+        assert_eq!(
+            s,
+            format!(
+                "bitcoin:{}?amount=0.00025&label=Caf%C3%A9%20%26%20bar&pj=https%3A%2F%2Fexample.com%2Fpj%3Fa%3Db",
+                ADDRESS
+            )
+        );
+        assert_eq!(PaymentUri::from_str(&s), Ok(uri));
+    }
+}
@@ -0,0 +1,642 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Wallet recovery
+//!
+//! When restoring a wallet from its seed, the script type and the accounts it used are often
+//! unknown. [`recover`] tries the standard [templates](crate::descriptor::template) and returns the
+//! accounts that have been used.
+//!
+//! For each template, accounts are discovered as described in [BIP44]: they are scanned in order
+//! until one without history is found, and each keychain is scanned until `gap_limit` consecutive
+//! addresses without history are found. The next account of every template is scanned in the same
+//! round, so the chain source is scanned once per account rather than once per template and
+//! account.
+//!
+//! The scan itself is done by the caller, with the keychain scan of any chain source: `recover`
+//! passes the candidate keychains of each round in a [`ScanRequest`], and expects the last index
+//! with history of each keychain in return. [`ScanRequest::spks`] gives the script pubkeys to pass
+//! to the scans of `bdk_esplora` and `bdk_electrum`, which return these indexes:
+//!
+//! ```no_run
+//! # use bdk::bitcoin::bip32::ExtendedPrivKey;
+//! use bdk::bitcoin::Network;
+//! use bdk::chain::TxGraph;
+//! use bdk::wallet::recovery::{recover, RecoveryOptions};
+//! use bdk_esplora::{esplora_client, EsploraExt};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let master_key: ExtendedPrivKey = todo!();
+//! let client =
+//!     esplora_client::Builder::new("https://blockstream.info/testnet/api").build_blocking()?;
+//! let accounts = recover(
+//!     master_key,
+//!     Network::Testnet,
+//!     &RecoveryOptions::default(),
+//!     |request| {
+//!         client
+//!             .scan_txs_with_keychains(
+//!                 &TxGraph::<()>::default(),
+//!                 request.spks(),
+//!                 None,
+//!                 None,
+//!                 request.stop_gap(),
+//!                 5,
+//!             )
+//!             .map(|(_, last_active)| last_active)
+//!     },
+//! )?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Chain sources that emit blocks, like the `Emitter` of `bdk_bitcoind_rpc`, can be scanned with
+//! [`ScanRequest::index_block`] instead. Each round goes through the blocks again, so it's worth
+//! starting from the block where the wallet was created:
+//!
+//! ```
+//! # use bdk::bitcoin::bip32::ExtendedPrivKey;
+//! # use bdk::bitcoin::{Block, Network};
+//! # use bdk::wallet::recovery::{recover, RecoveryOptions};
+//! # use bdk::Wallet;
+//! fn restore(
+//!     master_key: ExtendedPrivKey,
+//!     blocks: &[Block],
+//! ) -> Result<Option<Wallet>, Box<dyn std::error::Error>> {
+//!     let accounts = recover(
+//!         master_key,
+//!         Network::Testnet,
+//!         &RecoveryOptions::default(),
+//!         |mut request| {
+//!             for block in blocks {
+//!                 request.index_block(block);
+//!             }
+//!             Ok::<_, core::convert::Infallible>(request.last_active_indices())
+//!         },
+//!     )?;
+//!     match accounts.into_iter().next() {
+//!         Some(account) => {
+//!             let wallet = Wallet::new_no_persist(
+//!                 account.descriptor,
+//!                 Some(account.change_descriptor),
+//!                 Network::Testnet,
+//!             )?;
+//!             Ok(Some(wallet))
+//!         }
+//!         None => Ok(None),
+//!     }
+//! }
+//! ```
+//!
+//! [BIP44]: https://github.com/bitcoin/bips/blob/master/bip-0044.mediawiki#account-discovery
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt;
+use core::future::Future;
+
+use bdk_chain::indexed_tx_graph::Indexer;
+use bdk_chain::keychain::{self, KeychainTxOutIndex};
+use bdk_chain::{Append, SpkIterator};
+use bitcoin::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey};
+use bitcoin::{Block, Network};
+
+use crate::descriptor::template::{
+    DescriptorTemplate, DescriptorTemplateOut, P2Pkh, P2Wpkh, P2Wpkh_P2Sh, P2TR,
+};
+use crate::descriptor::{DescriptorError, ExtendedDescriptor};
+use crate::types::KeychainKind;
+
+/// Errors related to wallet recovery
+#[derive(Debug)]
+pub enum RecoveryError<E> {
+    /// A descriptor couldn't be built from the key
+    Descriptor(DescriptorError),
+    /// The scan of the chain source failed
+    Source(E),
+}
+
+impl<E: fmt::Display> fmt::Display for RecoveryError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Descriptor(err) => write!(f, "Descriptor error: {}", err),
+            Self::Source(err) => write!(f, "Failed to scan the chain source: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Display + fmt::Debug> std::error::Error for RecoveryError<E> {}
+
+impl<E> From<DescriptorError> for RecoveryError<E> {
+    fn from(err: DescriptorError) -> Self {
+        RecoveryError::Descriptor(err)
+    }
+}
+
+/// A standard derivation template
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RecoveryTemplate {
+    /// [BIP44](crate::descriptor::template::Bip44), `pkh(key/44'/{0,1}'/account'/{0,1}/*)`
+    Bip44,
+    /// [BIP49](crate::descriptor::template::Bip49), `sh(wpkh(key/49'/{0,1}'/account'/{0,1}/*))`
+    Bip49,
+    /// [BIP84](crate::descriptor::template::Bip84), `wpkh(key/84'/{0,1}'/account'/{0,1}/*)`
+    Bip84,
+    /// [BIP86](crate::descriptor::template::Bip86), `tr(key/86'/{0,1}'/account'/{0,1}/*)`
+    Bip86,
+}
+
+impl RecoveryTemplate {
+    /// The BIP43 purpose of the template
+    pub fn purpose(&self) -> u32 {
+        match self {
+            RecoveryTemplate::Bip44 => 44,
+            RecoveryTemplate::Bip49 => 49,
+            RecoveryTemplate::Bip84 => 84,
+            RecoveryTemplate::Bip86 => 86,
+        }
+    }
+
+    /// Build the descriptor of `keychain` for `account`
+    pub fn build(
+        &self,
+        master_key: ExtendedPrivKey,
+        account: u32,
+        keychain: KeychainKind,
+        network: Network,
+    ) -> Result<DescriptorTemplateOut, DescriptorError> {
+        let chain = match keychain {
+            KeychainKind::External => 0,
+            KeychainKind::Internal => 1,
+            KeychainKind::Custom(_) => {
+                return Err(DescriptorError::Key(crate::keys::KeyError::Message(
+                    "templates only support the external and internal keychains".into(),
+                )))
+            }
+        };
+        let coin_type = match network {
+            Network::Bitcoin => 0,
+            _ => 1,
+        };
+        let path = DerivationPath::from(vec![
+            ChildNumber::from_hardened_idx(self.purpose())?,
+            ChildNumber::from_hardened_idx(coin_type)?,
+            ChildNumber::from_hardened_idx(account)?,
+            ChildNumber::from_normal_idx(chain)?,
+        ]);
+
+        let key = (master_key, path);
+        match self {
+            RecoveryTemplate::Bip44 => P2Pkh(key).build(network),
+            RecoveryTemplate::Bip49 => P2Wpkh_P2Sh(key).build(network),
+            RecoveryTemplate::Bip84 => P2Wpkh(key).build(network),
+            RecoveryTemplate::Bip86 => P2TR(key).build(network),
+        }
+    }
+}
+
+/// Options for [`recover`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveryOptions {
+    /// The templates to try
+    pub templates: Vec<RecoveryTemplate>,
+    /// The number of consecutive unused addresses after which a keychain is considered unused
+    pub gap_limit: u32,
+}
+
+impl Default for RecoveryOptions {
+    fn default() -> Self {
+        RecoveryOptions {
+            templates: vec![
+                RecoveryTemplate::Bip44,
+                RecoveryTemplate::Bip49,
+                RecoveryTemplate::Bip84,
+                RecoveryTemplate::Bip86,
+            ],
+            gap_limit: 20,
+        }
+    }
+}
+
+/// An account found by [`recover`]
+#[derive(Debug, Clone)]
+pub struct RecoveredAccount {
+    /// The template of the account
+    pub template: RecoveryTemplate,
+    /// The account number
+    pub account: u32,
+    /// The descriptor of the external keychain
+    pub descriptor: DescriptorTemplateOut,
+    /// The descriptor of the internal keychain
+    pub change_descriptor: DescriptorTemplateOut,
+    /// The last external index with history
+    pub last_external_index: Option<u32>,
+    /// The last internal index with history
+    pub last_internal_index: Option<u32>,
+}
+
+/// A keychain of a candidate account, as identified in a [`ScanRequest`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CandidateKeychain {
+    /// The template of the account
+    pub template: RecoveryTemplate,
+    /// The account number
+    pub account: u32,
+    /// The keychain of the account
+    pub keychain: KeychainKind,
+}
+
+/// The keychains to scan in a round of [`recover`]
+///
+/// See [the module-level docs](self) for how to scan them.
+#[derive(Debug, Clone)]
+pub struct ScanRequest {
+    index: KeychainTxOutIndex<CandidateKeychain>,
+    stop_gap: u32,
+}
+
+impl ScanRequest {
+    /// The number of consecutive unused addresses after which a keychain is considered unused
+    pub fn stop_gap(&self) -> usize {
+        self.stop_gap as usize
+    }
+
+    /// The script pubkeys of each keychain, to scan until a gap of [`stop_gap`] unused ones
+    ///
+    /// [`stop_gap`]: Self::stop_gap
+    pub fn spks(&self) -> BTreeMap<CandidateKeychain, SpkIterator<ExtendedDescriptor>> {
+        self.index.spks_of_all_keychains()
+    }
+
+    /// Look for the keychains in the outputs of `block`
+    ///
+    /// The keychains are watched up to [`stop_gap`] addresses after the last used one, so blocks
+    /// must be indexed in order: an address used in a block before the one using the address that
+    /// brings it within the gap is missed.
+    ///
+    /// [`stop_gap`]: Self::stop_gap
+    pub fn index_block(&mut self, block: &Block) {
+        // the outputs of a block are not ordered by index, so go through them again as long as
+        // using an address brings new ones within the gap
+        loop {
+            let mut changeset = keychain::ChangeSet::default();
+            for tx in &block.txdata {
+                changeset.append(self.index.index_tx(tx));
+            }
+            if changeset.is_empty() {
+                break;
+            }
+        }
+    }
+
+    /// The last index with history of each keychain found by [`index_block`]
+    ///
+    /// [`index_block`]: Self::index_block
+    pub fn last_active_indices(&self) -> BTreeMap<CandidateKeychain, u32> {
+        self.index.last_used_indices()
+    }
+}
+
+// The accounts found so far for a template, and the candidate to scan next
+struct TemplateRecovery {
+    template: RecoveryTemplate,
+    found: Vec<RecoveredAccount>,
+    // `None` once an account without history has been found
+    candidate: Option<RecoveredAccount>,
+}
+
+// The state shared by `recover` and `recover_async` between rounds
+struct Recovery {
+    master_key: ExtendedPrivKey,
+    network: Network,
+    gap_limit: u32,
+    templates: Vec<TemplateRecovery>,
+}
+
+impl Recovery {
+    fn new(
+        master_key: ExtendedPrivKey,
+        network: Network,
+        options: &RecoveryOptions,
+    ) -> Result<Self, DescriptorError> {
+        let templates = options
+            .templates
+            .iter()
+            .map(|&template| {
+                Ok(TemplateRecovery {
+                    template,
+                    found: Vec::new(),
+                    candidate: Some(candidate(template, master_key, 0, network)?),
+                })
+            })
+            .collect::<Result<_, DescriptorError>>()?;
+        Ok(Recovery {
+            master_key,
+            network,
+            gap_limit: options.gap_limit,
+            templates,
+        })
+    }
+
+    // The keychains of the candidates of the next round, or `None` if recovery is over
+    fn next_scan(&self) -> Option<ScanRequest> {
+        let mut index = KeychainTxOutIndex::default();
+        for candidate in self.templates.iter().filter_map(|t| t.candidate.as_ref()) {
+            for (keychain, descriptor) in [
+                (KeychainKind::External, &candidate.descriptor),
+                (KeychainKind::Internal, &candidate.change_descriptor),
+            ] {
+                let keychain = CandidateKeychain {
+                    template: candidate.template,
+                    account: candidate.account,
+                    keychain,
+                };
+                index.add_keychain(keychain, descriptor.0.clone());
+            }
+        }
+        if index.keychains().is_empty() {
+            return None;
+        }
+        index.set_lookahead_for_all(self.gap_limit);
+        Some(ScanRequest {
+            index,
+            stop_gap: self.gap_limit,
+        })
+    }
+
+    // Record the result of a round, and move on to the next account of the templates with history
+    fn apply_scan(
+        &mut self,
+        last_active: &BTreeMap<CandidateKeychain, u32>,
+    ) -> Result<(), DescriptorError> {
+        for recovery in &mut self.templates {
+            let mut scanned = match recovery.candidate.take() {
+                Some(scanned) => scanned,
+                None => continue,
+            };
+            let last_index = |keychain| {
+                last_active
+                    .get(&CandidateKeychain {
+                        template: scanned.template,
+                        account: scanned.account,
+                        keychain,
+                    })
+                    .copied()
+            };
+            scanned.last_external_index = last_index(KeychainKind::External);
+            scanned.last_internal_index = last_index(KeychainKind::Internal);
+            if scanned.last_external_index.is_none() && scanned.last_internal_index.is_none() {
+                continue;
+            }
+
+            let next_account = scanned.account + 1;
+            recovery.found.push(scanned);
+            recovery.candidate = Some(candidate(
+                recovery.template,
+                self.master_key,
+                next_account,
+                self.network,
+            )?);
+        }
+        Ok(())
+    }
+
+    fn into_accounts(self) -> Vec<RecoveredAccount> {
+        self.templates
+            .into_iter()
+            .flat_map(|recovery| recovery.found)
+            .collect()
+    }
+}
+
+fn candidate(
+    template: RecoveryTemplate,
+    master_key: ExtendedPrivKey,
+    account: u32,
+    network: Network,
+) -> Result<RecoveredAccount, DescriptorError> {
+    Ok(RecoveredAccount {
+        template,
+        account,
+        descriptor: template.build(master_key, account, KeychainKind::External, network)?,
+        change_descriptor: template.build(master_key, account, KeychainKind::Internal, network)?,
+        last_external_index: None,
+        last_internal_index: None,
+    })
+}
+
+/// Find the accounts of `master_key` that have history, trying each of the templates of `options`
+///
+/// `scan` is called once per round with the keychains to scan, and returns the last index with
+/// history of each of them. See [the module-level docs](self) for how to scan them.
+pub fn recover<E>(
+    master_key: ExtendedPrivKey,
+    network: Network,
+    options: &RecoveryOptions,
+    mut scan: impl FnMut(ScanRequest) -> Result<BTreeMap<CandidateKeychain, u32>, E>,
+) -> Result<Vec<RecoveredAccount>, RecoveryError<E>> {
+    let mut recovery = Recovery::new(master_key, network, options)?;
+    while let Some(request) = recovery.next_scan() {
+        let last_active = scan(request).map_err(RecoveryError::Source)?;
+        recovery.apply_scan(&last_active)?;
+    }
+    Ok(recovery.into_accounts())
+}
+
+/// Async version of [`recover`], for chain sources with an async keychain scan.
+pub async fn recover_async<E, F, Fut>(
+    master_key: ExtendedPrivKey,
+    network: Network,
+    options: &RecoveryOptions,
+    mut scan: F,
+) -> Result<Vec<RecoveredAccount>, RecoveryError<E>>
+where
+    F: FnMut(ScanRequest) -> Fut,
+    Fut: Future<Output = Result<BTreeMap<CandidateKeychain, u32>, E>>,
+{
+    let mut recovery = Recovery::new(master_key, network, options)?;
+    while let Some(request) = recovery.next_scan() {
+        let last_active = scan(request).await.map_err(RecoveryError::Source)?;
+        recovery.apply_scan(&last_active)?;
+    }
+    Ok(recovery.into_accounts())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::collections::BTreeSet;
+    use bitcoin::ScriptBuf;
+    use core::str::FromStr;
+
+    const TPRV: &str = "tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m";
+
+    // Scans each keychain until `stop_gap` consecutive unused scripts, like the keychain scans of
+    // the chain source crates
+    fn scan_spks(
+        request: &ScanRequest,
+        used: &BTreeSet<ScriptBuf>,
+    ) -> BTreeMap<CandidateKeychain, u32> {
+        let mut last_active = BTreeMap::new();
+        for (keychain, spks) in request.spks() {
+            let mut unused = 0;
+            for (index, spk) in spks {
+                if used.contains(&spk) {
+                    last_active.insert(keychain, index);
+                    unused = 0;
+                } else {
+                    unused += 1;
+                    if unused == request.stop_gap() {
+                        break;
+                    }
+                }
+            }
+        }
+        last_active
+    }
+
+    fn script_at(
+        master_key: ExtendedPrivKey,
+        template: RecoveryTemplate,
+        account: u32,
+        keychain: KeychainKind,
+        index: u32,
+    ) -> ScriptBuf {
+        let (descriptor, _, _) = template
+            .build(master_key, account, keychain, Network::Testnet)
+            .unwrap();
+        descriptor
+            .at_derivation_index(index)
+            .unwrap()
+            .script_pubkey()
+    }
+
+    #[test]
+    fn test_template_matches_bip_templates() {
+        use crate::descriptor::template::Bip84;
+
+        let master_key = ExtendedPrivKey::from_str(TPRV).unwrap();
+        let (expected, _, _) = Bip84(master_key, KeychainKind::Internal)
+            .build(Network::Testnet)
+            .unwrap();
+        let (descriptor, _, _) = RecoveryTemplate::Bip84
+            .build(master_key, 0, KeychainKind::Internal, Network::Testnet)
+            .unwrap();
+        assert_eq!(descriptor, expected);
+    }
+
+    // The scripts used by the wallet, and the accounts expected to be found
+    fn used_scripts(master_key: ExtendedPrivKey) -> BTreeSet<ScriptBuf> {
+        let used = [
+            // account 0 is used up to index 25, within the gap limit of index 10
+            (RecoveryTemplate::Bip84, 0, KeychainKind::External, 10),
+            (RecoveryTemplate::Bip84, 0, KeychainKind::External, 25),
+            (RecoveryTemplate::Bip84, 0, KeychainKind::Internal, 3),
+            (RecoveryTemplate::Bip84, 1, KeychainKind::Internal, 0),
+            // not found, since account 0 is unused
+            (RecoveryTemplate::Bip86, 1, KeychainKind::External, 0),
+            // not found, beyond the gap limit
+            (RecoveryTemplate::Bip44, 0, KeychainKind::External, 20),
+        ];
+        used.iter()
+            .map(|&(template, account, keychain, index)| {
+                script_at(master_key, template, account, keychain, index)
+            })
+            .collect()
+    }
+
+    fn assert_found(recovered: &[RecoveredAccount]) {
+        let found = recovered
+            .iter()
+            .map(|account| {
+                (
+                    account.template,
+                    account.account,
+                    account.last_external_index,
+                    account.last_internal_index,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                (RecoveryTemplate::Bip84, 0, Some(25), Some(3)),
+                (RecoveryTemplate::Bip84, 1, None, Some(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recover() {
+        let master_key = ExtendedPrivKey::from_str(TPRV).unwrap();
+        let used = used_scripts(master_key);
+
+        let mut rounds = Vec::new();
+        let recovered = recover(
+            master_key,
+            Network::Testnet,
+            &RecoveryOptions::default(),
+            |request| {
+                rounds.push(request.spks().len());
+                Ok::<_, core::convert::Infallible>(scan_spks(&request, &used))
+            },
+        )
+        .unwrap();
+        assert_found(&recovered);
+        // the accounts 0 of the 4 templates, then the accounts 1 and 2 of BIP84
+        assert_eq!(rounds, vec![8, 2, 2]);
+    }
+
+    #[test]
+    fn test_recover_from_blocks() {
+        let master_key = ExtendedPrivKey::from_str(TPRV).unwrap();
+        let tx = bitcoin::Transaction {
+            version: 1,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: used_scripts(master_key)
+                .into_iter()
+                .map(|script_pubkey| bitcoin::TxOut {
+                    value: 10_000,
+                    script_pubkey,
+                })
+                .collect(),
+        };
+        let block = Block {
+            header: bitcoin::blockdata::constants::genesis_block(Network::Testnet).header,
+            txdata: vec![tx],
+        };
+
+        let recovered = recover(
+            master_key,
+            Network::Testnet,
+            &RecoveryOptions::default(),
+            |mut request| {
+                request.index_block(&block);
+                Ok::<_, core::convert::Infallible>(request.last_active_indices())
+            },
+        )
+        .unwrap();
+        assert_found(&recovered);
+    }
+
+    #[test]
+    fn test_recover_error() {
+        let master_key = ExtendedPrivKey::from_str(TPRV).unwrap();
+        let result = recover(
+            master_key,
+            Network::Testnet,
+            &RecoveryOptions::default(),
+            |_| Err("unreachable"),
+        );
+        assert!(matches!(result, Err(RecoveryError::Source("unreachable"))));
+    }
+}
@@ -0,0 +1,250 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Proof of reserves
+//!
+//! This module implements [BIP127] proofs of reserves: a transaction spending the UTXOs whose
+//! ownership is being proven, signed as usual, which is made invalid by an additional "challenge"
+//! input that spends an output that doesn't exist, derived from a message. The proof can't be
+//! broadcast, and can't be reused for a different message.
+//!
+//! A proof spending all the UTXOs of the wallet is created with [`Wallet::create_proof`] and
+//! signed with [`Wallet::sign`]. Anyone can then check it against a set of UTXOs with
+//! [`verify_proof`], for instance those of a [`TxGraph`] kept in sync with a chain source:
+//!
+//! ```
+//! # use bdk::bitcoin::secp256k1::Secp256k1;
+//! # use bdk::chain::TxGraph;
+//! # use bdk::wallet::reserves::verify_proof;
+//! # use bdk::*;
+//! # let mut wallet = doctest_wallet!();
+//! let message = "Reserves of Example Corp at block 800000";
+//! let mut proof = wallet.create_proof(message)?;
+//! wallet.sign(&mut proof, SignOptions::default())?;
+//!
+//! // the UTXOs must be unspent in the best chain
+//! let graph: &TxGraph<_> = wallet.as_ref();
+//! let chain = wallet.local_chain();
+//! let tip = chain.tip().map(|cp| cp.block_id()).unwrap_or_default();
+//! let amount = verify_proof(&Secp256k1::new(), &proof, message, |outpoint| {
+//!     graph
+//!         .filter_chain_unspents(chain, tip, [((), *outpoint)])
+//!         .next()
+//!         .map(|(_, txo)| txo.txout)
+//! })?;
+//! assert_eq!(amount, 500_000);
+//! # Ok::<_, Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! The signatures are checked with the miniscript interpreter, so only scripts that can be
+//! expressed as descriptors are supported.
+//!
+//! [BIP127]: https://github.com/bitcoin/bips/blob/master/bip-0127.mediawiki
+//! [`Wallet::create_proof`]: crate::wallet::Wallet::create_proof
+//! [`Wallet::sign`]: crate::wallet::Wallet::sign
+//! [`TxGraph`]: bdk_chain::tx_graph::TxGraph
+
+use crate::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use bitcoin::blockdata::opcodes;
+use bitcoin::hashes::{hash160, sha256d, Hash};
+use bitcoin::psbt::{self, PartiallySignedTransaction as Psbt};
+use bitcoin::secp256k1::{Secp256k1, Verification};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, TapSighashType};
+use bitcoin::{OutPoint, PubkeyHash, ScriptBuf, Sequence, TxIn, TxOut, Txid};
+use miniscript::interpreter::{Interpreter, KeySigPair, SatisfiedConstraint};
+
+/// Errors related to proofs of reserves
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofError {
+    /// The proof doesn't spend any UTXO besides the challenge
+    NoInputs,
+    /// The proof doesn't have exactly one output
+    WrongNumberOfOutputs,
+    /// The first input isn't the challenge derived from the message
+    ChallengeInputMismatch,
+    /// The output isn't the expected unspendable one
+    InvalidOutput,
+    /// The output doesn't have the value of the UTXOs spent
+    InAndOutValueNotEqual,
+    /// The input at this index isn't finalized
+    NotFinalized(usize),
+    /// The input at this index doesn't spend an output of the UTXO set
+    NonSpendableInput(usize),
+    /// The input at this index spends the same output as a previous one
+    DuplicateInput(usize),
+    /// The input at this index is signed with a sighash type other than `ALL`
+    UnsupportedSighashType(usize),
+    /// The signatures of the input at this index can't be verified
+    SignatureValidation(usize, String),
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoInputs => write!(f, "The proof doesn't spend any UTXO"),
+            Self::WrongNumberOfOutputs => write!(f, "The proof must have exactly one output"),
+            Self::ChallengeInputMismatch => {
+                write!(f, "The challenge input doesn't match the message")
+            }
+            Self::InvalidOutput => write!(f, "The output isn't unspendable"),
+            Self::InAndOutValueNotEqual => {
+                write!(f, "The value of the output doesn't match the inputs")
+            }
+            Self::NotFinalized(index) => write!(f, "Input {} isn't finalized", index),
+            Self::NonSpendableInput(index) => write!(f, "Input {} isn't spendable", index),
+            Self::DuplicateInput(index) => {
+                write!(f, "Input {} spends the same output as another input", index)
+            }
+            Self::UnsupportedSighashType(index) => {
+                write!(f, "Input {} isn't signed with SIGHASH_ALL", index)
+            }
+            Self::SignatureValidation(index, err) => {
+                write!(f, "Invalid signature for input {}: {}", index, err)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ProofError {}
+
+/// The challenge input of a proof for `message`
+///
+/// It spends the first output of a transaction whose txid is the double SHA256 of
+/// `"Proof-of-Reserves: "` followed by the message.
+pub fn challenge_txin(message: &str) -> TxIn {
+    let message = format!("Proof-of-Reserves: {}", message);
+    TxIn {
+        previous_output: OutPoint {
+            txid: Txid::from_raw_hash(sha256d::Hash::hash(message.as_bytes())),
+            vout: 0,
+        },
+        sequence: Sequence::MAX,
+        ..Default::default()
+    }
+}
+
+// The challenge doesn't need a signature, and spends nothing
+pub(crate) fn challenge_psbt_input() -> psbt::Input {
+    psbt::Input {
+        witness_utxo: Some(challenge_txout()),
+        final_script_sig: Some(ScriptBuf::new()),
+        ..Default::default()
+    }
+}
+
+fn challenge_txout() -> TxOut {
+    TxOut {
+        value: 0,
+        script_pubkey: ScriptBuf::from(vec![opcodes::OP_TRUE.to_u8()]),
+    }
+}
+
+// The output of a proof, a P2PKH script for a key that can't exist
+pub(crate) fn proof_output_script() -> ScriptBuf {
+    let hash = PubkeyHash::from_raw_hash(hash160::Hash::hash(&[0]));
+    ScriptBuf::new_p2pkh(&hash)
+}
+
+/// Verify a finalized `proof` for `message`, returning the amount of reserves it proves
+///
+/// `utxo_fn` looks up the outputs spent by the proof in the UTXO set: the proof is rejected if
+/// any of them has been spent since it was created.
+pub fn verify_proof<C, F>(
+    secp: &Secp256k1<C>,
+    proof: &Psbt,
+    message: &str,
+    mut utxo_fn: F,
+) -> Result<u64, ProofError>
+where
+    C: Verification,
+    F: FnMut(&OutPoint) -> Option<TxOut>,
+{
+    let tx = &proof.unsigned_tx;
+    if tx.input.len() < 2 {
+        return Err(ProofError::NoInputs);
+    }
+    if tx.output.len() != 1 {
+        return Err(ProofError::WrongNumberOfOutputs);
+    }
+    if tx.input[0].previous_output != challenge_txin(message).previous_output {
+        return Err(ProofError::ChallengeInputMismatch);
+    }
+    if tx.output[0].script_pubkey != proof_output_script() {
+        return Err(ProofError::InvalidOutput);
+    }
+    if let Some(index) =
+        proof.inputs.iter().skip(1).position(|input| {
+            input.final_script_sig.is_none() && input.final_script_witness.is_none()
+        })
+    {
+        return Err(ProofError::NotFinalized(index + 1));
+    }
+
+    // the proof is never checked by consensus, so an output spent twice would be counted twice
+    let mut outpoints = BTreeSet::new();
+    if let Some(index) = tx
+        .input
+        .iter()
+        .position(|txin| !outpoints.insert(txin.previous_output))
+    {
+        return Err(ProofError::DuplicateInput(index));
+    }
+
+    let mut prevouts = Vec::with_capacity(tx.input.len());
+    prevouts.push(challenge_txout());
+    for (index, txin) in tx.input.iter().enumerate().skip(1) {
+        let txout = utxo_fn(&txin.previous_output).ok_or(ProofError::NonSpendableInput(index))?;
+        prevouts.push(txout);
+    }
+    let amount = prevouts.iter().map(|txout| txout.value).sum();
+    if tx.output[0].value != amount {
+        return Err(ProofError::InAndOutValueNotEqual);
+    }
+
+    let tx = proof.clone().extract_tx();
+    let all_prevouts = Prevouts::All(&prevouts);
+    for (index, txin) in tx.input.iter().enumerate().skip(1) {
+        let invalid = |err: miniscript::interpreter::Error| {
+            ProofError::SignatureValidation(index, err.to_string())
+        };
+        let interpreter = Interpreter::from_txdata(
+            &prevouts[index].script_pubkey,
+            &txin.script_sig,
+            &txin.witness,
+            txin.sequence,
+            tx.lock_time,
+        )
+        .map_err(invalid)?;
+        for constraint in interpreter.iter(secp, &tx, index, &all_prevouts) {
+            let key_sig = match constraint.map_err(invalid)? {
+                SatisfiedConstraint::PublicKey { key_sig }
+                | SatisfiedConstraint::PublicKeyHash { key_sig, .. } => key_sig,
+                _ => continue,
+            };
+            // signatures committing to only part of the transaction could be reused
+            let sighash_all = match key_sig {
+                KeySigPair::Ecdsa(_, sig) => sig.hash_ty == EcdsaSighashType::All,
+                KeySigPair::Schnorr(_, sig) => {
+                    matches!(sig.hash_ty, TapSighashType::Default | TapSighashType::All)
+                }
+            };
+            if !sighash_all {
+                return Err(ProofError::UnsupportedSighashType(index));
+            }
+        }
+    }
+
+    Ok(amount)
+}
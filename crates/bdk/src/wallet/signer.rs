@@ -87,7 +87,7 @@ use core::fmt;
 use core::ops::{Bound::Included, Deref};
 
 use bitcoin::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey, Fingerprint};
-use bitcoin::hashes::hash160;
+use bitcoin::hashes::{hash160, ripemd160, sha256, sha256d, Hash};
 use bitcoin::secp256k1::Message;
 use bitcoin::sighash::{EcdsaSighashType, TapSighash, TapSighashType};
 use bitcoin::{ecdsa, psbt, sighash, taproot};
@@ -98,8 +98,12 @@ use miniscript::descriptor::{
     Descriptor, DescriptorMultiXKey, DescriptorPublicKey, DescriptorSecretKey, DescriptorXKey,
     InnerXKey, KeyMap, SinglePriv, SinglePubKey,
 };
-use miniscript::{Legacy, Segwitv0, SigType, Tap, ToPublicKey};
+use miniscript::{
+    hash256, DefiniteDescriptorKey, Legacy, Segwitv0, SigType, Tap, ToPublicKey, TranslatePk,
+    Translator,
+};
 
+use super::musig::MusigError;
 use super::utils::SecpCtx;
 use crate::descriptor::{DescriptorMeta, XKeyUtils};
 use crate::psbt::PsbtUtils;
@@ -159,6 +163,8 @@ pub enum SignerError {
     InvalidSighash,
     /// Error while computing the hash to sign
     SighashError(sighash::Error),
+    /// Error in a MuSig2 signing session
+    Musig(MusigError),
     /// Error while signing using hardware wallets
     #[cfg(feature = "hardware-signer")]
     HWIError(hwi::error::Error),
@@ -177,6 +183,12 @@ impl From<sighash::Error> for SignerError {
     }
 }
 
+impl From<MusigError> for SignerError {
+    fn from(e: MusigError) -> Self {
+        SignerError::Musig(e)
+    }
+}
+
 impl fmt::Display for SignerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -192,6 +204,7 @@ impl fmt::Display for SignerError {
             Self::NonStandardSighash => write!(f, "The psbt contains a non standard sighash"),
             Self::InvalidSighash => write!(f, "Invalid SIGHASH for the signing context in use"),
             Self::SighashError(err) => write!(f, "Error while computing the hash to sign: {}", err),
+            Self::Musig(err) => write!(f, "MuSig2 error: {}", err),
             #[cfg(feature = "hardware-signer")]
             Self::HWIError(err) => write!(f, "Error while signing using hardware wallets: {}", err),
         }
@@ -629,14 +642,164 @@ impl From<(SignerId, SignerOrdering)> for SignersContainerKey {
     }
 }
 
+/// Hash preimages known to the wallet, used to satisfy the hash locks of HTLC-style descriptors
+///
+/// Every preimage is indexed under its `sha256`, `hash256`, `ripemd160` and `hash160` images, so
+/// that it can be looked up by any of the hash fragments supported by miniscript.
+///
+/// The [`Debug`](fmt::Debug) implementation only prints the `sha256` images, never the preimages.
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct Preimages {
+    sha256: BTreeMap<sha256::Hash, [u8; 32]>,
+    hash256: BTreeMap<hash256::Hash, [u8; 32]>,
+    ripemd160: BTreeMap<ripemd160::Hash, [u8; 32]>,
+    hash160: BTreeMap<hash160::Hash, [u8; 32]>,
+}
+
+impl fmt::Debug for Preimages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Preimages")
+            .field("sha256", &self.sha256.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Preimages {
+    /// Default constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a 32-byte preimage, indexing it under all of its images
+    pub fn add(&mut self, preimage: [u8; 32]) {
+        self.sha256.insert(sha256::Hash::hash(&preimage), preimage);
+        self.hash256
+            .insert(hash256::Hash::hash(&preimage), preimage);
+        self.ripemd160
+            .insert(ripemd160::Hash::hash(&preimage), preimage);
+        self.hash160
+            .insert(hash160::Hash::hash(&preimage), preimage);
+    }
+
+    /// Adds all the preimages of `other`
+    pub fn extend(&mut self, other: &Preimages) {
+        self.sha256.extend(other.sha256.iter());
+        self.hash256.extend(other.hash256.iter());
+        self.ripemd160.extend(other.ripemd160.iter());
+        self.hash160.extend(other.hash160.iter());
+    }
+
+    /// Returns whether there are no preimages
+    pub fn is_empty(&self) -> bool {
+        self.sha256.is_empty()
+    }
+
+    /// Returns the list of preimages
+    pub fn preimages(&self) -> Vec<&[u8; 32]> {
+        self.sha256.values().collect()
+    }
+
+    /// Finds the preimage of a `sha256` image
+    pub fn sha256(&self, image: &sha256::Hash) -> Option<&[u8; 32]> {
+        self.sha256.get(image)
+    }
+
+    /// Finds the preimage of a `hash256` image
+    pub fn hash256(&self, image: &hash256::Hash) -> Option<&[u8; 32]> {
+        self.hash256.get(image)
+    }
+
+    /// Finds the preimage of a `ripemd160` image
+    pub fn ripemd160(&self, image: &ripemd160::Hash) -> Option<&[u8; 32]> {
+        self.ripemd160.get(image)
+    }
+
+    /// Finds the preimage of a `hash160` image
+    pub fn hash160(&self, image: &hash160::Hash) -> Option<&[u8; 32]> {
+        self.hash160.get(image)
+    }
+
+    /// Adds to `psbt_input` the preimages required by the hash fragments of `descriptor`
+    ///
+    /// Preimages are secrets, so only the ones that are actually needed to satisfy the descriptor
+    /// are disclosed in the PSBT.
+    pub(crate) fn update_psbt_input(
+        &self,
+        descriptor: &Descriptor<DefiniteDescriptorKey>,
+        psbt_input: &mut psbt::Input,
+    ) {
+        struct PreimagesTranslator<'a> {
+            preimages: &'a Preimages,
+            psbt_input: &'a mut psbt::Input,
+        }
+
+        impl<'a> Translator<DefiniteDescriptorKey, DefiniteDescriptorKey, ()> for PreimagesTranslator<'a> {
+            fn pk(&mut self, pk: &DefiniteDescriptorKey) -> Result<DefiniteDescriptorKey, ()> {
+                Ok(pk.clone())
+            }
+
+            fn sha256(&mut self, image: &sha256::Hash) -> Result<sha256::Hash, ()> {
+                if let Some(preimage) = self.preimages.sha256(image) {
+                    self.psbt_input
+                        .sha256_preimages
+                        .insert(*image, preimage.to_vec());
+                }
+                Ok(*image)
+            }
+
+            fn hash256(&mut self, image: &hash256::Hash) -> Result<hash256::Hash, ()> {
+                if let Some(preimage) = self.preimages.hash256(image) {
+                    self.psbt_input.hash256_preimages.insert(
+                        sha256d::Hash::from_byte_array(image.to_byte_array()),
+                        preimage.to_vec(),
+                    );
+                }
+                Ok(*image)
+            }
+
+            fn ripemd160(&mut self, image: &ripemd160::Hash) -> Result<ripemd160::Hash, ()> {
+                if let Some(preimage) = self.preimages.ripemd160(image) {
+                    self.psbt_input
+                        .ripemd160_preimages
+                        .insert(*image, preimage.to_vec());
+                }
+                Ok(*image)
+            }
+
+            fn hash160(&mut self, image: &hash160::Hash) -> Result<hash160::Hash, ()> {
+                if let Some(preimage) = self.preimages.hash160(image) {
+                    self.psbt_input
+                        .hash160_preimages
+                        .insert(*image, preimage.to_vec());
+                }
+                Ok(*image)
+            }
+        }
+
+        if self.is_empty() {
+            return;
+        }
+
+        let mut translator = PreimagesTranslator {
+            preimages: self,
+            psbt_input,
+        };
+        // The translation can't fail, we only use it to visit all the hash fragments
+        let _ = descriptor.translate_pk(&mut translator);
+    }
+}
+
 /// Container for multiple signers
 #[derive(Debug, Default, Clone)]
-pub struct SignersContainer(BTreeMap<SignersContainerKey, Arc<dyn TransactionSigner>>);
+pub struct SignersContainer {
+    signers: BTreeMap<SignersContainerKey, Arc<dyn TransactionSigner>>,
+    preimages: Preimages,
+}
 
 impl SignersContainer {
     /// Create a map of public keys to secret keys
     pub fn as_key_map(&self, secp: &SecpCtx) -> KeyMap {
-        self.0
+        self.signers
             .values()
             .filter_map(|signer| signer.descriptor_secret_key())
             .filter_map(|secret| secret.to_public(secp).ok().map(|public| (public, secret)))
@@ -694,7 +857,7 @@ impl SignersContainer {
 impl SignersContainer {
     /// Default constructor
     pub fn new() -> Self {
-        SignersContainer(Default::default())
+        SignersContainer::default()
     }
 
     /// Adds an external signer to the container for the specified id. Optionally returns the
@@ -705,7 +868,7 @@ impl SignersContainer {
         ordering: SignerOrdering,
         signer: Arc<dyn TransactionSigner>,
     ) -> Option<Arc<dyn TransactionSigner>> {
-        self.0.insert((id, ordering).into(), signer)
+        self.signers.insert((id, ordering).into(), signer)
     }
 
     /// Removes a signer from the container and returns it
@@ -714,12 +877,12 @@ impl SignersContainer {
         id: SignerId,
         ordering: SignerOrdering,
     ) -> Option<Arc<dyn TransactionSigner>> {
-        self.0.remove(&(id, ordering).into())
+        self.signers.remove(&(id, ordering).into())
     }
 
     /// Returns the list of identifiers of all the signers in the container
     pub fn ids(&self) -> Vec<&SignerId> {
-        self.0
+        self.signers
             .keys()
             .map(|SignersContainerKey { id, .. }| id)
             .collect()
@@ -727,12 +890,12 @@ impl SignersContainer {
 
     /// Returns the list of signers in the container, sorted by lowest to highest `ordering`
     pub fn signers(&self) -> Vec<&Arc<dyn TransactionSigner>> {
-        self.0.values().collect()
+        self.signers.values().collect()
     }
 
     /// Finds the signer with lowest ordering for a given id in the container.
     pub fn find(&self, id: SignerId) -> Option<&Arc<dyn TransactionSigner>> {
-        self.0
+        self.signers
             .range((
                 Included(&(id.clone(), SignerOrdering(0)).into()),
                 Included(&(id.clone(), SignerOrdering(usize::MAX)).into()),
@@ -741,6 +904,16 @@ impl SignersContainer {
             .map(|(_, v)| v)
             .next()
     }
+
+    /// Adds a hash preimage to the container, used to satisfy the hash locks of the descriptor
+    pub fn add_preimage(&mut self, preimage: [u8; 32]) {
+        self.preimages.add(preimage);
+    }
+
+    /// Returns the hash preimages in the container
+    pub fn preimages(&self) -> &Preimages {
+        &self.preimages
+    }
 }
 
 /// Options for a software signer
@@ -802,6 +975,15 @@ pub struct SignOptions {
     /// or not.
     /// Defaults to `true`, i.e., we always grind ECDSA signature to sign with low r.
     pub allow_grinding: bool,
+
+    /// Additional hash preimages used to satisfy the hash locks of the inputs being signed
+    ///
+    /// These are used together with the preimages registered with
+    /// [`Wallet::add_preimage`](super::Wallet::add_preimage), and only the ones required by an
+    /// input's descriptor are added to the PSBT.
+    ///
+    /// Defaults to no preimages.
+    pub preimages: Preimages,
 }
 
 /// Customize which taproot script-path leaves the signer should sign.
@@ -836,6 +1018,7 @@ impl Default for SignOptions {
             tap_leaves_options: TapLeavesOptions::default(),
             sign_with_tap_internal_key: true,
             allow_grinding: true,
+            preimages: Preimages::default(),
         }
     }
 }
@@ -0,0 +1,1165 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Silent payments
+//!
+//! This module implements [BIP352] silent payments: a receiver publishes a static `sp1...`
+//! address, and every payment to it lands on a different taproot output, derived from the keys
+//! of the inputs of the sending transaction.
+//!
+//! To send, add a [`SilentPaymentAddress`] to a transaction with
+//! [`TxBuilder::add_silent_payment_recipient`]: the output key is computed once the inputs have
+//! been selected, using the wallet's private keys.
+//!
+//! To receive, a [`SilentPaymentReceiver`] scans transactions (usually whole blocks) with the
+//! scan key, and keeps the outputs it finds together with the tweak needed to spend them. The
+//! outputs aren't derived from the wallet's descriptors, so they are spent as foreign UTXOs,
+//! signed by the [`SilentPaymentSigner`]:
+//!
+//! ```
+//! # use std::sync::Arc;
+//! # use bdk::bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+//! # use bdk::bitcoin::{Block, Network, TxOut};
+//! # use bdk::chain::TxGraph;
+//! # use bdk::signer::SignerOrdering;
+//! # use bdk::wallet::silent_payments::{SilentPaymentOutput, SilentPaymentReceiver};
+//! # use bdk::*;
+//! # let mut wallet = doctest_wallet!();
+//! # let graph = TxGraph::<()>::default();
+//! # let blocks = Vec::<Block>::new();
+//! let secp = Secp256k1::new();
+//! let scan_key = SecretKey::from_slice(&[0x01; 32])?;
+//! let spend_key = SecretKey::from_slice(&[0x02; 32])?;
+//!
+//! let mut receiver = SilentPaymentReceiver::new(
+//!     &secp,
+//!     Network::Testnet,
+//!     scan_key,
+//!     PublicKey::from_secret_key(&secp, &spend_key),
+//! );
+//! println!("Silent payment address: {}", receiver.address());
+//!
+//! // scan the blocks, looking up the outputs they spend in a `TxGraph`
+//! for block in &blocks {
+//!     receiver.scan_block(&secp, block, |outpoint| graph.get_txout(*outpoint).cloned());
+//! }
+//!
+//! // spend what we found
+//! wallet.add_signer(
+//!     KeychainKind::External,
+//!     SignerOrdering::default(),
+//!     Arc::new(receiver.signer(&secp, spend_key)?),
+//! );
+//! let mut builder = wallet.build_tx();
+//! for (outpoint, output) in receiver.outputs() {
+//!     builder.add_foreign_utxo(
+//!         *outpoint,
+//!         output.psbt_input(),
+//!         SilentPaymentOutput::SATISFACTION_WEIGHT,
+//!     )?;
+//! }
+//! // sign with `SignOptions::trust_witness_utxo`, see `SilentPaymentOutput::psbt_input`
+//! # Ok::<_, Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Labels let the receiver tell apart payments to different addresses that share the same scan
+//! key, see [`SilentPaymentReceiver::add_label`].
+//!
+//! [BIP352]: https://github.com/bitcoin/bips/blob/master/bip-0352.mediawiki
+//! [`TxBuilder::add_silent_payment_recipient`]: crate::wallet::tx_builder::TxBuilder::add_silent_payment_recipient
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::bech32::{self, FromBase32, ToBase32, Variant};
+use bitcoin::blockdata::script::Instruction;
+use bitcoin::consensus::encode::serialize;
+use bitcoin::hashes::{hash160, sha256, Hash, HashEngine};
+use bitcoin::key::{TweakedPublicKey, XOnlyPublicKey};
+use bitcoin::secp256k1::{KeyPair, Message, Parity, PublicKey, Scalar, SecretKey};
+use bitcoin::{psbt, taproot, Block, Network, OutPoint, Script, ScriptBuf, Transaction, TxOut};
+use miniscript::Tap;
+use serde::{Deserialize, Serialize};
+
+use super::signer::{
+    ComputeSighash, InputSigner, SignOptions, SignerCommon, SignerError, SignerId,
+};
+use super::utils::SecpCtx;
+use crate::collections::BTreeMap;
+use crate::psbt::PsbtUtils;
+
+// The x coordinate of the BIP341 "nothing up my sleeve" point, used as internal key by outputs
+// that can only be spent through a script
+const NUMS_H: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+/// Errors related to silent payments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SilentPaymentError {
+    /// The address isn't valid bech32m
+    Bech32(bech32::Error),
+    /// The address doesn't start with `sp` or `tsp`
+    InvalidHrp(String),
+    /// The address version isn't supported
+    InvalidVersion(u8),
+    /// The address payload doesn't contain two valid public keys
+    InvalidPayload,
+    /// The address is for a different network
+    InvalidNetwork(Network),
+    /// None of the inputs of the transaction can be used to derive the outputs, or their keys
+    /// sum to zero
+    NoEligibleInputs,
+    /// The private key of an eligible input is not known, so the outputs can't be derived
+    MissingInputKey(OutPoint),
+    /// The spend key doesn't match the spend public key of the receiver
+    InvalidSpendKey,
+    /// A tweak is out of range (this happens with negligible probability)
+    InvalidTweak,
+}
+
+impl fmt::Display for SilentPaymentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bech32(err) => write!(f, "Invalid bech32m encoding: {}", err),
+            Self::InvalidHrp(hrp) => write!(f, "Invalid human readable part: {}", hrp),
+            Self::InvalidVersion(version) => write!(f, "Unsupported version: {}", version),
+            Self::InvalidPayload => write!(f, "Invalid payload"),
+            Self::InvalidNetwork(network) => write!(f, "The address is not valid on {}", network),
+            Self::NoEligibleInputs => write!(f, "No eligible inputs"),
+            Self::MissingInputKey(outpoint) => {
+                write!(f, "Missing the private key of input {}", outpoint)
+            }
+            Self::InvalidSpendKey => write!(f, "Invalid spend key"),
+            Self::InvalidTweak => write!(f, "Invalid tweak"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SilentPaymentError {}
+
+impl From<bech32::Error> for SilentPaymentError {
+    fn from(err: bech32::Error) -> Self {
+        SilentPaymentError::Bech32(err)
+    }
+}
+
+/// A silent payment address
+///
+/// The address encodes the scan public key, used by the receiver to find its payments, and the
+/// spend public key, from which the output keys are derived.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SilentPaymentAddress {
+    /// [`Network::Bitcoin`] for `sp` addresses, [`Network::Testnet`] for `tsp` addresses
+    pub network: Network,
+    /// The scan public key
+    pub scan_pubkey: PublicKey,
+    /// The spend public key, possibly tweaked with a label
+    pub spend_pubkey: PublicKey,
+}
+
+impl SilentPaymentAddress {
+    /// Create an address from its keys
+    ///
+    /// All the networks other than [`Network::Bitcoin`] share the `tsp` prefix.
+    pub fn new(network: Network, scan_pubkey: PublicKey, spend_pubkey: PublicKey) -> Self {
+        let network = match network {
+            Network::Bitcoin => Network::Bitcoin,
+            _ => Network::Testnet,
+        };
+        SilentPaymentAddress {
+            network,
+            scan_pubkey,
+            spend_pubkey,
+        }
+    }
+
+    /// Whether the address can be used on `network`
+    pub fn is_valid_for_network(&self, network: Network) -> bool {
+        (self.network == Network::Bitcoin) == (network == Network::Bitcoin)
+    }
+}
+
+impl fmt::Display for SilentPaymentAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hrp = match self.network {
+            Network::Bitcoin => "sp",
+            _ => "tsp",
+        };
+        let mut payload = self.scan_pubkey.serialize().to_vec();
+        payload.extend(self.spend_pubkey.serialize());
+
+        let mut data = vec![bech32::u5::try_from_u8(0).expect("0 is a valid u5")];
+        data.extend(payload.to_base32());
+        let encoded = bech32::encode(hrp, data, Variant::Bech32m).map_err(|_| fmt::Error)?;
+
+        write!(f, "{}", encoded)
+    }
+}
+
+impl FromStr for SilentPaymentAddress {
+    type Err = SilentPaymentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hrp, data, variant) = bech32::decode(s)?;
+        let network = match hrp.as_str() {
+            "sp" => Network::Bitcoin,
+            "tsp" => Network::Testnet,
+            _ => return Err(SilentPaymentError::InvalidHrp(hrp)),
+        };
+        if variant != Variant::Bech32m {
+            return Err(SilentPaymentError::Bech32(bech32::Error::InvalidChecksum));
+        }
+
+        let version = data
+            .first()
+            .ok_or(SilentPaymentError::InvalidPayload)?
+            .to_u8();
+        let payload = Vec::<u8>::from_base32(&data[1..])?;
+        // Later versions are backwards compatible: the first 66 bytes are the keys
+        let payload = match version {
+            0 if payload.len() == 66 => &payload[..],
+            1..=30 if payload.len() >= 66 => &payload[..66],
+            0..=30 => return Err(SilentPaymentError::InvalidPayload),
+            _ => return Err(SilentPaymentError::InvalidVersion(version)),
+        };
+        let parse = |data: &[u8]| {
+            PublicKey::from_slice(data).map_err(|_| SilentPaymentError::InvalidPayload)
+        };
+
+        Ok(SilentPaymentAddress {
+            network,
+            scan_pubkey: parse(&payload[..33])?,
+            spend_pubkey: parse(&payload[33..])?,
+        })
+    }
+}
+
+fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag[..]);
+    engine.input(&tag[..]);
+    for chunk in data {
+        engine.input(chunk);
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+fn hash_to_key(hash: [u8; 32]) -> Result<SecretKey, SilentPaymentError> {
+    SecretKey::from_slice(&hash).map_err(|_| SilentPaymentError::InvalidTweak)
+}
+
+// The scalar committing to the inputs of the transaction
+fn input_hash<'a>(
+    outpoints: impl IntoIterator<Item = &'a OutPoint>,
+    sum: &PublicKey,
+) -> Result<SecretKey, SilentPaymentError> {
+    let smallest = outpoints
+        .into_iter()
+        .map(serialize)
+        .min()
+        .ok_or(SilentPaymentError::NoEligibleInputs)?;
+    hash_to_key(tagged_hash(
+        "BIP0352/Inputs",
+        &[&smallest, &sum.serialize()],
+    ))
+}
+
+// The tweak of the `k`-th output paying to the same scan key
+fn shared_secret_tweak(ecdh: &PublicKey, k: u32) -> Result<SecretKey, SilentPaymentError> {
+    hash_to_key(tagged_hash(
+        "BIP0352/SharedSecret",
+        &[&ecdh.serialize(), &k.to_be_bytes()],
+    ))
+}
+
+fn label_tweak(scan_key: &SecretKey, m: u32) -> Result<SecretKey, SilentPaymentError> {
+    hash_to_key(tagged_hash(
+        "BIP0352/Label",
+        &[&scan_key.secret_bytes(), &m.to_be_bytes()],
+    ))
+}
+
+/// Derive the output keys paying to silent payment addresses
+///
+/// `outpoints` are all the outpoints spent by the transaction, and `input_keys` the private keys
+/// of the eligible inputs: the key of P2PKH, P2WPKH and P2SH-P2WPKH inputs, or the tweaked key of
+/// P2TR inputs, flagged with `true`. The output keys are returned in the same order as the
+/// `recipients`.
+pub fn create_outputs(
+    secp: &SecpCtx,
+    outpoints: &[OutPoint],
+    input_keys: &[(SecretKey, bool)],
+    recipients: &[SilentPaymentAddress],
+) -> Result<Vec<XOnlyPublicKey>, SilentPaymentError> {
+    let sum = input_keys
+        .iter()
+        .map(|(key, is_taproot)| {
+            match *is_taproot && key.x_only_public_key(secp).1 == Parity::Odd {
+                true => key.negate(),
+                false => *key,
+            }
+        })
+        .try_fold(None, |sum: Option<SecretKey>, key| match sum {
+            Some(sum) => sum.add_tweak(&Scalar::from(key)).map(Some),
+            None => Ok(Some(key)),
+        })
+        .ok()
+        .flatten()
+        .ok_or(SilentPaymentError::NoEligibleInputs)?;
+    let input_hash = input_hash(outpoints, &sum.public_key(secp))?;
+    let sum = sum
+        .mul_tweak(&Scalar::from(input_hash))
+        .expect("the product of non-zero scalars is not zero");
+
+    let mut counts = BTreeMap::new();
+    recipients
+        .iter()
+        .map(|recipient| {
+            let k = counts.entry(recipient.scan_pubkey).or_insert(0);
+            let ecdh = recipient
+                .scan_pubkey
+                .mul_tweak(secp, &Scalar::from(sum))
+                .expect("not zero");
+            let tweak = shared_secret_tweak(&ecdh, *k)?;
+            *k += 1;
+            let output = recipient
+                .spend_pubkey
+                .add_exp_tweak(secp, &Scalar::from(tweak))
+                .map_err(|_| SilentPaymentError::InvalidTweak)?;
+            Ok(output.x_only_public_key().0)
+        })
+        .collect()
+}
+
+/// The script of a taproot output whose output key is `output_key`
+pub(crate) fn output_script(output_key: XOnlyPublicKey) -> ScriptBuf {
+    ScriptBuf::new_v1_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(output_key))
+}
+
+// The public key of an eligible input, given the output it spends
+fn input_pubkey(txin: &bitcoin::TxIn, prevout: &Script) -> Option<PublicKey> {
+    let compressed = |data: &[u8]| {
+        if data.len() == 33 {
+            PublicKey::from_slice(data).ok()
+        } else {
+            None
+        }
+    };
+
+    if prevout.is_p2pkh() {
+        let pubkey_hash = &prevout.as_bytes()[3..23];
+        txin.script_sig
+            .instructions()
+            .filter_map(|ins| match ins {
+                Ok(Instruction::PushBytes(data)) => Some(data.as_bytes()),
+                _ => None,
+            })
+            .filter(|data| hash160::Hash::hash(data).as_byte_array() == pubkey_hash)
+            .last()
+            .and_then(compressed)
+    } else if prevout.is_p2sh() {
+        let redeem_script = match txin
+            .script_sig
+            .instructions()
+            .collect::<Vec<_>>()
+            .as_slice()
+        {
+            [Ok(Instruction::PushBytes(data))] => ScriptBuf::from(data.as_bytes().to_vec()),
+            _ => return None,
+        };
+        if redeem_script.is_v0_p2wpkh() {
+            txin.witness.last().and_then(compressed)
+        } else {
+            None
+        }
+    } else if prevout.is_v0_p2wpkh() {
+        txin.witness.last().and_then(compressed)
+    } else if prevout.is_v1_p2tr() {
+        let mut stack = txin.witness.iter().collect::<Vec<_>>();
+        if stack.len() > 1 && stack.last().map(|e| e.first()) == Some(Some(&0x50)) {
+            // annex
+            stack.pop();
+        }
+        // script spends through the NUMS internal key don't have a key to contribute
+        if stack.len() > 1 && stack.last().and_then(|cb| cb.get(1..33)) == Some(&NUMS_H[..]) {
+            return None;
+        }
+        let output_key = XOnlyPublicKey::from_slice(&prevout.as_bytes()[2..]).ok()?;
+        Some(PublicKey::from_x_only_public_key(output_key, Parity::Even))
+    } else {
+        None
+    }
+}
+
+/// An output paying to a [`SilentPaymentReceiver`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SilentPaymentOutput {
+    /// The output
+    pub txout: TxOut,
+    /// The tweak to add to the spend key to get the private key of the output
+    pub tweak: SecretKey,
+    /// The label of the address the payment was sent to
+    pub label: Option<u32>,
+}
+
+impl SilentPaymentOutput {
+    /// The weight of the signature spending the output, to be passed to
+    /// [`TxBuilder::add_foreign_utxo`](crate::wallet::tx_builder::TxBuilder::add_foreign_utxo)
+    pub const SATISFACTION_WEIGHT: usize = 4 + 1 + 1 + 65;
+
+    /// The PSBT input spending the output
+    ///
+    /// Only the `witness_utxo` is set, so the PSBT must be signed with
+    /// [`SignOptions::trust_witness_utxo`]: this is safe because taproot signatures commit to
+    /// the amounts of all the inputs.
+    pub fn psbt_input(&self) -> psbt::Input {
+        psbt::Input {
+            witness_utxo: Some(self.txout.clone()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Finds the silent payments sent to an address, and keeps track of them
+///
+/// The outputs found aren't part of the wallet's [`ChangeSet`](crate::wallet::ChangeSet), so the
+/// receiver has to be persisted on its own, e.g. serialized after each scan. Note that this
+/// includes the scan key and the tweaks, which are enough to spend the outputs with the spend key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SilentPaymentReceiver {
+    network: Network,
+    scan_key: SecretKey,
+    scan_pubkey: PublicKey,
+    spend_pubkey: PublicKey,
+    // `tweak * G` of each label -> (label, tweak)
+    labels: BTreeMap<PublicKey, (u32, SecretKey)>,
+    outputs: BTreeMap<OutPoint, SilentPaymentOutput>,
+}
+
+impl SilentPaymentReceiver {
+    /// Create a receiver for the address with the given keys
+    pub fn new(
+        secp: &SecpCtx,
+        network: Network,
+        scan_key: SecretKey,
+        spend_pubkey: PublicKey,
+    ) -> Self {
+        SilentPaymentReceiver {
+            network,
+            scan_key,
+            scan_pubkey: scan_key.public_key(secp),
+            spend_pubkey,
+            labels: BTreeMap::new(),
+            outputs: BTreeMap::new(),
+        }
+    }
+
+    /// The address without label
+    pub fn address(&self) -> SilentPaymentAddress {
+        SilentPaymentAddress::new(self.network, self.scan_pubkey, self.spend_pubkey)
+    }
+
+    /// Start scanning for payments to the address with label `m`, returning the address
+    ///
+    /// The label `0` is reserved for change.
+    pub fn add_label(
+        &mut self,
+        secp: &SecpCtx,
+        m: u32,
+    ) -> Result<SilentPaymentAddress, SilentPaymentError> {
+        let tweak = label_tweak(&self.scan_key, m)?;
+        let label = tweak.public_key(secp);
+        let spend_pubkey = self
+            .spend_pubkey
+            .combine(&label)
+            .map_err(|_| SilentPaymentError::InvalidTweak)?;
+        self.labels.insert(label, (m, tweak));
+
+        Ok(SilentPaymentAddress::new(
+            self.network,
+            self.scan_pubkey,
+            spend_pubkey,
+        ))
+    }
+
+    /// The outputs found so far
+    pub fn outputs(&self) -> &BTreeMap<OutPoint, SilentPaymentOutput> {
+        &self.outputs
+    }
+
+    /// Stop tracking an output, for instance once it has been spent
+    pub fn remove_output(&mut self, outpoint: &OutPoint) -> Option<SilentPaymentOutput> {
+        self.outputs.remove(outpoint)
+    }
+
+    /// Scan a transaction, given the outputs spent by its inputs, returning the outpoints of the
+    /// new payments found
+    pub fn scan_tx(
+        &mut self,
+        secp: &SecpCtx,
+        tx: &Transaction,
+        prevouts: &[TxOut],
+    ) -> Vec<OutPoint> {
+        let found = self.find_outputs(secp, tx, prevouts).unwrap_or_default();
+        for (vout, tweak, label) in &found {
+            self.outputs.insert(
+                OutPoint::new(tx.txid(), *vout),
+                SilentPaymentOutput {
+                    txout: tx.output[*vout as usize].clone(),
+                    tweak: *tweak,
+                    label: *label,
+                },
+            );
+        }
+
+        found
+            .into_iter()
+            .map(|(vout, _, _)| OutPoint::new(tx.txid(), vout))
+            .collect()
+    }
+
+    /// Scan all the transactions of a block, returning the outpoints of the new payments found
+    ///
+    /// `prevout` looks up the outputs spent by the transactions, for instance from the
+    /// [`TxGraph`](bdk_chain::tx_graph::TxGraph) the block has been applied to. Transactions
+    /// spending unknown outputs are skipped.
+    pub fn scan_block<F>(&mut self, secp: &SecpCtx, block: &Block, mut prevout: F) -> Vec<OutPoint>
+    where
+        F: FnMut(&OutPoint) -> Option<TxOut>,
+    {
+        let mut found = Vec::new();
+        for tx in block.txdata.iter().filter(|tx| !tx.is_coin_base()) {
+            let prevouts = tx
+                .input
+                .iter()
+                .map(|txin| prevout(&txin.previous_output))
+                .collect::<Option<Vec<_>>>();
+            if let Some(prevouts) = prevouts {
+                found.extend(self.scan_tx(secp, tx, &prevouts));
+            }
+        }
+        found
+    }
+
+    // Returns the index, tweak and label of the outputs paying to us
+    fn find_outputs(
+        &self,
+        secp: &SecpCtx,
+        tx: &Transaction,
+        prevouts: &[TxOut],
+    ) -> Result<Vec<(u32, SecretKey, Option<u32>)>, SilentPaymentError> {
+        if prevouts.len() != tx.input.len() {
+            return Ok(Vec::new());
+        }
+        // Transactions spending outputs of unknown segwit versions are skipped
+        if prevouts.iter().any(|prevout| {
+            prevout
+                .script_pubkey
+                .witness_version()
+                .map_or(false, |version| version.to_num() > 1)
+        }) {
+            return Ok(Vec::new());
+        }
+
+        let pubkeys = tx
+            .input
+            .iter()
+            .zip(prevouts)
+            .filter_map(|(txin, prevout)| input_pubkey(txin, &prevout.script_pubkey))
+            .collect::<Vec<_>>();
+        if pubkeys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let sum = match PublicKey::combine_keys(&pubkeys.iter().collect::<Vec<_>>()) {
+            Ok(sum) => sum,
+            Err(_) => return Ok(Vec::new()),
+        };
+        let input_hash = input_hash(tx.input.iter().map(|txin| &txin.previous_output), &sum)?;
+        let ecdh = sum
+            .mul_tweak(secp, &Scalar::from(input_hash))
+            .and_then(|point| point.mul_tweak(secp, &Scalar::from(self.scan_key)))
+            .map_err(|_| SilentPaymentError::InvalidTweak)?;
+
+        let mut candidates = tx
+            .output
+            .iter()
+            .enumerate()
+            .filter(|(_, txout)| txout.script_pubkey.is_v1_p2tr())
+            .filter_map(|(vout, txout)| {
+                let key = XOnlyPublicKey::from_slice(&txout.script_pubkey.as_bytes()[2..]).ok()?;
+                Some((vout as u32, key))
+            })
+            .collect::<Vec<_>>();
+
+        let mut found = Vec::new();
+        for k in 0.. {
+            let tweak = shared_secret_tweak(&ecdh, k)?;
+            let output = self
+                .spend_pubkey
+                .add_exp_tweak(secp, &Scalar::from(tweak))
+                .map_err(|_| SilentPaymentError::InvalidTweak)?;
+
+            let position = candidates.iter().find_map(|(vout, key)| {
+                if *key == output.x_only_public_key().0 {
+                    return Some((*vout, tweak, None));
+                }
+                // The output could also pay to one of the labels: `output + label`
+                let negated_output = output.negate(secp);
+                [Parity::Even, Parity::Odd].iter().find_map(|parity| {
+                    let label = PublicKey::from_x_only_public_key(*key, *parity)
+                        .combine(&negated_output)
+                        .ok()?;
+                    let (m, label_tweak) = self.labels.get(&label)?;
+                    let tweak = tweak.add_tweak(&Scalar::from(*label_tweak)).ok()?;
+                    Some((*vout, tweak, Some(*m)))
+                })
+            });
+            match position {
+                Some(output) => {
+                    candidates.retain(|(vout, _)| *vout != output.0);
+                    found.push(output);
+                }
+                None => break,
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Create a signer for the outputs found so far, given the spend key
+    pub fn signer(
+        &self,
+        secp: &SecpCtx,
+        spend_key: SecretKey,
+    ) -> Result<SilentPaymentSigner, SilentPaymentError> {
+        if spend_key.public_key(secp) != self.spend_pubkey {
+            return Err(SilentPaymentError::InvalidSpendKey);
+        }
+
+        Ok(SilentPaymentSigner {
+            spend_key,
+            tweaks: self
+                .outputs
+                .values()
+                .map(|output| (output.txout.script_pubkey.clone(), output.tweak))
+                .collect(),
+        })
+    }
+}
+
+/// Signs the inputs spending the outputs found by a [`SilentPaymentReceiver`]
+#[derive(Debug, Clone)]
+pub struct SilentPaymentSigner {
+    spend_key: SecretKey,
+    tweaks: BTreeMap<ScriptBuf, SecretKey>,
+}
+
+impl SignerCommon for SilentPaymentSigner {
+    fn id(&self, secp: &SecpCtx) -> SignerId {
+        SignerId::from(hash160::Hash::hash(
+            &self.spend_key.public_key(secp).serialize(),
+        ))
+    }
+}
+
+impl InputSigner for SilentPaymentSigner {
+    fn sign_input(
+        &self,
+        psbt: &mut psbt::PartiallySignedTransaction,
+        input_index: usize,
+        _sign_options: &SignOptions,
+        secp: &SecpCtx,
+    ) -> Result<(), SignerError> {
+        if input_index >= psbt.inputs.len() || input_index >= psbt.unsigned_tx.input.len() {
+            return Err(SignerError::InputIndexOutOfRange);
+        }
+
+        let input = &psbt.inputs[input_index];
+        if input.final_script_sig.is_some()
+            || input.final_script_witness.is_some()
+            || input.tap_key_sig.is_some()
+        {
+            return Ok(());
+        }
+        let tweak = match psbt
+            .get_utxo_for(input_index)
+            .and_then(|txout| self.tweaks.get(&txout.script_pubkey))
+        {
+            Some(tweak) => tweak,
+            None => return Ok(()),
+        };
+
+        // The output key is not tweaked with BIP341, so the key is used as is
+        let secret_key = self
+            .spend_key
+            .add_tweak(&Scalar::from(*tweak))
+            .map_err(|_| SignerError::InvalidKey)?;
+        let keypair = KeyPair::from_secret_key(secp, &secret_key);
+
+        let (hash, hash_ty) = Tap::sighash(psbt, input_index, None)?;
+        let msg = Message::from(hash);
+        let sig = secp.sign_schnorr(&msg, &keypair);
+        secp.verify_schnorr(&sig, &msg, &keypair.x_only_public_key().0)
+            .expect("invalid or corrupted schnorr signature");
+        psbt.inputs[input_index].tap_key_sig = Some(taproot::Signature { sig, hash_ty });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::ToString;
+
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::{absolute, Witness};
+
+    use super::*;
+
+    fn secret_key(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    fn receiver(secp: &SecpCtx) -> SilentPaymentReceiver {
+        SilentPaymentReceiver::new(
+            secp,
+            Network::Testnet,
+            secret_key(0x01),
+            secret_key(0x02).public_key(secp),
+        )
+    }
+
+    // A transaction spending a P2WPKH output with the key `0x11..` and a P2TR output with the
+    // key `0x12..`, paying to the `outputs`
+    fn transaction(
+        secp: &SecpCtx,
+        outputs: Vec<XOnlyPublicKey>,
+    ) -> (Transaction, Vec<TxOut>, Vec<(SecretKey, bool)>) {
+        let wpkh_key = bitcoin::PublicKey::new(secret_key(0x11).public_key(secp));
+        let (tr_key, _) = secret_key(0x12).x_only_public_key(secp);
+        let prevouts = vec![
+            TxOut {
+                value: 10_000,
+                script_pubkey: ScriptBuf::new_v0_p2wpkh(&wpkh_key.wpubkey_hash().unwrap()),
+            },
+            TxOut {
+                value: 10_000,
+                script_pubkey: output_script(tr_key),
+            },
+        ];
+        let tx = Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![
+                bitcoin::TxIn {
+                    previous_output: OutPoint::new(bitcoin::Txid::all_zeros(), 1),
+                    witness: Witness::from_slice(&[vec![0; 72], wpkh_key.to_bytes()]),
+                    ..Default::default()
+                },
+                bitcoin::TxIn {
+                    previous_output: OutPoint::new(bitcoin::Txid::all_zeros(), 0),
+                    witness: Witness::from_slice(&[vec![0; 64]]),
+                    ..Default::default()
+                },
+            ],
+            output: outputs
+                .into_iter()
+                .map(|key| TxOut {
+                    value: 5_000,
+                    script_pubkey: output_script(key),
+                })
+                .collect(),
+        };
+
+        (
+            tx,
+            prevouts,
+            vec![(secret_key(0x11), false), (secret_key(0x12), true)],
+        )
+    }
+
+    #[test]
+    fn test_address_encoding() {
+        let secp = Secp256k1::new();
+        let address = receiver(&secp).address();
+        let encoded = address.to_string();
+        assert!(encoded.starts_with("tsp1q"));
+        assert_eq!(SilentPaymentAddress::from_str(&encoded), Ok(address));
+        assert!(address.is_valid_for_network(Network::Regtest));
+        assert!(!address.is_valid_for_network(Network::Bitcoin));
+
+        let mainnet =
+            SilentPaymentAddress::new(Network::Bitcoin, address.scan_pubkey, address.spend_pubkey);
+        assert!(mainnet.to_string().starts_with("sp1q"));
+
+        let encode = |hrp: &str, version: u8, variant| {
+            let mut payload = address.scan_pubkey.serialize().to_vec();
+            payload.extend(address.spend_pubkey.serialize());
+            let mut data = vec![bech32::u5::try_from_u8(version).unwrap()];
+            data.extend(payload.to_base32());
+            bech32::encode(hrp, data, variant).unwrap()
+        };
+        assert_eq!(
+            SilentPaymentAddress::from_str(&encode("bc", 0, Variant::Bech32m)),
+            Err(SilentPaymentError::InvalidHrp("bc".into()))
+        );
+        assert_eq!(
+            SilentPaymentAddress::from_str(&encode("tsp", 31, Variant::Bech32m)),
+            Err(SilentPaymentError::InvalidVersion(31))
+        );
+        assert_eq!(
+            SilentPaymentAddress::from_str(&encode("tsp", 1, Variant::Bech32m)),
+            Ok(address)
+        );
+        assert!(SilentPaymentAddress::from_str(&encode("tsp", 0, Variant::Bech32)).is_err());
+    }
+
+    #[test]
+    fn test_send_and_receive() {
+        let secp = Secp256k1::new();
+        let mut receiver = receiver(&secp);
+        let address = receiver.address();
+        let labeled = receiver.add_label(&secp, 1).unwrap();
+        assert_ne!(address, labeled);
+
+        let (tx, prevouts, _) = transaction(&secp, vec![]);
+        let outpoints = tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect::<Vec<_>>();
+        let (_, _, input_keys) = transaction(&secp, vec![]);
+        let outputs =
+            create_outputs(&secp, &outpoints, &input_keys, &[address, labeled, address]).unwrap();
+        assert_eq!(outputs.len(), 3);
+        assert_ne!(outputs[0], outputs[2]);
+
+        let (tx, _, _) = transaction(&secp, outputs);
+        let found = receiver.scan_tx(&secp, &tx, &prevouts);
+        assert_eq!(found.len(), 3);
+        let labels = receiver
+            .outputs()
+            .values()
+            .map(|output| output.label)
+            .collect::<Vec<_>>();
+        assert_eq!(labels.iter().filter(|l| **l == Some(1)).count(), 1);
+
+        // the tweaks give the private keys of the outputs
+        for output in receiver.outputs().values() {
+            let key = secret_key(0x02)
+                .add_tweak(&Scalar::from(output.tweak))
+                .unwrap();
+            assert_eq!(
+                output_script(key.x_only_public_key(&secp).0),
+                output.txout.script_pubkey
+            );
+        }
+
+        // another receiver doesn't find anything
+        let mut other = SilentPaymentReceiver::new(
+            &secp,
+            Network::Testnet,
+            secret_key(0x03),
+            secret_key(0x02).public_key(&secp),
+        );
+        assert!(other.scan_tx(&secp, &tx, &prevouts).is_empty());
+    }
+
+    // The keys and outpoints of the BIP352 test vectors
+    const SCAN_KEY: &str = "0f694e068028a717f8af6b9411f9a133dd3565258714cc226594b34db90c1f2c";
+    const SPEND_KEY: &str = "9d6ad855ce3417ef84e836892e5a56392bfba05fa5d97ccea30e266f540e08b3";
+    const TXID_A: &str = "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16";
+    const TXID_B: &str = "a1075db55d416d3ca199f55b6084e2115b9345e16c5cf302fc80e9d5fbf5d48d";
+    const KEY_1: &str = "eadc78165ff1f8ea94ad7cfdc54990738a4c53f6e0507b42154201b8e5dff3b1";
+    const KEY_2: &str = "93f5ed907ad5b2bdbbdcb5d9116ebc0a4e1f92f910d5260237fa45a9408aad16";
+    // taproot key with an even y-value
+    const KEY_3: &str = "fc8716a97a48ba9a05a98ae47b5cd201a25a7fd5d8b73c203c5f7b6b6b3b6ad7";
+    // taproot key with an odd y-value
+    const KEY_4: &str = "1d37787c2b7116ee983e9f9c13269df29091b391c04db94239e0d2bc2182c3bf";
+    const KEY_5: &str = "8d4751f6e8a3586880fb66c19ae277969bd5aa06f61c4ee2f1e2486efdf666d3";
+
+    fn vector_receiver(secp: &SecpCtx) -> SilentPaymentReceiver {
+        SilentPaymentReceiver::new(
+            secp,
+            Network::Bitcoin,
+            SecretKey::from_str(SCAN_KEY).unwrap(),
+            SecretKey::from_str(SPEND_KEY).unwrap().public_key(secp),
+        )
+    }
+
+    // A transaction spending P2WPKH or P2TR outputs with the `inputs` keys, paying to `outputs`
+    fn vector_transaction(
+        secp: &SecpCtx,
+        inputs: &[(OutPoint, SecretKey, bool)],
+        outputs: &[XOnlyPublicKey],
+    ) -> (Transaction, Vec<TxOut>) {
+        let (input, prevouts) = inputs
+            .iter()
+            .map(|(outpoint, key, is_taproot)| {
+                let (witness, script_pubkey) = if *is_taproot {
+                    (
+                        Witness::from_slice(&[vec![0; 64]]),
+                        output_script(key.x_only_public_key(secp).0),
+                    )
+                } else {
+                    let pubkey = bitcoin::PublicKey::new(key.public_key(secp));
+                    (
+                        Witness::from_slice(&[vec![0; 72], pubkey.to_bytes()]),
+                        ScriptBuf::new_v0_p2wpkh(&pubkey.wpubkey_hash().unwrap()),
+                    )
+                };
+                let txin = bitcoin::TxIn {
+                    previous_output: *outpoint,
+                    witness,
+                    ..Default::default()
+                };
+                let prevout = TxOut {
+                    value: 10_000,
+                    script_pubkey,
+                };
+                (txin, prevout)
+            })
+            .unzip();
+        let tx = Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input,
+            output: outputs
+                .iter()
+                .map(|key| TxOut {
+                    value: 1_000,
+                    script_pubkey: output_script(*key),
+                })
+                .collect(),
+        };
+
+        (tx, prevouts)
+    }
+
+    #[test]
+    fn test_bip352_vectors() {
+        let secp = Secp256k1::new();
+        let address = vector_receiver(&secp).address();
+        assert_eq!(
+            address.to_string(),
+            "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqjuexzk6murw56suy3e0rd2cgqvycxttddwsvgxe2usfpxumr70xc9pkqwv"
+        );
+
+        let outpoint =
+            |txid: &str, vout: u32| OutPoint::new(bitcoin::Txid::from_str(txid).unwrap(), vout);
+        let key = |key: &str| SecretKey::from_str(key).unwrap();
+        let cases = [
+            // Simple send: two inputs
+            (
+                vec![
+                    (outpoint(TXID_A, 0), KEY_1, false),
+                    (outpoint(TXID_B, 0), KEY_2, false),
+                ],
+                "3e9fce73d4e77a4809908e3c3a2e54ee147b9312dc5044a193d1fc85de46e3c1",
+            ),
+            // Simple send: two inputs, order reversed
+            (
+                vec![
+                    (outpoint(TXID_B, 0), KEY_2, false),
+                    (outpoint(TXID_A, 0), KEY_1, false),
+                ],
+                "3e9fce73d4e77a4809908e3c3a2e54ee147b9312dc5044a193d1fc85de46e3c1",
+            ),
+            // Simple send: two inputs from the same transaction
+            (
+                vec![
+                    (outpoint(TXID_A, 3), KEY_1, false),
+                    (outpoint(TXID_A, 7), KEY_2, false),
+                ],
+                "79e71baa2ba3fc66396de3a04f168c7bf24d6870ec88ca877754790c1db357b6",
+            ),
+            // Single recipient: multiple UTXOs from the same public key
+            (
+                vec![
+                    (outpoint(TXID_A, 0), KEY_1, false),
+                    (outpoint(TXID_B, 0), KEY_1, false),
+                ],
+                "548ae55c8eec1e736e8d3e520f011f1f42a56d166116ad210b3937599f87f566",
+            ),
+            // Single recipient: taproot only inputs with even y-values
+            (
+                vec![
+                    (outpoint(TXID_A, 0), KEY_1, true),
+                    (outpoint(TXID_B, 0), KEY_3, true),
+                ],
+                "de88bea8e7ffc9ce1af30d1132f910323c505185aec8eae361670421e749a1fb",
+            ),
+            // Single recipient: taproot only with mixed even/odd y-values
+            (
+                vec![
+                    (outpoint(TXID_A, 0), KEY_1, true),
+                    (outpoint(TXID_B, 0), KEY_4, true),
+                ],
+                "77cab7dd12b10259ee82c6ea4b509774e33e7078e7138f568092241bf26b99f1",
+            ),
+            // Single recipient: taproot input with even y-value and non-taproot input
+            (
+                vec![
+                    (outpoint(TXID_A, 0), KEY_1, true),
+                    (outpoint(TXID_B, 0), KEY_5, false),
+                ],
+                "30523cca96b2a9ae3c98beb5e60f7d190ec5bc79b2d11a0b2d4d09a608c448f0",
+            ),
+            // Single recipient: taproot input with odd y-value and non-taproot input
+            (
+                vec![
+                    (outpoint(TXID_A, 0), KEY_4, true),
+                    (outpoint(TXID_B, 0), KEY_5, false),
+                ],
+                "359358f59ee9e9eec3f00bdf4882570fd5c182e451aa2650b788544aff012a3a",
+            ),
+        ];
+        assert_eq!(key(KEY_3).x_only_public_key(&secp).1, Parity::Even);
+        assert_eq!(key(KEY_4).x_only_public_key(&secp).1, Parity::Odd);
+
+        for (inputs, expected) in cases {
+            let inputs = inputs
+                .into_iter()
+                .map(|(outpoint, k, is_taproot)| (outpoint, key(k), is_taproot))
+                .collect::<Vec<_>>();
+            let outpoints = inputs.iter().map(|(op, _, _)| *op).collect::<Vec<_>>();
+            let input_keys = inputs
+                .iter()
+                .map(|(_, k, is_taproot)| (*k, *is_taproot))
+                .collect::<Vec<_>>();
+            let outputs = create_outputs(&secp, &outpoints, &input_keys, &[address]).unwrap();
+            assert_eq!(outputs, vec![XOnlyPublicKey::from_str(expected).unwrap()]);
+
+            let mut receiver = vector_receiver(&secp);
+            let (tx, prevouts) = vector_transaction(&secp, &inputs, &outputs);
+            assert_eq!(
+                receiver.scan_tx(&secp, &tx, &prevouts),
+                vec![OutPoint::new(tx.txid(), 0)]
+            );
+            let output = receiver.outputs().values().next().unwrap();
+            let output_key = key(SPEND_KEY)
+                .add_tweak(&Scalar::from(output.tweak))
+                .unwrap()
+                .x_only_public_key(&secp)
+                .0;
+            assert_eq!(output_key, outputs[0]);
+        }
+    }
+
+    #[test]
+    fn test_bip352_labels_and_multiple_outputs() {
+        let secp = Secp256k1::new();
+        let mut receiver = vector_receiver(&secp);
+        let address = receiver.address();
+        let labeled = [2, 3, 1001337]
+            .iter()
+            .map(|m| receiver.add_label(&secp, *m).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labeled.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+            vec![
+                "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqjex54dmqmmv6rw353tsuqhs99ydvadxzrsy9nuvk74epvee55drs734pqq",
+                "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqsg59z2rppn4qlkx0yz9sdltmjv3j8zgcqadjn4ug98m3t6plujsq9qvu5n",
+                "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgq7c2zfthc6x3a5yecwc52nxa0kfd20xuz08zyrjpfw4l2j257yq6qgnkdh5",
+            ]
+        );
+
+        // several outputs to the same scan key, with and without labels, from a taproot input
+        // with an odd y-value
+        let inputs = [
+            (
+                OutPoint::new(bitcoin::Txid::from_str(TXID_A).unwrap(), 0),
+                SecretKey::from_str(KEY_4).unwrap(),
+                true,
+            ),
+            (
+                OutPoint::new(bitcoin::Txid::from_str(TXID_B).unwrap(), 0),
+                SecretKey::from_str(KEY_5).unwrap(),
+                false,
+            ),
+        ];
+        let outpoints = inputs.iter().map(|(op, _, _)| *op).collect::<Vec<_>>();
+        let input_keys = inputs
+            .iter()
+            .map(|(_, k, is_taproot)| (*k, *is_taproot))
+            .collect::<Vec<_>>();
+        let recipients = [address, labeled[0], address, labeled[2]];
+        let outputs = create_outputs(&secp, &outpoints, &input_keys, &recipients).unwrap();
+        assert_eq!(
+            outputs[0],
+            XOnlyPublicKey::from_str(
+                "359358f59ee9e9eec3f00bdf4882570fd5c182e451aa2650b788544aff012a3a"
+            )
+            .unwrap()
+        );
+
+        // the receiver finds the outputs in any order
+        let mut shuffled = outputs.clone();
+        shuffled.reverse();
+        let (tx, prevouts) = vector_transaction(&secp, &inputs, &shuffled);
+        assert_eq!(receiver.scan_tx(&secp, &tx, &prevouts).len(), 4);
+        let mut labels = receiver
+            .outputs()
+            .values()
+            .map(|output| output.label)
+            .collect::<Vec<_>>();
+        labels.sort_unstable();
+        assert_eq!(labels, vec![None, None, Some(2), Some(1001337)]);
+
+        // the outputs and labels are persisted with the receiver
+        let mut restored: SilentPaymentReceiver =
+            serde_json::from_str(&serde_json::to_string(&receiver).unwrap()).unwrap();
+        assert_eq!(restored.address(), receiver.address());
+        assert_eq!(restored.outputs(), receiver.outputs());
+        restored.remove_output(&OutPoint::new(tx.txid(), 0));
+        assert_eq!(restored.scan_tx(&secp, &tx, &prevouts).len(), 4);
+        assert_eq!(restored.outputs(), receiver.outputs());
+        for output in receiver.outputs().values() {
+            let key = SecretKey::from_str(SPEND_KEY)
+                .unwrap()
+                .add_tweak(&Scalar::from(output.tweak))
+                .unwrap();
+            assert_eq!(
+                output_script(key.x_only_public_key(&secp).0),
+                output.txout.script_pubkey
+            );
+        }
+    }
+
+    #[test]
+    fn test_ineligible_inputs() {
+        let secp = Secp256k1::new();
+        let mut receiver = receiver(&secp);
+        let address = receiver.address();
+        let (tx, mut prevouts, input_keys) = transaction(&secp, vec![]);
+        let outpoints = tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            create_outputs(&secp, &outpoints, &[], &[address]),
+            Err(SilentPaymentError::NoEligibleInputs)
+        );
+
+        // spending a segwit v2 output makes the whole transaction ineligible
+        let outputs = create_outputs(&secp, &outpoints, &input_keys, &[address]).unwrap();
+        let (tx, _, _) = transaction(&secp, outputs);
+        prevouts[0].script_pubkey = ScriptBuf::from(vec![0x52, 0x02, 0x00, 0x00]);
+        assert!(receiver.scan_tx(&secp, &tx, &prevouts).is_empty());
+    }
+}
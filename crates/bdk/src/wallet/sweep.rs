@@ -0,0 +1,320 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2023 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Sweeping private keys
+//!
+//! This module moves the funds controlled by private keys that don't belong to the wallet, like
+//! the ones of a paper wallet, into the wallet. A [`Sweep`] is created from WIF keys or single-key
+//! descriptors, its UTXOs are looked up with a [`UtxoSource`] (or an [`AsyncUtxoSource`] with the
+//! `async-interface` feature), then [`Wallet::sweep`] spends all of them to one of the wallet's
+//! addresses.
+//!
+//! A WIF key is looked up as P2PKH, and, if it's compressed, as P2WPKH, P2SH-P2WPKH and P2TR too.
+//!
+//! ```
+//! # use bdk::bitcoin::Network;
+//! # use bdk::wallet::sweep::{Sweep, UtxoSource};
+//! # use bdk::{FeeRate, Wallet};
+//! fn sweep_paper_wallet<S: UtxoSource>(
+//!     wallet: &mut Wallet,
+//!     source: &S,
+//! ) -> Result<(), Box<dyn std::error::Error>>
+//! where
+//!     S::Error: std::error::Error + 'static,
+//! {
+//!     let mut sweep = Sweep::new(
+//!         &["cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW"],
+//!         Network::Testnet,
+//!     )?;
+//!     sweep.sync(source)?;
+//!     println!("Sweeping {} sats", sweep.balance());
+//!
+//!     let psbt = wallet.sweep(&sweep, FeeRate::from_sat_per_vb(2.0))?;
+//!     // `psbt` is signed and finalized, it can be extracted and broadcast
+//!     # let _ = psbt;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`UtxoSource`] and [`AsyncUtxoSource`] are re-exported from [`bdk_chain::chain_source`].
+//! Implementations are provided by the chain source crates for wrappers around their clients:
+//!
+//! * `bdk_esplora`: `EsploraSource`, for both the blocking and the async client
+//! * `bdk_electrum`: `ElectrumSource`
+//! * `bdk_bitcoind_rpc`: `BitcoindSource`, which only sees confirmed UTXOs
+//!
+//! [`Wallet::sweep`]: crate::Wallet::sweep
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use bitcoin::psbt::{self, PartiallySignedTransaction as Psbt};
+use bitcoin::{Network, OutPoint, PrivateKey, ScriptBuf, Transaction};
+use miniscript::descriptor::KeyMap;
+use miniscript::psbt::{PsbtExt, PsbtInputExt};
+
+use super::signer::{SignOptions, SignersContainer};
+use super::utils::SecpCtx;
+use crate::descriptor::{
+    DerivedDescriptor, DescriptorMeta, ExtendedDescriptor, IntoWalletDescriptor,
+};
+use crate::error::{Error, MiniscriptPsbtError};
+
+/// Errors related to sweeping private keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SweepError {
+    /// The descriptor has more than one script, it should be imported as a wallet instead
+    NotSingleKey,
+    /// The descriptor doesn't have any private key
+    MissingPrivateKey,
+    /// None of the keys has any UTXO
+    NoUtxos,
+    /// The sweep transaction couldn't be finalized
+    Finalize(String),
+}
+
+impl fmt::Display for SweepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotSingleKey => write!(f, "The descriptor must have a single script"),
+            Self::MissingPrivateKey => write!(f, "The descriptor doesn't have any private key"),
+            Self::NoUtxos => write!(f, "There is nothing to sweep"),
+            Self::Finalize(err) => write!(f, "Failed to finalize the sweep transaction: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SweepError {}
+
+/// Errors that can occur while looking up the UTXOs of a [`Sweep`]
+#[derive(Debug)]
+pub enum SyncError<E> {
+    /// The chain source couldn't retrieve the UTXOs
+    Source(E),
+    /// The chain source returned an outpoint that isn't an output of its transaction, or that
+    /// doesn't pay to the key being swept
+    InvalidUtxo(OutPoint),
+}
+
+impl<E: fmt::Display> fmt::Display for SyncError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Source(err) => write!(f, "Failed to look up the UTXOs: {}", err),
+            Self::InvalidUtxo(outpoint) => {
+                write!(f, "The chain source returned an invalid UTXO: {}", outpoint)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Display + fmt::Debug> std::error::Error for SyncError<E> {}
+
+#[cfg(feature = "async-interface")]
+pub use bdk_chain::chain_source::AsyncUtxoSource;
+pub use bdk_chain::chain_source::UtxoSource;
+
+#[derive(Debug)]
+struct SweepKey {
+    descriptor: ExtendedDescriptor,
+    derived: DerivedDescriptor,
+    keymap: KeyMap,
+    script_pubkey: ScriptBuf,
+    utxos: Vec<(OutPoint, Transaction)>,
+}
+
+impl SweepKey {
+    // Check that the UTXOs returned by a chain source are outputs paying to the key
+    fn check_utxos<E>(&self, utxos: &[(OutPoint, Transaction)]) -> Result<(), SyncError<E>> {
+        for (outpoint, tx) in utxos {
+            let pays_to_key = tx
+                .output
+                .get(outpoint.vout as usize)
+                .map_or(false, |txout| txout.script_pubkey == self.script_pubkey);
+            if tx.txid() != outpoint.txid || !pays_to_key {
+                return Err(SyncError::InvalidUtxo(*outpoint));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A set of private keys to sweep into a wallet, see [the module-level docs](self).
+#[derive(Debug)]
+pub struct Sweep {
+    keys: Vec<SweepKey>,
+}
+
+impl Sweep {
+    /// Create a sweep from WIF private keys or single-key descriptors for `network`
+    pub fn new(keys: &[&str], network: Network) -> Result<Self, Error> {
+        let secp = SecpCtx::new();
+        let mut sweep_keys = Vec::new();
+        for key in keys {
+            let descriptors = match PrivateKey::from_wif(key) {
+                Ok(private_key) if private_key.compressed => vec![
+                    format!("pkh({})", key),
+                    format!("wpkh({})", key),
+                    format!("sh(wpkh({}))", key),
+                    format!("tr({})", key),
+                ],
+                Ok(_) => vec![format!("pkh({})", key)],
+                Err(_) => vec![key.to_string()],
+            };
+
+            for descriptor in descriptors {
+                let (descriptor, keymap) = descriptor.into_wallet_descriptor(&secp, network)?;
+                if descriptor.has_wildcard() {
+                    return Err(SweepError::NotSingleKey.into());
+                }
+                if keymap.is_empty() {
+                    return Err(SweepError::MissingPrivateKey.into());
+                }
+                let derived = descriptor
+                    .at_derivation_index(0)
+                    .expect("the descriptor doesn't have a wildcard");
+                sweep_keys.push(SweepKey {
+                    script_pubkey: derived.script_pubkey(),
+                    descriptor,
+                    derived,
+                    keymap,
+                    utxos: Vec::new(),
+                });
+            }
+        }
+
+        Ok(Sweep { keys: sweep_keys })
+    }
+
+    /// Look up the UTXOs of the keys with `source`
+    ///
+    /// Fails with [`SyncError::InvalidUtxo`] if `source` returns an outpoint that isn't an output
+    /// paying to the key in the transaction returned with it. The UTXOs found by the previous sync
+    /// are kept if it fails.
+    pub fn sync<S: UtxoSource>(&mut self, source: &S) -> Result<(), SyncError<S::Error>> {
+        let mut utxos = Vec::with_capacity(self.keys.len());
+        for key in &self.keys {
+            let key_utxos = source
+                .list_unspent(&key.script_pubkey)
+                .map_err(SyncError::Source)?;
+            key.check_utxos(&key_utxos)?;
+            utxos.push(key_utxos);
+        }
+        self.set_utxos(utxos);
+        Ok(())
+    }
+
+    /// Async version of [`sync`].
+    ///
+    /// [`sync`]: Self::sync
+    #[cfg(feature = "async-interface")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async-interface")))]
+    pub async fn sync_async<S: AsyncUtxoSource>(
+        &mut self,
+        source: &S,
+    ) -> Result<(), SyncError<S::Error>> {
+        let mut utxos = Vec::with_capacity(self.keys.len());
+        for key in &self.keys {
+            let key_utxos = source
+                .list_unspent(&key.script_pubkey)
+                .await
+                .map_err(SyncError::Source)?;
+            key.check_utxos(&key_utxos)?;
+            utxos.push(key_utxos);
+        }
+        self.set_utxos(utxos);
+        Ok(())
+    }
+
+    fn set_utxos(&mut self, utxos: Vec<Vec<(OutPoint, Transaction)>>) {
+        for (key, key_utxos) in self.keys.iter_mut().zip(utxos) {
+            key.utxos = key_utxos;
+        }
+    }
+
+    /// The total value of the UTXOs found by the last sync
+    pub fn balance(&self) -> u64 {
+        // `sync` checked that the outpoints are outputs of their transactions
+        self.keys
+            .iter()
+            .flat_map(|key| &key.utxos)
+            .map(|(outpoint, tx)| tx.output[outpoint.vout as usize].value)
+            .sum()
+    }
+
+    // The UTXOs to spend, as foreign UTXOs for the `TxBuilder`
+    pub(crate) fn foreign_utxos(&self) -> Result<Vec<(OutPoint, psbt::Input, usize)>, Error> {
+        let mut utxos = Vec::new();
+        for key in &self.keys {
+            let is_taproot = key.descriptor.is_taproot();
+            let is_witness = key.descriptor.is_witness();
+            #[allow(deprecated)]
+            let satisfaction_weight = key
+                .derived
+                .max_satisfaction_weight()
+                .expect("the descriptor is satisfiable");
+            for (outpoint, tx) in &key.utxos {
+                let mut input = psbt::Input::default();
+                if is_witness || is_taproot {
+                    input.witness_utxo = Some(tx.output[outpoint.vout as usize].clone());
+                }
+                if !is_taproot {
+                    input.non_witness_utxo = Some(tx.clone());
+                }
+                input
+                    .update_with_descriptor_unchecked(&key.derived)
+                    .map_err(MiniscriptPsbtError::Conversion)?;
+                utxos.push((*outpoint, input, satisfaction_weight));
+            }
+        }
+
+        if utxos.is_empty() {
+            return Err(SweepError::NoUtxos.into());
+        }
+        Ok(utxos)
+    }
+
+    // Sign and finalize the inputs of `psbt` with the keys of the sweep
+    pub(crate) fn sign(&self, psbt: &mut Psbt, secp: &SecpCtx) -> Result<(), Error> {
+        for key in self.keys.iter().filter(|key| !key.utxos.is_empty()) {
+            // a key can be swept from several types of script, which are signed for in different
+            // ways: the signers skip finalized inputs, so mark the other ones as such
+            let is_ours = |txin: &bitcoin::TxIn| {
+                key.utxos
+                    .iter()
+                    .any(|(outpoint, _)| *outpoint == txin.previous_output)
+            };
+            let mut signed = psbt.clone();
+            for (input, txin) in signed.inputs.iter_mut().zip(&psbt.unsigned_tx.input) {
+                if !is_ours(txin) {
+                    input.final_script_sig = Some(ScriptBuf::new());
+                }
+            }
+
+            let signers = SignersContainer::build(key.keymap.clone(), &key.descriptor, secp);
+            for signer in signers.signers() {
+                signer.sign_transaction(&mut signed, &SignOptions::default(), secp)?;
+            }
+            for (index, txin) in psbt.unsigned_tx.input.iter().enumerate() {
+                if is_ours(txin) {
+                    psbt.inputs[index] = signed.inputs[index].clone();
+                }
+            }
+        }
+
+        psbt.finalize_mut(secp).map_err(|errors| {
+            let errors = errors.iter().map(|err| err.to_string()).collect::<Vec<_>>();
+            SweepError::Finalize(errors.join(", "))
+        })?;
+        Ok(())
+    }
+}
@@ -47,7 +47,10 @@ use bitcoin::psbt::{self, PartiallySignedTransaction as Psbt};
 use bitcoin::{absolute, script::PushBytes, OutPoint, ScriptBuf, Sequence, Transaction};
 
 use super::coin_selection::{CoinSelectionAlgorithm, DefaultCoinSelectionAlgorithm};
+use super::payment_uri::{PaymentUri, PaymentUriError};
+use super::silent_payments::SilentPaymentAddress;
 use super::ChangeSet;
+use crate::descriptor::policy::Condition;
 use crate::types::{FeeRate, KeychainKind, LocalUtxo, WeightedUtxo};
 use crate::{Error, Utxo, Wallet};
 /// Context in which the [`TxBuilder`] is valid
@@ -127,11 +130,12 @@ pub struct TxBuilder<'a, D, Cs, Ctx> {
 #[derive(Default, Debug, Clone)]
 pub(crate) struct TxParams {
     pub(crate) recipients: Vec<(ScriptBuf, u64)>,
+    pub(crate) silent_payments: Vec<(SilentPaymentAddress, u64)>,
     pub(crate) drain_wallet: bool,
     pub(crate) drain_to: Option<ScriptBuf>,
     pub(crate) fee_policy: Option<FeePolicy>,
-    pub(crate) internal_policy_path: Option<BTreeMap<String, Vec<usize>>>,
-    pub(crate) external_policy_path: Option<BTreeMap<String, Vec<usize>>>,
+    pub(crate) policy_paths: BTreeMap<KeychainKind, BTreeMap<String, Vec<usize>>>,
+    pub(crate) tap_leaves: BTreeMap<KeychainKind, usize>,
     pub(crate) utxos: Vec<WeightedUtxo>,
     pub(crate) unspendable: HashSet<OutPoint>,
     pub(crate) manually_selected_only: bool,
@@ -270,12 +274,41 @@ impl<'a, D, Cs: CoinSelectionAlgorithm, Ctx: TxBuilderContext> TxBuilder<'a, D,
         policy_path: BTreeMap<String, Vec<usize>>,
         keychain: KeychainKind,
     ) -> &mut Self {
-        let to_update = match keychain {
-            KeychainKind::Internal => &mut self.params.internal_policy_path,
-            KeychainKind::External => &mut self.params.external_policy_path,
-        };
+        self.params.policy_paths.insert(keychain, policy_path);
+        self
+    }
 
-        *to_update = Some(policy_path);
+    /// Return the timelocks required to spend with the policy paths selected so far
+    ///
+    /// The requirements of all the keychains whose outputs can be spent are combined together,
+    /// that is the ones allowed by the [`ChangeSpendPolicy`] (excluding the watch-only custom
+    /// keychains) and the ones of the UTXOs added manually: the transaction
+    /// built by [`finish`](Self::finish) will use an `nLockTime` and an `nSequence` that satisfy
+    /// them. An error is returned if the selected paths are ambiguous, or if they require
+    /// timelocks that can't be satisfied together, like a height-based and a time-based
+    /// `nLockTime`.
+    pub fn policy_requirements(&self) -> Result<Condition, Error> {
+        self.wallet.borrow().policy_requirements(&self.params)
+    }
+
+    /// Spend the outputs of a `tr()` keychain through the script path of one of its tap leaves.
+    ///
+    /// `leaf_index` is the position of the leaf in the descriptor's tap tree, counting the leaves
+    /// in the order in which they appear in the descriptor. The fee is estimated using the
+    /// satisfaction weight of that leaf instead of the largest one in the tree, and the PSBT
+    /// inputs spending the keychain's outputs only carry the `tap_scripts` and `tap_key_origins`
+    /// relevant to that leaf.
+    ///
+    /// Signers holding the internal key of the descriptor may still sign for the key path,
+    /// unless [`SignOptions::sign_with_tap_internal_key`] is disabled.
+    ///
+    /// If the keychain's descriptor is not `tr()` or doesn't have a leaf at `leaf_index`,
+    /// [`finish`] returns [`Error::InvalidTapLeaf`].
+    ///
+    /// [`SignOptions::sign_with_tap_internal_key`]: crate::signer::SignOptions::sign_with_tap_internal_key
+    /// [`finish`]: Self::finish
+    pub fn tap_leaf(&mut self, keychain: KeychainKind, leaf_index: usize) -> &mut Self {
+        self.params.tap_leaves.insert(keychain, leaf_index);
         self
     }
 
@@ -608,6 +641,44 @@ impl<'a, D, Cs: CoinSelectionAlgorithm> TxBuilder<'a, D, Cs, CreateTx> {
         self
     }
 
+    /// Add the recipient of a [BIP21] payment URI
+    ///
+    /// Fails if the address isn't valid for the wallet's network, or if the URI doesn't request
+    /// an amount: in that case it's up to the user to choose one and call [`add_recipient`]
+    /// instead.
+    ///
+    /// [BIP21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+    /// [`add_recipient`]: Self::add_recipient
+    pub fn add_payment_uri(&mut self, uri: &PaymentUri) -> Result<&mut Self, Error> {
+        let network = self.wallet.borrow().network();
+        let address = uri
+            .address
+            .clone()
+            .require_network(network)
+            .map_err(PaymentUriError::from)?;
+        let amount = uri.amount.ok_or(PaymentUriError::MissingAmount)?;
+
+        Ok(self.add_recipient(address.script_pubkey(), amount))
+    }
+
+    /// Add a silent payment (BIP352) recipient
+    ///
+    /// The output key is derived from the keys of the inputs, so it's only computed once they
+    /// have been selected, using the private keys of the wallet's signers. Every P2PKH, P2WPKH,
+    /// P2SH-P2WPKH or P2TR input must be spent with a key known to the wallet, foreign UTXOs of
+    /// these types can't be used.
+    ///
+    /// Changing the inputs afterwards, for instance when bumping the fee, makes the payment
+    /// undetectable by the receiver.
+    pub fn add_silent_payment_recipient(
+        &mut self,
+        address: SilentPaymentAddress,
+        amount: u64,
+    ) -> &mut Self {
+        self.params.silent_payments.push((address, amount));
+        self
+    }
+
     /// Add data as an output, using OP_RETURN
     pub fn add_data<T: AsRef<PushBytes>>(&mut self, data: &T) -> &mut Self {
         let script = ScriptBuf::new_op_return(data);
@@ -788,10 +859,15 @@ impl Default for ChangeSpendPolicy {
 
 impl ChangeSpendPolicy {
     pub(crate) fn is_satisfied_by(&self, utxo: &LocalUtxo) -> bool {
+        self.allows_keychain(utxo.keychain)
+    }
+
+    /// Whether outputs of `keychain` may be spent under this policy.
+    pub(crate) fn allows_keychain(&self, keychain: KeychainKind) -> bool {
         match self {
             ChangeSpendPolicy::ChangeAllowed => true,
-            ChangeSpendPolicy::OnlyChange => utxo.keychain == KeychainKind::Internal,
-            ChangeSpendPolicy::ChangeForbidden => utxo.keychain == KeychainKind::External,
+            ChangeSpendPolicy::OnlyChange => keychain == KeychainKind::Internal,
+            ChangeSpendPolicy::ChangeForbidden => keychain != KeychainKind::Internal,
         }
     }
 }
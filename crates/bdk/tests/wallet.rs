@@ -1,13 +1,16 @@
 use assert_matches::assert_matches;
 use bdk::descriptor::calc_checksum;
+use bdk::descriptor::policy::Condition;
 use bdk::psbt::PsbtUtils;
 use bdk::signer::{SignOptions, SignerError};
+use bdk::wallet::broadcaster::{BroadcastError, Broadcaster, RejectReason};
 use bdk::wallet::coin_selection::LargestFirstCoinSelection;
 use bdk::wallet::AddressIndex::*;
-use bdk::wallet::{AddressIndex, AddressInfo, Balance, Wallet};
+use bdk::wallet::{AddressIndex, AddressInfo, Balance, Birthday, ChangeSet, ChangeSetV0, Wallet};
 use bdk::{Error, FeeRate, KeychainKind};
 use bdk_chain::COINBASE_MATURITY;
-use bdk_chain::{BlockId, ConfirmationTime};
+use bdk_chain::{BlockId, ChainPosition, ConfirmationTime};
+use bitcoin::hash_types::TxMerkleNode;
 use bitcoin::hashes::Hash;
 use bitcoin::sighash::{EcdsaSighashType, TapSighashType};
 use bitcoin::ScriptBuf;
@@ -15,8 +18,8 @@ use bitcoin::{
     absolute, script::PushBytesBuf, taproot::TapNodeHash, Address, OutPoint, Sequence, Transaction,
     TxIn, TxOut, Weight,
 };
+use bitcoin::{block, Block, BlockHash, CompactTarget, Txid};
 use bitcoin::{psbt, Network};
-use bitcoin::{BlockHash, Txid};
 use core::str::FromStr;
 
 mod common;
@@ -989,6 +992,31 @@ fn test_create_tx_policy_path_use_csv() {
     assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence(144));
 }
 
+#[test]
+fn test_create_tx_policy_requirements() {
+    let (mut wallet, _) = get_funded_wallet(get_test_a_or_b_plus_csv());
+
+    let external_policy = wallet.policies(KeychainKind::External).unwrap().unwrap();
+    let root_id = external_policy.id;
+    // child #1 is or(pk(B),older(144))
+    let path = vec![(root_id, vec![1])].into_iter().collect();
+
+    let mut builder = wallet.build_tx();
+    assert_matches!(
+        builder.policy_requirements(),
+        Err(Error::SpendingPolicyRequired(KeychainKind::External))
+    );
+
+    builder.policy_path(path, KeychainKind::External);
+    assert_eq!(
+        builder.policy_requirements().unwrap(),
+        Condition {
+            csv: Some(Sequence(144)),
+            timelock: None,
+        }
+    );
+}
+
 #[test]
 fn test_create_tx_policy_path_ignored_subtree_with_csv() {
     let (mut wallet, _) = get_funded_wallet("wsh(or_d(pk(cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu),or_i(and_v(v:pkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW),older(30)),and_v(v:pkh(cMnkdebixpXMPfkcNEjjGin7s94hiehAH4mLbYkZoh9KSiNNmqC8),older(90)))))");
@@ -3581,3 +3609,1475 @@ fn test_tx_cancellation() {
         .unwrap();
     assert_eq!(change_derivation_4, (KeychainKind::Internal, 2));
 }
+
+#[test]
+fn test_set_birthday() {
+    let (mut wallet, _) = get_funded_wallet(get_test_wpkh());
+    assert_eq!(wallet.birthday(), None);
+
+    wallet.set_birthday(Birthday::Height(1_000));
+    assert_eq!(wallet.birthday(), Some(Birthday::Height(1_000)));
+    assert_eq!(wallet.staged().birthday, Some(Birthday::Height(1_000)));
+
+    wallet.set_birthday(Birthday::Time(1_690_000_000));
+    assert_eq!(wallet.birthday(), Some(Birthday::Time(1_690_000_000)));
+    assert_eq!(
+        wallet.staged().birthday,
+        Some(Birthday::Time(1_690_000_000))
+    );
+}
+
+#[test]
+fn test_migrate_changeset_v0() {
+    let (wallet, _) = get_funded_wallet(get_test_wpkh());
+    let staged = wallet.staged().clone();
+    let old = ChangeSetV0 {
+        chain: staged.chain.clone(),
+        indexed_tx_graph: staged.indexed_tx_graph.clone(),
+    };
+    // a wallet from before birthdays has none
+    assert_eq!(ChangeSet::from(old), staged);
+}
+
+#[test]
+fn test_rescan_from() {
+    // the funding tx is confirmed at height 1_000 and the spending tx at height 2_000
+    let (mut wallet, txid) = get_funded_wallet(get_test_wpkh());
+    assert_matches!(
+        wallet.get_tx(txid).unwrap().chain_position,
+        ChainPosition::Confirmed(_)
+    );
+
+    assert!(wallet.rescan_from(1_500));
+    assert_eq!(wallet.latest_checkpoint().unwrap().height(), 1_000);
+    assert_eq!(
+        wallet.staged().chain.get(&2_000),
+        Some(&None),
+        "removal of checkpoint must be staged"
+    );
+    // transactions are kept, but become unconfirmed
+    assert_matches!(
+        wallet.get_tx(txid).unwrap().chain_position,
+        ChainPosition::Unconfirmed(_)
+    );
+    assert_eq!(wallet.get_balance().confirmed, 0);
+    assert_eq!(wallet.get_balance().untrusted_pending, 50_000);
+
+    // nothing left to remove
+    assert!(!wallet.rescan_from(1_500));
+
+    // syncing the block again re-confirms the transaction
+    wallet
+        .insert_checkpoint(BlockId {
+            height: 2_000,
+            hash: BlockHash::all_zeros(),
+        })
+        .unwrap();
+    assert_matches!(
+        wallet.get_tx(txid).unwrap().chain_position,
+        ChainPosition::Confirmed(_)
+    );
+    assert_eq!(wallet.get_balance().confirmed, 50_000);
+}
+
+// A block at `height` on top of `prev_blockhash`, paying `value` to `script_pubkey`
+fn block_paying_to(
+    prev_blockhash: BlockHash,
+    height: u32,
+    script_pubkey: ScriptBuf,
+    value: u64,
+) -> Block {
+    let tx = Transaction {
+        version: 1,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), height),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value,
+            script_pubkey,
+        }],
+    };
+    Block {
+        header: block::Header {
+            version: block::Version::TWO,
+            prev_blockhash,
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: 1_700_000_000 + height,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        },
+        txdata: vec![tx],
+    }
+}
+
+#[test]
+fn test_sync_from_birthday() {
+    let mut wallet = Wallet::new_no_persist(get_test_wpkh(), None, Network::Regtest).unwrap();
+    let no_time = |_: u64| -> Result<u32, ()> { panic!("the birthday is not a time") };
+    // without a birthday, sync from the latest checkpoint
+    assert_eq!(wallet.birthday_start_height(no_time), Ok(None));
+
+    // a time birthday is mapped to a height by the chain source
+    wallet.set_birthday(Birthday::Time(1_700_000_000));
+    assert_eq!(
+        wallet.birthday_start_height(|time| {
+            assert_eq!(time, 1_700_000_000);
+            Ok::<_, ()>(90)
+        }),
+        Ok(Some(90))
+    );
+    assert_eq!(
+        wallet.birthday_start_height(|_| Err("rpc error")),
+        Err("rpc error")
+    );
+
+    wallet.set_birthday(Birthday::Height(100));
+    assert_eq!(wallet.birthday_start_height(no_time), Ok(Some(100)));
+
+    // the first block emitted from the birthday connects to the wallet's chain
+    let spk = wallet.get_address(New).script_pubkey();
+    let block = block_paying_to(BlockHash::all_zeros(), 100, spk.clone(), 10_000);
+    wallet.apply_block(&block, 100).unwrap();
+    assert_eq!(wallet.latest_checkpoint().unwrap().height(), 100);
+    assert_eq!(wallet.get_balance().confirmed, 10_000);
+    assert_eq!(wallet.birthday_start_height(no_time), Ok(None));
+
+    // following blocks extend it, and irrelevant transactions are not kept
+    let other_spk = ScriptBuf::new_v0_p2wpkh(&bitcoin::WPubkeyHash::all_zeros());
+    let next = block_paying_to(block.block_hash(), 101, other_spk, 20_000);
+    wallet.apply_block(&next, 101).unwrap();
+    assert_eq!(wallet.latest_checkpoint().unwrap().height(), 101);
+    assert_eq!(wallet.transactions().count(), 1);
+
+    // a block that doesn't connect is rejected
+    let orphan = block_paying_to(BlockHash::all_zeros(), 101, spk, 5_000);
+    assert!(wallet.apply_block(&orphan, 101).is_err());
+    assert_eq!(wallet.get_balance().confirmed, 10_000);
+}
+
+struct MockBroadcaster(Option<RejectReason>);
+
+impl Broadcaster for MockBroadcaster {
+    type Error = ();
+
+    fn broadcast_tx(&self, _tx: &Transaction) -> Result<(), BroadcastError<()>> {
+        match self.0 {
+            Some(reason) => Err(BroadcastError::Rejected(reason)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn test_broadcast() {
+    let (mut wallet, _) = get_funded_wallet(get_test_wpkh());
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), 25_000);
+    let mut psbt = builder.finish().unwrap();
+    wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+    let tx = psbt.extract_tx();
+    let txid = tx.txid();
+    let fee = wallet.calculate_fee(&tx).unwrap();
+
+    // a rejected tx is not inserted
+    assert_matches!(
+        wallet.broadcast(
+            &MockBroadcaster(Some(RejectReason::MempoolMinFee)),
+            tx.clone(),
+            1_000
+        ),
+        Err(BroadcastError::Rejected(RejectReason::MempoolMinFee))
+    );
+    assert!(wallet.get_tx(txid).is_none());
+    assert_eq!(wallet.get_balance().confirmed, 50_000);
+
+    // an accepted tx is inserted as unconfirmed and immediately spends the funding output
+    wallet.broadcast(&MockBroadcaster(None), tx, 1_000).unwrap();
+    assert_eq!(
+        wallet.get_tx(txid).unwrap().chain_position,
+        ChainPosition::Unconfirmed(1_000)
+    );
+    assert_eq!(wallet.get_balance().confirmed, 0);
+    assert_eq!(wallet.get_balance().total(), 50_000 - 25_000 - fee);
+}
+
+#[test]
+fn test_custom_keychains() {
+    let mut wallet = Wallet::new_with_keychains(
+        get_test_wpkh(),
+        None,
+        vec![(
+            0,
+            "wpkh(cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu)",
+        )],
+        (),
+        Network::Regtest,
+    )
+    .unwrap();
+    let custom = KeychainKind::Custom(0);
+    assert_eq!(wallet.keychains().len(), 2);
+
+    let addr = wallet.get_keychain_address(custom, New).unwrap();
+    assert_eq!(addr.keychain, custom);
+    assert!(wallet
+        .get_keychain_address(KeychainKind::Custom(1), New)
+        .is_none());
+    assert_ne!(addr.address, wallet.get_address(New).address);
+
+    let tx = Transaction {
+        version: 1,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![],
+        output: vec![TxOut {
+            script_pubkey: addr.script_pubkey(),
+            value: 25_000,
+        }],
+    };
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+    receive_output(
+        &mut wallet,
+        50_000,
+        ConfirmationTime::Unconfirmed { last_seen: 0 },
+    );
+
+    assert_eq!(wallet.get_balance().total(), 75_000);
+    assert_eq!(wallet.get_keychain_balance(custom).total(), 25_000);
+    assert_eq!(
+        wallet.get_keychain_balance(KeychainKind::External).total(),
+        50_000
+    );
+    assert_eq!(
+        wallet
+            .list_unspent()
+            .filter(|utxo| utxo.keychain == custom)
+            .count(),
+        1
+    );
+
+    let send_to = wallet.get_address(New);
+    let mut builder = wallet.build_tx();
+    builder.drain_to(send_to.script_pubkey()).drain_wallet();
+    let mut psbt = builder.finish().unwrap();
+    assert_eq!(psbt.inputs.len(), 2);
+    let finalized = wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+    assert!(finalized);
+}
+
+#[test]
+fn test_watch_only_custom_keychain() {
+    // a watch-only copy of `get_test_a_or_b_plus_csv`, whose policy requires a path
+    let secp = bitcoin::secp256k1::Secp256k1::new();
+    let pubkey = |wif: &str| {
+        bitcoin::PrivateKey::from_wif(wif)
+            .unwrap()
+            .public_key(&secp)
+    };
+    let watch_only = format!(
+        "wsh(or_d(pk({}),and_v(v:pk({}),older(144))))",
+        pubkey("cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu"),
+        pubkey("cMnkdebixpXMPfkcNEjjGin7s94hiehAH4mLbYkZoh9KSiNNmqC8"),
+    );
+    let mut wallet = Wallet::new_with_keychains(
+        get_test_wpkh(),
+        None,
+        vec![(0, watch_only.as_str())],
+        (),
+        Network::Regtest,
+    )
+    .unwrap();
+    let custom = KeychainKind::Custom(0);
+
+    let addr = wallet.get_keychain_address(custom, New).unwrap();
+    let tx = Transaction {
+        version: 1,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![],
+        output: vec![TxOut {
+            script_pubkey: addr.script_pubkey(),
+            value: 25_000,
+        }],
+    };
+    wallet
+        .insert_tx(tx.clone(), ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+    receive_output(
+        &mut wallet,
+        50_000,
+        ConfirmationTime::Unconfirmed { last_seen: 0 },
+    );
+
+    // the outputs of the watch-only keychain are not part of the wallet's balance
+    assert_eq!(wallet.get_balance().total(), 50_000);
+    assert_eq!(wallet.get_keychain_balance(custom).total(), 25_000);
+
+    // the outputs of the watch-only keychain are not selected, and its policy is ignored
+    let send_to = wallet.get_address(New);
+    let mut builder = wallet.build_tx();
+    builder.drain_to(send_to.script_pubkey()).drain_wallet();
+    assert!(builder.policy_requirements().unwrap().is_null());
+    let psbt = builder.finish().unwrap();
+    assert_eq!(psbt.inputs.len(), 1);
+    assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence::MAX);
+    assert_eq!(wallet.sent_and_received(&psbt.unsigned_tx).0, 50_000);
+
+    // adding them manually requires a policy path for the keychain
+    let outpoint = OutPoint {
+        txid: tx.txid(),
+        vout: 0,
+    };
+    let mut builder = wallet.build_tx();
+    builder
+        .add_utxo(outpoint)
+        .unwrap()
+        .drain_to(send_to.script_pubkey());
+    assert_matches!(
+        builder.finish(),
+        Err(Error::SpendingPolicyRequired(KeychainKind::Custom(0)))
+    );
+}
+
+#[test]
+fn test_multipath_descriptor() {
+    let descriptor = "wpkh([c258d2e4/84'/1'/0']tpubDD3ynpHgJQW8VvWRzQ5WFDCrs4jqVFGHB3vLC3r49XHJSqP8bHKdK4AriuUKLccK68zfzowx7YhmDN8SiSkgCDENUFx9qVw65YyqM78vyVe/<0;1>/*)";
+    let mut wallet = Wallet::new_multipath(descriptor, (), Network::Testnet).unwrap();
+
+    let mut external = Wallet::new_no_persist(
+        "wpkh([c258d2e4/84'/1'/0']tpubDD3ynpHgJQW8VvWRzQ5WFDCrs4jqVFGHB3vLC3r49XHJSqP8bHKdK4AriuUKLccK68zfzowx7YhmDN8SiSkgCDENUFx9qVw65YyqM78vyVe/0/*)",
+        Some("wpkh([c258d2e4/84'/1'/0']tpubDD3ynpHgJQW8VvWRzQ5WFDCrs4jqVFGHB3vLC3r49XHJSqP8bHKdK4AriuUKLccK68zfzowx7YhmDN8SiSkgCDENUFx9qVw65YyqM78vyVe/1/*)"),
+        Network::Testnet,
+    )
+    .unwrap();
+    assert_eq!(wallet.keychains(), external.keychains());
+    assert_eq!(
+        wallet.get_internal_address(New),
+        external.get_internal_address(New)
+    );
+
+    let exported = wallet.multipath_descriptor().unwrap();
+    assert_eq!(exported.to_string().split('#').next().unwrap(), descriptor);
+    assert_eq!(
+        external.multipath_descriptor().unwrap().to_string(),
+        exported.to_string()
+    );
+
+    // private multipath keys are split between the keychains
+    let mut wallet = Wallet::new_multipath(
+        "wpkh(tprv8ZgxMBicQKsPdDArR4xSAECuVxeX1jwwSXR4ApKbkYgZiziDc4LdBy2WvJeGDfUSE4UT4hHhbgEwbdq8ajjUHiKDegkwrNU6V55CxcxonVN/<0;1>/*)",
+        (),
+        Network::Regtest,
+    )
+    .unwrap();
+    assert!(wallet.multipath_descriptor().is_some());
+    receive_output(
+        &mut wallet,
+        50_000,
+        ConfirmationTime::Unconfirmed { last_seen: 0 },
+    );
+    let addr = wallet.get_address(New);
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), 25_000);
+    let mut psbt = builder.finish().unwrap();
+    assert!(wallet.sign(&mut psbt, SignOptions::default()).unwrap());
+
+    assert_matches!(
+        Wallet::new_multipath(get_test_wpkh(), (), Network::Regtest),
+        Err(bdk::wallet::NewError::Descriptor(
+            bdk::descriptor::DescriptorError::MultiPathCount(1)
+        ))
+    );
+    assert!(get_funded_wallet(get_test_wpkh())
+        .0
+        .multipath_descriptor()
+        .is_none());
+}
+
+#[test]
+fn test_taproot_tap_leaf_selection() {
+    use bitcoin::taproot::{LeafVersion, TapLeafHash};
+
+    let (mut wallet, _) = get_funded_wallet(get_test_tr_with_taptree_both_priv());
+    let addr = wallet.get_address(New);
+
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), 25_000)
+        .tap_leaf(KeychainKind::External, 1);
+    let mut psbt = builder.finish().unwrap();
+
+    assert_eq!(psbt.inputs[0].tap_scripts.len(), 1);
+    let (script, _) = psbt.inputs[0].tap_scripts.values().next().unwrap().clone();
+    let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
+    // the key of the selected leaf, and the internal key
+    assert_eq!(psbt.inputs[0].tap_key_origins.len(), 2);
+    let internal_key = psbt.inputs[0].tap_internal_key.unwrap();
+    assert!(psbt.inputs[0]
+        .tap_key_origins
+        .iter()
+        .all(|(key, (leaf_hashes, _))| if key == &internal_key {
+            leaf_hashes.is_empty()
+        } else {
+            leaf_hashes == &vec![leaf_hash]
+        }));
+
+    let finalized = wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+    assert!(finalized);
+    let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+    assert_eq!(witness.second_to_last(), Some(script.as_bytes()));
+
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), 25_000)
+        .tap_leaf(KeychainKind::External, 2);
+    assert_matches!(
+        builder.finish(),
+        Err(Error::InvalidTapLeaf(KeychainKind::External, 2))
+    );
+
+    let (mut wallet, _) = get_funded_wallet(get_test_wpkh());
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), 25_000)
+        .tap_leaf(KeychainKind::External, 0);
+    assert_matches!(
+        builder.finish(),
+        Err(Error::InvalidTapLeaf(KeychainKind::External, 0))
+    );
+}
+
+#[test]
+fn test_taproot_tap_leaf_sign_with_internal_key() {
+    let (mut wallet, _) = get_funded_wallet("tr(tprv8ZgxMBicQKsPdDArR4xSAECuVxeX1jwwSXR4ApKbkYgZiziDc4LdBy2WvJeGDfUSE4UT4hHhbgEwbdq8ajjUHiKDegkwrNU6V55CxcxonVN/*,{pk(cPZzKuNmpuUjD1e8jUU4PVzy2b5LngbSip8mBsxf4e7rSFZVb4Uh),pk(8aee2b8120a5f157f1223f72b5e62b825831a27a9fdf427db7cc697494d4a642)})");
+    let addr = wallet.get_address(New);
+
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), 25_000)
+        .tap_leaf(KeychainKind::External, 0);
+    let mut psbt = builder.finish().unwrap();
+
+    let internal_key = psbt.inputs[0].tap_internal_key.unwrap();
+    assert!(psbt.inputs[0].tap_key_origins.contains_key(&internal_key));
+
+    // the internal key still signs for the key path
+    let finalized = wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+    assert!(finalized);
+    assert!(psbt.inputs[0].tap_key_sig.is_some());
+    let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+    assert_eq!(witness.len(), 1);
+}
+
+#[test]
+fn test_taproot_tap_leaf_satisfaction_weight() {
+    // the second leaf needs two signatures, so it's heavier than the first one
+    let (mut wallet, _) = get_funded_wallet("tr(b511bd5771e47ee27558b1765e87b541668304ec567721c7b880edc0a010da55,{pk(8aee2b8120a5f157f1223f72b5e62b825831a27a9fdf427db7cc697494d4a642),and_v(v:pk(cPZzKuNmpuUjD1e8jUU4PVzy2b5LngbSip8mBsxf4e7rSFZVb4Uh),pk(cNaQCDwmmh4dS9LzCgVtyy1e1xjCJ21GUDHe9K98nzb689JvinGV))})");
+    let addr = wallet.get_address(New);
+
+    let fee_for_leaf = |wallet: &mut Wallet, leaf: Option<usize>| {
+        let mut builder = wallet.build_tx();
+        builder
+            .add_recipient(addr.script_pubkey(), 25_000)
+            .fee_rate(FeeRate::from_sat_per_vb(10.0));
+        if let Some(leaf) = leaf {
+            builder.tap_leaf(KeychainKind::External, leaf);
+        }
+        let psbt = builder.finish().unwrap();
+        psbt.fee_amount().unwrap()
+    };
+
+    let max_fee = fee_for_leaf(&mut wallet, None);
+    let light_leaf_fee = fee_for_leaf(&mut wallet, Some(0));
+    let heavy_leaf_fee = fee_for_leaf(&mut wallet, Some(1));
+
+    assert!(light_leaf_fee < heavy_leaf_fee);
+    assert_eq!(heavy_leaf_fee, max_fee);
+}
+
+fn get_test_htlc(preimage: &[u8; 32]) -> String {
+    let hash = bitcoin::hashes::sha256::Hash::hash(preimage);
+    format!("wsh(and_v(v:pk(tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/*),sha256({})))", hash)
+}
+
+#[test]
+fn test_sign_htlc_with_preimage() {
+    let preimage = [0x42; 32];
+    let (mut wallet, _) = get_funded_wallet(&get_test_htlc(&preimage));
+    let addr = wallet.get_address(New);
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let mut psbt = builder.finish().unwrap();
+
+    // without the preimage the input is signed but can't be finalized
+    let finalized = wallet.sign(&mut psbt, Default::default()).unwrap();
+    assert!(!finalized);
+    assert!(psbt.inputs[0].sha256_preimages.is_empty());
+
+    wallet.add_preimage(KeychainKind::External, preimage);
+    let finalized = wallet.sign(&mut psbt, Default::default()).unwrap();
+    assert!(finalized);
+
+    let extracted = psbt.extract_tx();
+    assert_eq!(extracted.input[0].witness.len(), 3);
+    assert_eq!(extracted.input[0].witness.nth(0).unwrap(), &preimage[..]);
+}
+
+#[test]
+fn test_preimages_debug_redacts_secrets() {
+    let preimage = [0x42; 32];
+    let mut preimages = bdk::signer::Preimages::new();
+    preimages.add(preimage);
+
+    let debug = format!("{:?}", preimages);
+    assert!(debug.contains(&bitcoin::hashes::sha256::Hash::hash(&preimage).to_string()));
+    assert!(!debug.contains("66, 66"));
+    assert!(!debug.contains(&"42".repeat(32)));
+}
+
+#[test]
+fn test_sign_htlc_with_preimage_in_sign_options() {
+    let preimage = [0x42; 32];
+    let (mut wallet, _) = get_funded_wallet(&get_test_htlc(&preimage));
+    let addr = wallet.get_address(New);
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let mut psbt = builder.finish().unwrap();
+
+    // unrelated preimages are never disclosed in the psbt
+    let mut preimages = bdk::signer::Preimages::new();
+    preimages.add([0x01; 32]);
+    preimages.add(preimage);
+    let finalized = wallet
+        .sign(
+            &mut psbt,
+            SignOptions {
+                preimages,
+                try_finalize: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(!finalized);
+    let sha256_preimages = &psbt.inputs[0].sha256_preimages;
+    assert_eq!(sha256_preimages.len(), 1);
+    assert_eq!(
+        sha256_preimages.get(&bitcoin::hashes::sha256::Hash::hash(&preimage)),
+        Some(&preimage.to_vec())
+    );
+    assert!(psbt.inputs[0].hash160_preimages.is_empty());
+
+    let finalized = wallet.finalize_psbt(&mut psbt, Default::default()).unwrap();
+    assert!(finalized);
+}
+
+#[test]
+fn test_htlc_policy_preimage_contribution() {
+    use bdk::descriptor::policy::{Satisfaction, SatisfiableItem};
+
+    let preimage = [0x42; 32];
+    let (mut wallet, _) = get_funded_wallet(&get_test_htlc(&preimage));
+
+    let policy = wallet.policies(KeychainKind::External).unwrap().unwrap();
+    let hash_policy = match &policy.item {
+        SatisfiableItem::Thresh {
+            items,
+            threshold: 2,
+        } => items[1].clone(),
+        _ => panic!("unexpected policy"),
+    };
+    assert_matches!(hash_policy.item, SatisfiableItem::Sha256Preimage { .. });
+    assert_matches!(hash_policy.contribution, Satisfaction::None);
+    assert_matches!(policy.contribution, Satisfaction::Partial { .. });
+
+    wallet.add_preimage(KeychainKind::External, preimage);
+    let policy = wallet.policies(KeychainKind::External).unwrap().unwrap();
+    assert_matches!(policy.contribution, Satisfaction::PartialComplete { .. });
+}
+
+#[test]
+fn test_policy_satisfaction_checks_signatures() {
+    use bdk::descriptor::policy::{BuildSatisfaction, Satisfaction};
+    use bdk::descriptor::ExtractPolicy;
+    use bdk::signer::SignersContainer;
+
+    let (mut wallet, _) = get_funded_wallet("wsh(multi(2,tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/*,tpubD6NzVbkrYhZ4Xferm7Pz4VnjdcDPFyjVu5K4iZXQ4pVN8Cks4pHVowTBXBKRhX64pkRyJZJN5xAKj4UDNnLPb5p2sSKXhewoYx5GbTdUFWq/*))");
+    let addr = wallet.get_address(New);
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let mut psbt = builder.finish().unwrap();
+    let finalized = wallet.sign(&mut psbt, Default::default()).unwrap();
+    assert!(!finalized);
+
+    let descriptor = wallet.public_descriptor(KeychainKind::External).unwrap();
+    let satisfaction = |psbt: &psbt::PartiallySignedTransaction| {
+        descriptor
+            .extract_policy(
+                &SignersContainer::default(),
+                BuildSatisfaction::Psbt(psbt),
+                wallet.secp_ctx(),
+            )
+            .unwrap()
+            .unwrap()
+            .satisfaction
+    };
+    assert_matches!(satisfaction(&psbt), Satisfaction::Partial { items, .. } if items == vec![0]);
+
+    // a signature that doesn't verify isn't counted
+    let mut bogus_psbt = psbt.clone();
+    for sig in bogus_psbt.inputs[0].partial_sigs.values_mut() {
+        let mut compact = sig.sig.serialize_compact();
+        compact[63] ^= 0x01;
+        sig.sig = bitcoin::secp256k1::ecdsa::Signature::from_compact(&compact).unwrap();
+    }
+    assert_matches!(satisfaction(&bogus_psbt), Satisfaction::Partial { items, .. } if items.is_empty());
+
+    // neither is a signature made with a key that isn't derived as the descriptor requires
+    let mut wrong_key_psbt = psbt.clone();
+    let signed_key = wrong_key_psbt.inputs[0]
+        .partial_sigs
+        .keys()
+        .next()
+        .unwrap()
+        .inner;
+    wrong_key_psbt.inputs[0]
+        .bip32_derivation
+        .get_mut(&signed_key)
+        .unwrap()
+        .1 = bitcoin::bip32::DerivationPath::from_str("m/42").unwrap();
+    assert_matches!(satisfaction(&wrong_key_psbt), Satisfaction::Partial { items, .. } if items.is_empty());
+}
+
+#[test]
+fn test_coordinator_multisig_workflow() {
+    use bdk::descriptor::policy::PkOrF;
+    use bdk::wallet::coordinator::{Coordinator, CoordinatorError};
+    use bitcoin::bip32::{ExtendedPrivKey, ExtendedPubKey};
+
+    const TPRVS: [&str; 3] = [
+        "tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS",
+        "tprv8ZgxMBicQKsPdZXrcHNLf5JAJWFAoJ2TrstMRdSKtEggz6PddbuSkvHKM9oKJyFgZV1B7rw8oChspxyYbtmEXYyg1AjfWbL3ho3XHDpHRZf",
+        "tprv8ZgxMBicQKsPdpkqS7Eair4YxjcuuvDPNYmKX3sCniCf16tHEVrjjiSXEkFRnUH77yXc6ZcwHHcLNfjdi5qUvw3VDfgYiH5mNsj5izuiu2N",
+    ];
+    let secp = bitcoin::secp256k1::Secp256k1::new();
+    let tprvs = TPRVS
+        .iter()
+        .map(|tprv| ExtendedPrivKey::from_str(tprv).unwrap())
+        .collect::<Vec<_>>();
+    let fingerprints = tprvs
+        .iter()
+        .map(|tprv| PkOrF::Fingerprint(tprv.fingerprint(&secp)))
+        .collect::<Vec<_>>();
+    // the descriptor of the cosigner holding the private key at `signer`
+    let descriptor = |signer: usize| {
+        let keys = tprvs
+            .iter()
+            .enumerate()
+            .map(|(i, tprv)| {
+                if i == signer {
+                    format!("{}/*", tprv)
+                } else {
+                    format!("{}/*", ExtendedPubKey::from_priv(&secp, tprv))
+                }
+            })
+            .collect::<Vec<_>>();
+        format!("wsh(multi(2,{}))", keys.join(","))
+    };
+
+    let (mut wallet, _) = get_funded_wallet(&descriptor(0));
+    let cosigner = Wallet::new_no_persist(&descriptor(1), None, Network::Regtest).unwrap();
+
+    let addr = wallet.get_address(New);
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let psbt = builder.finish().unwrap();
+    let sign_options = SignOptions {
+        try_finalize: false,
+        ..Default::default()
+    };
+
+    let mut coordinator = Coordinator::new();
+    let txid = coordinator.track(psbt.clone()).unwrap();
+    wallet
+        .sign(coordinator.get_mut(&txid).unwrap(), sign_options.clone())
+        .unwrap();
+
+    let status = coordinator.signing_status(&wallet, &txid).unwrap();
+    assert!(!status.is_complete());
+    let input = status.inputs[0].as_ref().unwrap();
+    assert_eq!(input.keychain, KeychainKind::External);
+    assert_eq!(input.signed, vec![fingerprints[0].clone()]);
+    assert_eq!(status.missing_keys(), fingerprints[1..].to_vec());
+    assert_matches!(
+        coordinator.finalize(&wallet, &txid, SignOptions::default()),
+        Err(CoordinatorError::ThresholdNotMet)
+    );
+
+    // the cosigner signs its own copy of the psbt
+    let mut cosigner_psbt = psbt;
+    cosigner.sign(&mut cosigner_psbt, sign_options).unwrap();
+    assert_eq!(coordinator.combine(cosigner_psbt).unwrap(), txid);
+
+    let status = coordinator.signing_status(&wallet, &txid).unwrap();
+    assert!(status.is_complete());
+    assert!(status.missing_keys().is_empty());
+    assert_eq!(
+        status.inputs[0].as_ref().unwrap().signed,
+        fingerprints[..2].to_vec()
+    );
+
+    let tx = coordinator
+        .finalize(&wallet, &txid, SignOptions::default())
+        .unwrap();
+    assert_eq!(tx.txid(), txid);
+    // the dummy element, two signatures and the witness script
+    assert_eq!(tx.input[0].witness.len(), 4);
+    assert!(coordinator.get(&txid).is_none());
+}
+
+#[test]
+fn test_coordinator_track_inconsistent_psbt() {
+    use bdk::wallet::coordinator::{Coordinator, CoordinatorError};
+    use bitcoin::bip32::{DerivationPath, ExtendedPubKey, Fingerprint};
+
+    let (mut wallet, _) = get_funded_wallet(get_test_wpkh());
+    let addr = wallet.get_address(New);
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let psbt = builder.finish().unwrap();
+
+    let xpub = ExtendedPubKey::from_str("tpubD6NzVbkrYhZ4XHndKkuB8FifXm8r5FQHwrN6oZuWCz13qb93rtgKvD4PQsqC4HP4yhV3tA2fqr2RbY5mNXfM7RxXUoeABoDtsFUq2zJq6YK").unwrap();
+    // the same xpub with two unrelated derivation paths
+    let fingerprint = Fingerprint::from([0x01; 4]);
+    let mut tracked = psbt.clone();
+    tracked.xpub.insert(
+        xpub,
+        (
+            fingerprint,
+            DerivationPath::from_str("m/48'/1'/0'/2'").unwrap(),
+        ),
+    );
+    let mut inconsistent = psbt;
+    inconsistent.xpub.insert(
+        xpub,
+        (
+            fingerprint,
+            DerivationPath::from_str("m/84'/1'/0'/0'").unwrap(),
+        ),
+    );
+
+    let mut coordinator = Coordinator::new();
+    let txid = coordinator.track(tracked.clone()).unwrap();
+    assert_matches!(
+        coordinator.track(inconsistent.clone()),
+        Err(CoordinatorError::Combine(
+            bitcoin::psbt::Error::CombineInconsistentKeySources(_)
+        ))
+    );
+    assert_matches!(
+        coordinator.combine(inconsistent),
+        Err(CoordinatorError::Combine(_))
+    );
+    assert_eq!(coordinator.get(&txid), Some(&tracked));
+}
+
+#[test]
+fn test_musig_key_spend() {
+    use bdk::signer::SignerOrdering;
+    use bdk::wallet::musig::{psbt_partial_sigs, psbt_pub_nonces, KeyAggContext, MusigSigner};
+    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use std::sync::Arc;
+
+    let secp = Secp256k1::new();
+    let secret_keys = [
+        SecretKey::from_slice(&[0x01; 32]).unwrap(),
+        SecretKey::from_slice(&[0x02; 32]).unwrap(),
+    ];
+    let key_agg = KeyAggContext::new(
+        &secp,
+        secret_keys
+            .iter()
+            .map(|sk| PublicKey::from_secret_key(&secp, sk))
+            .collect(),
+    )
+    .unwrap();
+    let descriptor = format!("tr({}/0/*)", key_agg.to_xpub(Network::Regtest));
+
+    let (mut wallet, _) = get_funded_wallet(&descriptor);
+    let mut cosigner = Wallet::new_no_persist(&descriptor, None, Network::Regtest).unwrap();
+    for (wallet, secret_key) in [&mut wallet, &mut cosigner].into_iter().zip(secret_keys) {
+        let signer = MusigSigner::new(&secp, secret_key, key_agg.clone()).unwrap();
+        wallet.add_signer(
+            KeychainKind::External,
+            SignerOrdering::default(),
+            Arc::new(signer),
+        );
+    }
+
+    let addr = wallet.get_address(New);
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let mut psbt = builder.finish().unwrap();
+    let internal_key = psbt.inputs[0].tap_internal_key.unwrap();
+    let (_, (_, path)) = &psbt.inputs[0].tap_key_origins[&internal_key];
+    let agg_pubkey = key_agg.derive(&secp, path).unwrap().aggregated_pubkey();
+
+    // first round: both participants add their nonce
+    assert!(!wallet.sign(&mut psbt, SignOptions::default()).unwrap());
+    let mut cosigner_psbt = psbt.clone();
+    assert!(!cosigner
+        .sign(&mut cosigner_psbt, SignOptions::default())
+        .unwrap());
+    psbt.combine(cosigner_psbt).unwrap();
+    assert_eq!(psbt_pub_nonces(&psbt.inputs[0], &agg_pubkey).len(), 2);
+    assert!(psbt_partial_sigs(&psbt.inputs[0], &agg_pubkey).is_empty());
+    let first_round_psbt = psbt.clone();
+
+    // second round: the cosigner adds its partial signature, then the wallet adds its own and
+    // aggregates them
+    let mut cosigner_psbt = psbt.clone();
+    assert!(!cosigner
+        .sign(&mut cosigner_psbt, SignOptions::default())
+        .unwrap());
+    assert_eq!(
+        psbt_partial_sigs(&cosigner_psbt.inputs[0], &agg_pubkey).len(),
+        1
+    );
+    assert!(cosigner_psbt.inputs[0].tap_key_sig.is_none());
+    psbt.combine(cosigner_psbt).unwrap();
+
+    assert!(wallet.sign(&mut psbt, SignOptions::default()).unwrap());
+    let tx = psbt.extract_tx();
+    assert_eq!(tx.input[0].witness.len(), 1);
+
+    // the secret nonce is forgotten once used, so it can't be reused for a partial signature
+    let mut psbt = first_round_psbt;
+    assert_matches!(
+        cosigner.sign(&mut psbt, SignOptions::default()),
+        Err(Error::Signer(SignerError::Musig(
+            bdk::wallet::musig::MusigError::MissingSecretNonce
+        )))
+    );
+}
+
+#[test]
+fn test_silent_payment_send_and_spend() {
+    use bdk::signer::SignerOrdering;
+    use bdk::wallet::silent_payments::{
+        SilentPaymentAddress, SilentPaymentError, SilentPaymentOutput, SilentPaymentReceiver,
+    };
+    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use std::sync::Arc;
+
+    let secp = Secp256k1::new();
+    let spend_key = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let mut receiver = SilentPaymentReceiver::new(
+        &secp,
+        Network::Regtest,
+        SecretKey::from_slice(&[0x01; 32]).unwrap(),
+        PublicKey::from_secret_key(&secp, &spend_key),
+    );
+    let address = receiver.address();
+
+    // mainnet addresses are rejected
+    let (mut wallet, _) = get_funded_wallet(get_test_wpkh());
+    let mainnet_address =
+        SilentPaymentAddress::new(Network::Bitcoin, address.scan_pubkey, address.spend_pubkey);
+    let mut builder = wallet.build_tx();
+    builder.add_silent_payment_recipient(mainnet_address, 25_000);
+    assert_matches!(
+        builder.finish(),
+        Err(Error::SilentPayment(SilentPaymentError::InvalidNetwork(
+            Network::Regtest
+        )))
+    );
+
+    let mut builder = wallet.build_tx();
+    builder.add_silent_payment_recipient(address, 25_000);
+    let mut psbt = builder.finish().unwrap();
+    let prevouts = (0..psbt.inputs.len())
+        .map(|i| psbt.get_utxo_for(i).unwrap())
+        .collect::<Vec<_>>();
+    assert!(wallet.sign(&mut psbt, SignOptions::default()).unwrap());
+    let tx = psbt.extract_tx();
+
+    let found = receiver.scan_tx(&secp, &tx, &prevouts);
+    assert_eq!(found.len(), 1);
+    let output = receiver.outputs()[&found[0]].clone();
+
+    // inputs whose key we can't sign for can't be used to derive the outputs
+    let (mut watch_only, _) = get_funded_wallet(get_test_tr_with_taptree());
+    let mut builder = watch_only.build_tx();
+    builder.add_silent_payment_recipient(address, 25_000);
+    assert!(matches!(
+        builder.finish(),
+        Err(Error::SilentPayment(SilentPaymentError::MissingInputKey(_)))
+    ));
+
+    // taproot inputs contribute their tweaked key
+    let (mut tr_wallet, _) = get_funded_wallet(get_test_tr_with_taptree_xprv());
+    let mut builder = tr_wallet.build_tx();
+    builder.add_silent_payment_recipient(address, 25_000);
+    let mut tr_psbt = builder.finish().unwrap();
+    let tr_prevouts = (0..tr_psbt.inputs.len())
+        .map(|i| tr_psbt.get_utxo_for(i).unwrap())
+        .collect::<Vec<_>>();
+    assert!(tr_wallet
+        .sign(&mut tr_psbt, SignOptions::default())
+        .unwrap());
+    let mut tr_receiver = receiver.clone();
+    assert_eq!(
+        tr_receiver
+            .scan_tx(&secp, &tr_psbt.extract_tx(), &tr_prevouts)
+            .len(),
+        1
+    );
+    assert_eq!(output.txout.value, 25_000);
+    assert_eq!(output.label, None);
+
+    // spend the output with another wallet
+    let (mut spender, _) = get_funded_wallet(get_test_tr_single_sig());
+    spender.add_signer(
+        KeychainKind::External,
+        SignerOrdering::default(),
+        Arc::new(receiver.signer(&secp, spend_key).unwrap()),
+    );
+    let addr = spender.get_address(New);
+    let mut builder = spender.build_tx();
+    builder
+        .add_foreign_utxo(
+            found[0],
+            output.psbt_input(),
+            SilentPaymentOutput::SATISFACTION_WEIGHT,
+        )
+        .unwrap()
+        .manually_selected_only()
+        .drain_to(addr.script_pubkey());
+    let mut psbt = builder.finish().unwrap();
+    let sign_options = SignOptions {
+        trust_witness_utxo: true,
+        ..Default::default()
+    };
+    assert!(spender.sign(&mut psbt, sign_options).unwrap());
+    assert_eq!(psbt.extract_tx().input[0].witness.len(), 1);
+}
+
+#[test]
+fn test_payment_uri() {
+    use bdk::wallet::payment_uri::{PaymentUri, PaymentUriError};
+
+    let (mut receiver, _) = get_funded_wallet(get_test_wpkh());
+    let uri = receiver.get_payment_uri(New, Some(25_000), Some("Alice".to_string()));
+    let address = receiver.get_address(Peek(0)).address;
+    assert_eq!(
+        uri.to_string(),
+        format!("bitcoin:{}?amount=0.00025&label=Alice", address)
+    );
+
+    let (mut wallet, _) = get_funded_wallet(get_test_wpkh());
+    let uri = PaymentUri::from_str(&uri.to_string()).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.add_payment_uri(&uri).unwrap();
+    let psbt = builder.finish().unwrap();
+    assert!(psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .any(|txout| txout.script_pubkey == address.script_pubkey() && txout.value == 25_000));
+
+    // the address must be valid for the wallet's network
+    let mainnet =
+        PaymentUri::from_str("bitcoin:bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq?amount=0.00025")
+            .unwrap();
+    assert!(matches!(
+        wallet.build_tx().add_payment_uri(&mainnet),
+        Err(Error::PaymentUri(PaymentUriError::Address(_)))
+    ));
+
+    // and it must request an amount
+    let mut no_amount = uri;
+    no_amount.amount = None;
+    assert!(matches!(
+        wallet.build_tx().add_payment_uri(&no_amount),
+        Err(Error::PaymentUri(PaymentUriError::MissingAmount))
+    ));
+}
+
+#[test]
+fn test_payjoin() {
+    use bdk::wallet::payjoin::{
+        error_response, PayjoinError, PayjoinParams, PayjoinRequest, PayjoinTransport, SendError,
+    };
+    use core::cell::RefCell;
+
+    // in-process stand-in for the HTTP endpoint of the receiver, which can tamper with the
+    // proposal before returning it
+    struct Endpoint<'a> {
+        receiver: RefCell<&'a mut Wallet>,
+        tamper: fn(&mut psbt::PartiallySignedTransaction),
+    }
+
+    impl PayjoinTransport for Endpoint<'_> {
+        type Error = core::convert::Infallible;
+
+        fn post(&self, url: &str, body: &str) -> Result<String, Self::Error> {
+            let (_, query) = url.split_once('?').unwrap();
+            let mut receiver = self.receiver.borrow_mut();
+            Ok(
+                match receiver.process_payjoin(query, body, SignOptions::default()) {
+                    Ok(mut psbt) => {
+                        (self.tamper)(&mut psbt);
+                        psbt.to_string()
+                    }
+                    Err(err) => error_response(&err),
+                },
+            )
+        }
+    }
+
+    let (mut receiver, _) =
+        get_funded_wallet("wpkh(cVbZ8ovhye9AoAHFsqobCf7LxbXDAECy9Kb8TZdfsDYMZGBUyCnm)");
+    let mut uri = receiver.get_payment_uri(New, Some(25_000), None);
+    uri.extras
+        .push(("pj".to_string(), "https://example.com/pj".to_string()));
+    let payment_script = uri.address.payload.script_pubkey();
+
+    let (mut sender, _) = get_funded_wallet(get_test_wpkh());
+    let mut builder = sender.build_tx();
+    builder.add_payment_uri(&uri).unwrap();
+    let mut psbt = builder.finish().unwrap();
+    assert!(sender.sign(&mut psbt, SignOptions::default()).unwrap());
+    let original_fee = psbt.fee_amount().unwrap();
+    let change = psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .position(|txout| sender.is_mine(&txout.script_pubkey))
+        .unwrap();
+    let change_value = psbt.unsigned_tx.output[change].value;
+    let params = PayjoinParams {
+        additional_fee_output_index: Some(change),
+        max_additional_fee_contribution: 1_000,
+        ..Default::default()
+    };
+    let request = PayjoinRequest::new(psbt.clone(), &uri, params).unwrap();
+    assert_eq!(
+        request.url(),
+        format!(
+            "https://example.com/pj?v=1&additionalfeeoutputindex={}&maxadditionalfeecontribution=1000",
+            change
+        )
+    );
+
+    // a receiver can't take more than allowed from the change
+    let tampering = Endpoint {
+        receiver: RefCell::new(&mut receiver),
+        tamper: |psbt| {
+            for txout in &mut psbt.unsigned_tx.output {
+                txout.value = txout.value.wrapping_add(2_000);
+            }
+        },
+    };
+    assert!(matches!(
+        sender.send_payjoin(&request, &tampering, SignOptions::default()),
+        Err(SendError::Proposal(Error::Payjoin(
+            PayjoinError::InvalidProposal(_)
+        )))
+    ));
+
+    // nor more than the fees of its input at the fee rate of the original transaction, even if
+    // within the maximum allowed by the sender
+    let tampering = Endpoint {
+        receiver: RefCell::new(&mut receiver),
+        tamper: |psbt| {
+            let outputs = &mut psbt.unsigned_tx.output;
+            outputs.sort_by_key(|txout| txout.value);
+            outputs[0].value -= 500;
+            outputs[1].value += 500;
+        },
+    };
+    assert!(matches!(
+        sender.send_payjoin(&request, &tampering, SignOptions::default()),
+        Err(SendError::Proposal(Error::Payjoin(
+            PayjoinError::InvalidProposal(
+                "the receiver took more than the fees of its inputs at the original fee rate"
+            )
+        )))
+    ));
+
+    let endpoint = Endpoint {
+        receiver: RefCell::new(&mut receiver),
+        tamper: |_| {},
+    };
+    let payjoin = sender
+        .send_payjoin(&request, &endpoint, SignOptions::default())
+        .unwrap();
+    let fee = payjoin.fee_amount().unwrap();
+    let tx = payjoin.extract_tx();
+    assert_eq!(tx.input.len(), 2);
+    assert!(tx
+        .input
+        .iter()
+        .any(|txin| txin.previous_output == psbt.unsigned_tx.input[0].previous_output));
+    assert!(tx.input.iter().all(|txin| !txin.witness.is_empty()));
+    // the sender pays for the fees of the receiver's input out of its change
+    let contribution = change_value - tx.output[change].value;
+    assert!(contribution > 0 && contribution <= 1_000);
+    assert!(fee >= original_fee + contribution);
+    // and the receiver gets the payment together with the value of its input
+    let payment = tx
+        .output
+        .iter()
+        .find(|txout| txout.script_pubkey == payment_script)
+        .unwrap();
+    assert_eq!(
+        payment.value,
+        25_000 + 50_000 - (fee - original_fee - contribution)
+    );
+
+    // a receiver without inputs to contribute returns an error, the original must be broadcast
+    let mut empty = Wallet::new_no_persist(
+        "wpkh(cVbZ8ovhye9AoAHFsqobCf7LxbXDAECy9Kb8TZdfsDYMZGBUyCnm)",
+        None,
+        Network::Regtest,
+    )
+    .unwrap();
+    assert_eq!(empty.get_address(New).script_pubkey(), payment_script);
+    let endpoint = Endpoint {
+        receiver: RefCell::new(&mut empty),
+        tamper: |_| {},
+    };
+    match sender.send_payjoin(&request, &endpoint, SignOptions::default()) {
+        Err(SendError::Proposal(Error::Payjoin(PayjoinError::Receiver { code, .. }))) => {
+            assert_eq!(code, "unavailable")
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert_eq!(request.original_tx(), psbt.clone().extract_tx());
+
+    uri.extras.clear();
+    assert!(matches!(
+        PayjoinRequest::new(psbt, &uri, PayjoinParams::default()),
+        Err(PayjoinError::MissingEndpoint)
+    ));
+}
+
+#[test]
+fn test_proof_of_reserves() {
+    use bdk::wallet::reserves::ProofError;
+
+    let (mut wallet, _) = get_funded_wallet(get_test_wpkh());
+    receive_output(
+        &mut wallet,
+        25_000,
+        ConfirmationTime::Confirmed {
+            height: 2_000,
+            time: 0,
+        },
+    );
+    let message = "This is a proof of reserves";
+
+    let unsigned = wallet.create_proof(message).unwrap();
+    assert_eq!(unsigned.unsigned_tx.input.len(), 3);
+    assert_eq!(unsigned.unsigned_tx.output.len(), 1);
+    assert_matches!(
+        wallet.verify_proof(&unsigned, message),
+        Err(Error::Proof(ProofError::NotFinalized(1)))
+    );
+
+    let mut proof = unsigned.clone();
+    let finalized = wallet.sign(&mut proof, SignOptions::default()).unwrap();
+    assert!(finalized);
+    assert_eq!(wallet.verify_proof(&proof, message).unwrap(), 75_000);
+    assert_matches!(
+        wallet.verify_proof(&proof, "Another message"),
+        Err(Error::Proof(ProofError::ChallengeInputMismatch))
+    );
+
+    let mut tampered = proof.clone();
+    tampered.unsigned_tx.output[0].value += 1;
+    assert_matches!(
+        wallet.verify_proof(&tampered, message),
+        Err(Error::Proof(ProofError::InAndOutValueNotEqual))
+    );
+    let mut tampered = proof.clone();
+    tampered.unsigned_tx.output[0].script_pubkey = wallet.get_address(New).script_pubkey();
+    assert_matches!(
+        wallet.verify_proof(&tampered, message),
+        Err(Error::Proof(ProofError::InvalidOutput))
+    );
+
+    // an output spent twice can't be counted twice
+    let mut duplicated = unsigned.clone();
+    let input = duplicated.unsigned_tx.input[1].clone();
+    duplicated.unsigned_tx.input.push(input);
+    let psbt_input = duplicated.inputs[1].clone();
+    duplicated.inputs.push(psbt_input);
+    duplicated.unsigned_tx.output[0].value +=
+        duplicated.inputs[1].witness_utxo.as_ref().unwrap().value;
+    wallet
+        .sign(&mut duplicated, SignOptions::default())
+        .unwrap();
+    assert_matches!(
+        wallet.verify_proof(&duplicated, message),
+        Err(Error::Proof(ProofError::DuplicateInput(3)))
+    );
+
+    // signatures that don't commit to the whole proof are rejected
+    let mut proof_none = unsigned;
+    for input in proof_none.inputs.iter_mut().skip(1) {
+        input.sighash_type = Some(EcdsaSighashType::None.into());
+    }
+    let sign_options = SignOptions {
+        allow_all_sighashes: true,
+        ..Default::default()
+    };
+    wallet.sign(&mut proof_none, sign_options).unwrap();
+    assert_matches!(
+        wallet.verify_proof(&proof_none, message),
+        Err(Error::Proof(ProofError::UnsupportedSighashType(1)))
+    );
+
+    // the proof is no longer valid once one of its UTXOs is spent
+    let spent = proof.unsigned_tx.input[1].previous_output;
+    let spending_tx = Transaction {
+        version: 1,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: spent,
+            ..Default::default()
+        }],
+        output: vec![],
+    };
+    wallet
+        .insert_tx(spending_tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+    assert_matches!(
+        wallet.verify_proof(&proof, message),
+        Err(Error::Proof(ProofError::NonSpendableInput(1)))
+    );
+}
+
+#[test]
+fn test_sign_message() {
+    use bdk::wallet::message::{verify_message, MessageError, MessageFormat, MessageSignature};
+
+    let secp = bitcoin::secp256k1::Secp256k1::new();
+    let message = "Hello World";
+
+    for (descriptor, formats) in [
+        (
+            get_test_wpkh(),
+            &[MessageFormat::Simple, MessageFormat::Full][..],
+        ),
+        (
+            get_test_tr_single_sig(),
+            &[MessageFormat::Simple, MessageFormat::Full],
+        ),
+        (get_test_tr_with_taptree(), &[MessageFormat::Simple]),
+        (
+            "sh(wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW))",
+            &[MessageFormat::Full],
+        ),
+        (
+            "pkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)",
+            &[MessageFormat::Full],
+        ),
+    ] {
+        let (mut wallet, _) = get_funded_wallet(descriptor);
+        let address = wallet.get_address(New).address;
+        for &format in formats {
+            let signature = wallet
+                .sign_message(&address, message, format, SignOptions::default())
+                .unwrap();
+            assert_eq!(signature.format(), format);
+            let signature = MessageSignature::from_str(&signature.to_string()).unwrap();
+            assert!(verify_message(&secp, &address, message, &signature).is_ok());
+            assert_matches!(
+                verify_message(&secp, &address, "Another message", &signature),
+                Err(MessageError::SignatureValidation(_)) | Err(MessageError::InvalidToSign(_))
+            );
+        }
+    }
+
+    // the simple format needs a native segwit address
+    let (mut wallet, _) =
+        get_funded_wallet("pkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)");
+    let address = wallet.get_address(New).address;
+    assert_matches!(
+        wallet.sign_message(
+            &address,
+            message,
+            MessageFormat::Simple,
+            SignOptions::default()
+        ),
+        Err(Error::Message(MessageError::UnsupportedFormat))
+    );
+
+    let foreign = Address::from_str("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080")
+        .unwrap()
+        .assume_checked();
+    assert_matches!(
+        wallet.sign_message(
+            &foreign,
+            message,
+            MessageFormat::Full,
+            SignOptions::default()
+        ),
+        Err(Error::Message(MessageError::UnknownAddress))
+    );
+}
+
+#[test]
+fn test_sweep() {
+    use bdk::miniscript::psbt::PsbtExt;
+    use bdk::wallet::sweep::{Sweep, SweepError, UtxoSource};
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::PrivateKey;
+
+    struct MockUtxoSource(Vec<Transaction>);
+
+    impl UtxoSource for MockUtxoSource {
+        type Error = core::convert::Infallible;
+
+        fn list_unspent(
+            &self,
+            script_pubkey: &bitcoin::Script,
+        ) -> Result<Vec<(OutPoint, Transaction)>, Self::Error> {
+            let mut utxos = vec![];
+            for tx in &self.0 {
+                for (vout, txout) in tx.output.iter().enumerate() {
+                    if txout.script_pubkey.as_script() == script_pubkey {
+                        utxos.push((OutPoint::new(tx.txid(), vout as u32), tx.clone()));
+                    }
+                }
+            }
+            Ok(utxos)
+        }
+    }
+
+    let secp = Secp256k1::new();
+    let wif = "cVbZ8ovhye9AoAHFsqobCf7LxbXDAECy9Kb8TZdfsDYMZGBUyCnm";
+    let public_key = PrivateKey::from_wif(wif).unwrap().public_key(&secp);
+    let paper_wallet = Transaction {
+        version: 1,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![],
+        output: vec![
+            TxOut {
+                value: 10_000,
+                script_pubkey: ScriptBuf::new_p2pkh(&public_key.pubkey_hash()),
+            },
+            TxOut {
+                value: 20_000,
+                script_pubkey: ScriptBuf::new_v0_p2wpkh(&public_key.wpubkey_hash().unwrap()),
+            },
+            TxOut {
+                value: 30_000,
+                script_pubkey: Address::p2tr(
+                    &secp,
+                    public_key.inner.into(),
+                    None,
+                    Network::Regtest,
+                )
+                .script_pubkey(),
+            },
+        ],
+    };
+    let source = MockUtxoSource(vec![paper_wallet]);
+
+    let (mut wallet, _) = get_funded_wallet(get_test_wpkh());
+    let mut sweep = Sweep::new(&[wif], Network::Regtest).unwrap();
+    assert_matches!(
+        wallet.sweep(&sweep, FeeRate::from_sat_per_vb(1.0)),
+        Err(Error::Sweep(SweepError::NoUtxos))
+    );
+
+    sweep.sync(&source).unwrap();
+    assert_eq!(sweep.balance(), 60_000);
+    let psbt = wallet.sweep(&sweep, FeeRate::from_sat_per_vb(1.0)).unwrap();
+    assert_eq!(psbt.inputs.len(), 3);
+    assert!(psbt
+        .inputs
+        .iter()
+        .all(|input| input.final_script_sig.is_some() || input.final_script_witness.is_some()));
+    let fee = psbt.fee_amount().unwrap();
+    assert!(fee > 0);
+
+    // the signatures are valid, and all the funds go to the wallet
+    let tx = psbt.extract(&secp).unwrap();
+    assert_eq!(tx.output.len(), 1);
+    assert!(wallet.is_mine(&tx.output[0].script_pubkey));
+    assert_eq!(tx.output[0].value, 60_000 - fee);
+
+    assert_matches!(
+        Sweep::new(&[get_test_tr_single_sig_xprv()], Network::Regtest),
+        Err(Error::Sweep(SweepError::NotSingleKey))
+    );
+    assert_matches!(
+        Sweep::new(
+            &["wpkh(02e96fe52ef0e22d2f131dd425ce1893073a3c6ad20e8cac36726393dfb4856a4c)"],
+            Network::Regtest
+        ),
+        Err(Error::Sweep(SweepError::MissingPrivateKey))
+    );
+}
+
+#[test]
+fn test_sweep_invalid_utxo() {
+    use bdk::wallet::sweep::{Sweep, SyncError, UtxoSource};
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::PrivateKey;
+
+    // Returns every transaction with the outpoint `vout` of it
+    struct MockUtxoSource(Vec<Transaction>, u32);
+
+    impl UtxoSource for MockUtxoSource {
+        type Error = core::convert::Infallible;
+
+        fn list_unspent(
+            &self,
+            _script_pubkey: &bitcoin::Script,
+        ) -> Result<Vec<(OutPoint, Transaction)>, Self::Error> {
+            Ok(self
+                .0
+                .iter()
+                .map(|tx| (OutPoint::new(tx.txid(), self.1), tx.clone()))
+                .collect())
+        }
+    }
+
+    let secp = Secp256k1::new();
+    let wif = "cVbZ8ovhye9AoAHFsqobCf7LxbXDAECy9Kb8TZdfsDYMZGBUyCnm";
+    let public_key = PrivateKey::from_wif(wif).unwrap().public_key(&secp);
+    let paper_wallet = Transaction {
+        version: 1,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![],
+        output: vec![TxOut {
+            value: 10_000,
+            script_pubkey: ScriptBuf::new_p2pkh(&public_key.pubkey_hash()),
+        }],
+    };
+    let txid = paper_wallet.txid();
+    let mut sweep = Sweep::new(&[wif], Network::Regtest).unwrap();
+
+    // the outpoint is not an output of the transaction
+    assert_matches!(
+        sweep.sync(&MockUtxoSource(vec![paper_wallet.clone()], 1)),
+        Err(SyncError::InvalidUtxo(outpoint)) if outpoint == OutPoint::new(txid, 1)
+    );
+    assert_eq!(sweep.balance(), 0);
+
+    // the output doesn't pay to the script being looked up: the P2PKH output is returned for the
+    // P2WPKH, P2SH-P2WPKH and P2TR scripts of the key too
+    assert_matches!(
+        sweep.sync(&MockUtxoSource(vec![paper_wallet], 0)),
+        Err(SyncError::InvalidUtxo(outpoint)) if outpoint == OutPoint::new(txid, 0)
+    );
+    assert_eq!(sweep.balance(), 0);
+}
@@ -7,10 +7,20 @@
 //! [`Emitter::next_block`] or/and [`Emitter::next_header`] until it returns `Ok(None)` (which means
 //! the chain tip is reached). A separate method, [`Emitter::mempool`] can be used to emit the whole
 //! mempool.
+//!
+//! When there are many blocks to emit (e.g. when syncing from a wallet birthday far in the past),
+//! [`Emitter::with_prefetch`] can be used to fetch blocks concurrently ahead of emission. A
+//! birthday timestamp can be mapped to the height to start from with [`height_at_time`].
 #![warn(missing_docs)]
 
-use bdk_chain::{local_chain::CheckPoint, BlockId};
-use bitcoin::{block::Header, Block, BlockHash, Transaction};
+use std::{collections::VecDeque, sync::mpsc};
+
+use bdk_chain::{
+    chain_source::{BroadcastError, Broadcaster, FeeEstimator, UtxoSource},
+    local_chain::CheckPoint,
+    BlockId, FeeRate,
+};
+use bitcoin::{block::Header, Block, BlockHash, OutPoint, Script, Transaction};
 pub use bitcoincore_rpc;
 use bitcoincore_rpc::bitcoincore_rpc_json;
 
@@ -40,6 +50,9 @@ pub struct Emitter<'c, C> {
     /// The last emitted block during our last mempool emission. This is used to determine whether
     /// there has been a reorg since our last mempool emission.
     last_mempool_tip: Option<u32>,
+
+    /// Fetches blocks ahead of emission if enabled with [`Emitter::with_prefetch`].
+    prefetcher: Option<Prefetcher>,
 }
 
 impl<'c, C: bitcoincore_rpc::RpcApi> Emitter<'c, C> {
@@ -54,6 +67,7 @@ impl<'c, C: bitcoincore_rpc::RpcApi> Emitter<'c, C> {
             last_block: None,
             last_mempool_time: 0,
             last_mempool_tip: None,
+            prefetcher: None,
         }
     }
 
@@ -69,9 +83,36 @@ impl<'c, C: bitcoincore_rpc::RpcApi> Emitter<'c, C> {
             last_block: None,
             last_mempool_time: 0,
             last_mempool_tip: None,
+            prefetcher: None,
         }
     }
 
+    /// Fetch blocks concurrently ahead of emission with the given `workers`.
+    ///
+    /// This speeds up [`next_block`] when there are many blocks to emit, such as when syncing from
+    /// a birthday height far below the chain tip. Each worker should be a separate RPC client, as a
+    /// single client sends requests one at a time. Blocks are requested from all workers at once,
+    /// and prefetching stops once the queued blocks reach `max_bytes` in serialized size. Memory
+    /// usage is therefore bounded by `max_bytes` plus one block per worker.
+    ///
+    /// Prefetched blocks are still emitted in order, and each must connect to the last emitted
+    /// block. If the best chain changes while blocks are queued, the queue is discarded and the
+    /// [`Emitter`] finds the point of agreement as usual. If a worker panics, prefetching is turned
+    /// off and the remaining blocks are fetched one at a time with the [`Emitter`]'s own client.
+    ///
+    /// [`next_block`]: Self::next_block
+    pub fn with_prefetch<W>(
+        mut self,
+        workers: impl IntoIterator<Item = W>,
+        max_bytes: usize,
+    ) -> Self
+    where
+        W: bitcoincore_rpc::RpcApi + Send + 'static,
+    {
+        self.prefetcher = Prefetcher::new(workers, max_bytes);
+        self
+    }
+
     /// Emit mempool transactions, alongside their first-seen unix timestamps.
     ///
     /// This method emits each transaction only once, unless we cannot guarantee the transaction's
@@ -146,8 +187,183 @@ impl<'c, C: bitcoincore_rpc::RpcApi> Emitter<'c, C> {
 
     /// Emit the next block height and block (if any).
     pub fn next_block(&mut self) -> Result<Option<(u32, Block)>, bitcoincore_rpc::Error> {
+        if let Some(item) = self.next_prefetched_block()? {
+            return Ok(Some(item));
+        }
         poll(self, |hash| self.client.get_block(hash))
     }
+
+    /// Emit the next prefetched block, refilling the prefetch queue if it is empty.
+    ///
+    /// Returns `Ok(None)` if prefetching is disabled, there is at most one block left to emit, or
+    /// the next prefetched block does not connect to the last emitted block. In these cases, the
+    /// caller should fall back to [`poll`].
+    fn next_prefetched_block(&mut self) -> Result<Option<(u32, Block)>, bitcoincore_rpc::Error> {
+        let prefetcher = match self.prefetcher.as_mut() {
+            Some(prefetcher) => prefetcher,
+            None => return Ok(None),
+        };
+
+        if prefetcher.queue.is_empty() {
+            let next_height = match &self.last_cp {
+                Some(cp) => cp.height() + 1,
+                None => self.start_height,
+            };
+            let tip_height = self.client.get_block_count()? as u32;
+            // prefetching only pays off if there is more than one block to fetch
+            if tip_height <= next_height {
+                return Ok(None);
+            }
+            if !prefetcher.fill(next_height, tip_height)? {
+                // a worker is gone, so blocks are fetched one at a time from now on
+                self.prefetcher = None;
+                return Ok(None);
+            }
+        }
+
+        let (height, block) = match prefetcher.queue.pop_front() {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        let this_id = BlockId {
+            height,
+            hash: block.block_hash(),
+        };
+
+        let new_cp = match &self.last_cp {
+            Some(cp) if cp.height() + 1 == height && cp.hash() == block.header.prev_blockhash => {
+                cp.clone().push(this_id).expect("must push")
+            }
+            // The best chain has changed since the blocks were prefetched. We discard the queue
+            // so that `poll` can find the point of agreement.
+            Some(_) => {
+                prefetcher.queue.clear();
+                self.last_block = None;
+                return Ok(None);
+            }
+            // As in `poll`, the previous checkpoint is also included so that the receiver's
+            // `LocalChain` update can connect.
+            None if height > 0 => CheckPoint::new(BlockId {
+                height: height - 1,
+                hash: block.header.prev_blockhash,
+            })
+            .push(this_id)
+            .expect("must push"),
+            None => CheckPoint::new(this_id),
+        };
+
+        self.last_cp = Some(new_cp);
+        // The last block result is not updated when emitting prefetched blocks, so it must be
+        // re-fetched by `poll`.
+        self.last_block = None;
+        Ok(Some((height, block)))
+    }
+}
+
+/// Fetches blocks concurrently with a set of worker threads, each with its own RPC client.
+struct Prefetcher {
+    /// Each worker receives block heights to fetch from its channel.
+    workers: Vec<mpsc::Sender<u32>>,
+    /// Fetched blocks (or errors) from all workers, or `None` if the worker panicked.
+    results: mpsc::Receiver<(u32, Option<Result<Block, bitcoincore_rpc::Error>>)>,
+    /// Prefetching stops once `queue` reaches this serialized size.
+    max_bytes: usize,
+    /// Prefetched blocks which are yet to be emitted, in ascending height order.
+    queue: VecDeque<(u32, Block)>,
+}
+
+impl Prefetcher {
+    fn new<W>(workers: impl IntoIterator<Item = W>, max_bytes: usize) -> Option<Self>
+    where
+        W: bitcoincore_rpc::RpcApi + Send + 'static,
+    {
+        let (results_tx, results) = mpsc::channel();
+        let workers = workers
+            .into_iter()
+            .map(|client| {
+                let (heights_tx, heights_rx) = mpsc::channel::<u32>();
+                let results_tx = results_tx.clone();
+                // The worker exits once the prefetcher (and therefore the sender) is dropped.
+                std::thread::spawn(move || {
+                    for height in heights_rx {
+                        // a panic is reported rather than leaving the prefetcher waiting for the
+                        // block forever
+                        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                            client
+                                .get_block_hash(height as _)
+                                .and_then(|hash| client.get_block(&hash))
+                        }));
+                        let panicked = res.is_err();
+                        if results_tx.send((height, res.ok())).is_err() || panicked {
+                            break;
+                        }
+                    }
+                });
+                heights_tx
+            })
+            .collect::<Vec<_>>();
+
+        if workers.is_empty() {
+            return None;
+        }
+        Some(Self {
+            workers,
+            results,
+            max_bytes,
+            queue: VecDeque::new(),
+        })
+    }
+
+    /// Fetch blocks from `start_height` up to `tip_height` (inclusive) into the queue, one round
+    /// (one block per worker) at a time, until the queue reaches `max_bytes`.
+    ///
+    /// Returns `Ok(false)` if a worker has terminated, in which case the prefetcher can't be used
+    /// anymore.
+    fn fill(&mut self, start_height: u32, tip_height: u32) -> Result<bool, bitcoincore_rpc::Error> {
+        let mut queued_bytes = self
+            .queue
+            .iter()
+            .map(|(_, block)| block.size())
+            .sum::<usize>();
+        let mut next_height = start_height;
+
+        while next_height <= tip_height && queued_bytes < self.max_bytes {
+            let round_heights = (next_height..=tip_height).take(self.workers.len());
+            let mut request_count = 0_usize;
+            for (worker, height) in self.workers.iter().zip(round_heights) {
+                if worker.send(height).is_err() {
+                    return Ok(false);
+                }
+                request_count += 1;
+            }
+            next_height += request_count as u32;
+
+            // we receive all results of the round before returning any error, so that stale
+            // results are not left in the channel
+            let mut fetched = Vec::with_capacity(request_count);
+            for _ in 0..request_count {
+                match self.results.recv() {
+                    Ok((height, Some(res))) => fetched.push((height, res)),
+                    _ => return Ok(false),
+                }
+            }
+            fetched.sort_by_key(|(height, _)| *height);
+
+            for (height, res) in fetched {
+                let block = match res {
+                    Ok(block) => block,
+                    Err(err) => {
+                        self.queue.clear();
+                        return Err(err);
+                    }
+                };
+                queued_bytes += block.size();
+                self.queue.push_back((height, block));
+            }
+        }
+
+        Ok(true)
+    }
 }
 
 enum PollResponse {
@@ -280,6 +496,37 @@ where
     }
 }
 
+/// How far block timestamps may be below the time a transaction was created, as in Bitcoin Core's
+/// rescans.
+const TIMESTAMP_WINDOW: u64 = 2 * 60 * 60;
+
+/// Find the height to start syncing a wallet created at the unix timestamp `time` from.
+///
+/// Block timestamps are not monotonic, so this searches for the first block whose median time past
+/// is at least `time` minus two hours. Returns the tip height if there is no such block yet.
+pub fn height_at_time<C: bitcoincore_rpc::RpcApi>(
+    client: &C,
+    time: u64,
+) -> Result<u32, bitcoincore_rpc::Error> {
+    let target = time.saturating_sub(TIMESTAMP_WINDOW);
+    let median_time = |height: u32| -> Result<u64, bitcoincore_rpc::Error> {
+        let hash = client.get_block_hash(height as u64)?;
+        let header = client.get_block_header_info(&hash)?;
+        Ok(header.median_time.unwrap_or(header.time) as u64)
+    };
+
+    let (mut low, mut high) = (0_u32, client.get_block_count()? as u32);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if median_time(mid)? < target {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    Ok(low)
+}
+
 /// Extends [`bitcoincore_rpc::Error`].
 pub trait BitcoindRpcErrorExt {
     /// Returns whether the error is a "not found" error.
@@ -299,3 +546,77 @@ impl BitcoindRpcErrorExt for bitcoincore_rpc::Error {
         }
     }
 }
+
+/// A `bitcoind` RPC client as a [`chain_source`] for wallets.
+///
+/// See [`chain_source`] for why the traits are implemented for this wrapper.
+///
+/// [`chain_source`]: bdk_chain::chain_source
+#[derive(Debug, Clone, Copy)]
+pub struct BitcoindSource<'a, C>(pub &'a C);
+
+impl<C: bitcoincore_rpc::RpcApi> FeeEstimator for BitcoindSource<'_, C> {
+    type Error = bitcoincore_rpc::Error;
+
+    /// Targets above [`u16::MAX`] are clamped, Bitcoin Core itself clamps the target to the
+    /// longest horizon it tracks.
+    fn estimate_fee(&self, target: usize) -> Result<FeeRate, Self::Error> {
+        let conf_target = u16::try_from(target).unwrap_or(u16::MAX);
+        let res = self.0.estimate_smart_fee(conf_target, None)?;
+        match res.fee_rate {
+            // rounded up to a whole sat/kwu, so that the conversion never makes it smaller
+            Some(per_kvb) => Ok(FeeRate::from_sat_per_kwu(
+                ((per_kvb.to_sat() + 3) / 4) as f32,
+            )),
+            None => Err(bitcoincore_rpc::Error::ReturnedError(
+                res.errors
+                    .map(|errors| errors.join(", "))
+                    .unwrap_or_else(|| "no fee estimate available".into()),
+            )),
+        }
+    }
+}
+
+impl<C: bitcoincore_rpc::RpcApi> Broadcaster for BitcoindSource<'_, C> {
+    type Error = bitcoincore_rpc::Error;
+
+    fn broadcast_tx(&self, tx: &Transaction) -> Result<(), BroadcastError<Self::Error>> {
+        use bitcoincore_rpc::jsonrpc;
+
+        match self.0.send_raw_transaction(tx) {
+            Ok(_) => Ok(()),
+            Err(bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(err))) => {
+                Err(BroadcastError::from_message(
+                    err.message.clone(),
+                    bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(err)),
+                ))
+            }
+            Err(err) => Err(BroadcastError::Other(err)),
+        }
+    }
+}
+
+impl<C: bitcoincore_rpc::RpcApi> UtxoSource for BitcoindSource<'_, C> {
+    type Error = bitcoincore_rpc::Error;
+
+    /// Looks up the UTXOs with `scantxoutset`, so only confirmed UTXOs are found.
+    fn list_unspent(
+        &self,
+        script_pubkey: &Script,
+    ) -> Result<Vec<(OutPoint, Transaction)>, Self::Error> {
+        use bitcoincore_rpc::json::ScanTxOutRequest;
+
+        let request = ScanTxOutRequest::Single(format!("raw({:x})", script_pubkey));
+        self.0
+            .scan_tx_out_set_blocking(&[request])?
+            .unspents
+            .into_iter()
+            .map(|utxo| {
+                // without `-txindex`, the node only finds transactions in a given block
+                let block_hash = self.0.get_block_hash(utxo.height)?;
+                let tx = self.0.get_raw_transaction(&utxo.txid, Some(&block_hash))?;
+                Ok((OutPoint::new(utxo.txid, utxo.vout), tx))
+            })
+            .collect()
+    }
+}
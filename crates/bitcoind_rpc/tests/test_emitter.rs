@@ -1,6 +1,6 @@
 use std::collections::{BTreeMap, BTreeSet};
 
-use bdk_bitcoind_rpc::Emitter;
+use bdk_bitcoind_rpc::{height_at_time, Emitter};
 use bdk_chain::{
     bitcoin::{Address, Amount, BlockHash, Txid},
     keychain::Balance,
@@ -18,7 +18,6 @@ use bitcoincore_rpc::{
 };
 
 struct TestEnv {
-    #[allow(dead_code)]
     daemon: bitcoind::BitcoinD,
     client: bitcoincore_rpc::Client,
 }
@@ -36,6 +35,13 @@ impl TestEnv {
         Ok(Self { daemon, client })
     }
 
+    fn new_client(&self) -> anyhow::Result<bitcoincore_rpc::Client> {
+        Ok(bitcoincore_rpc::Client::new(
+            &self.daemon.rpc_url(),
+            bitcoincore_rpc::Auth::CookieFile(self.daemon.params.cookie_file.clone()),
+        )?)
+    }
+
     fn mine_blocks(
         &self,
         count: usize,
@@ -278,6 +284,94 @@ pub fn test_sync_local_chain() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Ensure that blocks emitted with prefetching enabled are in order, and that a reorg of
+/// prefetched blocks is detected.
+///
+/// 1. Mine 101 blocks.
+/// 2. Emit half of the blocks from a prefetching [`Emitter`], so that the rest are queued.
+/// 3. Reorg highest 6 blocks.
+/// 4. Emit the remaining blocks and check that the [`LocalChain`] ends up with the new best chain.
+#[test]
+pub fn test_prefetch_sync_local_chain() -> anyhow::Result<()> {
+    const PREMINE_COUNT: usize = 101;
+    const WORKER_COUNT: usize = 4;
+
+    let env = TestEnv::new()?;
+    let workers = (0..WORKER_COUNT)
+        .map(|_| env.new_client())
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    // the budget is small enough that blocks are fetched over multiple rounds
+    let mut emitter = Emitter::from_height(&env.client, 0).with_prefetch(workers, 4_000);
+    let mut local_chain = LocalChain::default();
+
+    env.mine_blocks(PREMINE_COUNT, None)?;
+
+    let mut exp_height = 0_u32;
+    while exp_height <= (PREMINE_COUNT / 2) as u32 {
+        let (height, block) = emitter.next_block()?.expect("must emit block");
+        assert_eq!(height, exp_height, "emitted block has unexpected height");
+        assert_eq!(block.block_hash(), env.client.get_block_hash(height as _)?);
+        let _ = local_chain.apply_update(block_to_chain_update(&block, height))?;
+        exp_height += 1;
+    }
+
+    let reorged_blocks = env.reorg(6)?;
+
+    while let Some((height, block)) = emitter.next_block()? {
+        let _ = local_chain.apply_update(block_to_chain_update(&block, height))?;
+    }
+
+    let tip = local_chain.tip().expect("must have tip");
+    assert_eq!(tip.height(), PREMINE_COUNT as u32);
+    assert_eq!(
+        tip.hash(),
+        *reorged_blocks.last().expect("must have reorged blocks")
+    );
+    assert_eq!(
+        local_chain.blocks(),
+        &(0..=PREMINE_COUNT as u64)
+            .map(|h| Ok((h as u32, env.client.get_block_hash(h)?)))
+            .collect::<anyhow::Result<BTreeMap<_, _>>>()?,
+        "final local_chain state is unexpected after reorg",
+    );
+
+    Ok(())
+}
+
+/// An RPC client whose every call panics, standing in for a prefetch worker that crashes.
+struct PanickingClient;
+
+impl RpcApi for PanickingClient {
+    fn call<T: for<'a> serde::Deserialize<'a>>(
+        &self,
+        cmd: &str,
+        _args: &[bitcoincore_rpc::jsonrpc::serde_json::Value],
+    ) -> bitcoincore_rpc::Result<T> {
+        panic!("{} failed", cmd)
+    }
+}
+
+#[test]
+pub fn test_prefetch_falls_back_when_worker_panics() -> anyhow::Result<()> {
+    const PREMINE_COUNT: usize = 20;
+
+    let env = TestEnv::new()?;
+    let mut emitter =
+        Emitter::from_height(&env.client, 0).with_prefetch(vec![PanickingClient], 4_000);
+    env.mine_blocks(PREMINE_COUNT, None)?;
+
+    // the blocks are still emitted in order, fetched by the emitter's own client
+    let mut exp_height = 0_u32;
+    while let Some((height, block)) = emitter.next_block()? {
+        assert_eq!(height, exp_height, "emitted block has unexpected height");
+        assert_eq!(block.block_hash(), env.client.get_block_hash(height as _)?);
+        exp_height += 1;
+    }
+    assert_eq!(exp_height, PREMINE_COUNT as u32 + 1);
+
+    Ok(())
+}
+
 /// Ensure that [`EmittedUpdate::into_tx_graph_update`] behaves appropriately for both mempool and
 /// block updates.
 ///
@@ -412,6 +506,26 @@ fn ensure_block_emitted_after_reorg_is_at_reorg_height() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn height_at_time_finds_first_block_after_time() -> anyhow::Result<()> {
+    const CHAIN_TIP_HEIGHT: usize = 20;
+
+    let env = TestEnv::new()?;
+    env.mine_blocks(CHAIN_TIP_HEIGHT, None)?;
+    let now = std::time::UNIX_EPOCH.elapsed()?.as_secs();
+
+    // the regtest genesis block is from 2011, the blocks mined above it are from now
+    assert_eq!(height_at_time(&env.client, 0)?, 0);
+    assert_eq!(height_at_time(&env.client, now)?, 1);
+    // a wallet created in the future starts syncing from the tip
+    assert_eq!(
+        height_at_time(&env.client, now + 24 * 60 * 60)?,
+        CHAIN_TIP_HEIGHT as u32
+    );
+
+    Ok(())
+}
+
 fn process_block(
     recv_chain: &mut LocalChain,
     recv_graph: &mut IndexedTxGraph<BlockId, SpkTxOutIndex<()>>,
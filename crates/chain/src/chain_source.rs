@@ -0,0 +1,217 @@
+//! Interfaces to the services of a chain source
+//!
+//! These traits describe what a wallet asks of a chain source beyond the chain data itself, like
+//! estimating fees, broadcasting transactions or looking up the UTXOs of keys it doesn't own, so
+//! that wallets can be written against any chain source.
+//!
+//! The chain source crates implement them for wrappers around their clients: `EsploraSource` in
+//! `bdk_esplora`, `ElectrumSource` in `bdk_electrum` and `BitcoindSource` in `bdk_bitcoind_rpc`.
+//! The traits can't be implemented for the clients themselves, because neither the traits nor the
+//! clients are defined in those crates.
+//!
+//! The async versions of the traits are enabled by the `async-interface` feature.
+
+use core::fmt;
+
+#[cfg(feature = "async-interface")]
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use bitcoin::{OutPoint, Script, Transaction};
+
+use crate::FeeRate;
+
+/// Estimates the fee rate needed for a transaction to confirm within a number of blocks.
+pub trait FeeEstimator {
+    /// Error returned when no estimate could be obtained.
+    type Error;
+
+    /// Returns the fee rate a transaction should pay to be confirmed within `target` blocks.
+    fn estimate_fee(&self, target: usize) -> Result<FeeRate, Self::Error>;
+}
+
+/// Async version of [`FeeEstimator`].
+#[cfg(feature = "async-interface")]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+pub trait AsyncFeeEstimator {
+    /// Error returned when no estimate could be obtained.
+    type Error;
+
+    /// Returns the fee rate a transaction should pay to be confirmed within `target` blocks.
+    async fn estimate_fee(&self, target: usize) -> Result<FeeRate, Self::Error>;
+}
+
+/// A common reason for a node to reject a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// The transaction doesn't pay enough fees to enter the node's mempool, either because the
+    /// fee rate is below the mempool minimum or because it doesn't pay enough to replace the
+    /// transactions it conflicts with.
+    MempoolMinFee,
+    /// The transaction spends an output that is already spent by another transaction.
+    Conflict,
+    /// The transaction spends outputs the node doesn't know about, either because the transactions
+    /// creating them haven't been broadcast, or because they have already been spent in a block.
+    MissingInputs,
+    /// The transaction's locktime or relative locktimes are not satisfied yet.
+    NonFinal,
+    /// The transaction is already confirmed.
+    AlreadyInChain,
+}
+
+impl RejectReason {
+    /// Recognizes the reason for a rejection from the message returned by a node.
+    ///
+    /// Electrum and Esplora servers forward the message of the Bitcoin Core node they're backed by,
+    /// so this works for all of the supported chain sources. Returns `None` if the reason is not one
+    /// of the known ones.
+    pub fn from_message(message: &str) -> Option<Self> {
+        const PATTERNS: &[(&str, RejectReason)] = &[
+            ("mempool min fee not met", RejectReason::MempoolMinFee),
+            ("min relay fee not met", RejectReason::MempoolMinFee),
+            ("insufficient fee", RejectReason::MempoolMinFee),
+            ("txn-mempool-conflict", RejectReason::Conflict),
+            (
+                "bad-txns-inputs-missingorspent",
+                RejectReason::MissingInputs,
+            ),
+            ("missing-inputs", RejectReason::MissingInputs),
+            ("non-final", RejectReason::NonFinal),
+            ("non-bip68-final", RejectReason::NonFinal),
+            ("already in block chain", RejectReason::AlreadyInChain),
+            ("outputs already in utxo set", RejectReason::AlreadyInChain),
+            ("txn-already-confirmed", RejectReason::AlreadyInChain),
+        ];
+
+        let message = message.to_lowercase();
+        PATTERNS
+            .iter()
+            .find(|(pattern, _)| message.contains(pattern))
+            .map(|&(_, reason)| reason)
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::MempoolMinFee => write!(f, "fee is below the mempool minimum"),
+            RejectReason::Conflict => write!(f, "conflicts with another transaction"),
+            RejectReason::MissingInputs => write!(f, "inputs are missing or already spent"),
+            RejectReason::NonFinal => write!(f, "transaction is not final"),
+            RejectReason::AlreadyInChain => write!(f, "transaction is already confirmed"),
+        }
+    }
+}
+
+/// Error returned by a [`Broadcaster`].
+#[derive(Debug)]
+pub enum BroadcastError<E> {
+    /// The transaction was rejected for a known reason.
+    Rejected(RejectReason),
+    /// The transaction could not be broadcast, or was rejected for a reason not covered by
+    /// [`RejectReason`].
+    Other(E),
+}
+
+impl<E: fmt::Display> fmt::Display for BroadcastError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BroadcastError::Rejected(reason) => write!(f, "transaction rejected: {}", reason),
+            BroadcastError::Other(err) => write!(f, "failed to broadcast transaction: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Display + fmt::Debug> std::error::Error for BroadcastError<E> {}
+
+impl<E> BroadcastError<E> {
+    /// Classifies the error message `message` of a rejected transaction, keeping `err` when the
+    /// reason is not a known one.
+    pub fn from_message(message: impl ToString, err: E) -> Self {
+        match RejectReason::from_message(&message.to_string()) {
+            Some(reason) => BroadcastError::Rejected(reason),
+            None => BroadcastError::Other(err),
+        }
+    }
+}
+
+/// Submits transactions to the network.
+pub trait Broadcaster {
+    /// Error returned when the transaction could not be broadcast.
+    type Error;
+
+    /// Broadcasts `tx`.
+    fn broadcast_tx(&self, tx: &Transaction) -> Result<(), BroadcastError<Self::Error>>;
+}
+
+/// Async version of [`Broadcaster`].
+#[cfg(feature = "async-interface")]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+pub trait AsyncBroadcaster {
+    /// Error returned when the transaction could not be broadcast.
+    type Error;
+
+    /// Broadcasts `tx`.
+    async fn broadcast_tx(&self, tx: &Transaction) -> Result<(), BroadcastError<Self::Error>>;
+}
+
+/// Looks up the UTXOs paying to a script through a chain source.
+pub trait UtxoSource {
+    /// Error returned when the UTXOs could not be retrieved.
+    type Error;
+
+    /// Returns the unspent outputs paying to `script_pubkey`, with the transactions creating them.
+    fn list_unspent(
+        &self,
+        script_pubkey: &Script,
+    ) -> Result<Vec<(OutPoint, Transaction)>, Self::Error>;
+}
+
+/// Async version of [`UtxoSource`].
+#[cfg(feature = "async-interface")]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+pub trait AsyncUtxoSource {
+    /// Error returned when the UTXOs could not be retrieved.
+    type Error;
+
+    /// Returns the unspent outputs paying to `script_pubkey`, with the transactions creating them.
+    async fn list_unspent(
+        &self,
+        script_pubkey: &Script,
+    ) -> Result<Vec<(OutPoint, Transaction)>, Self::Error>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reject_reason_from_message() {
+        let cases = [
+            (
+                "sendrawtransaction RPC error: {\"code\":-26,\"message\":\"mempool min fee not met, 110 < 141\"}",
+                Some(RejectReason::MempoolMinFee),
+            ),
+            ("min relay fee not met, 0 < 110", Some(RejectReason::MempoolMinFee)),
+            (
+                "insufficient fee, rejecting replacement 1a2b, less fees than conflicting txs; 0.00001 < 0.00002",
+                Some(RejectReason::MempoolMinFee),
+            ),
+            ("txn-mempool-conflict", Some(RejectReason::Conflict)),
+            ("bad-txns-inputs-missingorspent", Some(RejectReason::MissingInputs)),
+            ("missing-inputs", Some(RejectReason::MissingInputs)),
+            ("non-final", Some(RejectReason::NonFinal)),
+            ("non-BIP68-final", Some(RejectReason::NonFinal)),
+            ("Transaction already in block chain", Some(RejectReason::AlreadyInChain)),
+            ("Transaction outputs already in utxo set", Some(RejectReason::AlreadyInChain)),
+            ("bad-txns-vout-empty", None),
+        ];
+        for (message, expected) in cases {
+            assert_eq!(RejectReason::from_message(message), expected, "{}", message);
+        }
+    }
+}
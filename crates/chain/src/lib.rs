@@ -38,6 +38,9 @@ mod chain_oracle;
 pub use chain_oracle::*;
 mod persist;
 pub use persist::*;
+pub mod chain_source;
+mod fee_rate;
+pub use fee_rate::FeeRate;
 
 #[doc(hidden)]
 pub mod example_utils;
@@ -398,6 +398,23 @@ impl LocalChain {
         Ok(changeset)
     }
 
+    /// Removes all checkpoints at and above `height`.
+    ///
+    /// This is useful for re-syncing the chain (and re-anchoring transactions) from `height`.
+    /// Transactions anchored in the removed blocks will no longer be considered confirmed.
+    ///
+    /// The method returns the [`ChangeSet`] of removed checkpoints, which is empty if there are no
+    /// checkpoints at or above `height`.
+    pub fn disconnect_from(&mut self, height: u32) -> ChangeSet {
+        let changeset = self
+            .index
+            .range(height..)
+            .map(|(&height, _)| (height, None))
+            .collect::<ChangeSet>();
+        self.apply_changeset(&changeset);
+        changeset
+    }
+
     /// Reindex the heights in the chain from (and including) `from` height
     fn reindex(&mut self, from: u32) {
         let _ = self.index.split_off(&from);
@@ -346,3 +346,57 @@ fn local_chain_insert_block() {
         assert_eq!(chain, t.expected_final, "[{}] unexpected final chain", i,);
     }
 }
+
+#[test]
+fn local_chain_disconnect_from() {
+    struct TestCase {
+        original: LocalChain,
+        disconnect_from: u32,
+        expected_changeset: ChangeSet,
+        expected_final: LocalChain,
+    }
+
+    let test_cases = [
+        TestCase {
+            original: local_chain![],
+            disconnect_from: 0,
+            expected_changeset: [].into(),
+            expected_final: local_chain![],
+        },
+        TestCase {
+            original: local_chain![(1, h!("A")), (2, h!("B")), (4, h!("D"))],
+            disconnect_from: 5,
+            expected_changeset: [].into(),
+            expected_final: local_chain![(1, h!("A")), (2, h!("B")), (4, h!("D"))],
+        },
+        TestCase {
+            original: local_chain![(1, h!("A")), (2, h!("B")), (4, h!("D"))],
+            disconnect_from: 2,
+            expected_changeset: [(2, None), (4, None)].into(),
+            expected_final: local_chain![(1, h!("A"))],
+        },
+        TestCase {
+            original: local_chain![(1, h!("A")), (2, h!("B")), (4, h!("D"))],
+            disconnect_from: 3,
+            expected_changeset: [(4, None)].into(),
+            expected_final: local_chain![(1, h!("A")), (2, h!("B"))],
+        },
+        TestCase {
+            original: local_chain![(1, h!("A")), (2, h!("B"))],
+            disconnect_from: 0,
+            expected_changeset: [(1, None), (2, None)].into(),
+            expected_final: local_chain![],
+        },
+    ];
+
+    for (i, t) in test_cases.into_iter().enumerate() {
+        let mut chain = t.original;
+        assert_eq!(
+            chain.disconnect_from(t.disconnect_from),
+            t.expected_changeset,
+            "[{}] unexpected changeset when disconnecting",
+            i,
+        );
+        assert_eq!(chain, t.expected_final, "[{}] unexpected final chain", i);
+    }
+}
@@ -1,8 +1,9 @@
 use bdk_chain::{
-    bitcoin::{OutPoint, ScriptBuf, Transaction, Txid},
+    bitcoin::{OutPoint, Script, ScriptBuf, Transaction, Txid},
+    chain_source::{BroadcastError, Broadcaster, FeeEstimator, UtxoSource},
     local_chain::{self, CheckPoint},
     tx_graph::{self, TxGraph},
-    Anchor, BlockId, ConfirmationHeightAnchor, ConfirmationTimeAnchor,
+    Anchor, BlockId, ConfirmationHeightAnchor, ConfirmationTimeAnchor, FeeRate,
 };
 use electrum_client::{Client, ElectrumApi, Error, HeaderNotification};
 use std::{
@@ -286,6 +287,63 @@ impl ElectrumExt for Client {
     }
 }
 
+/// An Electrum client as a [`chain_source`] for wallets.
+///
+/// See [`chain_source`] for why the traits are implemented for this wrapper.
+///
+/// [`chain_source`]: bdk_chain::chain_source
+#[derive(Debug, Clone, Copy)]
+pub struct ElectrumSource<'a, C>(pub &'a C);
+
+impl<C: ElectrumApi> FeeEstimator for ElectrumSource<'_, C> {
+    type Error = Error;
+
+    fn estimate_fee(&self, target: usize) -> Result<FeeRate, Error> {
+        let btc_per_kvb = self.0.estimate_fee(target)?;
+        // servers return -1 when they don't have enough data for an estimate
+        if !btc_per_kvb.is_finite() || btc_per_kvb < 0.0 {
+            return Err(Error::Message(format!(
+                "server has no fee estimate for a target of {} blocks",
+                target
+            )));
+        }
+        // rounded up to a whole sat/kwu, so that the conversion never makes it smaller
+        let sat_per_kwu = (btc_per_kvb * 100_000_000.0 / 4.0).ceil();
+        Ok(FeeRate::from_sat_per_kwu(sat_per_kwu as f32))
+    }
+}
+
+impl<C: ElectrumApi> Broadcaster for ElectrumSource<'_, C> {
+    type Error = Error;
+
+    fn broadcast_tx(&self, tx: &Transaction) -> Result<(), BroadcastError<Self::Error>> {
+        match self.0.transaction_broadcast(tx) {
+            Ok(_) => Ok(()),
+            Err(Error::Protocol(value)) => Err(BroadcastError::from_message(
+                &value,
+                Error::Protocol(value.clone()),
+            )),
+            Err(err) => Err(BroadcastError::Other(err)),
+        }
+    }
+}
+
+impl<C: ElectrumApi> UtxoSource for ElectrumSource<'_, C> {
+    type Error = Error;
+
+    fn list_unspent(&self, script_pubkey: &Script) -> Result<Vec<(OutPoint, Transaction)>, Error> {
+        let utxos = self.0.script_list_unspent(script_pubkey)?;
+        let txs = self
+            .0
+            .batch_transaction_get(utxos.iter().map(|utxo| &utxo.tx_hash))?;
+        Ok(utxos
+            .iter()
+            .zip(txs)
+            .map(|(utxo, tx)| (OutPoint::new(utxo.tx_hash, utxo.tx_pos as u32), tx))
+            .collect())
+    }
+}
+
 /// Return a [`CheckPoint`] of the latest tip, that connects with `prev_tip`.
 fn construct_update_tip(
     client: &Client,
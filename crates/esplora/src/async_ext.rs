@@ -1,15 +1,22 @@
 use async_trait::async_trait;
 use bdk_chain::collections::btree_map;
 use bdk_chain::{
-    bitcoin::{BlockHash, OutPoint, ScriptBuf, Txid},
+    bitcoin::{
+        consensus::encode::serialize_hex, BlockHash, OutPoint, Script, ScriptBuf, Transaction, Txid,
+    },
+    chain_source::{AsyncBroadcaster, AsyncFeeEstimator, AsyncUtxoSource, BroadcastError},
     collections::{BTreeMap, BTreeSet},
     local_chain::{self, CheckPoint},
-    BlockId, ConfirmationTimeAnchor, TxGraph,
+    BlockId, ConfirmationTimeAnchor, FeeRate, TxGraph,
 };
-use esplora_client::{Error, TxStatus};
+use core::future::Future;
+use esplora_client::TxStatus;
 use futures::{stream::FuturesOrdered, TryStreamExt};
 
-use crate::{anchor_from_status, ASSUME_FINAL_DEPTH};
+use crate::{
+    anchor_from_status, chain_page_info, fee_rate_from_estimates, is_rate_limited, unspent_outputs,
+    Error, EsploraSource, ASSUME_FINAL_DEPTH, CHAIN_TXS_PER_PAGE, MAX_RETRIES,
+};
 
 /// Trait to extend the functionality of [`esplora_client::AsyncClient`].
 ///
@@ -39,17 +46,25 @@ pub trait EsploraAsyncExt {
     /// Scan Esplora for the data specified and return a [`TxGraph`] and a map of last active
     /// indices.
     ///
+    /// * `known_graph`: transactions we already have, these are not downloaded again and are left
+    ///   out of the returned [`TxGraph`] (their anchors are still included)
     /// * `keychain_spks`: keychains that we want to scan transactions for
     /// * `txids`: transactions for which we want updated [`ConfirmationTimeAnchor`]s
     /// * `outpoints`: transactions associated with these outpoints (residing, spending) that we
-    ///     want to include in the update
+    ///   want to include in the update
     ///
     /// The scan for each keychain stops after a gap of `stop_gap` script pubkeys with no associated
     /// transactions. `parallel_requests` specifies the max number of HTTP requests to make in
     /// parallel.
+    ///
+    /// Requests that are rate-limited by the server are retried with an exponential backoff, using
+    /// the timer of the `tokio` feature. If the server keeps rate-limiting, [`Error::RateLimited`]
+    /// is returned. Without the `tokio` feature, or on `wasm32` targets, there is no timer to back
+    /// off with, so rate-limited requests fail right away.
     #[allow(clippy::result_large_err)]
-    async fn scan_txs_with_keychains<K: Ord + Clone + Send>(
+    async fn scan_txs_with_keychains<K: Ord + Clone + Send, A: Sync>(
         &self,
+        known_graph: &TxGraph<A>,
         keychain_spks: BTreeMap<
             K,
             impl IntoIterator<IntoIter = impl Iterator<Item = (u32, ScriptBuf)> + Send> + Send,
@@ -64,14 +79,16 @@ pub trait EsploraAsyncExt {
     ///
     /// [`scan_txs_with_keychains`]: EsploraAsyncExt::scan_txs_with_keychains
     #[allow(clippy::result_large_err)]
-    async fn scan_txs(
+    async fn scan_txs<A: Sync>(
         &self,
+        known_graph: &TxGraph<A>,
         misc_spks: impl IntoIterator<IntoIter = impl Iterator<Item = ScriptBuf> + Send> + Send,
         txids: impl IntoIterator<IntoIter = impl Iterator<Item = Txid> + Send> + Send,
         outpoints: impl IntoIterator<IntoIter = impl Iterator<Item = OutPoint> + Send> + Send,
         parallel_requests: usize,
     ) -> Result<TxGraph<ConfirmationTimeAnchor>, Error> {
         self.scan_txs_with_keychains(
+            known_graph,
             [(
                 (),
                 misc_spks
@@ -99,13 +116,12 @@ impl EsploraAsyncExt for esplora_client::AsyncClient {
         request_heights: impl IntoIterator<IntoIter = impl Iterator<Item = u32> + Send> + Send,
     ) -> Result<local_chain::Update, Error> {
         let request_heights = request_heights.into_iter().collect::<BTreeSet<_>>();
-        let new_tip_height = self.get_height().await?;
+        let new_tip_height = retry(|| self.get_height()).await?;
 
         // atomically fetch blocks from esplora
         let mut fetched_blocks = {
             let heights = (0..=new_tip_height).rev();
-            let hashes = self
-                .get_blocks(Some(new_tip_height))
+            let hashes = retry(|| self.get_blocks(Some(new_tip_height)))
                 .await?
                 .into_iter()
                 .map(|b| b.id);
@@ -120,7 +136,7 @@ impl EsploraAsyncExt for esplora_client::AsyncClient {
             }
             // only fetch what is missing
             if let btree_map::Entry::Vacant(entry) = fetched_blocks.entry(height) {
-                let hash = self.get_block_hash(height).await?;
+                let hash = retry(|| self.get_block_hash(height)).await?;
                 entry.insert(hash);
             }
         }
@@ -146,7 +162,7 @@ impl EsploraAsyncExt for esplora_client::AsyncClient {
                             if local_tip_height - local_block.height >= ASSUME_FINAL_DEPTH {
                                 local_block.hash
                             } else {
-                                self.get_block_hash(local_block.height).await?
+                                retry(|| self.get_block_hash(local_block.height)).await?
                             },
                         ),
                     };
@@ -201,8 +217,9 @@ impl EsploraAsyncExt for esplora_client::AsyncClient {
         })
     }
 
-    async fn scan_txs_with_keychains<K: Ord + Clone + Send>(
+    async fn scan_txs_with_keychains<K: Ord + Clone + Send, A: Sync>(
         &self,
+        known_graph: &TxGraph<A>,
         keychain_spks: BTreeMap<
             K,
             impl IntoIterator<IntoIter = impl Iterator<Item = (u32, ScriptBuf)> + Send> + Send,
@@ -229,17 +246,8 @@ impl EsploraAsyncExt for esplora_client::AsyncClient {
                     .map(|(spk_index, spk)| {
                         let client = self.clone();
                         async move {
-                            let mut last_seen = None;
-                            let mut spk_txs = Vec::new();
-                            loop {
-                                let txs = client.scripthash_txs(&spk, last_seen).await?;
-                                let tx_count = txs.len();
-                                last_seen = txs.last().map(|tx| tx.txid);
-                                spk_txs.extend(txs);
-                                if tx_count < 25 {
-                                    break Result::<_, Error>::Ok((spk_index, spk_txs));
-                                }
-                            }
+                            let txs = script_history(&client, &spk).await?;
+                            Result::<_, Error>::Ok((spk_index, txs))
                         }
                     })
                     .collect::<FuturesOrdered<_>>();
@@ -254,7 +262,9 @@ impl EsploraAsyncExt for esplora_client::AsyncClient {
                         last_active_index = Some(index);
                     }
                     for tx in txs {
-                        let _ = graph.insert_tx(tx.to_tx());
+                        if known_graph.get_tx(tx.txid).is_none() {
+                            let _ = graph.insert_tx(tx.to_tx());
+                        }
                         if let Some(anchor) = anchor_from_status(&tx.status) {
                             let _ = graph.insert_anchor(tx.txid, anchor);
                         }
@@ -275,11 +285,15 @@ impl EsploraAsyncExt for esplora_client::AsyncClient {
         loop {
             let handles = txids
                 .by_ref()
-                .take(parallel_requests)
                 .filter(|&txid| graph.get_tx(txid).is_none())
+                .take(parallel_requests)
                 .map(|txid| {
                     let client = self.clone();
-                    async move { client.get_tx_status(&txid).await.map(|s| (txid, s)) }
+                    async move {
+                        retry(|| client.get_tx_status(&txid))
+                            .await
+                            .map(|s| (txid, s))
+                    }
                 })
                 .collect::<FuturesOrdered<_>>();
 
@@ -296,22 +310,28 @@ impl EsploraAsyncExt for esplora_client::AsyncClient {
 
         for op in outpoints.into_iter() {
             if graph.get_tx(op.txid).is_none() {
-                if let Some(tx) = self.get_tx(&op.txid).await? {
-                    let _ = graph.insert_tx(tx);
+                if known_graph.get_tx(op.txid).is_none() {
+                    if let Some(tx) = retry(|| self.get_tx(&op.txid)).await? {
+                        let _ = graph.insert_tx(tx);
+                    }
                 }
-                let status = self.get_tx_status(&op.txid).await?;
+                let status = retry(|| self.get_tx_status(&op.txid)).await?;
                 if let Some(anchor) = anchor_from_status(&status) {
                     let _ = graph.insert_anchor(op.txid, anchor);
                 }
             }
 
-            if let Some(op_status) = self.get_output_status(&op.txid, op.vout as _).await? {
+            if let Some(op_status) =
+                retry(|| self.get_output_status(&op.txid, op.vout as _)).await?
+            {
                 if let Some(txid) = op_status.txid {
                     if graph.get_tx(txid).is_none() {
-                        if let Some(tx) = self.get_tx(&txid).await? {
-                            let _ = graph.insert_tx(tx);
+                        if known_graph.get_tx(txid).is_none() {
+                            if let Some(tx) = retry(|| self.get_tx(&txid)).await? {
+                                let _ = graph.insert_tx(tx);
+                            }
                         }
-                        let status = self.get_tx_status(&txid).await?;
+                        let status = retry(|| self.get_tx_status(&txid)).await?;
                         if let Some(anchor) = anchor_from_status(&status) {
                             let _ = graph.insert_anchor(txid, anchor);
                         }
@@ -323,3 +343,101 @@ impl EsploraAsyncExt for esplora_client::AsyncClient {
         Ok((graph, last_active_indexes))
     }
 }
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl AsyncFeeEstimator for EsploraSource<'_, esplora_client::AsyncClient> {
+    type Error = Error;
+
+    async fn estimate_fee(&self, target: usize) -> Result<FeeRate, Error> {
+        let estimates = retry(|| self.0.get_fee_estimates()).await?;
+        fee_rate_from_estimates(target, &estimates).ok_or(Error::NoFeeEstimate { target })
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl AsyncBroadcaster for EsploraSource<'_, esplora_client::AsyncClient> {
+    type Error = Error;
+
+    async fn broadcast_tx(&self, tx: &Transaction) -> Result<(), BroadcastError<Self::Error>> {
+        // `AsyncClient::broadcast` drops the response body, which is where the reason for the
+        // rejection is, so we make the request ourselves.
+        let resp = self
+            .0
+            .client()
+            .post(format!("{}/tx", self.0.url()))
+            .body(serialize_hex(tx))
+            .send()
+            .await
+            .map_err(|err| BroadcastError::Other(Error::Client(err.into())))?;
+
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let err = Error::Client(esplora_client::Error::HttpResponse(status.as_u16()));
+        match resp.text().await {
+            Ok(message) => Err(BroadcastError::from_message(message, err)),
+            Err(_) => Err(BroadcastError::Other(err)),
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl AsyncUtxoSource for EsploraSource<'_, esplora_client::AsyncClient> {
+    type Error = Error;
+
+    async fn list_unspent(
+        &self,
+        script_pubkey: &Script,
+    ) -> Result<Vec<(OutPoint, Transaction)>, Error> {
+        let txs = script_history(self.0, script_pubkey).await?;
+        Ok(unspent_outputs(script_pubkey, &txs))
+    }
+}
+
+/// Fetches the whole history of `spk`, one page at a time.
+#[allow(clippy::result_large_err)]
+async fn script_history(
+    client: &esplora_client::AsyncClient,
+    spk: &Script,
+) -> Result<Vec<esplora_client::Tx>, Error> {
+    let mut last_seen = None;
+    let mut spk_txs = Vec::new();
+    loop {
+        let txs = retry(|| client.scripthash_txs(spk, last_seen)).await?;
+        let (last_confirmed, confirmed_count) = chain_page_info(&txs);
+        spk_txs.extend(txs);
+        if confirmed_count < CHAIN_TXS_PER_PAGE {
+            return Ok(spk_txs);
+        }
+        last_seen = last_confirmed;
+    }
+}
+
+/// Calls `f` until it returns something other than a rate-limit error, waiting for an
+/// exponentially increasing backoff between attempts.
+#[allow(clippy::result_large_err)]
+async fn retry<T, F, Fut>(mut f: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, esplora_client::Error>>,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match f().await {
+            Err(err) if is_rate_limited(&err) => {
+                let can_sleep = cfg!(all(feature = "tokio", not(target_arch = "wasm32")));
+                if attempts > MAX_RETRIES || !can_sleep {
+                    return Err(Error::RateLimited { attempts });
+                }
+                #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+                tokio::time::sleep(crate::backoff(attempts)).await;
+            }
+            res => return res.map_err(Error::Client),
+        }
+    }
+}
@@ -3,13 +3,19 @@ use std::thread::JoinHandle;
 use bdk_chain::collections::btree_map;
 use bdk_chain::collections::{BTreeMap, BTreeSet};
 use bdk_chain::{
-    bitcoin::{BlockHash, OutPoint, ScriptBuf, Txid},
+    bitcoin::{
+        consensus::encode::serialize_hex, BlockHash, OutPoint, Script, ScriptBuf, Transaction, Txid,
+    },
+    chain_source::{BroadcastError, Broadcaster, FeeEstimator, UtxoSource},
     local_chain::{self, CheckPoint},
-    BlockId, ConfirmationTimeAnchor, TxGraph,
+    BlockId, ConfirmationTimeAnchor, FeeRate, TxGraph,
 };
-use esplora_client::{Error, TxStatus};
+use esplora_client::TxStatus;
 
-use crate::{anchor_from_status, ASSUME_FINAL_DEPTH};
+use crate::{
+    anchor_from_status, backoff, chain_page_info, fee_rate_from_estimates, is_rate_limited,
+    unspent_outputs, Error, EsploraSource, ASSUME_FINAL_DEPTH, CHAIN_TXS_PER_PAGE, MAX_RETRIES,
+};
 
 /// Trait to extend the functionality of [`esplora_client::BlockingClient`].
 ///
@@ -37,17 +43,23 @@ pub trait EsploraExt {
     /// Scan Esplora for the data specified and return a [`TxGraph`] and a map of last active
     /// indices.
     ///
+    /// * `known_graph`: transactions we already have, these are not downloaded again and are left
+    ///   out of the returned [`TxGraph`] (their anchors are still included)
     /// * `keychain_spks`: keychains that we want to scan transactions for
     /// * `txids`: transactions for which we want updated [`ConfirmationTimeAnchor`]s
     /// * `outpoints`: transactions associated with these outpoints (residing, spending) that we
-    ///     want to include in the update
+    ///   want to include in the update
     ///
     /// The scan for each keychain stops after a gap of `stop_gap` script pubkeys with no associated
     /// transactions. `parallel_requests` specifies the max number of HTTP requests to make in
     /// parallel.
+    ///
+    /// Requests that are rate-limited by the server are retried with an exponential backoff. If
+    /// the server keeps rate-limiting, [`Error::RateLimited`] is returned.
     #[allow(clippy::result_large_err)]
-    fn scan_txs_with_keychains<K: Ord + Clone>(
+    fn scan_txs_with_keychains<K: Ord + Clone, A>(
         &self,
+        known_graph: &TxGraph<A>,
         keychain_spks: BTreeMap<K, impl IntoIterator<Item = (u32, ScriptBuf)>>,
         txids: impl IntoIterator<Item = Txid>,
         outpoints: impl IntoIterator<Item = OutPoint>,
@@ -59,14 +71,16 @@ pub trait EsploraExt {
     ///
     /// [`scan_txs_with_keychains`]: EsploraExt::scan_txs_with_keychains
     #[allow(clippy::result_large_err)]
-    fn scan_txs(
+    fn scan_txs<A>(
         &self,
+        known_graph: &TxGraph<A>,
         misc_spks: impl IntoIterator<Item = ScriptBuf>,
         txids: impl IntoIterator<Item = Txid>,
         outpoints: impl IntoIterator<Item = OutPoint>,
         parallel_requests: usize,
     ) -> Result<TxGraph<ConfirmationTimeAnchor>, Error> {
         self.scan_txs_with_keychains(
+            known_graph,
             [(
                 (),
                 misc_spks
@@ -85,19 +99,19 @@ pub trait EsploraExt {
 }
 
 impl EsploraExt for esplora_client::BlockingClient {
+    #[allow(clippy::result_large_err)]
     fn update_local_chain(
         &self,
         local_tip: Option<CheckPoint>,
         request_heights: impl IntoIterator<Item = u32>,
     ) -> Result<local_chain::Update, Error> {
         let request_heights = request_heights.into_iter().collect::<BTreeSet<_>>();
-        let new_tip_height = self.get_height()?;
+        let new_tip_height = retry(|| self.get_height())?;
 
         // atomically fetch blocks from esplora
         let mut fetched_blocks = {
             let heights = (0..=new_tip_height).rev();
-            let hashes = self
-                .get_blocks(Some(new_tip_height))?
+            let hashes = retry(|| self.get_blocks(Some(new_tip_height)))?
                 .into_iter()
                 .map(|b| b.id);
             heights.zip(hashes).collect::<BTreeMap<u32, BlockHash>>()
@@ -111,7 +125,7 @@ impl EsploraExt for esplora_client::BlockingClient {
             }
             // only fetch what is missing
             if let btree_map::Entry::Vacant(entry) = fetched_blocks.entry(height) {
-                let hash = self.get_block_hash(height)?;
+                let hash = retry(|| self.get_block_hash(height))?;
                 entry.insert(hash);
             }
         }
@@ -137,7 +151,7 @@ impl EsploraExt for esplora_client::BlockingClient {
                             if local_tip_height - local_block.height >= ASSUME_FINAL_DEPTH {
                                 local_block.hash
                             } else {
-                                self.get_block_hash(local_block.height)?
+                                retry(|| self.get_block_hash(local_block.height))?
                             },
                         ),
                     };
@@ -192,8 +206,10 @@ impl EsploraExt for esplora_client::BlockingClient {
         })
     }
 
-    fn scan_txs_with_keychains<K: Ord + Clone>(
+    #[allow(clippy::result_large_err)]
+    fn scan_txs_with_keychains<K: Ord + Clone, A>(
         &self,
+        known_graph: &TxGraph<A>,
         keychain_spks: BTreeMap<K, impl IntoIterator<Item = (u32, ScriptBuf)>>,
         txids: impl IntoIterator<Item = Txid>,
         outpoints: impl IntoIterator<Item = OutPoint>,
@@ -218,17 +234,7 @@ impl EsploraExt for esplora_client::BlockingClient {
                         std::thread::spawn({
                             let client = self.clone();
                             move || -> Result<TxsOfSpkIndex, Error> {
-                                let mut last_seen = None;
-                                let mut spk_txs = Vec::new();
-                                loop {
-                                    let txs = client.scripthash_txs(&spk, last_seen)?;
-                                    let tx_count = txs.len();
-                                    last_seen = txs.last().map(|tx| tx.txid);
-                                    spk_txs.extend(txs);
-                                    if tx_count < 25 {
-                                        break Ok((spk_index, spk_txs));
-                                    }
-                                }
+                                Ok((spk_index, script_history(&client, &spk)?))
                             }
                         })
                     })
@@ -245,7 +251,9 @@ impl EsploraExt for esplora_client::BlockingClient {
                         last_active_index = Some(index);
                     }
                     for tx in txs {
-                        let _ = graph.insert_tx(tx.to_tx());
+                        if known_graph.get_tx(tx.txid).is_none() {
+                            let _ = graph.insert_tx(tx.to_tx());
+                        }
                         if let Some(anchor) = anchor_from_status(&tx.status) {
                             let _ = graph.insert_anchor(tx.txid, anchor);
                         }
@@ -266,12 +274,12 @@ impl EsploraExt for esplora_client::BlockingClient {
         loop {
             let handles = txids
                 .by_ref()
-                .take(parallel_requests)
                 .filter(|&txid| graph.get_tx(txid).is_none())
+                .take(parallel_requests)
                 .map(|txid| {
                     std::thread::spawn({
                         let client = self.clone();
-                        move || client.get_tx_status(&txid).map(|s| (txid, s))
+                        move || retry(|| client.get_tx_status(&txid)).map(|s| (txid, s))
                     })
                 })
                 .collect::<Vec<JoinHandle<Result<(Txid, TxStatus), Error>>>>();
@@ -290,22 +298,26 @@ impl EsploraExt for esplora_client::BlockingClient {
 
         for op in outpoints.into_iter() {
             if graph.get_tx(op.txid).is_none() {
-                if let Some(tx) = self.get_tx(&op.txid)? {
-                    let _ = graph.insert_tx(tx);
+                if known_graph.get_tx(op.txid).is_none() {
+                    if let Some(tx) = retry(|| self.get_tx(&op.txid))? {
+                        let _ = graph.insert_tx(tx);
+                    }
                 }
-                let status = self.get_tx_status(&op.txid)?;
+                let status = retry(|| self.get_tx_status(&op.txid))?;
                 if let Some(anchor) = anchor_from_status(&status) {
                     let _ = graph.insert_anchor(op.txid, anchor);
                 }
             }
 
-            if let Some(op_status) = self.get_output_status(&op.txid, op.vout as _)? {
+            if let Some(op_status) = retry(|| self.get_output_status(&op.txid, op.vout as _))? {
                 if let Some(txid) = op_status.txid {
                     if graph.get_tx(txid).is_none() {
-                        if let Some(tx) = self.get_tx(&txid)? {
-                            let _ = graph.insert_tx(tx);
+                        if known_graph.get_tx(txid).is_none() {
+                            if let Some(tx) = retry(|| self.get_tx(&txid))? {
+                                let _ = graph.insert_tx(tx);
+                            }
                         }
-                        let status = self.get_tx_status(&txid)?;
+                        let status = retry(|| self.get_tx_status(&txid))?;
                         if let Some(anchor) = anchor_from_status(&status) {
                             let _ = graph.insert_anchor(txid, anchor);
                         }
@@ -317,3 +329,86 @@ impl EsploraExt for esplora_client::BlockingClient {
         Ok((graph, last_active_indexes))
     }
 }
+
+impl FeeEstimator for EsploraSource<'_, esplora_client::BlockingClient> {
+    type Error = Error;
+
+    #[allow(clippy::result_large_err)]
+    fn estimate_fee(&self, target: usize) -> Result<FeeRate, Error> {
+        let estimates = retry(|| self.0.get_fee_estimates())?;
+        fee_rate_from_estimates(target, &estimates).ok_or(Error::NoFeeEstimate { target })
+    }
+}
+
+impl Broadcaster for EsploraSource<'_, esplora_client::BlockingClient> {
+    type Error = Error;
+
+    fn broadcast_tx(&self, tx: &Transaction) -> Result<(), BroadcastError<Self::Error>> {
+        // `BlockingClient::broadcast` drops the response body, which is where the reason for the
+        // rejection is, so we make the request ourselves.
+        let resp = self
+            .0
+            .agent()
+            .post(&format!("{}/tx", self.0.url()))
+            .send_string(&serialize_hex(tx));
+
+        match resp {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(code, resp)) => {
+                let err = Error::Client(esplora_client::Error::HttpResponse(code));
+                match resp.into_string() {
+                    Ok(message) => Err(BroadcastError::from_message(message, err)),
+                    Err(_) => Err(BroadcastError::Other(err)),
+                }
+            }
+            Err(err) => Err(BroadcastError::Other(Error::Client(err.into()))),
+        }
+    }
+}
+
+impl UtxoSource for EsploraSource<'_, esplora_client::BlockingClient> {
+    type Error = Error;
+
+    fn list_unspent(&self, script_pubkey: &Script) -> Result<Vec<(OutPoint, Transaction)>, Error> {
+        let txs = script_history(self.0, script_pubkey)?;
+        Ok(unspent_outputs(script_pubkey, &txs))
+    }
+}
+
+/// Fetches the whole history of `spk`, one page at a time.
+#[allow(clippy::result_large_err)]
+fn script_history(
+    client: &esplora_client::BlockingClient,
+    spk: &Script,
+) -> Result<Vec<esplora_client::Tx>, Error> {
+    let mut last_seen = None;
+    let mut spk_txs = Vec::new();
+    loop {
+        let txs = retry(|| client.scripthash_txs(spk, last_seen))?;
+        let (last_confirmed, confirmed_count) = chain_page_info(&txs);
+        spk_txs.extend(txs);
+        if confirmed_count < CHAIN_TXS_PER_PAGE {
+            return Ok(spk_txs);
+        }
+        last_seen = last_confirmed;
+    }
+}
+
+/// Calls `f` until it returns something other than a rate-limit error, sleeping for an
+/// exponentially increasing backoff between attempts.
+#[allow(clippy::result_large_err)]
+fn retry<T>(mut f: impl FnMut() -> Result<T, esplora_client::Error>) -> Result<T, Error> {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match f() {
+            Err(err) if is_rate_limited(&err) => {
+                if attempts > MAX_RETRIES {
+                    return Err(Error::RateLimited { attempts });
+                }
+                std::thread::sleep(backoff(attempts));
+            }
+            res => return res.map_err(Error::Client),
+        }
+    }
+}
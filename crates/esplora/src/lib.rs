@@ -1,5 +1,12 @@
 #![doc = include_str!("../README.md")]
-use bdk_chain::{BlockId, ConfirmationTimeAnchor};
+use core::fmt;
+
+use std::collections::HashMap;
+
+use bdk_chain::{
+    bitcoin::{OutPoint, Script, Transaction, Txid},
+    BlockId, ConfirmationTimeAnchor, FeeRate,
+};
 use esplora_client::TxStatus;
 
 pub use esplora_client;
@@ -16,6 +23,152 @@ pub use async_ext::*;
 
 const ASSUME_FINAL_DEPTH: u32 = 15;
 
+/// Number of confirmed transactions Esplora returns per page of script history.
+const CHAIN_TXS_PER_PAGE: usize = 25;
+
+/// Maximum number of times a rate-limited request is retried before giving up.
+const MAX_RETRIES: u32 = 5;
+
+/// Delay before the first retry of a rate-limited request. Doubles with every retry.
+#[cfg(any(
+    feature = "blocking",
+    all(feature = "tokio", not(target_arch = "wasm32"))
+))]
+const BASE_BACKOFF: core::time::Duration = core::time::Duration::from_millis(250);
+
+/// Errors that can occur when updating from an Esplora server.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum Error {
+    /// An error returned by the underlying [`esplora_client`].
+    Client(esplora_client::Error),
+    /// The server kept rate-limiting requests (HTTP 429) after `attempts` tries.
+    RateLimited {
+        /// Number of times the request was attempted.
+        attempts: u32,
+    },
+    /// The server has no fee estimate for a confirmation target of `target` blocks or less.
+    NoFeeEstimate {
+        /// The confirmation target, in blocks.
+        target: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Client(err) => write!(f, "esplora client error: {}", err),
+            Error::RateLimited { attempts } => write!(
+                f,
+                "rate-limited by the esplora server after {} attempts",
+                attempts
+            ),
+            Error::NoFeeEstimate { target } => write!(
+                f,
+                "the esplora server has no fee estimate for a target of {} blocks",
+                target
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<esplora_client::Error> for Error {
+    fn from(err: esplora_client::Error) -> Self {
+        Error::Client(err)
+    }
+}
+
+/// An Esplora client as a [`chain_source`] for wallets.
+///
+/// See [`chain_source`] for why the traits are implemented for this wrapper. Broadcasting with a
+/// blocking client looks like:
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "blocking")]
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use bdk_chain::chain_source::Broadcaster;
+/// use bdk_esplora::{esplora_client, EsploraSource};
+/// # let tx: bdk_chain::bitcoin::Transaction = todo!();
+///
+/// let client = esplora_client::Builder::new("https://blockstream.info/api").build_blocking()?;
+/// EsploraSource(&client).broadcast_tx(&tx)?;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "blocking"))]
+/// # fn main() {}
+/// ```
+///
+/// [`chain_source`]: bdk_chain::chain_source
+#[derive(Debug, Clone, Copy)]
+pub struct EsploraSource<'a, C>(pub &'a C);
+
+fn is_rate_limited(err: &esplora_client::Error) -> bool {
+    match err {
+        esplora_client::Error::HttpResponse(429) => true,
+        #[cfg(feature = "async")]
+        esplora_client::Error::Reqwest(err) => err.status().map_or(false, |s| s.as_u16() == 429),
+        _ => false,
+    }
+}
+
+/// Backoff to wait before retrying a request that has been rate-limited `attempts` times.
+#[cfg(any(
+    feature = "blocking",
+    all(feature = "tokio", not(target_arch = "wasm32"))
+))]
+fn backoff(attempts: u32) -> core::time::Duration {
+    BASE_BACKOFF * 2_u32.pow(attempts.saturating_sub(1))
+}
+
+/// Returns the txid of the last confirmed transaction in a page of script history, which is what
+/// Esplora expects as `last_seen` when requesting the next page, along with the number of
+/// confirmed transactions in the page.
+///
+/// The first page also contains unconfirmed transactions, which do not count toward the page size.
+fn chain_page_info(txs: &[esplora_client::Tx]) -> (Option<Txid>, usize) {
+    let mut confirmed = txs.iter().filter(|tx| tx.status.confirmed);
+    let count = confirmed.clone().count();
+    (confirmed.next_back().map(|tx| tx.txid), count)
+}
+
+/// Returns the outputs paying to `script_pubkey` that aren't spent by any of `txs`, the history of
+/// the script.
+fn unspent_outputs(
+    script_pubkey: &Script,
+    txs: &[esplora_client::Tx],
+) -> Vec<(OutPoint, Transaction)> {
+    let spent = txs
+        .iter()
+        .flat_map(|tx| tx.vin.iter().map(|vin| OutPoint::new(vin.txid, vin.vout)))
+        .collect::<std::collections::HashSet<_>>();
+    let mut utxos = Vec::new();
+    for tx in txs {
+        for (vout, txout) in tx.vout.iter().enumerate() {
+            let outpoint = OutPoint::new(tx.txid, vout as u32);
+            if txout.scriptpubkey.as_script() == script_pubkey && !spent.contains(&outpoint) {
+                utxos.push((outpoint, tx.to_tx()));
+            }
+        }
+    }
+    utxos
+}
+
+/// Picks the estimate for the longest confirmation target up to `target` blocks among the
+/// `estimates` returned by the server, in sat/vB by target.
+///
+/// The estimate is rounded up to a whole sat/kwu before it's converted to a [`FeeRate`], so that
+/// the conversion never makes it smaller.
+fn fee_rate_from_estimates(target: usize, estimates: &HashMap<String, f64>) -> Option<FeeRate> {
+    let (_, sat_per_vb) = estimates
+        .iter()
+        .filter_map(|(k, v)| Some((k.parse::<usize>().ok()?, *v)))
+        .filter(|(k, v)| *k <= target && v.is_finite() && *v >= 0.0)
+        .max_by_key(|(k, _)| *k)?;
+    Some(FeeRate::from_sat_per_kwu((sat_per_vb * 250.0).ceil() as f32))
+}
+
 fn anchor_from_status(status: &TxStatus) -> Option<ConfirmationTimeAnchor> {
     if let TxStatus {
         block_height: Some(height),
@@ -33,3 +186,36 @@ fn anchor_from_status(status: &TxStatus) -> Option<ConfirmationTimeAnchor> {
         None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fee_rate_from_estimates() {
+        let estimates = [("1", 20.5), ("3", 10.0), ("6", 5.001), ("144", -1.0)]
+            .iter()
+            .map(|&(target, sat_per_vb)| (target.to_string(), sat_per_vb))
+            .collect::<HashMap<_, _>>();
+
+        assert_eq!(fee_rate_from_estimates(0, &estimates), None);
+        assert_eq!(
+            fee_rate_from_estimates(1, &estimates),
+            Some(FeeRate::from_sat_per_vb(20.5))
+        );
+        assert_eq!(
+            fee_rate_from_estimates(5, &estimates),
+            Some(FeeRate::from_sat_per_vb(10.0))
+        );
+        // rounded up to 1251 sat/kwu
+        assert_eq!(
+            fee_rate_from_estimates(6, &estimates),
+            Some(FeeRate::from_sat_per_kwu(1251.0))
+        );
+        // invalid estimates are ignored
+        assert_eq!(
+            fee_rate_from_estimates(1000, &estimates),
+            Some(FeeRate::from_sat_per_kwu(1251.0))
+        );
+    }
+}
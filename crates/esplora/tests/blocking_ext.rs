@@ -8,6 +8,7 @@ use std::thread::sleep;
 use std::time::Duration;
 
 use bdk_chain::bitcoin::{Address, Amount, BlockHash, Txid};
+use bdk_chain::{ConfirmationTimeAnchor, TxGraph};
 
 struct TestEnv {
     bitcoind: BitcoinD,
@@ -99,7 +100,8 @@ pub fn test_update_tx_graph_without_keychain() -> anyhow::Result<()> {
     }
 
     let graph_update = env.client.scan_txs(
-        misc_spks.into_iter(),
+        &TxGraph::<ConfirmationTimeAnchor>::default(),
+        misc_spks.iter().cloned(),
         vec![].into_iter(),
         vec![].into_iter(),
         1,
@@ -110,5 +112,76 @@ pub fn test_update_tx_graph_without_keychain() -> anyhow::Result<()> {
     let mut expected_txids = vec![txid1, txid2];
     expected_txids.sort();
     assert_eq!(graph_update_txids, expected_txids);
+
+    // scanning again with what we already have should only return the anchors
+    let known_graph = graph_update;
+    let graph_update = env.client.scan_txs(
+        &known_graph,
+        misc_spks.iter().cloned(),
+        vec![].into_iter(),
+        vec![].into_iter(),
+        1,
+    )?;
+    assert_eq!(graph_update.full_txs().count(), 0);
+    assert_eq!(graph_update.all_anchors(), known_graph.all_anchors());
+    Ok(())
+}
+
+/// A script pubkey with more history than fits in a single page must have all of its confirmed
+/// transactions paged through, even when the first page also contains unconfirmed ones.
+#[test]
+pub fn test_scan_txs_paginates_spk_history() -> anyhow::Result<()> {
+    let env = TestEnv::new()?;
+    let address =
+        Address::from_str("bcrt1qc6fweuf4xjvz4x3gx3t9e0fh4hvqyu2qw4wvxm")?.assume_checked();
+
+    let _block_hashes = env.mine_blocks(101, None)?;
+    let mut expected_txids = Vec::new();
+    // mine in between batches to stay below the mempool chain limit
+    for _ in 0..3 {
+        for _ in 0..20 {
+            let txid = env.bitcoind.client.send_to_address(
+                &address,
+                Amount::from_sat(10000),
+                None,
+                None,
+                None,
+                None,
+                Some(1),
+                None,
+            )?;
+            expected_txids.push(txid);
+        }
+        let _block_hashes = env.mine_blocks(1, None)?;
+    }
+    for _ in 0..5 {
+        let txid = env.bitcoind.client.send_to_address(
+            &address,
+            Amount::from_sat(10000),
+            None,
+            None,
+            None,
+            None,
+            Some(1),
+            None,
+        )?;
+        expected_txids.push(txid);
+    }
+    while env.client.get_height().unwrap() < 104 {
+        sleep(Duration::from_millis(10))
+    }
+
+    let graph_update = env.client.scan_txs(
+        &TxGraph::<ConfirmationTimeAnchor>::default(),
+        [address.script_pubkey()],
+        vec![].into_iter(),
+        vec![].into_iter(),
+        1,
+    )?;
+
+    let mut graph_update_txids: Vec<Txid> = graph_update.full_txs().map(|tx| tx.txid).collect();
+    graph_update_txids.sort();
+    expected_txids.sort();
+    assert_eq!(graph_update_txids, expected_txids);
     Ok(())
 }
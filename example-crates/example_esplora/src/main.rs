@@ -187,6 +187,7 @@ fn main() -> anyhow::Result<()> {
             // (`keychain_indices_update`).
             let (graph_update, last_active_indices) = client
                 .scan_txs_with_keychains(
+                    graph.lock().expect("mutex must not be poisoned").graph(),
                     keychain_spks,
                     core::iter::empty(),
                     core::iter::empty(),
@@ -309,8 +310,13 @@ fn main() -> anyhow::Result<()> {
                 }
             }
 
-            let graph_update =
-                client.scan_txs(spks, txids, outpoints, scan_options.parallel_requests)?;
+            let graph_update = client.scan_txs(
+                graph.lock().unwrap().graph(),
+                spks,
+                txids,
+                outpoints,
+                scan_options.parallel_requests,
+            )?;
 
             graph.lock().unwrap().apply_update(graph_update)
         }
@@ -10,10 +10,7 @@ use bdk::bitcoin::Address;
 use bdk::wallet::Update;
 use bdk::SignOptions;
 use bdk::{bitcoin::Network, Wallet};
-use bdk_electrum::{
-    electrum_client::{self, ElectrumApi},
-    ElectrumExt, ElectrumUpdate,
-};
+use bdk_electrum::{electrum_client, ElectrumExt, ElectrumSource, ElectrumUpdate};
 use bdk_file_store::Store;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -100,8 +97,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     assert!(finalized);
 
     let tx = psbt.extract_tx();
-    client.transaction_broadcast(&tx)?;
-    println!("Tx broadcasted! Txid: {}", tx.txid());
+    let txid = tx.txid();
+    let now = std::time::UNIX_EPOCH.elapsed()?.as_secs();
+    wallet.broadcast(&ElectrumSource(&client), tx, now)?;
+    wallet.commit()?;
+    println!("Tx broadcasted! Txid: {}", txid);
 
     Ok(())
 }
@@ -5,7 +5,7 @@ use bdk::{
     wallet::{AddressIndex, Update},
     SignOptions, Wallet,
 };
-use bdk_esplora::{esplora_client, EsploraAsyncExt};
+use bdk_esplora::{esplora_client, EsploraAsyncExt, EsploraSource};
 use bdk_file_store::Store;
 
 const DB_MAGIC: &str = "bdk_wallet_esplora_async_example";
@@ -54,7 +54,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         })
         .collect();
     let (update_graph, last_active_indices) = client
-        .scan_txs_with_keychains(keychain_spks, None, None, STOP_GAP, PARALLEL_REQUESTS)
+        .scan_txs_with_keychains(
+            wallet.tx_graph(),
+            keychain_spks,
+            None,
+            None,
+            STOP_GAP,
+            PARALLEL_REQUESTS,
+        )
         .await?;
     let missing_heights = update_graph.missing_heights(wallet.local_chain());
     let chain_update = client.update_local_chain(prev_tip, missing_heights).await?;
@@ -91,8 +98,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     assert!(finalized);
 
     let tx = psbt.extract_tx();
-    client.broadcast(&tx).await?;
-    println!("Tx broadcasted! Txid: {}", tx.txid());
+    let txid = tx.txid();
+    let now = std::time::UNIX_EPOCH.elapsed()?.as_secs();
+    wallet
+        .broadcast_async(&EsploraSource(&client), tx, now)
+        .await?;
+    wallet.commit()?;
+    println!("Tx broadcasted! Txid: {}", txid);
 
     Ok(())
 }
@@ -10,7 +10,7 @@ use bdk::{
     wallet::{AddressIndex, Update},
     SignOptions, Wallet,
 };
-use bdk_esplora::{esplora_client, EsploraExt};
+use bdk_esplora::{esplora_client, EsploraExt, EsploraSource};
 use bdk_file_store::Store;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -53,8 +53,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         })
         .collect();
 
-    let (update_graph, last_active_indices) =
-        client.scan_txs_with_keychains(keychain_spks, None, None, STOP_GAP, PARALLEL_REQUESTS)?;
+    let (update_graph, last_active_indices) = client.scan_txs_with_keychains(
+        wallet.tx_graph(),
+        keychain_spks,
+        None,
+        None,
+        STOP_GAP,
+        PARALLEL_REQUESTS,
+    )?;
     let missing_heights = update_graph.missing_heights(wallet.local_chain());
     let chain_update = client.update_local_chain(prev_tip, missing_heights)?;
     let update = Update {
@@ -91,8 +97,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     assert!(finalized);
 
     let tx = psbt.extract_tx();
-    client.broadcast(&tx)?;
-    println!("Tx broadcasted! Txid: {}", tx.txid());
+    let txid = tx.txid();
+    let now = std::time::UNIX_EPOCH.elapsed()?.as_secs();
+    wallet.broadcast(&EsploraSource(&client), tx, now)?;
+    wallet.commit()?;
+    println!("Tx broadcasted! Txid: {}", txid);
 
     Ok(())
 }
@@ -0,0 +1,77 @@
+const DB_MAGIC: &str = "bdk_wallet_rpc_example";
+/// The wallet has no transactions below this height, so a new wallet starts syncing from there.
+const BIRTHDAY_HEIGHT: u32 = 2_500_000;
+
+use std::path::PathBuf;
+
+use bdk::{
+    bitcoin::Network,
+    wallet::{AddressIndex, Birthday},
+    Wallet,
+};
+use bdk_bitcoind_rpc::{
+    bitcoincore_rpc::{Auth, Client},
+    height_at_time, Emitter,
+};
+use bdk_file_store::Store;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_url = std::env::var("RPC_URL").unwrap_or_else(|_| "127.0.0.1:18332".to_string());
+    let rpc_auth = match std::env::var("RPC_COOKIE") {
+        Ok(path) => Auth::CookieFile(PathBuf::from(path)),
+        Err(_) => Auth::None,
+    };
+
+    let db_path = std::env::temp_dir().join("bdk-rpc-example");
+    let db = Store::<bdk::wallet::ChangeSet>::new_from_path(DB_MAGIC.as_bytes(), db_path)?;
+    let external_descriptor = "wpkh(tprv8ZgxMBicQKsPdy6LMhUtFHAgpocR8GC6QmwMSFpZs7h6Eziw3SpThFfczTDh5rW2krkqffa11UpX3XkeTTB2FvzZKWXqPY54Y6Rq4AQ5R8L/84'/1'/0'/0/*)";
+    let internal_descriptor = "wpkh(tprv8ZgxMBicQKsPdy6LMhUtFHAgpocR8GC6QmwMSFpZs7h6Eziw3SpThFfczTDh5rW2krkqffa11UpX3XkeTTB2FvzZKWXqPY54Y6Rq4AQ5R8L/84'/1'/0'/1/*)";
+
+    let mut wallet = Wallet::new(
+        external_descriptor,
+        Some(internal_descriptor),
+        db,
+        Network::Testnet,
+    )?;
+    if wallet.birthday().is_none() {
+        wallet.set_birthday(Birthday::Height(BIRTHDAY_HEIGHT));
+        wallet.commit()?;
+    }
+
+    let address = wallet.get_address(AddressIndex::New);
+    println!("Generated Address: {}", address);
+
+    let balance = wallet.get_balance();
+    println!("Wallet balance before syncing: {} sats", balance.total());
+
+    let rpc_client = Client::new(&rpc_url, rpc_auth)?;
+    // a new wallet skips the blocks before its birthday, otherwise continue from the last sync
+    let start_height = wallet.birthday_start_height(|time| height_at_time(&rpc_client, time))?;
+    let mut emitter = match start_height {
+        Some(height) => {
+            println!("Syncing from the wallet birthday at height {}...", height);
+            Emitter::from_height(&rpc_client, height)
+        }
+        None => {
+            let cp = wallet
+                .latest_checkpoint()
+                .expect("wallet must have a genesis checkpoint");
+            println!("Syncing from height {}...", cp.height());
+            Emitter::from_checkpoint(&rpc_client, cp)
+        }
+    };
+
+    while let Some((height, block)) = emitter.next_block()? {
+        wallet.apply_block(&block, height)?;
+        if height % 1_000 == 0 {
+            wallet.commit()?;
+            println!("Synced up to height {}", height);
+        }
+    }
+    wallet.commit()?;
+
+    let balance = wallet.get_balance();
+    println!("Wallet balance after syncing: {} sats", balance.total());
+
+    Ok(())
+}
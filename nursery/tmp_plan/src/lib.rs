@@ -22,19 +22,44 @@ use bitcoin::{
     blockdata::transaction::Sequence,
     ecdsa,
     hashes::{hash160, ripemd160, sha256},
+    script,
     secp256k1::Secp256k1,
     taproot::{self, LeafVersion, TapLeafHash},
     ScriptBuf, TxIn, Witness,
 };
 use miniscript::{
     descriptor::{InnerXKey, Tr},
-    hash256, DefiniteDescriptorKey, Descriptor, DescriptorPublicKey, ScriptContext, ToPublicKey,
+    hash256, DefiniteDescriptorKey, Descriptor, DescriptorPublicKey, MiniscriptKey, ScriptContext,
+    ToPublicKey,
 };
 
 pub(crate) fn varint_len(v: usize) -> usize {
     bitcoin::VarInt(v as u64).len() as usize
 }
 
+/// The size of the opcode pushing `len` bytes of data onto the stack
+pub(crate) fn push_opcode_size(len: usize) -> usize {
+    if len < 76 {
+        1
+    } else if len < 0x100 {
+        2
+    } else if len < 0x10000 {
+        3
+    } else {
+        5
+    }
+}
+
+/// Pushes a satisfaction element with the minimal push, the way it has to appear in a script sig
+fn push_element(builder: script::Builder, element: &[u8]) -> script::Builder {
+    match element {
+        [n @ 1..=16] => builder.push_int(*n as i64),
+        _ => builder.push_slice(
+            <&script::PushBytes>::try_from(element).expect("satisfaction elements are small"),
+        ),
+    }
+}
+
 mod plan_impls;
 mod requirements;
 mod template;
@@ -53,9 +78,17 @@ enum TrSpend {
 
 #[derive(Clone, Debug)]
 enum Target {
-    Legacy,
+    Legacy {
+        script_code: ScriptBuf,
+        /// The redeem script of a P2SH output
+        redeem_script: Option<ScriptBuf>,
+    },
     Segwitv0 {
         script_code: ScriptBuf,
+        /// The witness script of a P2WSH output
+        witness_script: Option<ScriptBuf>,
+        /// The redeem script of a P2SH wrapped output
+        redeem_script: Option<ScriptBuf>,
     },
     Segwitv1 {
         tr: Tr<DefiniteDescriptorKey>,
@@ -63,8 +96,6 @@ enum Target {
     },
 }
 
-impl Target {}
-
 #[derive(Clone, Debug)]
 /// A plan represents a particular spending path for a descriptor.
 ///
@@ -76,12 +107,6 @@ pub struct Plan<AK> {
     set_sequence: Option<Sequence>,
 }
 
-impl Default for Target {
-    fn default() -> Self {
-        Target::Legacy
-    }
-}
-
 #[derive(Clone, Debug, Default)]
 /// Signatures and hash pre-images that can be used to complete a plan.
 pub struct SatisfactionMaterial {
@@ -105,23 +130,30 @@ where
 {
     /// The expected satisfaction weight for the plan if it is completed.
     pub fn expected_weight(&self) -> usize {
-        let script_sig_size = match self.target {
-            Target::Legacy => unimplemented!(), // self
-            // .template
-            // .iter()
-            // .map(|step| {
-            //     let size = step.expected_size();
-            //     size + push_opcode_size(size)
-            // })
-            // .sum()
-            Target::Segwitv0 { .. } | Target::Segwitv1 { .. } => 1,
+        let redeem_script_size = |redeem_script: &Option<ScriptBuf>| {
+            redeem_script
+                .as_ref()
+                .map_or(0, |script| push_opcode_size(script.len()) + script.len())
         };
+        let script_sig_len = match &self.target {
+            Target::Legacy { redeem_script, .. } => {
+                self.template
+                    .iter()
+                    .map(|step| step.expected_script_sig_size())
+                    .sum::<usize>()
+                    + redeem_script_size(redeem_script)
+            }
+            Target::Segwitv0 { redeem_script, .. } => redeem_script_size(redeem_script),
+            Target::Segwitv1 { .. } => 0,
+        };
+        let script_sig_size = varint_len(script_sig_len) + script_sig_len;
         let witness_elem_sizes: Option<Vec<usize>> = match &self.target {
-            Target::Legacy => None,
-            Target::Segwitv0 { .. } => Some(
+            Target::Legacy { .. } => None,
+            Target::Segwitv0 { witness_script, .. } => Some(
                 self.template
                     .iter()
                     .map(|step| step.expected_size())
+                    .chain(witness_script.as_ref().map(|script| script.len()))
                     .collect(),
             ),
             Target::Segwitv1 { tr, tr_plan } => {
@@ -177,13 +209,19 @@ where
                 TemplateItem::Sign(key) => {
                     !auth_data.schnorr_sigs.contains_key(&key.descriptor_key)
                 }
+                TemplateItem::EcdsaSign(key) => {
+                    !auth_data.ecdsa_sigs.contains_key(&key.descriptor_key)
+                }
                 TemplateItem::Hash160(image) => !auth_data.hash160_preimages.contains_key(image),
                 TemplateItem::Hash256(image) => !auth_data.hash256_preimages.contains_key(image),
                 TemplateItem::Sha256(image) => !auth_data.sha256_preimages.contains_key(image),
                 TemplateItem::Ripemd160(image) => {
                     !auth_data.ripemd160_preimages.contains_key(image)
                 }
-                TemplateItem::Pk { .. } | TemplateItem::One | TemplateItem::Zero => false,
+                TemplateItem::Pk { .. }
+                | TemplateItem::One
+                | TemplateItem::Zero
+                | TemplateItem::HashDissatisfaction => false,
             })
             .collect::<Vec<_>>();
 
@@ -194,8 +232,36 @@ where
                 .flat_map(|step| step.to_witness_stack(&auth_data))
                 .collect::<Vec<_>>();
             match &self.target {
-                Target::Segwitv0 { .. } => todo!(),
-                Target::Legacy => todo!(),
+                Target::Legacy { redeem_script, .. } => {
+                    let mut script_sig = witness
+                        .iter()
+                        .fold(script::Builder::new(), |builder, element| {
+                            push_element(builder, element)
+                        });
+                    if let Some(redeem_script) = redeem_script {
+                        script_sig = push_element(script_sig, redeem_script.as_bytes());
+                    }
+                    PlanState::Complete {
+                        final_script_sig: Some(script_sig.into_script()),
+                        final_script_witness: None,
+                    }
+                }
+                Target::Segwitv0 {
+                    witness_script,
+                    redeem_script,
+                    ..
+                } => {
+                    if let Some(witness_script) = witness_script {
+                        witness.push(witness_script.to_bytes());
+                    }
+                    PlanState::Complete {
+                        final_script_sig: redeem_script.as_ref().map(|redeem_script| {
+                            push_element(script::Builder::new(), redeem_script.as_bytes())
+                                .into_script()
+                        }),
+                        final_script_witness: Some(Witness::from(witness)),
+                    }
+                }
                 Target::Segwitv1 {
                     tr_plan: TrSpend::KeySpend,
                     ..
@@ -228,11 +294,17 @@ where
             let mut requirements = Requirements::default();
 
             match &self.target {
-                Target::Legacy => {
-                    todo!()
+                Target::Legacy { script_code, .. } => {
+                    requirements.signatures = RequiredSignatures::Legacy {
+                        script_code: script_code.clone(),
+                        keys: vec![],
+                    };
                 }
-                Target::Segwitv0 { .. } => {
-                    todo!()
+                Target::Segwitv0 { script_code, .. } => {
+                    requirements.signatures = RequiredSignatures::Segwitv0 {
+                        script_code: script_code.clone(),
+                        keys: vec![],
+                    };
                 }
                 Target::Segwitv1 { tr, tr_plan } => {
                     let spend_info = tr.spend_info();
@@ -261,8 +333,8 @@ where
             }
 
             let required_signatures = match requirements.signatures {
-                RequiredSignatures::Legacy { .. } => todo!(),
-                RequiredSignatures::Segwitv0 { .. } => todo!(),
+                RequiredSignatures::Legacy { ref mut keys, .. }
+                | RequiredSignatures::Segwitv0 { ref mut keys, .. } => keys,
                 RequiredSignatures::TapKey { .. } => return PlanState::Incomplete(requirements),
                 RequiredSignatures::TapScript {
                     plan_keys: ref mut keys,
@@ -272,7 +344,7 @@ where
 
             for step in unsatisfied_items {
                 match step {
-                    TemplateItem::Sign(plan_key) => {
+                    TemplateItem::Sign(plan_key) | TemplateItem::EcdsaSign(plan_key) => {
                         required_signatures.push(plan_key.clone());
                     }
                     TemplateItem::Hash160(image) => {
@@ -287,8 +359,10 @@ where
                     TemplateItem::Ripemd160(image) => {
                         requirements.ripemd160_images.insert(image.clone());
                     }
-                    TemplateItem::Pk { .. } | TemplateItem::One | TemplateItem::Zero => { /* no requirements */
-                    }
+                    TemplateItem::Pk { .. }
+                    | TemplateItem::One
+                    | TemplateItem::Zero
+                    | TemplateItem::HashDissatisfaction => { /* no requirements */ }
                 }
             }
 
@@ -299,7 +373,7 @@ where
     /// Witness version for the plan
     pub fn witness_version(&self) -> Option<WitnessVersion> {
         match self.target {
-            Target::Legacy => None,
+            Target::Legacy { .. } => None,
             Target::Segwitv0 { .. } => Some(WitnessVersion::V0),
             Target::Segwitv1 { .. } => Some(WitnessVersion::V1),
         }
@@ -383,7 +457,7 @@ impl CanDerive for KeySource {
             DescriptorPublicKey::MultiXPub(_) => {
                 // This crate will be replaced by
                 // https://github.com/rust-bitcoin/rust-miniscript/pull/481 anyways
-                todo!();
+                None
             }
         }
     }
@@ -432,11 +506,11 @@ where
     Ak: CanDerive + Clone,
 {
     match desc {
-        Descriptor::Bare(_) => todo!(),
-        Descriptor::Pkh(_) => todo!(),
-        Descriptor::Wpkh(_) => todo!(),
-        Descriptor::Sh(_) => todo!(),
-        Descriptor::Wsh(_) => todo!(),
+        Descriptor::Bare(bare) => crate::plan_impls::plan_satisfaction_bare(bare, assets),
+        Descriptor::Pkh(pkh) => crate::plan_impls::plan_satisfaction_pkh(pkh, assets),
+        Descriptor::Wpkh(wpkh) => crate::plan_impls::plan_satisfaction_wpkh(wpkh, None, assets),
+        Descriptor::Sh(sh) => crate::plan_impls::plan_satisfaction_sh(sh, assets),
+        Descriptor::Wsh(wsh) => crate::plan_impls::plan_satisfaction_wsh(wsh, None, assets),
         Descriptor::Tr(tr) => crate::plan_impls::plan_satisfaction_tr(tr, assets),
     }
 }
@@ -1,6 +1,9 @@
 use bdk_chain::{bitcoin, miniscript};
 use bitcoin::locktime::absolute;
-use miniscript::Terminal;
+use miniscript::{
+    descriptor::{Bare, Pkh, Sh, ShInner, Wpkh, Wsh, WshInner},
+    Legacy, Segwitv0, SigType, Terminal,
+};
 
 use super::*;
 
@@ -53,53 +56,105 @@ impl<Ak> TermPlan<Ak> {
     pub(crate) fn expected_size(&self) -> usize {
         self.template.iter().map(|step| step.expected_size()).sum()
     }
+
+    fn into_plan(self, target: Target) -> Plan<Ak> {
+        Plan {
+            template: self.template,
+            target,
+            set_locktime: self.min_locktime,
+            set_sequence: self.min_sequence,
+        }
+    }
 }
 
-// impl crate::descriptor::Pkh<DefiniteDescriptorKey> {
-//     pub(crate) fn plan_satisfaction<Ak>(&self, assets: &Assets<Ak>) -> Option<Plan<Ak>>
-//     where
-//         Ak: CanDerive + Clone,
-//     {
-//         let (asset_key, derivation_hint) = assets.keys.iter().find_map(|asset_key| {
-//             let derivation_hint = asset_key.can_derive(self.as_inner())?;
-//             Some((asset_key, derivation_hint))
-//         })?;
+pub(crate) fn plan_satisfaction_bare<Ak>(
+    bare: &Bare<DefiniteDescriptorKey>,
+    assets: &Assets<Ak>,
+) -> Option<Plan<Ak>>
+where
+    Ak: CanDerive + Clone,
+{
+    let plan = plan_steps(&bare.as_inner().node, assets)?;
+    Some(plan.into_plan(Target::Legacy {
+        script_code: bare.ecdsa_sighash_script_code(),
+        redeem_script: None,
+    }))
+}
 
-//         Some(Plan {
-//             template: vec![TemplateItem::Sign(PlanKey {
-//                 asset_key: asset_key.clone(),
-//                 descriptor_key: self.as_inner().clone(),
-//                 derivation_hint,
-//             })],
-//             target: Target::Legacy,
-//             set_locktime: None,
-//             set_sequence: None,
-//         })
-//     }
-// }
+pub(crate) fn plan_satisfaction_pkh<Ak>(
+    pkh: &Pkh<DefiniteDescriptorKey>,
+    assets: &Assets<Ak>,
+) -> Option<Plan<Ak>>
+where
+    Ak: CanDerive + Clone,
+{
+    let plan = plan_steps(&Terminal::<_, Legacy>::PkH(pkh.as_inner().clone()), assets)?;
+    Some(plan.into_plan(Target::Legacy {
+        script_code: pkh.ecdsa_sighash_script_code(),
+        redeem_script: None,
+    }))
+}
 
-// impl crate::descriptor::Wpkh<DefiniteDescriptorKey> {
-//     pub(crate) fn plan_satisfaction<Ak>(&self, assets: &Assets<Ak>) -> Option<Plan<Ak>>
-//     where
-//         Ak: CanDerive + Clone,
-//     {
-//         let (asset_key, derivation_hint) = assets.keys.iter().find_map(|asset_key| {
-//             let derivation_hint = asset_key.can_derive(self.as_inner())?;
-//             Some((asset_key, derivation_hint))
-//         })?;
+/// Plans a `wpkh()` descriptor, wrapped in P2SH with `redeem_script` if it's set.
+pub(crate) fn plan_satisfaction_wpkh<Ak>(
+    wpkh: &Wpkh<DefiniteDescriptorKey>,
+    redeem_script: Option<ScriptBuf>,
+    assets: &Assets<Ak>,
+) -> Option<Plan<Ak>>
+where
+    Ak: CanDerive + Clone,
+{
+    let plan = plan_steps(
+        &Terminal::<_, Segwitv0>::PkH(wpkh.as_inner().clone()),
+        assets,
+    )?;
+    Some(plan.into_plan(Target::Segwitv0 {
+        script_code: wpkh.ecdsa_sighash_script_code(),
+        witness_script: None,
+        redeem_script,
+    }))
+}
+
+/// Plans a `wsh()` descriptor, wrapped in P2SH with `redeem_script` if it's set.
+pub(crate) fn plan_satisfaction_wsh<Ak>(
+    wsh: &Wsh<DefiniteDescriptorKey>,
+    redeem_script: Option<ScriptBuf>,
+    assets: &Assets<Ak>,
+) -> Option<Plan<Ak>>
+where
+    Ak: CanDerive + Clone,
+{
+    let plan = match wsh.as_inner() {
+        WshInner::SortedMulti(smv) => plan_steps(&smv.sorted_node(), assets)?,
+        WshInner::Ms(ms) => plan_steps(&ms.node, assets)?,
+    };
+    Some(plan.into_plan(Target::Segwitv0 {
+        script_code: wsh.ecdsa_sighash_script_code(),
+        witness_script: Some(wsh.inner_script()),
+        redeem_script,
+    }))
+}
 
-//         Some(Plan {
-//             template: vec![TemplateItem::Sign(PlanKey {
-//                 asset_key: asset_key.clone(),
-//                 descriptor_key: self.as_inner().clone(),
-//                 derivation_hint,
-//             })],
-//             target: Target::Segwitv0,
-//             set_locktime: None,
-//             set_sequence: None,
-//         })
-//     }
-// }
+pub(crate) fn plan_satisfaction_sh<Ak>(
+    sh: &Sh<DefiniteDescriptorKey>,
+    assets: &Assets<Ak>,
+) -> Option<Plan<Ak>>
+where
+    Ak: CanDerive + Clone,
+{
+    let (plan, redeem_script) = match sh.as_inner() {
+        ShInner::Wsh(wsh) => return plan_satisfaction_wsh(wsh, Some(wsh.script_pubkey()), assets),
+        ShInner::Wpkh(wpkh) => {
+            return plan_satisfaction_wpkh(wpkh, Some(wpkh.script_pubkey()), assets)
+        }
+        ShInner::SortedMulti(smv) => (plan_steps(&smv.sorted_node(), assets)?, smv.encode()),
+        ShInner::Ms(ms) => (plan_steps(&ms.node, assets)?, ms.encode()),
+    };
+    Some(plan.into_plan(Target::Legacy {
+        script_code: sh.ecdsa_sighash_script_code(),
+        redeem_script: Some(redeem_script),
+    }))
+}
 
 pub(crate) fn plan_satisfaction_tr<Ak>(
     tr: &miniscript::descriptor::Tr<DefiniteDescriptorKey>,
@@ -138,18 +193,13 @@ where
 
     let (script, best_plan) = plans.into_iter().next()?;
 
-    Some(Plan {
-        target: Target::Segwitv1 {
-            tr: tr.clone(),
-            tr_plan: TrSpend::LeafSpend {
-                script: script.encode(),
-                leaf_version: LeafVersion::TapScript,
-            },
+    Some(best_plan.into_plan(Target::Segwitv1 {
+        tr: tr.clone(),
+        tr_plan: TrSpend::LeafSpend {
+            script: script.encode(),
+            leaf_version: LeafVersion::TapScript,
         },
-        set_locktime: best_plan.min_locktime.clone(),
-        set_sequence: best_plan.min_sequence.clone(),
-        template: best_plan.template,
-    })
+    }))
 }
 
 #[derive(Debug)]
@@ -178,6 +228,49 @@ impl<Ak> Default for TermPlan<Ak> {
     }
 }
 
+fn find_plan_key<Ak: Clone + CanDerive>(
+    key: &DefiniteDescriptorKey,
+    assets: &Assets<Ak>,
+) -> Option<PlanKey<Ak>> {
+    let (asset_key, derivation_hint) = assets
+        .keys
+        .iter()
+        .find_map(|asset_key| Some((asset_key, asset_key.can_derive(key)?)))?;
+    Some(PlanKey {
+        asset_key: asset_key.clone(),
+        derivation_hint,
+        descriptor_key: key.clone(),
+    })
+}
+
+fn sign_step<Ak, Ctx: ScriptContext>(plan_key: PlanKey<Ak>) -> TemplateItem<Ak> {
+    match Ctx::sig_type() {
+        SigType::Schnorr => TemplateItem::Sign(plan_key),
+        SigType::Ecdsa => TemplateItem::EcdsaSign(plan_key),
+    }
+}
+
+fn pk_step<Ak, Ctx: ScriptContext>(key: &DefiniteDescriptorKey) -> TemplateItem<Ak> {
+    TemplateItem::Pk {
+        key: key.clone(),
+        x_only: Ctx::sig_type() == SigType::Schnorr,
+    }
+}
+
+/// The smallest of the plans that are possible
+fn cheapest<Ak>(plans: impl IntoIterator<Item = Option<TermPlan<Ak>>>) -> Option<TermPlan<Ak>> {
+    plans
+        .into_iter()
+        .flatten()
+        .min_by_key(|plan| plan.expected_size())
+}
+
+/// Puts the witness of `first` below the one of `second` on the stack, so that `second` is
+/// consumed first
+fn concat<Ak>(first: Option<TermPlan<Ak>>, second: Option<TermPlan<Ak>>) -> Option<TermPlan<Ak>> {
+    first?.combine(second?)
+}
+
 fn plan_steps<Ak: Clone + CanDerive, Ctx: ScriptContext>(
     term: &Terminal<DefiniteDescriptorKey, Ctx>,
     assets: &Assets<Ak>,
@@ -186,44 +279,26 @@ fn plan_steps<Ak: Clone + CanDerive, Ctx: ScriptContext>(
         Terminal::True => Some(TermPlan::new(vec![])),
         Terminal::False => return None,
         Terminal::PkH(key) => {
-            let (asset_key, derivation_hint) = assets
-                .keys
-                .iter()
-                .find_map(|asset_key| Some((asset_key, asset_key.can_derive(key)?)))?;
+            let plan_key = find_plan_key(key, assets)?;
             Some(TermPlan::new(vec![
-                TemplateItem::Sign(PlanKey {
-                    asset_key: asset_key.clone(),
-                    derivation_hint,
-                    descriptor_key: key.clone(),
-                }),
-                TemplateItem::Pk { key: key.clone() },
+                sign_step::<_, Ctx>(plan_key),
+                pk_step::<_, Ctx>(key),
             ]))
         }
         Terminal::PkK(key) => {
-            let (asset_key, derivation_hint) = assets
-                .keys
-                .iter()
-                .find_map(|asset_key| Some((asset_key, asset_key.can_derive(key)?)))?;
-            Some(TermPlan::new(vec![TemplateItem::Sign(PlanKey {
-                asset_key: asset_key.clone(),
-                derivation_hint,
-                descriptor_key: key.clone(),
-            })]))
-        }
-        Terminal::RawPkH(_pk_hash) => {
-            /* TODO */
-            None
+            let plan_key = find_plan_key(key, assets)?;
+            Some(TermPlan::new(vec![sign_step::<_, Ctx>(plan_key)]))
         }
+        // Only the hash of the key is in the script, so there's no telling which key to sign with
+        Terminal::RawPkH(_pk_hash) => None,
         Terminal::After(locktime) => {
             let max_locktime = assets.max_locktime?;
             let locktime = absolute::LockTime::from(*locktime);
             let (height, time) = match max_locktime {
-                absolute::LockTime::Blocks(height) => {
-                    (height, absolute::Time::from_consensus(0).unwrap())
-                }
+                absolute::LockTime::Blocks(height) => (height, absolute::Time::MIN),
                 absolute::LockTime::Seconds(seconds) => (absolute::Height::ZERO, seconds),
             };
-            if max_locktime.is_satisfied_by(height, time) {
+            if locktime.is_satisfied_by(height, time) {
                 Some(TermPlan {
                     min_locktime: Some(locktime),
                     ..Default::default()
@@ -233,19 +308,13 @@ fn plan_steps<Ak: Clone + CanDerive, Ctx: ScriptContext>(
             }
         }
         Terminal::Older(older) => {
-            // FIXME: older should be a height or time not a sequence.
-            let max_sequence = assets.txo_age?;
-            //TODO: this whole thing is probably wrong but upstream should provide a way of
-            // doing it properly.
-            if max_sequence.is_height_locked() == older.is_height_locked() {
-                if max_sequence.to_consensus_u32() >= older.to_consensus_u32() {
-                    Some(TermPlan {
-                        min_sequence: Some(*older),
-                        ..Default::default()
-                    })
-                } else {
-                    None
-                }
+            // the age of the output has to be at least the relative timelock, in the same unit
+            let txo_age = assets.txo_age?.to_relative_lock_time()?;
+            if older.to_relative_lock_time()?.is_implied_by(txo_age) {
+                Some(TermPlan {
+                    min_sequence: Some(*older),
+                    ..Default::default()
+                })
             } else {
                 None
             }
@@ -290,14 +359,32 @@ fn plan_steps<Ak: Clone + CanDerive, Ctx: ScriptContext>(
             Some(plan)
         }
         Terminal::AndV(l, r) | Terminal::AndB(l, r) => {
-            let lhs = plan_steps(&l.node, assets)?;
-            let rhs = plan_steps(&r.node, assets)?;
-            lhs.combine(rhs)
+            concat(plan_steps(&r.node, assets), plan_steps(&l.node, assets))
         }
-        Terminal::AndOr(_, _, _) => todo!(),
-        Terminal::OrB(_, _) => todo!(),
-        Terminal::OrD(_, _) => todo!(),
-        Terminal::OrC(_, _) => todo!(),
+        Terminal::AndOr(x, y, z) => cheapest([
+            concat(plan_steps(&y.node, assets), plan_steps(&x.node, assets)),
+            concat(
+                plan_steps(&z.node, assets),
+                dissatisfy_steps(&x.node, assets),
+            ),
+        ]),
+        Terminal::OrB(l, r) => cheapest([
+            concat(
+                dissatisfy_steps(&r.node, assets),
+                plan_steps(&l.node, assets),
+            ),
+            concat(
+                plan_steps(&r.node, assets),
+                dissatisfy_steps(&l.node, assets),
+            ),
+        ]),
+        Terminal::OrD(l, r) | Terminal::OrC(l, r) => cheapest([
+            plan_steps(&l.node, assets),
+            concat(
+                plan_steps(&r.node, assets),
+                dissatisfy_steps(&l.node, assets),
+            ),
+        ]),
         Terminal::OrI(lhs, rhs) => {
             let lplan = plan_steps(&lhs.node, assets).map(|mut plan| {
                 plan.template.push(TemplateItem::One);
@@ -318,8 +405,141 @@ fn plan_steps<Ak: Clone + CanDerive, Ctx: ScriptContext>(
                 (lplan, rplan) => lplan.or(rplan),
             }
         }
-        Terminal::Thresh(_, _) => todo!(),
-        Terminal::Multi(_, _) => todo!(),
-        Terminal::MultiA(_, _) => todo!(),
+        Terminal::Thresh(k, subs) => {
+            let mut steps = subs
+                .iter()
+                .map(|sub| {
+                    (
+                        plan_steps(&sub.node, assets),
+                        dissatisfy_steps(&sub.node, assets),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            // Satisfy the subs that can't be dissatisfied, then the ones that cost the least more
+            // to satisfy than to dissatisfy
+            let mut satisfiable = (0..steps.len())
+                .filter(|&i| steps[i].0.is_some())
+                .collect::<Vec<_>>();
+            satisfiable.sort_by_key(|&i| match &steps[i] {
+                (Some(sat), Some(dissat)) => (
+                    true,
+                    sat.expected_size() as i64 - dissat.expected_size() as i64,
+                ),
+                _ => (false, 0),
+            });
+            if satisfiable.len() < *k {
+                return None;
+            }
+            for &i in &satisfiable[*k..] {
+                steps[i].0 = None;
+            }
+
+            // The first sub consumes the top of the stack
+            steps
+                .into_iter()
+                .rev()
+                .try_fold(TermPlan::default(), |plan, (sat, dissat)| {
+                    plan.combine(sat.or(dissat)?)
+                })
+        }
+        Terminal::Multi(k, keys) => {
+            // The signatures go in the order of their keys, after the dummy element
+            let plan_keys = keys
+                .iter()
+                .filter_map(|key| find_plan_key(key, assets))
+                .take(*k)
+                .collect::<Vec<_>>();
+            if plan_keys.len() < *k {
+                return None;
+            }
+            let template = core::iter::once(TemplateItem::Zero)
+                .chain(plan_keys.into_iter().map(sign_step::<_, Ctx>))
+                .collect();
+            Some(TermPlan::new(template))
+        }
+        Terminal::MultiA(k, keys) => {
+            let mut signatures = 0;
+            let mut template = keys
+                .iter()
+                .map(|key| match find_plan_key(key, assets) {
+                    Some(plan_key) if signatures < *k => {
+                        signatures += 1;
+                        sign_step::<_, Ctx>(plan_key)
+                    }
+                    _ => TemplateItem::Zero,
+                })
+                .collect::<Vec<_>>();
+            if signatures < *k {
+                return None;
+            }
+            // The signature for the first key has to be on top of the stack
+            template.reverse();
+            Some(TermPlan::new(template))
+        }
+    }
+}
+
+/// The steps to dissatisfy `term`, following the non-malleable dissatisfactions of miniscript.
+///
+/// Returns `None` if the fragment can't be dissatisfied, or if doing so requires assets we don't
+/// have.
+fn dissatisfy_steps<Ak: Clone + CanDerive, Ctx: ScriptContext>(
+    term: &Terminal<DefiniteDescriptorKey, Ctx>,
+    assets: &Assets<Ak>,
+) -> Option<TermPlan<Ak>> {
+    match term {
+        Terminal::False => Some(TermPlan::new(vec![])),
+        Terminal::PkK(_) => Some(TermPlan::new(vec![TemplateItem::Zero])),
+        Terminal::PkH(key) => Some(TermPlan::new(vec![
+            TemplateItem::Zero,
+            pk_step::<_, Ctx>(key),
+        ])),
+        Terminal::Sha256(_)
+        | Terminal::Hash256(_)
+        | Terminal::Ripemd160(_)
+        | Terminal::Hash160(_) => Some(TermPlan::new(vec![TemplateItem::HashDissatisfaction])),
+        Terminal::Alt(ms)
+        | Terminal::Swap(ms)
+        | Terminal::Check(ms)
+        | Terminal::ZeroNotEqual(ms) => dissatisfy_steps(&ms.node, assets),
+        Terminal::DupIf(_) | Terminal::NonZero(_) => Some(TermPlan::new(vec![TemplateItem::Zero])),
+        Terminal::AndV(l, r) => concat(
+            dissatisfy_steps(&r.node, assets),
+            plan_steps(&l.node, assets),
+        ),
+        Terminal::AndB(l, r) | Terminal::OrB(l, r) | Terminal::OrD(l, r) => concat(
+            dissatisfy_steps(&r.node, assets),
+            dissatisfy_steps(&l.node, assets),
+        ),
+        Terminal::AndOr(x, _, z) => concat(
+            dissatisfy_steps(&z.node, assets),
+            dissatisfy_steps(&x.node, assets),
+        ),
+        Terminal::OrI(lhs, rhs) => cheapest([
+            dissatisfy_steps(&lhs.node, assets).map(|mut plan| {
+                plan.template.push(TemplateItem::One);
+                plan
+            }),
+            dissatisfy_steps(&rhs.node, assets).map(|mut plan| {
+                plan.template.push(TemplateItem::Zero);
+                plan
+            }),
+        ]),
+        Terminal::Thresh(_, subs) => subs
+            .iter()
+            .rev()
+            .try_fold(TermPlan::default(), |plan, sub| {
+                plan.combine(dissatisfy_steps(&sub.node, assets)?)
+            }),
+        Terminal::Multi(k, _) => Some(TermPlan::new(vec![TemplateItem::Zero; k + 1])),
+        Terminal::MultiA(_, keys) => Some(TermPlan::new(vec![TemplateItem::Zero; keys.len()])),
+        // The key of a raw public key hash isn't known and timelocks can't be dissatisfied
+        Terminal::True
+        | Terminal::RawPkH(_)
+        | Terminal::After(_)
+        | Terminal::Older(_)
+        | Terminal::Verify(_)
+        | Terminal::OrC(_, _) => None,
     }
 }
@@ -3,10 +3,10 @@ use core::ops::Deref;
 
 use bitcoin::{
     bip32,
-    hashes::{hash160, ripemd160, sha256},
+    hashes::{hash160, ripemd160, sha256, Hash},
     key::XOnlyPublicKey,
     psbt::Prevouts,
-    secp256k1::{KeyPair, Message, PublicKey, Signing, Verification},
+    secp256k1::{KeyPair, Message, PublicKey, SecretKey, Signing, Verification},
     sighash,
     sighash::{EcdsaSighashType, SighashCache, TapSighashType},
     taproot, Transaction, TxOut,
@@ -36,6 +36,7 @@ pub struct Requirements<Ak> {
 impl<Ak> Default for RequiredSignatures<Ak> {
     fn default() -> Self {
         RequiredSignatures::Legacy {
+            script_code: Default::default(),
             keys: Default::default(),
         }
     }
@@ -67,9 +68,19 @@ impl<Ak> Requirements<Ak> {
 #[derive(Clone, Debug)]
 pub enum RequiredSignatures<Ak> {
     /// Legacy ECDSA signatures are required
-    Legacy { keys: Vec<PlanKey<Ak>> },
+    Legacy {
+        /// The script code the signatures commit to
+        script_code: ScriptBuf,
+        /// The keys that require signatures
+        keys: Vec<PlanKey<Ak>>,
+    },
     /// Segwitv0 ECDSA signatures are required
-    Segwitv0 { keys: Vec<PlanKey<Ak>> },
+    Segwitv0 {
+        /// The script code the signatures commit to
+        script_code: ScriptBuf,
+        /// The keys that require signatures
+        keys: Vec<PlanKey<Ak>>,
+    },
     /// A Taproot key spend signature is required
     TapKey {
         /// the internal key
@@ -90,6 +101,8 @@ pub enum RequiredSignatures<Ak> {
 pub enum SigningError {
     SigHashError(sighash::Error),
     DerivationError(bip32::Error),
+    /// The secret key is a multipath extended key, which doesn't tell which key to sign with
+    MultipathKey,
 }
 
 impl From<sighash::Error> for SigningError {
@@ -103,6 +116,7 @@ impl core::fmt::Display for SigningError {
         match self {
             SigningError::SigHashError(e) => e.fmt(f),
             SigningError::DerivationError(e) => e.fmt(f),
+            SigningError::MultipathKey => write!(f, "can't sign with a multipath extended key"),
         }
     }
 }
@@ -123,13 +137,56 @@ impl RequiredSignatures<DescriptorPublicKey> {
         keymap: &KeyMap,
         prevouts: &Prevouts<'_, impl core::borrow::Borrow<TxOut>>,
         schnorr_sighashty: Option<TapSighashType>,
-        _ecdsa_sighashty: Option<EcdsaSighashType>,
+        ecdsa_sighashty: Option<EcdsaSighashType>,
         sighash_cache: &mut SighashCache<T>,
         auth_data: &mut SatisfactionMaterial,
         secp: &Secp256k1<impl Signing + Verification>,
     ) -> Result<bool, SigningError> {
         match self {
-            RequiredSignatures::Legacy { .. } | RequiredSignatures::Segwitv0 { .. } => todo!(),
+            RequiredSignatures::Legacy { script_code, keys }
+            | RequiredSignatures::Segwitv0 { script_code, keys } => {
+                let sighash_type = ecdsa_sighashty.unwrap_or(EcdsaSighashType::All);
+                let sighash = match self {
+                    RequiredSignatures::Legacy { .. } => sighash_cache
+                        .legacy_signature_hash(input_index, script_code, sighash_type.to_u32())?
+                        .to_byte_array(),
+                    _ => {
+                        let prevout = match prevouts {
+                            Prevouts::All(prevouts) => prevouts.get(input_index),
+                            Prevouts::One(index, prevout) if *index == input_index => Some(prevout),
+                            Prevouts::One(..) => None,
+                        }
+                        .ok_or(sighash::Error::PrevoutIndex)?;
+                        sighash_cache
+                            .segwit_signature_hash(
+                                input_index,
+                                script_code,
+                                prevout.borrow().value,
+                                sighash_type,
+                            )?
+                            .to_byte_array()
+                    }
+                };
+                let msg = Message::from_slice(&sighash).expect("Sighashes are 32 bytes");
+
+                let mut modified = false;
+
+                for plan_key in keys {
+                    if let Some(secret_key) = keymap.get(&plan_key.asset_key) {
+                        let secret_key = derive_secret_key(secret_key, plan_key, secp)?;
+                        let bitcoin_sig = ecdsa::Signature {
+                            sig: secp.sign_ecdsa_low_r(&msg, &secret_key),
+                            hash_ty: sighash_type,
+                        };
+
+                        auth_data
+                            .ecdsa_sigs
+                            .insert(plan_key.descriptor_key.clone(), bitcoin_sig);
+                        modified = true;
+                    }
+                }
+                Ok(modified)
+            }
             RequiredSignatures::TapKey {
                 plan_key,
                 merkle_root,
@@ -144,19 +201,7 @@ impl RequiredSignatures<DescriptorPublicKey> {
                     Some(secret_key) => secret_key,
                     None => return Ok(false),
                 };
-                let secret_key = match secret_key {
-                    DescriptorSecretKey::Single(single) => single.key.inner,
-                    DescriptorSecretKey::XPrv(xprv) => {
-                        xprv.xkey
-                            .derive_priv(&secp, &plan_key.derivation_hint)?
-                            .private_key
-                    }
-                    DescriptorSecretKey::MultiXPrv(_) => {
-                        // This crate will be replaced by
-                        // https://github.com/rust-bitcoin/rust-miniscript/pull/481 anyways
-                        todo!();
-                    }
-                };
+                let secret_key = derive_secret_key(secret_key, plan_key, secp)?;
 
                 let pubkey = PublicKey::from_secret_key(&secp, &secret_key);
                 let x_only_pubkey = XOnlyPublicKey::from(pubkey);
@@ -196,19 +241,7 @@ impl RequiredSignatures<DescriptorPublicKey> {
 
                 for plan_key in plan_keys {
                     if let Some(secret_key) = keymap.get(&plan_key.asset_key) {
-                        let secret_key = match secret_key {
-                            DescriptorSecretKey::Single(single) => single.key.inner,
-                            DescriptorSecretKey::XPrv(xprv) => {
-                                xprv.xkey
-                                    .derive_priv(&secp, &plan_key.derivation_hint)?
-                                    .private_key
-                            }
-                            DescriptorSecretKey::MultiXPrv(_) => {
-                                // This crate will be replaced by
-                                // https://github.com/rust-bitcoin/rust-miniscript/pull/481 anyways
-                                todo!();
-                            }
-                        };
+                        let secret_key = derive_secret_key(secret_key, plan_key, secp)?;
                         let keypair = KeyPair::from_secret_key(&secp, &secret_key.clone());
                         let msg =
                             Message::from_slice(sighash.as_ref()).expect("Sighashes are 32 bytes");
@@ -229,3 +262,18 @@ impl RequiredSignatures<DescriptorPublicKey> {
         }
     }
 }
+
+fn derive_secret_key(
+    secret_key: &DescriptorSecretKey,
+    plan_key: &PlanKey<DescriptorPublicKey>,
+    secp: &Secp256k1<impl Signing>,
+) -> Result<SecretKey, SigningError> {
+    match secret_key {
+        DescriptorSecretKey::Single(single) => Ok(single.key.inner),
+        DescriptorSecretKey::XPrv(xprv) => Ok(xprv
+            .xkey
+            .derive_priv(secp, &plan_key.derivation_hint)?
+            .private_key),
+        DescriptorSecretKey::MultiXPrv(_) => Err(SigningError::MultipathKey),
+    }
+}
@@ -5,14 +5,23 @@ use bitcoin::{
 };
 
 use super::*;
-use crate::{hash256, varint_len, DefiniteDescriptorKey};
+use crate::{hash256, push_opcode_size, varint_len, DefiniteDescriptorKey};
 
 #[derive(Clone, Debug)]
 pub(crate) enum TemplateItem<Ak> {
+    /// A schnorr signature
     Sign(PlanKey<Ak>),
-    Pk { key: DefiniteDescriptorKey },
+    /// An ECDSA signature
+    EcdsaSign(PlanKey<Ak>),
+    /// A public key, serialized as an x-only key in tapscript
+    Pk {
+        key: DefiniteDescriptorKey,
+        x_only: bool,
+    },
     One,
     Zero,
+    /// A 32 byte element that is not the pre-image of a hash
+    HashDissatisfaction,
     Sha256(sha256::Hash),
     Hash256(hash256::Hash),
     Ripemd160(ripemd160::Hash),
@@ -36,9 +45,19 @@ impl<Ak> TemplateItem<Ak> {
     pub fn expected_size(&self) -> usize {
         match self {
             TemplateItem::Sign { .. } => 64, /* size of sig TODO: take into consideration sighash flag */
-            TemplateItem::Pk { .. } => 32,
+            // a DER encoded signature with a low R value and the sighash flag
+            TemplateItem::EcdsaSign { .. } => 72,
+            TemplateItem::Pk { x_only: true, .. } => 32,
+            TemplateItem::Pk { key, .. } => {
+                if key.is_uncompressed() {
+                    65
+                } else {
+                    33
+                }
+            }
             TemplateItem::One => varint_len(1),
             TemplateItem::Zero => 0, /* zero means an empty witness element */
+            TemplateItem::HashDissatisfaction => 32,
             // I'm not sure if it should be 32 here (it's a 20 byte hash) but that's what other
             // parts of the code were doing.
             TemplateItem::Hash160(_) | TemplateItem::Ripemd160(_) => 32,
@@ -46,6 +65,18 @@ impl<Ak> TemplateItem<Ak> {
         }
     }
 
+    /// The size of the item when it's pushed in a script sig
+    pub fn expected_script_sig_size(&self) -> usize {
+        match self {
+            // pushed with OP_0 and OP_1
+            TemplateItem::Zero | TemplateItem::One => 1,
+            item => {
+                let size = item.expected_size();
+                push_opcode_size(size) + size
+            }
+        }
+    }
+
     // this can only be called if we are sure that auth_data has what we need
     pub(super) fn to_witness_stack(&self, auth_data: &SatisfactionMaterial) -> Vec<Vec<u8>> {
         match self {
@@ -56,7 +87,15 @@ impl<Ak> TemplateItem<Ak> {
                     .unwrap()
                     .to_vec()]
             }
+            TemplateItem::EcdsaSign(plan_key) => {
+                vec![auth_data
+                    .ecdsa_sigs
+                    .get(&plan_key.descriptor_key)
+                    .unwrap()
+                    .to_vec()]
+            }
             TemplateItem::One => vec![vec![1]],
+            TemplateItem::HashDissatisfaction => vec![vec![0; 32]],
             TemplateItem::Zero => vec![vec![]],
             TemplateItem::Sha256(image) => {
                 vec![auth_data.sha256_preimages.get(image).unwrap().to_vec()]
@@ -70,7 +109,10 @@ impl<Ak> TemplateItem<Ak> {
             TemplateItem::Hash256(image) => {
                 vec![auth_data.hash256_preimages.get(image).unwrap().to_vec()]
             }
-            TemplateItem::Pk { key } => vec![key.to_public_key().to_bytes()],
+            TemplateItem::Pk { key, x_only: true } => {
+                vec![key.to_x_only_pubkey().serialize().to_vec()]
+            }
+            TemplateItem::Pk { key, .. } => vec![key.to_public_key().to_bytes()],
         }
     }
 }